@@ -0,0 +1,391 @@
+//! Dominance and loop structure of a control flow graph.
+
+use std::collections::{HashMap, HashSet};
+
+use petgraph::{
+    algo::dominators::{self, Dominators},
+    stable_graph::NodeIndex,
+    visit::{DfsPostOrder, Reversed, Walker},
+    Direction,
+};
+
+use super::Cfg;
+
+/// The dominators of every node reachable from the root, as a tree in which
+/// each node hangs below its immediate dominator.
+pub struct DominatorTree {
+    dominators: Dominators<NodeIndex>,
+    children: HashMap<NodeIndex, Vec<NodeIndex>>,
+}
+
+impl DominatorTree {
+    fn new(dominators: Dominators<NodeIndex>, nodes: impl Iterator<Item = NodeIndex>) -> Self {
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+
+        for node in nodes {
+            if let Some(parent) = dominators.immediate_dominator(node) {
+                children.entry(parent).or_default().push(node);
+            }
+        }
+
+        DominatorTree {
+            dominators,
+            children,
+        }
+    }
+
+    pub fn root(&self) -> NodeIndex {
+        self.dominators.root()
+    }
+
+    /// `None` for the root and for nodes the root never reaches.
+    pub fn immediate_dominator(&self, node: NodeIndex) -> Option<NodeIndex> {
+        self.dominators.immediate_dominator(node)
+    }
+
+    /// Every node dominates itself.
+    pub fn dominates(&self, dominator: NodeIndex, node: NodeIndex) -> bool {
+        self.dominators
+            .dominators(node)
+            .is_some_and(|mut dominators| dominators.any(|n| n == dominator))
+    }
+
+    pub fn is_reachable(&self, node: NodeIndex) -> bool {
+        node == self.root() || self.immediate_dominator(node).is_some()
+    }
+
+    /// The nodes immediately dominated by `node`.
+    pub fn children(&self, node: NodeIndex) -> &[NodeIndex] {
+        self.children.get(&node).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every node of the tree, each before the nodes it dominates.
+    pub fn preorder(&self) -> Vec<NodeIndex> {
+        let mut order = vec![];
+        let mut stack = vec![self.root()];
+
+        while let Some(node) = stack.pop() {
+            order.push(node);
+            stack.extend(self.children(node).iter().rev());
+        }
+
+        order
+    }
+}
+
+/// A loop with a single entry: the header dominates every node in the body.
+pub struct Loop {
+    pub header: NodeIndex,
+    /// The nodes of the loop, including the header and any nested loops.
+    pub body: HashSet<NodeIndex>,
+    /// The sources of the back edges, which jump to the header again.
+    pub latches: Vec<NodeIndex>,
+    /// The innermost loop this one is nested in, as an index into the loops.
+    pub parent: Option<usize>,
+    /// How many loops enclose this one; outermost loops have a depth of 0.
+    pub depth: usize,
+}
+
+pub struct Analysis {
+    pub dominators: DominatorTree,
+    pub post_dominators: DominatorTree,
+    pub frontiers: HashMap<NodeIndex, HashSet<NodeIndex>>,
+    pub loops: Vec<Loop>,
+}
+
+/// The dominator tree rooted at the entry.
+pub fn dominators(cfg: &Cfg) -> DominatorTree {
+    DominatorTree::new(
+        dominators::simple_fast(&cfg.graph, cfg.entry),
+        cfg.graph.node_indices(),
+    )
+}
+
+/// The dominator tree of the reversed graph, rooted at the exit. Nodes that
+/// never reach the exit, such as those of an infinite loop, are not in it.
+pub fn post_dominators(cfg: &Cfg) -> DominatorTree {
+    DominatorTree::new(
+        dominators::simple_fast(Reversed(&cfg.graph), cfg.exit),
+        cfg.graph.node_indices(),
+    )
+}
+
+/// The nodes where the dominance of every node ends: the successors it does
+/// not strictly dominate, of the nodes it does dominate.
+pub fn dominance_frontiers(
+    cfg: &Cfg,
+    dominators: &DominatorTree,
+) -> HashMap<NodeIndex, HashSet<NodeIndex>> {
+    let mut frontiers: HashMap<_, HashSet<_>> = HashMap::new();
+
+    for node in cfg.graph.node_indices() {
+        let predecessors = cfg
+            .graph
+            .neighbors_directed(node, Direction::Incoming)
+            .filter(|&pred| dominators.is_reachable(pred))
+            .collect::<HashSet<_>>();
+
+        if predecessors.len() < 2 {
+            continue;
+        }
+
+        let Some(idom) = dominators.immediate_dominator(node) else {
+            continue;
+        };
+
+        // walk up from every predecessor until reaching a node that dominates
+        // this one, which it is then no longer a frontier of
+        for mut runner in predecessors {
+            while runner != idom {
+                frontiers.entry(runner).or_default().insert(node);
+
+                match dominators.immediate_dominator(runner) {
+                    Some(parent) => runner = parent,
+                    None => break,
+                }
+            }
+        }
+    }
+
+    frontiers
+}
+
+/// Finds the natural loop of every back edge, merging those that share a
+/// header, and orders them so that every loop comes before its nested loops.
+pub fn natural_loops(cfg: &Cfg, dominators: &DominatorTree) -> Vec<Loop> {
+    let preorder = dominators.preorder();
+    let mut loops: HashMap<NodeIndex, Loop> = HashMap::new();
+
+    for &node in preorder.iter() {
+        for succ in cfg.graph.neighbors_directed(node, Direction::Outgoing) {
+            if !dominators.dominates(succ, node) {
+                continue;
+            }
+
+            let header = succ;
+            let l = loops.entry(header).or_insert_with(|| Loop {
+                header,
+                body: HashSet::from([header]),
+                latches: vec![],
+                parent: None,
+                depth: 0,
+            });
+
+            l.latches.push(node);
+
+            // everything that reaches the latch without passing the header
+            let mut stack = vec![node];
+
+            while let Some(n) = stack.pop() {
+                if l.body.insert(n) {
+                    stack.extend(
+                        cfg.graph
+                            .neighbors_directed(n, Direction::Incoming)
+                            .filter(|&pred| dominators.is_reachable(pred)),
+                    );
+                }
+            }
+        }
+    }
+
+    // the header of an enclosing loop dominates the headers nested in it, so
+    // it comes first in preorder
+    let mut loops = preorder
+        .iter()
+        .filter_map(|header| loops.remove(header))
+        .collect::<Vec<_>>();
+
+    for i in 0..loops.len() {
+        let header = loops[i].header;
+        let parent = (0..i).rev().find(|&j| loops[j].body.contains(&header));
+
+        loops[i].parent = parent;
+        loops[i].depth = parent.map_or(0, |j| loops[j].depth + 1);
+    }
+
+    loops
+}
+
+/// Runs every analysis of this module.
+pub fn analyze(cfg: &Cfg) -> Analysis {
+    let dominators = dominators(cfg);
+    let frontiers = dominance_frontiers(cfg, &dominators);
+    let loops = natural_loops(cfg, &dominators);
+
+    Analysis {
+        post_dominators: post_dominators(cfg),
+        dominators,
+        frontiers,
+        loops,
+    }
+}
+
+/// The nodes reachable from the entry, in reverse postorder, so that every
+/// node comes before its successors except along back edges.
+pub fn reverse_postorder(cfg: &Cfg) -> Vec<NodeIndex> {
+    let mut order = DfsPostOrder::new(&cfg.graph, cfg.entry)
+        .iter(&cfg.graph)
+        .collect::<Vec<_>>();
+    order.reverse();
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use petgraph::{stable_graph::NodeIndex, Graph};
+
+    use crate::cfg::{CFGEdge, CFGNode, Cfg};
+
+    use super::{analyze, reverse_postorder};
+
+    /// entry -> a, a -> b | c, b -> d, c -> d, d -> e, e -> f | g, f -> e,
+    /// f -> f, g -> exit: a diamond followed by a loop at `e` with a nested
+    /// self-loop at `f`. Returns the graph with its blocks `a` to `g`.
+    fn graph() -> (Cfg, [NodeIndex; 7]) {
+        let mut graph = Graph::new();
+        let entry = graph.add_node(CFGNode::Entry);
+        let exit = graph.add_node(CFGNode::Exit);
+        let [a, b, c, d, e, f, g] = [(); 7].map(|_| graph.add_node(CFGNode::Block(Box::default())));
+
+        for (from, to, edge) in [
+            (entry, a, CFGEdge::Fallthrough),
+            (a, b, CFGEdge::True),
+            (a, c, CFGEdge::False),
+            (b, d, CFGEdge::Fallthrough),
+            (c, d, CFGEdge::Fallthrough),
+            (d, e, CFGEdge::Fallthrough),
+            (e, f, CFGEdge::True),
+            (e, g, CFGEdge::False),
+            (f, e, CFGEdge::True),
+            (f, f, CFGEdge::False),
+            (g, exit, CFGEdge::Fallthrough),
+        ] {
+            graph.add_edge(from, to, edge);
+        }
+
+        (Cfg { graph, entry, exit }, [a, b, c, d, e, f, g])
+    }
+
+    #[test]
+    fn dominators() {
+        let (cfg, [a, b, c, d, e, f, g]) = graph();
+        let analysis = analyze(&cfg);
+        let dominators = analysis.dominators;
+
+        for (node, idom) in [
+            (a, cfg.entry),
+            (b, a),
+            (c, a),
+            (d, a),
+            (e, d),
+            (f, e),
+            (g, e),
+            (cfg.exit, g),
+        ] {
+            assert_eq!(dominators.immediate_dominator(node), Some(idom));
+        }
+
+        assert_eq!(dominators.immediate_dominator(cfg.entry), None);
+        assert!(dominators.dominates(a, f));
+        assert!(dominators.dominates(e, e));
+        assert!(!dominators.dominates(b, d));
+        assert!(!dominators.dominates(f, g));
+
+        let mut children = dominators.children(a).to_vec();
+        children.sort();
+        assert_eq!(children, vec![b, c, d]);
+
+        let preorder = dominators.preorder();
+        assert_eq!(preorder.len(), 9);
+        assert_eq!(preorder[0], cfg.entry);
+        assert!(preorder.iter().position(|&n| n == e) < preorder.iter().position(|&n| n == f));
+    }
+
+    #[test]
+    fn post_dominators() {
+        let (cfg, [a, b, c, d, e, f, g]) = graph();
+        let post_dominators = analyze(&cfg).post_dominators;
+
+        for (node, ipdom) in [
+            (cfg.entry, a),
+            (a, d),
+            (b, d),
+            (c, d),
+            (d, e),
+            (e, g),
+            (f, e),
+            (g, cfg.exit),
+        ] {
+            assert_eq!(post_dominators.immediate_dominator(node), Some(ipdom));
+        }
+    }
+
+    #[test]
+    fn unreachable_nodes() {
+        let (mut cfg, [_, _, _, _, _, _, g]) = graph();
+        let dead = cfg.graph.add_node(CFGNode::Block(Box::default()));
+        cfg.graph.add_edge(dead, g, CFGEdge::Fallthrough);
+
+        let analysis = analyze(&cfg);
+
+        assert!(!analysis.dominators.is_reachable(dead));
+        assert!(analysis.dominators.is_reachable(g));
+        assert!(!reverse_postorder(&cfg).contains(&dead));
+    }
+
+    #[test]
+    fn dominance_frontiers() {
+        let (cfg, [a, b, c, d, e, f, g]) = graph();
+        let frontiers = analyze(&cfg).frontiers;
+
+        let expected = HashMap::from([
+            (b, HashSet::from([d])),
+            (c, HashSet::from([d])),
+            (e, HashSet::from([e])),
+            (f, HashSet::from([e, f])),
+        ]);
+
+        assert_eq!(frontiers, expected);
+
+        for node in [a, d, g] {
+            assert!(!frontiers.contains_key(&node));
+        }
+    }
+
+    #[test]
+    fn natural_loops() {
+        let (cfg, [.., e, f, _]) = graph();
+        let loops = analyze(&cfg).loops;
+
+        assert_eq!(loops.len(), 2);
+
+        assert_eq!(loops[0].header, e);
+        assert_eq!(loops[0].body, HashSet::from([e, f]));
+        assert_eq!(loops[0].latches, vec![f]);
+        assert_eq!(loops[0].parent, None);
+        assert_eq!(loops[0].depth, 0);
+
+        assert_eq!(loops[1].header, f);
+        assert_eq!(loops[1].body, HashSet::from([f]));
+        assert_eq!(loops[1].latches, vec![f]);
+        assert_eq!(loops[1].parent, Some(0));
+        assert_eq!(loops[1].depth, 1);
+    }
+
+    #[test]
+    fn reverse_postorder_puts_predecessors_first() {
+        let (cfg, [a, b, c, d, e, f, g]) = graph();
+        let order = reverse_postorder(&cfg);
+        let index = |node| order.iter().position(|&n| n == node).unwrap();
+
+        assert_eq!(order.len(), 9);
+        assert_eq!(order[0], cfg.entry);
+
+        for (from, to) in [(a, b), (a, c), (b, d), (c, d), (d, e), (e, f), (e, g)] {
+            assert!(index(from) < index(to));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::HashSet;
+
+use petgraph::{
+    stable_graph::{NodeIndex, StableGraph},
+    visit::{Dfs, EdgeRef},
+    Direction, Graph,
+};
+
+use super::{CFGEdge, CFGNode, Cfg};
+
+type Nodes = StableGraph<CFGNode, CFGEdge>;
+
+/// Removes the nodes that can never run and merges the blocks the translation
+/// left behind at joins and jumps, so that every block is a maximal run of
+/// straight-line statements.
+pub fn cleanup(mut graph: Nodes, entry: NodeIndex, exit: NodeIndex) -> Cfg {
+    remove_unreachable(&mut graph, entry, exit);
+
+    loop {
+        let mut changed = false;
+
+        for node in graph.node_indices().collect::<Vec<_>>() {
+            if !graph.contains_node(node) {
+                continue;
+            }
+
+            changed |= bypass_empty(&mut graph, node) || merge_successor(&mut graph, node);
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let graph = Graph::from(graph);
+
+    // the indices shift when the graph is compacted
+    let find = |kind: fn(&CFGNode) -> bool| graph.node_indices().find(|&n| kind(&graph[n]));
+    let entry = find(|node| matches!(node, CFGNode::Entry)).unwrap();
+    let exit = find(|node| matches!(node, CFGNode::Exit)).unwrap();
+
+    Cfg { graph, entry, exit }
+}
+
+/// Code after a jump, and labels nothing jumps to, are only reachable from
+/// the nodes the translation created for them. The exit is kept even when
+/// the chunk never ends.
+fn remove_unreachable(graph: &mut Nodes, entry: NodeIndex, exit: NodeIndex) {
+    let mut reachable = HashSet::new();
+    let mut dfs = Dfs::new(&*graph, entry);
+
+    while let Some(node) = dfs.next(&*graph) {
+        reachable.insert(node);
+    }
+
+    graph.retain_nodes(|_, node| node == exit || reachable.contains(&node));
+}
+
+/// Lets the predecessors of an empty block jump straight to its only
+/// successor.
+fn bypass_empty(graph: &mut Nodes, node: NodeIndex) -> bool {
+    if !graph[node].block().is_some_and(|block| block.is_empty()) {
+        return false;
+    }
+
+    let target = match single_edge(graph, node, Direction::Outgoing) {
+        Some((target, CFGEdge::Fallthrough)) if target != node => target,
+        _ => return false,
+    };
+
+    let incoming = graph
+        .edges_directed(node, Direction::Incoming)
+        .map(|edge| (edge.source(), *edge.weight()))
+        .collect::<Vec<_>>();
+
+    graph.remove_node(node);
+
+    for (source, weight) in incoming {
+        graph.add_edge(source, target, weight);
+    }
+
+    true
+}
+
+/// Appends the only successor of a block to it when nothing else can reach
+/// that successor.
+fn merge_successor(graph: &mut Nodes, node: NodeIndex) -> bool {
+    // a return can only be followed by the exit
+    if !matches!(&graph[node], CFGNode::Block(block) if block.last_statement.is_none()) {
+        return false;
+    }
+
+    let successor = match single_edge(graph, node, Direction::Outgoing) {
+        Some((successor, CFGEdge::Fallthrough)) if successor != node => successor,
+        _ => return false,
+    };
+
+    if graph[successor].block().is_none()
+        || single_edge(graph, successor, Direction::Incoming).is_none()
+    {
+        return false;
+    }
+
+    let outgoing = graph
+        .edges_directed(successor, Direction::Outgoing)
+        .map(|edge| (edge.target(), *edge.weight()))
+        .collect::<Vec<_>>();
+
+    let Some(CFGNode::Block(merged)) = graph.remove_node(successor) else {
+        unreachable!()
+    };
+
+    let block = graph[node].block_mut().unwrap();
+    block.statements.extend(merged.statements);
+    block.last_statement = merged.last_statement;
+    block.condition = merged.condition;
+
+    for (target, weight) in outgoing {
+        graph.add_edge(node, target, weight);
+    }
+
+    true
+}
+
+/// The neighbor across the only edge in the given direction, if there is
+/// exactly one.
+fn single_edge(
+    graph: &Nodes,
+    node: NodeIndex,
+    direction: Direction,
+) -> Option<(NodeIndex, CFGEdge)> {
+    let mut edges = graph.edges_directed(node, direction);
+    let edge = edges.next()?;
+
+    if edges.next().is_some() {
+        return None;
+    }
+
+    let neighbor = match direction {
+        Direction::Outgoing => edge.target(),
+        Direction::Incoming => edge.source(),
+    };
+
+    Some((neighbor, *edge.weight()))
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::stable_graph::{NodeIndex, StableGraph};
+
+    use crate::{
+        cfg::{BasicBlock, CFGEdge, CFGNode, Cfg},
+        parser::{ast::definition::LastStatementKind, parse},
+    };
+
+    use super::cleanup;
+
+    fn block(source: &str) -> CFGNode {
+        let chunk = parse(source).unwrap();
+
+        CFGNode::Block(Box::new(BasicBlock {
+            statements: chunk.block.statements,
+            last_statement: chunk.block.last_statement,
+            condition: None,
+        }))
+    }
+
+    /// A block that only tests `condition`.
+    fn test(condition: &str) -> CFGNode {
+        let chunk = parse(&format!("return {condition}")).unwrap();
+        let Some(LastStatementKind::Return(mut stmt)) =
+            chunk.block.last_statement.map(|stmt| stmt.kind)
+        else {
+            unreachable!()
+        };
+
+        CFGNode::Block(Box::new(BasicBlock {
+            condition: stmt.expression_list.pop(),
+            ..Default::default()
+        }))
+    }
+
+    fn empty() -> CFGNode {
+        CFGNode::Block(Box::default())
+    }
+
+    /// Builds a graph from its nodes, the first two being the entry and the
+    /// exit, and the edges between them.
+    fn graph(
+        nodes: Vec<CFGNode>,
+        edges: &[(usize, usize, CFGEdge)],
+    ) -> (StableGraph<CFGNode, CFGEdge>, NodeIndex, NodeIndex) {
+        let mut graph = StableGraph::new();
+        let nodes = nodes
+            .into_iter()
+            .map(|node| graph.add_node(node))
+            .collect::<Vec<_>>();
+
+        for &(from, to, edge) in edges {
+            graph.add_edge(nodes[from], nodes[to], edge);
+        }
+
+        (graph, nodes[0], nodes[1])
+    }
+
+    /// Every edge as `source -kind-> target`, with nodes written the way they
+    /// are displayed and statements separated by `;`.
+    fn edges(cfg: &Cfg) -> Vec<String> {
+        let name = |node| match format!("{:?}", cfg.graph[node]).replace('\n', "; ") {
+            name if name.is_empty() => "(empty)".to_string(),
+            name => name,
+        };
+
+        let mut edges = cfg
+            .graph
+            .edge_indices()
+            .map(|edge| {
+                let (source, target) = cfg.graph.edge_endpoints(edge).unwrap();
+                format!("{} -{:?}-> {}", name(source), cfg.graph[edge], name(target))
+            })
+            .collect::<Vec<_>>();
+        edges.sort();
+
+        edges
+    }
+
+    #[test]
+    fn straight_line_merged() {
+        let (graph, entry, exit) = graph(
+            vec![
+                CFGNode::Entry,
+                CFGNode::Exit,
+                block("a()"),
+                empty(),
+                block("b()"),
+                block("return c"),
+            ],
+            &[
+                (0, 2, CFGEdge::Fallthrough),
+                (2, 3, CFGEdge::Fallthrough),
+                (3, 4, CFGEdge::Fallthrough),
+                (4, 5, CFGEdge::Fallthrough),
+                (5, 1, CFGEdge::Fallthrough),
+            ],
+        );
+        let cfg = cleanup(graph, entry, exit);
+
+        assert_eq!(cfg.graph.node_count(), 3);
+        assert_eq!(
+            edges(&cfg),
+            vec![
+                "a(); b(); return c --> exit",
+                "entry --> a(); b(); return c"
+            ]
+        );
+    }
+
+    #[test]
+    fn join_bypassed_but_not_merged() {
+        // the empty join is skipped, but its successor has two predecessors
+        let (graph, entry, exit) = graph(
+            vec![
+                CFGNode::Entry,
+                CFGNode::Exit,
+                test("x"),
+                block("a()"),
+                block("b()"),
+                empty(),
+                block("c()"),
+            ],
+            &[
+                (0, 2, CFGEdge::Fallthrough),
+                (2, 3, CFGEdge::True),
+                (2, 4, CFGEdge::False),
+                (3, 5, CFGEdge::Fallthrough),
+                (4, 5, CFGEdge::Fallthrough),
+                (5, 6, CFGEdge::Fallthrough),
+                (6, 1, CFGEdge::Fallthrough),
+            ],
+        );
+        let cfg = cleanup(graph, entry, exit);
+
+        assert_eq!(cfg.graph.node_count(), 6);
+        assert_eq!(
+            edges(&cfg),
+            vec![
+                "a() --> c()",
+                "b() --> c()",
+                "c() --> exit",
+                "entry --> test x",
+                "test x -false-> b()",
+                "test x -true-> a()",
+            ]
+        );
+    }
+
+    #[test]
+    fn condition_ends_merge() {
+        // a block is only merged into the block before it, never past a test
+        let (graph, entry, exit) = graph(
+            vec![
+                CFGNode::Entry,
+                CFGNode::Exit,
+                block("a()"),
+                test("x"),
+                block("b()"),
+            ],
+            &[
+                (0, 2, CFGEdge::Fallthrough),
+                (2, 3, CFGEdge::Fallthrough),
+                (3, 4, CFGEdge::True),
+                (3, 1, CFGEdge::False),
+                (4, 1, CFGEdge::Fallthrough),
+            ],
+        );
+        let cfg = cleanup(graph, entry, exit);
+
+        assert_eq!(
+            edges(&cfg),
+            vec![
+                "a(); test x -false-> exit",
+                "a(); test x -true-> b()",
+                "b() --> exit",
+                "entry --> a(); test x",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_removed() {
+        // the code after a return, and a loop only it enters
+        let (graph, entry, exit) = graph(
+            vec![
+                CFGNode::Entry,
+                CFGNode::Exit,
+                block("return"),
+                block("a()"),
+                block("b()"),
+            ],
+            &[
+                (0, 2, CFGEdge::Fallthrough),
+                (2, 1, CFGEdge::Fallthrough),
+                (3, 4, CFGEdge::Fallthrough),
+                (4, 3, CFGEdge::Fallthrough),
+            ],
+        );
+        let cfg = cleanup(graph, entry, exit);
+
+        assert_eq!(cfg.graph.node_count(), 3);
+        assert_eq!(edges(&cfg), vec!["entry --> return", "return --> exit"]);
+    }
+
+    #[test]
+    fn exit_kept_and_empty_loop_kept() {
+        // `while true do end` never reaches the exit, and its empty body
+        // cannot be bypassed as it only leads to itself
+        let (graph, entry, exit) = graph(
+            vec![CFGNode::Entry, CFGNode::Exit, block("a()"), empty()],
+            &[
+                (0, 2, CFGEdge::Fallthrough),
+                (2, 3, CFGEdge::Fallthrough),
+                (3, 3, CFGEdge::Fallthrough),
+            ],
+        );
+        let cfg = cleanup(graph, entry, exit);
+
+        assert_eq!(cfg.graph.node_count(), 4);
+        assert_eq!(
+            edges(&cfg),
+            vec!["(empty) --> (empty)", "a() --> (empty)", "entry --> a()"]
+        );
+        assert!(matches!(cfg.graph[cfg.exit], CFGNode::Exit));
+    }
+}
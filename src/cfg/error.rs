@@ -0,0 +1,63 @@
+use std::fmt;
+
+use crate::parser::ast::definition::{Identifier, Position};
+
+/// A jump that has nowhere to go or a loop that cannot run, which Lua rejects
+/// when it compiles or runs the chunk.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    UnknownLabel {
+        label: Identifier,
+        position: Position,
+    },
+    DuplicateLabel {
+        label: Identifier,
+        position: Position,
+    },
+    BreakOutsideLoop {
+        position: Position,
+    },
+    ContinueOutsideLoop {
+        position: Position,
+    },
+    JumpIntoScope {
+        label: Identifier,
+        local: Identifier,
+        position: Position,
+    },
+    ZeroStep {
+        position: Position,
+    },
+}
+
+impl Error {
+    pub fn position(&self) -> Position {
+        match self {
+            Error::UnknownLabel { position, .. }
+            | Error::DuplicateLabel { position, .. }
+            | Error::BreakOutsideLoop { position }
+            | Error::ContinueOutsideLoop { position }
+            | Error::JumpIntoScope { position, .. }
+            | Error::ZeroStep { position } => *position,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownLabel { label, .. } => write!(f, "no visible label '{label}' for goto")?,
+            Error::DuplicateLabel { label, .. } => write!(f, "label '{label}' already defined")?,
+            Error::BreakOutsideLoop { .. } => write!(f, "break outside a loop")?,
+            Error::ContinueOutsideLoop { .. } => write!(f, "continue outside a loop")?,
+            Error::JumpIntoScope { label, local, .. } => {
+                write!(f, "goto '{label}' jumps into the scope of local '{local}'")?
+            }
+            Error::ZeroStep { .. } => write!(f, "'for' step is zero")?,
+        }
+
+        write!(f, " at {}", self.position())
+    }
+}
+
+impl std::error::Error for Error {}
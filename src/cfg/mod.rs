@@ -1,44 +1,145 @@
 use std::fmt::{self};
 
-use petgraph::{stable_graph::DefaultIx, Directed, Graph};
+use petgraph::{
+    stable_graph::{DefaultIx, NodeIndex, StableGraph},
+    Directed, Graph,
+};
 
-use crate::parser::ast::definition::{Expression, Statement};
+use crate::parser::ast::definition::{Expression, LastStatement, Span, Statement};
 
+pub mod analysis;
+mod cleanup;
+mod error;
+pub mod program;
 pub mod translator;
 pub mod visualization;
 
-#[derive(Clone)]
+pub use error::Error;
+
+/// How control leaves a block. A block that tests a condition has exactly one
+/// `True` and one `False` edge, any other block at most one `Fallthrough`.
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum CFGEdge {
-    Conditional(Expression),
-    Unconditional(),
+    True,
+    False,
+    Fallthrough,
 }
 
 impl fmt::Debug for CFGEdge {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Conditional(expr) => {
-                write!(f, "{}", expr)?;
-            }
-            _ => {}
+            Self::True => write!(f, "true"),
+            Self::False => write!(f, "false"),
+            Self::Fallthrough => Ok(()),
         }
-
-        Ok(())
     }
 }
 
+/// A maximal run of statements that always execute one after the other.
 #[derive(Default, Clone)]
-pub struct CFGNode {
-    statements: Vec<Statement>,
+pub struct BasicBlock {
+    pub statements: Vec<Statement>,
+    /// A `return` ending the block, whose only successor is then the exit.
+    pub last_statement: Option<LastStatement>,
+    /// The value tested after the statements, which picks the edge to take.
+    pub condition: Option<Expression>,
 }
 
-impl fmt::Debug for CFGNode {
+impl BasicBlock {
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty() && self.last_statement.is_none() && self.condition.is_none()
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.statements
+            .iter()
+            .map(|stmt| stmt.span)
+            .chain(self.last_statement.iter().map(|stmt| stmt.span))
+            .chain(self.condition.iter().map(|exp| exp.span))
+            .reduce(Span::to)
+    }
+}
+
+impl fmt::Debug for BasicBlock {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for stmt in self.statements.iter() {
+        for (i, stmt) in self.statements.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", stmt)?;
+        }
+
+        if let Some(stmt) = &self.last_statement {
+            if !self.statements.is_empty() {
+                writeln!(f)?;
+            }
+
             write!(f, "{}", stmt)?;
         }
 
+        if let Some(exp) = &self.condition {
+            if !self.statements.is_empty() {
+                writeln!(f)?;
+            }
+
+            write!(f, "test {}", exp)?;
+        }
+
         Ok(())
     }
 }
 
-pub type CFG = Graph<CFGNode, CFGEdge, Directed, DefaultIx>;
+#[derive(Clone)]
+pub enum CFGNode {
+    Entry,
+    Exit,
+    Block(Box<BasicBlock>),
+}
+
+impl CFGNode {
+    pub fn block(&self) -> Option<&BasicBlock> {
+        match self {
+            Self::Block(block) => Some(block),
+            _ => None,
+        }
+    }
+
+    pub fn block_mut(&mut self) -> Option<&mut BasicBlock> {
+        match self {
+            Self::Block(block) => Some(block),
+            _ => None,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.block().and_then(BasicBlock::span)
+    }
+}
+
+impl fmt::Debug for CFGNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Entry => write!(f, "entry"),
+            Self::Exit => write!(f, "exit"),
+            Self::Block(block) => block.fmt(f),
+        }
+    }
+}
+
+/// The control flow graph of a chunk. Execution starts at the entry node, and
+/// every path that leaves the chunk ends in the exit node.
+#[derive(Clone)]
+pub struct Cfg {
+    pub graph: Graph<CFGNode, CFGEdge, Directed, DefaultIx>,
+    pub entry: NodeIndex,
+    pub exit: NodeIndex,
+}
+
+impl Cfg {
+    /// Removes the blocks a pass left unreachable or empty, and merges the
+    /// ones that became straight-line code.
+    pub fn cleanup(self) -> Cfg {
+        cleanup::cleanup(StableGraph::from(self.graph), self.entry, self.exit)
+    }
+}
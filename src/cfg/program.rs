@@ -0,0 +1,627 @@
+use std::{collections::HashMap, fmt};
+
+use petgraph::{stable_graph::NodeIndex, Graph};
+
+use crate::parser::ast::{
+    definition::{
+        AnonFunctionExpression, AssignmentStatement, Block, Chunk, CompoundAssignmentStatement,
+        Expression, ExpressionKind, FunctionCallExpression, FunctionCallStatement,
+        FunctionDefinitionStatement, FunctionId, GenericForStatement, Identifier,
+        LocalDeclarationStatement, LocalFunctionDefinitionStatement, NumericForStatement,
+        Parameter, RepeatStatement, Span, Statement, StatementKind, Variable,
+    },
+    visitor::{self, Visitor, VisitorMut},
+};
+
+use super::{
+    translator::{self, Options},
+    CFGEdge, CFGNode, Cfg, Error,
+};
+
+/// A function prototype with its own control flow graph.
+pub struct Function {
+    /// The name the function is defined under, e.g. `a.b:c`.
+    pub name: Option<String>,
+    /// Methods start with their implicit `self`.
+    pub parameter_list: Vec<Parameter>,
+    pub cfg: Cfg,
+    /// The function the prototype is nested in. Only the main chunk has none.
+    pub parent: Option<NodeIndex>,
+    /// Where the function is defined, which is unknown for the main chunk.
+    pub span: Option<Span>,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, self.span) {
+            (Some(name), _) => write!(f, "{name}"),
+            (None, Some(span)) => write!(f, "function at {}", span.start),
+            (None, None) => write!(f, "main chunk"),
+        }
+    }
+}
+
+/// Every function of a chunk. The call graph has an edge from caller to
+/// callee for every call site whose callee is known statically.
+pub struct Program {
+    pub call_graph: Graph<Function, Span>,
+    pub main: NodeIndex,
+    /// The function of every definition, by the id it was given.
+    pub functions: HashMap<FunctionId, NodeIndex>,
+}
+
+impl Program {
+    /// The function of the definition that was given `id`.
+    pub fn function(&self, id: FunctionId) -> Option<NodeIndex> {
+        self.functions.get(&id).copied()
+    }
+}
+
+/// Gives every function definition of a chunk the next id, in the order they
+/// appear in the source.
+#[derive(Default)]
+struct Numbering {
+    next: usize,
+}
+
+impl Numbering {
+    fn number(&mut self, id: &mut Option<FunctionId>) {
+        *id = Some(FunctionId(self.next));
+        self.next += 1;
+    }
+}
+
+impl VisitorMut for Numbering {
+    fn visit_function_definition_mut(&mut self, stmt: &mut FunctionDefinitionStatement) {
+        self.number(&mut stmt.id);
+        visitor::walk_function_definition_mut(self, stmt);
+    }
+
+    fn visit_local_function_definition_mut(&mut self, stmt: &mut LocalFunctionDefinitionStatement) {
+        self.number(&mut stmt.id);
+        visitor::walk_local_function_definition_mut(self, stmt);
+    }
+
+    fn visit_anon_function_mut(&mut self, func: &mut AnonFunctionExpression) {
+        self.number(&mut func.id);
+        visitor::walk_anon_function_mut(self, func);
+    }
+}
+
+/// Where a value lives: a local declaration or a global, and the fields
+/// indexed from there, e.g. `a.b.c`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Path {
+    root: Root,
+    members: Vec<Identifier>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Root {
+    Local(usize),
+    Global(Identifier),
+}
+
+#[derive(Default)]
+struct Definition {
+    /// How often the path is written to, by definitions and assignments alike.
+    assignments: usize,
+    function: Option<NodeIndex>,
+}
+
+struct Builder {
+    options: Options,
+    call_graph: Graph<Function, Span>,
+    current: NodeIndex,
+    /// The locals of every enclosing block, innermost last.
+    scopes: Vec<Vec<(Identifier, usize)>>,
+    locals: usize,
+    definitions: HashMap<Path, Definition>,
+    calls: Vec<(NodeIndex, Path, Span)>,
+    functions: HashMap<FunctionId, NodeIndex>,
+    errors: Vec<Error>,
+}
+
+/// Translates a function body, or stands in an empty graph for one that
+/// fails, so that the errors of the other functions are still found.
+fn translate(block: &Block, options: Options, errors: &mut Vec<Error>) -> Cfg {
+    translator::translate(block, options).unwrap_or_else(|mut failed| {
+        errors.append(&mut failed);
+
+        let mut graph = Graph::new();
+        let entry = graph.add_node(CFGNode::Entry);
+        let exit = graph.add_node(CFGNode::Exit);
+        graph.add_edge(entry, exit, CFGEdge::Fallthrough);
+
+        Cfg { graph, entry, exit }
+    })
+}
+
+impl Builder {
+    fn declare(&mut self, identifier: &Identifier) -> Path {
+        let local = self.locals;
+        self.locals += 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push((identifier.clone(), local));
+
+        Path {
+            root: Root::Local(local),
+            members: vec![],
+        }
+    }
+
+    fn resolve(&self, identifier: &Identifier) -> Root {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(name, _)| name == identifier)
+            .map_or_else(
+                || Root::Global(identifier.clone()),
+                |&(_, local)| Root::Local(local),
+            )
+    }
+
+    fn path(&self, exp: &Expression) -> Option<Path> {
+        match &exp.kind {
+            ExpressionKind::Variable(var) => self.variable_path(var),
+            _ => None,
+        }
+    }
+
+    /// Only names and chains of named fields can be followed statically.
+    fn variable_path(&self, var: &Variable) -> Option<Path> {
+        let (base, member) = match var {
+            Variable::Identifier(identifier) => {
+                return Some(Path {
+                    root: self.resolve(identifier),
+                    members: vec![],
+                })
+            }
+            Variable::TableMember(member) => (&member.base, &member.member),
+            Variable::TableMethod(method) => (&method.base, &method.method),
+            Variable::TableIndex(_) => return None,
+        };
+
+        let mut path = self.path(base)?;
+        path.members.push(member.clone());
+
+        Some(path)
+    }
+
+    fn assign(&mut self, path: Path, function: Option<NodeIndex>) {
+        let definition = self.definitions.entry(path).or_default();
+        definition.assignments += 1;
+        definition.function = function;
+    }
+
+    fn assign_variable(&mut self, var: &Variable) {
+        if let Some(path) = self.variable_path(var) {
+            self.assign(path, None);
+        }
+    }
+
+    fn call(&mut self, callee: &Expression, span: Span) {
+        if let Some(path) = self.path(callee) {
+            self.calls.push((self.current, path, span));
+        }
+    }
+
+    /// Builds the graph of a function body, declaring its parameters in a new
+    /// scope on top of the ones it closes over.
+    fn function(
+        &mut self,
+        id: Option<FunctionId>,
+        name: Option<String>,
+        parameter_list: &[Parameter],
+        block: &Block,
+        span: Span,
+    ) -> NodeIndex {
+        let function = self.call_graph.add_node(Function {
+            name,
+            parameter_list: parameter_list.to_vec(),
+            cfg: translate(block, self.options, &mut self.errors),
+            parent: Some(self.current),
+            span: Some(span),
+        });
+
+        if let Some(id) = id {
+            self.functions.insert(id, function);
+        }
+
+        let parent = std::mem::replace(&mut self.current, function);
+        self.scopes.push(vec![]);
+
+        for param in parameter_list.iter() {
+            if let Parameter::Identifier(identifier) = param {
+                let path = self.declare(identifier);
+                self.assign(path, None);
+            }
+        }
+
+        self.visit_block(block);
+        self.scopes.pop();
+        self.current = parent;
+
+        function
+    }
+
+    /// A value may be defined under several names, or be overwritten through
+    /// any table it is stored in, so a call is only resolved when its path
+    /// and every table along it are written to exactly once.
+    fn resolve_call(&self, path: &Path) -> Option<NodeIndex> {
+        let is_unique = |members: &[Identifier]| {
+            let prefix = Path {
+                root: path.root.clone(),
+                members: members.to_vec(),
+            };
+
+            self.definitions
+                .get(&prefix)
+                .is_none_or(|definition| definition.assignments <= 1)
+        };
+
+        let unique = (0..path.members.len()).all(|len| is_unique(&path.members[..len]));
+        let definition = self.definitions.get(path)?;
+
+        if unique && definition.assignments == 1 {
+            definition.function
+        } else {
+            None
+        }
+    }
+}
+
+impl Visitor for Builder {
+    fn visit_block(&mut self, block: &Block) {
+        self.scopes.push(vec![]);
+        visitor::walk_block(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::FunctionCall(FunctionCallStatement { callee, arguments }) => {
+                self.call(callee, stmt.span);
+                self.visit_expression(callee);
+
+                for exp in arguments.iter() {
+                    self.visit_expression(exp);
+                }
+            }
+            StatementKind::FunctionDefinition(def) => {
+                let path = self.variable_path(&def.identifier);
+                self.visit_variable(&def.identifier);
+
+                let mut parameter_list = def.parameter_list.clone();
+
+                // methods receive their table as an implicit first parameter
+                if let Variable::TableMethod(_) = def.identifier {
+                    parameter_list.insert(0, Parameter::Identifier("self".to_string()));
+                }
+
+                let name = def.identifier.to_string();
+                let function =
+                    self.function(def.id, Some(name), &parameter_list, &def.block, stmt.span);
+
+                match path {
+                    Some(path) => self.assign(path, Some(function)),
+                    None => self.assign_variable(&def.identifier),
+                }
+            }
+            StatementKind::LocalFunctionDefinition(def) => {
+                // the local is in scope in its own body, so it may recurse
+                let path = match &def.identifier {
+                    Variable::Identifier(identifier) => Some(self.declare(identifier)),
+                    _ => None,
+                };
+
+                let name = def.identifier.to_string();
+                let function = self.function(
+                    def.id,
+                    Some(name),
+                    &def.parameter_list,
+                    &def.block,
+                    stmt.span,
+                );
+
+                if let Some(path) = path {
+                    self.assign(path, Some(function));
+                }
+            }
+            _ => visitor::walk_statement(self, stmt),
+        }
+    }
+
+    fn visit_local_declaration(&mut self, stmt: &LocalDeclarationStatement) {
+        let mut functions = vec![];
+
+        for (i, exp) in stmt.expression_list.iter().enumerate() {
+            // `local f = function() end` names the function after the local
+            let function = match (&exp.kind, stmt.identifier_list.get(i)) {
+                (ExpressionKind::AnonFunctionDefinition(func), Some(identifier)) => {
+                    Some(self.function(
+                        func.id,
+                        Some(identifier.clone()),
+                        &func.parameter_list,
+                        &func.block,
+                        exp.span,
+                    ))
+                }
+                _ => {
+                    self.visit_expression(exp);
+                    None
+                }
+            };
+
+            functions.push(function);
+        }
+
+        for (i, identifier) in stmt.identifier_list.iter().enumerate() {
+            let path = self.declare(identifier);
+
+            // a local without a value is only assigned later on
+            if !stmt.expression_list.is_empty() {
+                self.assign(path, functions.get(i).copied().flatten());
+            }
+        }
+    }
+
+    fn visit_assignment(&mut self, stmt: &AssignmentStatement) {
+        visitor::walk_assignment(self, stmt);
+
+        for var in stmt.variable_list.iter() {
+            self.assign_variable(var);
+        }
+    }
+
+    fn visit_compound_assignment(&mut self, stmt: &CompoundAssignmentStatement) {
+        visitor::walk_compound_assignment(self, stmt);
+        self.assign_variable(&stmt.variable);
+    }
+
+    fn visit_repeat(&mut self, stmt: &RepeatStatement) {
+        // the condition still sees the locals of the block
+        self.scopes.push(vec![]);
+        visitor::walk_block(self, &stmt.block);
+        self.visit_expression(&stmt.condition);
+        self.scopes.pop();
+    }
+
+    fn visit_numeric_for(&mut self, stmt: &NumericForStatement) {
+        self.visit_expression(&stmt.start);
+        self.visit_expression(&stmt.end);
+
+        if let Some(step) = &stmt.step {
+            self.visit_expression(step);
+        }
+
+        self.scopes.push(vec![]);
+        let path = self.declare(&stmt.identifier);
+        self.assign(path, None);
+        self.visit_block(&stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_generic_for(&mut self, stmt: &GenericForStatement) {
+        for exp in stmt.expression_list.iter() {
+            self.visit_expression(exp);
+        }
+
+        self.scopes.push(vec![]);
+
+        for identifier in stmt.identifier_list.iter() {
+            let path = self.declare(identifier);
+            self.assign(path, None);
+        }
+
+        self.visit_block(&stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_expression(&mut self, exp: &Expression) {
+        match &exp.kind {
+            ExpressionKind::FunctionCall(FunctionCallExpression { callee, .. }) => {
+                self.call(callee, exp.span);
+                visitor::walk_expression(self, exp);
+            }
+            ExpressionKind::AnonFunctionDefinition(func) => {
+                self.function(func.id, None, &func.parameter_list, &func.block, exp.span);
+            }
+            _ => visitor::walk_expression(self, exp),
+        }
+    }
+}
+
+/// Builds a graph for the main chunk and for every function nested in it, and
+/// links them by the calls that can be resolved statically. Every function
+/// definition of the chunk is numbered first, so that [`Program::function`]
+/// finds the graph of each from the tree as well as from the graphs. Fails
+/// with the errors of every function that cannot be translated.
+pub fn build(chunk: &mut Chunk, options: Options) -> Result<Program, Vec<Error>> {
+    Numbering::default().visit_chunk_mut(chunk);
+
+    let mut errors = vec![];
+
+    let mut call_graph = Graph::new();
+    let main = call_graph.add_node(Function {
+        name: None,
+        parameter_list: vec![Parameter::VariableArg],
+        cfg: translate(&chunk.block, options, &mut errors),
+        parent: None,
+        span: None,
+    });
+
+    let mut builder = Builder {
+        options,
+        call_graph,
+        current: main,
+        scopes: vec![],
+        locals: 0,
+        definitions: HashMap::new(),
+        calls: vec![],
+        functions: HashMap::new(),
+        errors,
+    };
+
+    builder.visit_chunk(chunk);
+
+    for (caller, path, span) in std::mem::take(&mut builder.calls) {
+        if let Some(callee) = builder.resolve_call(&path) {
+            builder.call_graph.add_edge(caller, callee, span);
+        }
+    }
+
+    if !builder.errors.is_empty() {
+        return Err(builder.errors);
+    }
+
+    Ok(Program {
+        call_graph: builder.call_graph,
+        main,
+        functions: builder.functions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cfg::Error,
+        parser::{
+            ast::{
+                definition::{
+                    AnonFunctionExpression, FunctionDefinitionStatement, FunctionId,
+                    LocalFunctionDefinitionStatement, Parameter, Position, Span,
+                },
+                visitor::{self, Visitor, VisitorMut},
+            },
+            parse,
+        },
+    };
+
+    use super::build;
+
+    /// Forgets where every node came from, like a tree built by a pass.
+    struct SpanEraser;
+
+    impl VisitorMut for SpanEraser {
+        fn visit_span_mut(&mut self, span: &mut Span) {
+            *span = Span::default();
+        }
+    }
+
+    #[test]
+    fn functions_found_by_id() {
+        let mut chunk = parse(
+            "local f = function() return 1 end
+            local function g() return function() end end
+            function h() end",
+        )
+        .unwrap();
+        SpanEraser.visit_chunk_mut(&mut chunk);
+
+        let program = build(&mut chunk, Default::default()).ok().unwrap();
+        let name = |id| {
+            let function = program.function(FunctionId(id)).unwrap();
+            format!("{:?}", program.call_graph[function])
+        };
+
+        assert_eq!(program.functions.len(), 4);
+        assert_eq!(name(0), "f");
+        assert_eq!(name(1), "g");
+        assert_eq!(name(2), "function at 0:0");
+        assert_eq!(name(3), "h");
+    }
+
+    /// The id of every function definition, in the order they are visited.
+    #[derive(Default)]
+    struct Ids(Vec<Option<FunctionId>>);
+
+    impl Visitor for Ids {
+        fn visit_function_definition(&mut self, stmt: &FunctionDefinitionStatement) {
+            self.0.push(stmt.id);
+            visitor::walk_function_definition(self, stmt);
+        }
+
+        fn visit_local_function_definition(&mut self, stmt: &LocalFunctionDefinitionStatement) {
+            self.0.push(stmt.id);
+            visitor::walk_local_function_definition(self, stmt);
+        }
+
+        fn visit_anon_function(&mut self, func: &AnonFunctionExpression) {
+            self.0.push(func.id);
+            visitor::walk_anon_function(self, func);
+        }
+    }
+
+    #[test]
+    fn functions_found_from_callers_tree() {
+        let mut chunk = parse(
+            "function t.m(a) return function(b) return a + b end end
+            local function k() end
+            print(function() end)",
+        )
+        .unwrap();
+
+        let program = build(&mut chunk, Default::default()).ok().unwrap();
+
+        let mut ids = Ids::default();
+        ids.visit_chunk(&chunk);
+        let functions = ids
+            .0
+            .into_iter()
+            .map(|id| {
+                let function = program.function(id.unwrap()).unwrap();
+                let function = &program.call_graph[function];
+
+                (format!("{function:?}"), function.parameter_list.clone())
+            })
+            .collect::<Vec<_>>();
+
+        let parameters = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| Parameter::Identifier(name.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            functions,
+            vec![
+                ("t.m".to_string(), parameters(&["a"])),
+                ("function at 1:24".to_string(), parameters(&["b"])),
+                ("k".to_string(), parameters(&[])),
+                ("function at 3:19".to_string(), parameters(&[])),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_of_nested_functions() {
+        let mut chunk =
+            parse("local f = function() break end\nwhile true do break end\nbreak").unwrap();
+
+        let errors = build(&mut chunk, Default::default()).err().unwrap();
+
+        assert_eq!(
+            errors,
+            vec![
+                Error::BreakOutsideLoop {
+                    position: Position {
+                        line: 3,
+                        column: 1,
+                        offset: 55,
+                    },
+                },
+                Error::BreakOutsideLoop {
+                    position: Position {
+                        line: 1,
+                        column: 22,
+                        offset: 21,
+                    },
+                },
+            ]
+        );
+    }
+}
@@ -1,114 +1,777 @@
-use std::{cell::RefCell, rc::Rc};
+use std::collections::{hash_map::Entry, HashMap};
+
+use petgraph::stable_graph::{NodeIndex, StableGraph};
+
+use crate::parser::ast::definition::{
+    AssignmentStatement, Block, Expression, ExpressionKind, FunctionCallExpression,
+    GenericForStatement, Identifier, LastStatement, LastStatementKind, LocalDeclarationStatement,
+    NumericForStatement, RepeatStatement, Span, Statement, StatementKind, Variable, WhileStatement,
+};
+
+use super::{cleanup, BasicBlock, CFGEdge, CFGNode, Cfg, Error};
+
+// Names of the hidden locals that hold the state of a for loop. They end up
+// in parentheses, which are not valid in identifiers, so they can never clash
+// with a local of the program.
+const FOR_INDEX: &str = "for index";
+const FOR_LIMIT: &str = "for limit";
+const FOR_STEP: &str = "for step";
+const FOR_GENERATOR: &str = "for generator";
+const FOR_STATE: &str = "for state";
+const FOR_CONTROL: &str = "for control";
+
+struct Loop {
+    /// Where `continue` goes: the next evaluation of the loop condition.
+    next: NodeIndex,
+    /// Where `break` goes: the first node after the loop.
+    after: NodeIndex,
+}
 
-use petgraph::{stable_graph::DefaultIx, stable_graph::NodeIndex, Directed, Direction, Graph};
+/// Settings that change the shape of the translated graph.
+#[derive(Clone, Copy, Default)]
+pub struct Options {
+    /// Splits `and`, `or` and `not` in conditions into a chain of blocks that
+    /// each test a single operand, in the order Lua evaluates them.
+    pub short_circuit: bool,
+}
 
-use crate::parser::ast::definition::{Block, Statement};
+/// A block whose labels are visible to the gotos inside it.
+struct Scope {
+    /// Every label of the block, with how many of its locals are in scope
+    /// there.
+    labels: HashMap<Identifier, (NodeIndex, usize)>,
+    /// Every local the block declares, in order.
+    locals: Vec<Identifier>,
+    /// How many of the locals are declared before the statement that is
+    /// being translated.
+    declared: usize,
+}
 
-use super::{CFGEdge, CFGNode, CFG};
+struct Translator {
+    options: Options,
+    /// Nodes are removed again by the cleanup, which must not move the others.
+    graph: StableGraph<CFGNode, CFGEdge>,
+    exit: NodeIndex,
+    loops: Vec<Loop>,
+    /// The labels visible in every enclosing block, innermost last.
+    scopes: Vec<Scope>,
+    errors: Vec<Error>,
+}
 
-fn translate_statement(cfg: &mut CFG, last: NodeIndex, stmt: &Statement) -> NodeIndex {
-    match stmt {
-        Statement::If(stmt) => {
-            let mut branches = vec![];
+impl Translator {
+    fn node(&mut self, statements: Vec<Statement>) -> NodeIndex {
+        self.graph.add_node(CFGNode::Block(Box::new(BasicBlock {
+            statements,
+            last_statement: None,
+            condition: None,
+        })))
+    }
 
-            let true_branch = stmt.block.statements.first().unwrap();
-            let true_node = cfg.add_node(CFGNode {
-                statements: vec![true_branch.clone()],
-            });
+    /// Appends a statement to the block that is currently being filled.
+    fn append(&mut self, last: NodeIndex, stmt: Statement) {
+        self.block(last).statements.push(stmt);
+    }
+
+    fn block(&mut self, node: NodeIndex) -> &mut BasicBlock {
+        self.graph[node]
+            .block_mut()
+            .expect("statements are only translated into blocks")
+    }
+
+    fn edge(&mut self, from: NodeIndex, to: NodeIndex) {
+        self.graph.add_edge(from, to, CFGEdge::Fallthrough);
+    }
 
-            cfg.add_edge(
-                last,
-                true_node,
-                CFGEdge::Conditional(stmt.condition.clone()),
-            );
+    /// Ends `from` with a test of `condition`, which leads to `then` when it
+    /// holds and to `otherwise` when it does not.
+    fn branch(
+        &mut self,
+        from: NodeIndex,
+        condition: &Expression,
+        then: NodeIndex,
+        otherwise: NodeIndex,
+    ) {
+        if self.options.short_circuit {
+            match &condition.kind {
+                // the right operand is only evaluated when the left one
+                // does not decide the outcome
+                ExpressionKind::And(a, b) => {
+                    let next = self.node(vec![]);
+                    self.branch(from, a, next, otherwise);
+                    self.branch(next, b, then, otherwise);
+                    return;
+                }
+                ExpressionKind::Or(a, b) => {
+                    let next = self.node(vec![]);
+                    self.branch(from, a, then, next);
+                    self.branch(next, b, then, otherwise);
+                    return;
+                }
+                ExpressionKind::Not(exp) => return self.branch(from, exp, otherwise, then),
+                // a condition is a single value either way
+                ExpressionKind::Parenthesized(exp) => {
+                    return self.branch(from, exp, then, otherwise)
+                }
+                _ => {}
+            }
+        }
 
-            branches.push(true_node);
+        self.block(from).condition = Some(condition.clone());
+        self.graph.add_edge(from, then, CFGEdge::True);
+        self.graph.add_edge(from, otherwise, CFGEdge::False);
+    }
 
-            for elseif_block in stmt.elseif_blocks.iter() {
-                let condition = elseif_block.condition.clone();
-                let branch = elseif_block.block.statements.first().unwrap();
+    /// Names the hidden locals of a for loop. A loop nested in another one
+    /// needs its own, or it would overwrite the state of the outer loop.
+    fn hidden<const N: usize>(&self, names: [&str; N]) -> [String; N] {
+        let depth = self.loops.len();
 
-                let node = cfg.add_node(CFGNode {
-                    statements: vec![branch.clone()],
-                });
-                cfg.add_edge(last, node, CFGEdge::Conditional(condition));
+        names.map(|name| match depth {
+            0 => format!("({name})"),
+            _ => format!("({name} {depth})"),
+        })
+    }
 
-                branches.push(node);
+    /// Continues after a jump. Whatever follows is only reachable through a
+    /// label, so it starts in a node without predecessors.
+    fn unreachable(&mut self) -> NodeIndex {
+        self.node(vec![])
+    }
+
+    fn translate_block(&mut self, last: NodeIndex, block: &Block) -> NodeIndex {
+        self.translate_scope(last, block, true)
+    }
+
+    /// Translates a block whose locals go out of scope at its end, unlike the
+    /// body of a repeat loop, where they are still visible to the condition.
+    fn translate_scope(&mut self, last: NodeIndex, block: &Block, closed: bool) -> NodeIndex {
+        // labels are visible in the whole block, so gotos may jump forward
+        let mut labels = HashMap::new();
+        let mut locals = vec![];
+
+        for (i, stmt) in block.statements.iter().enumerate() {
+            match &stmt.kind {
+                StatementKind::LocalDeclaration(local) => {
+                    locals.extend(local.identifier_list.iter().cloned())
+                }
+                StatementKind::LocalFunctionDefinition(function) => {
+                    if let Variable::Identifier(name) = &function.identifier {
+                        locals.push(name.clone());
+                    }
+                }
+                StatementKind::Label(label) => {
+                    // the locals of the block are out of scope at a label
+                    // that only void statements follow
+                    let end = closed
+                        && block.last_statement.is_none()
+                        && block.statements[i + 1..].iter().all(|stmt| {
+                            matches!(
+                                stmt.kind,
+                                StatementKind::Label(_) | StatementKind::Semicolon
+                            )
+                        });
+                    let in_scope = if end { 0 } else { locals.len() };
+
+                    match labels.entry(label.clone()) {
+                        Entry::Vacant(entry) => {
+                            entry.insert((self.node(vec![]), in_scope));
+                        }
+                        Entry::Occupied(_) => self.errors.push(Error::DuplicateLabel {
+                            label: label.clone(),
+                            position: stmt.span.start,
+                        }),
+                    }
+                }
+                _ => {}
             }
+        }
+
+        self.scopes.push(Scope {
+            labels,
+            locals,
+            declared: 0,
+        });
 
-            if let Some(else_block) = &stmt.else_block {
-                let else_branch = else_block.statements.first().unwrap();
+        let mut last = last;
 
-                let else_node = cfg.add_node(CFGNode {
-                    statements: vec![else_branch.clone()],
+        for stmt in block.statements.iter() {
+            last = self.translate_statement(last, stmt);
+
+            self.scopes.last_mut().unwrap().declared += match &stmt.kind {
+                StatementKind::LocalDeclaration(local) => local.identifier_list.len(),
+                StatementKind::LocalFunctionDefinition(_) => 1,
+                _ => 0,
+            };
+        }
+
+        if let Some(stmt) = &block.last_statement {
+            last = self.translate_last_statement(last, stmt);
+        }
+
+        self.scopes.pop();
+
+        last
+    }
+
+    fn translate_last_statement(&mut self, last: NodeIndex, stmt: &LastStatement) -> NodeIndex {
+        match &stmt.kind {
+            LastStatementKind::Break => self.translate_break(last, stmt.span),
+            LastStatementKind::Continue => match self.loops.last() {
+                Some(target) => {
+                    let next = target.next;
+                    self.edge(last, next);
+                    self.unreachable()
+                }
+                None => {
+                    self.errors.push(Error::ContinueOutsideLoop {
+                        position: stmt.span.start,
+                    });
+
+                    last
+                }
+            },
+            LastStatementKind::Return(_) => {
+                self.block(last).last_statement = Some(stmt.clone());
+                self.edge(last, self.exit);
+
+                self.unreachable()
+            }
+        }
+    }
+
+    fn translate_break(&mut self, last: NodeIndex, span: Span) -> NodeIndex {
+        match self.loops.last() {
+            Some(target) => {
+                let after = target.after;
+                self.edge(last, after);
+                self.unreachable()
+            }
+            None => {
+                self.errors.push(Error::BreakOutsideLoop {
+                    position: span.start,
                 });
-                cfg.add_edge(last, else_node, CFGEdge::Unconditional());
 
-                branches.push(else_node);
+                last
             }
+        }
+    }
 
-            let merge_node = cfg.add_node(CFGNode { statements: vec![] });
+    fn translate_statement(&mut self, last: NodeIndex, stmt: &Statement) -> NodeIndex {
+        match &stmt.kind {
+            StatementKind::If(stmt) => {
+                let after = self.node(vec![]);
+
+                let mut test = last;
+                let branches = std::iter::once((&stmt.condition, &stmt.block)).chain(
+                    stmt.elseif_blocks
+                        .iter()
+                        .map(|elseif| (&elseif.condition, &elseif.block)),
+                );
+
+                // every elseif is only tested once the previous conditions failed
+                for (condition, block) in branches {
+                    let then = self.node(vec![]);
+                    let otherwise = self.node(vec![]);
+                    self.branch(test, condition, then, otherwise);
+
+                    let end = self.translate_block(then, block);
+                    self.edge(end, after);
+
+                    test = otherwise;
+                }
+
+                let end = match &stmt.else_block {
+                    Some(block) => self.translate_block(test, block),
+                    None => test,
+                };
+                self.edge(end, after);
+
+                after
+            }
+            StatementKind::While(stmt) => self.translate_while(last, stmt),
+            StatementKind::Repeat(stmt) => self.translate_repeat(last, stmt),
+            StatementKind::NumericFor(for_stmt) => {
+                self.translate_numeric_for(last, for_stmt, stmt.span)
+            }
+            StatementKind::GenericFor(for_stmt) => {
+                self.translate_generic_for(last, for_stmt, stmt.span)
+            }
+            StatementKind::Scope(block) => self.translate_block(last, block),
+            StatementKind::Break => self.translate_break(last, stmt.span),
+            StatementKind::Goto(label) => {
+                let target = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| Some((scope, scope.labels.get(label)?)));
+
+                match target {
+                    Some((scope, &(_, in_scope))) if in_scope > scope.declared => {
+                        self.errors.push(Error::JumpIntoScope {
+                            label: label.clone(),
+                            local: scope.locals[scope.declared].clone(),
+                            position: stmt.span.start,
+                        });
+
+                        last
+                    }
+                    Some((_, &(target, _))) => {
+                        self.edge(last, target);
+                        self.unreachable()
+                    }
+                    None => {
+                        self.errors.push(Error::UnknownLabel {
+                            label: label.clone(),
+                            position: stmt.span.start,
+                        });
+
+                        last
+                    }
+                }
+            }
+            StatementKind::Label(label) => {
+                let (target, _) = self.scopes.last().unwrap().labels[label];
+                self.edge(last, target);
 
-            for branch in branches.iter() {
-                cfg.add_edge(*branch, merge_node, CFGEdge::Unconditional());
+                target
             }
+            _ => {
+                self.append(last, stmt.clone());
 
-            merge_node
+                last
+            }
         }
-        Statement::While(stmt) => {
-            let condition = &stmt.condition;
+    }
+
+    fn translate_loop(
+        &mut self,
+        next: NodeIndex,
+        after: NodeIndex,
+        body: NodeIndex,
+        block: &Block,
+        closed: bool,
+    ) -> NodeIndex {
+        self.loops.push(Loop { next, after });
+        let end = self.translate_scope(body, block, closed);
+        self.loops.pop();
+
+        end
+    }
 
-            let condition_node = cfg.add_node(CFGNode { statements: vec![] });
-            cfg.add_edge(last, condition_node, CFGEdge::Unconditional());
+    fn translate_while(&mut self, last: NodeIndex, stmt: &WhileStatement) -> NodeIndex {
+        let condition = self.node(vec![]);
+        let body = self.node(vec![]);
+        let after = self.node(vec![]);
 
-            let block = stmt.block.statements.iter().next().unwrap().clone();
-            let node = cfg.add_node(CFGNode {
-                statements: vec![block],
-            });
+        self.edge(last, condition);
+        self.branch(condition, &stmt.condition, body, after);
+
+        let end = self.translate_loop(condition, after, body, &stmt.block, true);
+        self.edge(end, condition);
 
-            cfg.add_edge(
-                condition_node,
-                node,
-                CFGEdge::Conditional(condition.clone()),
-            );
-            cfg.add_edge(node, condition_node, CFGEdge::Unconditional());
+        after
+    }
 
-            let merge = cfg.add_node(CFGNode { statements: vec![] });
+    fn translate_repeat(&mut self, last: NodeIndex, stmt: &RepeatStatement) -> NodeIndex {
+        let body = self.node(vec![]);
+        let condition = self.node(vec![]);
+        let after = self.node(vec![]);
 
-            cfg.add_edge(condition_node, merge, CFGEdge::Unconditional());
+        self.edge(last, body);
 
-            merge
-        }
-        _ => {
-            let node = cfg.add_node(CFGNode {
-                statements: vec![stmt.clone()],
+        let end = self.translate_loop(condition, after, body, &stmt.block, false);
+        self.edge(end, condition);
+        self.branch(condition, &stmt.condition, after, body);
+
+        after
+    }
+
+    /// Lowers `for i = start, limit, step do ... end` onto hidden locals that
+    /// are evaluated once, with a fresh `i` for every iteration.
+    fn translate_numeric_for(
+        &mut self,
+        last: NodeIndex,
+        stmt: &NumericForStatement,
+        span: Span,
+    ) -> NodeIndex {
+        let step = stmt
+            .step
+            .clone()
+            .unwrap_or_else(|| Expression::new(ExpressionKind::LiteralInteger(1), span));
+
+        if is_zero(&step) {
+            self.errors.push(Error::ZeroStep {
+                position: step.span.start,
             });
+        }
+
+        let [index, limit, step_variable] = self.hidden([FOR_INDEX, FOR_LIMIT, FOR_STEP]);
+
+        self.append(
+            last,
+            local(
+                &[&index, &limit, &step_variable],
+                vec![stmt.start.clone(), stmt.end.clone(), step.clone()],
+                span,
+            ),
+        );
+
+        let condition = self.node(vec![]);
+        let body = self.node(vec![local(
+            &[&stmt.identifier],
+            vec![variable(&index, span)],
+            span,
+        )]);
+        let increment = self.node(vec![assign(
+            &index,
+            binary(
+                ExpressionKind::Addition,
+                variable(&index, span),
+                variable(&step_variable, span),
+            ),
+            span,
+        )]);
+        let after = self.node(vec![]);
+
+        let test = numeric_for_condition([&index, &limit, &step_variable], &step, span);
+
+        self.edge(last, condition);
+        self.branch(condition, &test, body, after);
+
+        let end = self.translate_loop(increment, after, body, &stmt.block, true);
+        self.edge(end, increment);
+        self.edge(increment, condition);
+
+        after
+    }
 
-            cfg.add_edge(last, node, CFGEdge::Unconditional());
+    /// Lowers `for k, v in explist do ... end` onto the generator, state and
+    /// control values the loop keeps between iterations.
+    fn translate_generic_for(
+        &mut self,
+        last: NodeIndex,
+        stmt: &GenericForStatement,
+        span: Span,
+    ) -> NodeIndex {
+        let [generator, state, control] = self.hidden([FOR_GENERATOR, FOR_STATE, FOR_CONTROL]);
+
+        self.append(
+            last,
+            local(
+                &[&generator, &state, &control],
+                stmt.expression_list.clone(),
+                span,
+            ),
+        );
+
+        let identifiers = stmt
+            .identifier_list
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let first = identifiers[0];
+
+        let call = Expression::new(
+            ExpressionKind::FunctionCall(FunctionCallExpression {
+                callee: Box::new(variable(&generator, span)),
+                arguments: vec![variable(&state, span), variable(&control, span)],
+            }),
+            span,
+        );
+
+        let condition = self.node(vec![local(&identifiers, vec![call], span)]);
+        let body = self.node(vec![assign(&control, variable(first, span), span)]);
+        let after = self.node(vec![]);
+
+        let has_value = binary(
+            ExpressionKind::NotEqual,
+            variable(first, span),
+            Expression::new(ExpressionKind::Nil, span),
+        );
+
+        self.edge(last, condition);
+        self.branch(condition, &has_value, body, after);
+
+        let end = self.translate_loop(condition, after, body, &stmt.block, true);
+        self.edge(end, condition);
+
+        after
+    }
+}
 
-            node
+/// The condition under which a numeric for loop runs another iteration, which
+/// depends on the direction of the step when it is not a literal.
+fn numeric_for_condition(
+    [index, limit, step_variable]: [&str; 3],
+    step: &Expression,
+    span: Span,
+) -> Expression {
+    let index = || variable(index, span);
+    let limit = || variable(limit, span);
+
+    let ascending = || binary(ExpressionKind::LessThanOrEqual, index(), limit());
+    let descending = || binary(ExpressionKind::GreaterThanOrEqual, index(), limit());
+
+    match step.kind {
+        ExpressionKind::LiteralInteger(step) if step > 0 => ascending(),
+        ExpressionKind::LiteralFloat(step) if step > 0.0 => ascending(),
+        ExpressionKind::LiteralInteger(_) | ExpressionKind::LiteralFloat(_) => descending(),
+        ExpressionKind::Negative(ref step)
+            if matches!(
+                step.kind,
+                ExpressionKind::LiteralInteger(_) | ExpressionKind::LiteralFloat(_)
+            ) =>
+        {
+            descending()
+        }
+        _ => {
+            let zero = || Expression::new(ExpressionKind::LiteralInteger(0), span);
+            let step = || variable(step_variable, span);
+
+            binary(
+                ExpressionKind::Or,
+                binary(
+                    ExpressionKind::And,
+                    binary(ExpressionKind::GreaterThan, step(), zero()),
+                    ascending(),
+                ),
+                binary(
+                    ExpressionKind::And,
+                    binary(ExpressionKind::LessThanOrEqual, step(), zero()),
+                    descending(),
+                ),
+            )
         }
     }
 }
 
-fn translate_statements(statements: Vec<Statement>) -> CFG {
-    let mut graph = CFG::new();
+fn is_zero(exp: &Expression) -> bool {
+    match &exp.kind {
+        ExpressionKind::LiteralInteger(number) => *number == 0,
+        ExpressionKind::LiteralFloat(number) => *number == 0.0,
+        ExpressionKind::Negative(exp) | ExpressionKind::Parenthesized(exp) => is_zero(exp),
+        _ => false,
+    }
+}
 
-    let mut stmts = statements.iter();
+fn variable(identifier: &str, span: Span) -> Expression {
+    Expression::new(
+        ExpressionKind::Variable(Variable::Identifier(identifier.to_string())),
+        span,
+    )
+}
 
-    let mut last = graph.add_node(CFGNode { statements: vec![] });
+fn binary(
+    kind: fn(Box<Expression>, Box<Expression>) -> ExpressionKind,
+    a: Expression,
+    b: Expression,
+) -> Expression {
+    let span = a.span.to(b.span);
+    Expression::new(kind(Box::new(a), Box::new(b)), span)
+}
 
-    while let Some(stmt) = stmts.next() {
-        let new_last = translate_statement(&mut graph, last, stmt);
+fn local(identifiers: &[&str], expression_list: Vec<Expression>, span: Span) -> Statement {
+    Statement::new(
+        StatementKind::LocalDeclaration(LocalDeclarationStatement {
+            identifier_list: identifiers.iter().map(|x| x.to_string()).collect(),
+            attribute_list: vec![None; identifiers.len()],
+            type_list: vec![None; identifiers.len()],
+            expression_list,
+        }),
+        span,
+    )
+}
 
-        // let last_neighbors_count = graph.neighbors_directed(last, Direction::Outgoing).count();
+fn assign(identifier: &str, exp: Expression, span: Span) -> Statement {
+    Statement::new(
+        StatementKind::Assignment(AssignmentStatement {
+            variable_list: vec![Variable::Identifier(identifier.to_string())],
+            expression_list: vec![exp],
+        }),
+        span,
+    )
+}
 
-        last = new_last;
+/// Translates a block into a graph of basic blocks. Every return, as well as
+/// falling off the end of the block, leads to the exit node. Fails with every
+/// `goto`, `break` and `continue` that has nowhere to go, every `goto` into the
+/// scope of a local, every label defined twice in the same block and every
+/// numeric for loop with a constant step of zero.
+pub fn translate(block: &Block, options: Options) -> Result<Cfg, Vec<Error>> {
+    let mut graph = StableGraph::new();
+    let entry = graph.add_node(CFGNode::Entry);
+    let exit = graph.add_node(CFGNode::Exit);
+
+    let mut translator = Translator {
+        options,
+        graph,
+        exit,
+        loops: vec![],
+        scopes: vec![],
+        errors: vec![],
+    };
+
+    let start = translator.node(vec![]);
+    translator.edge(entry, start);
+
+    let end = translator.translate_block(start, block);
+    translator.edge(end, exit);
+
+    if !translator.errors.is_empty() {
+        return Err(translator.errors);
     }
 
-    graph
+    Ok(cleanup::cleanup(translator.graph, entry, exit))
 }
 
-pub fn translate(block: &Block) -> CFG {
-    translate_statements(block.statements.clone())
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cfg::Error,
+        parser::{ast::definition::Position, parse},
+    };
+
+    use super::translate;
+
+    fn errors(source: &str) -> Vec<Error> {
+        let chunk = parse(source).unwrap();
+
+        match translate(&chunk.block, Default::default()) {
+            Ok(_) => vec![],
+            Err(errors) => errors,
+        }
+    }
+
+    fn position(line: usize, column: usize, offset: usize) -> Position {
+        Position {
+            line,
+            column,
+            offset,
+        }
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        assert_eq!(
+            errors("local a = 1\nbreak"),
+            vec![Error::BreakOutsideLoop {
+                position: position(2, 1, 12),
+            }]
+        );
+        assert_eq!(errors("while true do break end"), vec![]);
+    }
+
+    #[cfg(feature = "luau")]
+    #[test]
+    fn continue_outside_loop() {
+        assert_eq!(
+            errors("do continue end"),
+            vec![Error::ContinueOutsideLoop {
+                position: position(1, 4, 3),
+            }]
+        );
+        assert_eq!(errors("for i = 1, 2 do continue end"), vec![]);
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn goto_without_visible_label() {
+        assert_eq!(
+            errors("goto done\ndo ::done:: end"),
+            vec![Error::UnknownLabel {
+                label: "done".to_string(),
+                position: position(1, 1, 0),
+            }]
+        );
+        assert_eq!(errors("do goto done end ::done::"), vec![]);
+        assert_eq!(errors("::top:: do goto top end"), vec![]);
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn duplicate_labels() {
+        assert_eq!(
+            errors("::a:: do end\n::a::"),
+            vec![Error::DuplicateLabel {
+                label: "a".to_string(),
+                position: position(2, 1, 13),
+            }]
+        );
+        assert_eq!(errors("::a:: do ::a:: end"), vec![]);
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn goto_into_scope_of_local() {
+        assert_eq!(
+            errors("goto skip\nlocal x = 1;\n::skip::\nprint(x)"),
+            vec![Error::JumpIntoScope {
+                label: "skip".to_string(),
+                local: "x".to_string(),
+                position: position(1, 1, 0),
+            }]
+        );
+        assert_eq!(
+            errors(
+                "local a\ndo goto skip end\nlocal b, c\nlocal function d() end\n::skip:: print(d)"
+            ),
+            vec![Error::JumpIntoScope {
+                label: "skip".to_string(),
+                local: "b".to_string(),
+                position: position(2, 4, 11),
+            }]
+        );
+        assert_eq!(
+            errors("repeat goto next local x = 1; ::next:: until x"),
+            vec![Error::JumpIntoScope {
+                label: "next".to_string(),
+                local: "x".to_string(),
+                position: position(1, 8, 7),
+            }]
+        );
+
+        // the local is already out of scope at a label that ends its block
+        assert_eq!(errors("do goto done local x = 1; ::done:: ; end"), vec![]);
+        assert_eq!(
+            errors("while true do goto next local x ::next:: end"),
+            vec![]
+        );
+        // jumping back leaves the scope instead of entering it
+        assert_eq!(errors("::top:: local x = 1 goto top"), vec![]);
+        assert_eq!(
+            errors("local x = 1 goto skip print(x); ::skip:: print(x)"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn zero_step() {
+        assert_eq!(
+            errors("for i = 1, 10, 0 do end"),
+            vec![Error::ZeroStep {
+                position: position(1, 16, 15),
+            }]
+        );
+        assert_eq!(
+            errors("for i = 1, 10, -0.0 do end")
+                .iter()
+                .map(Error::position)
+                .collect::<Vec<_>>(),
+            vec![position(1, 16, 15)]
+        );
+        assert_eq!(errors("for i = 10, 1, -1 do end"), vec![]);
+        assert_eq!(errors("local step = 0 for i = 1, 10, step do end"), vec![]);
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn every_error_reported() {
+        assert_eq!(
+            errors("goto a\n::b:: do end ::b::\nbreak")
+                .iter()
+                .map(Error::position)
+                .collect::<Vec<_>>(),
+            vec![position(2, 14, 20), position(1, 1, 0), position(3, 1, 26)]
+        );
+    }
 }
@@ -1,18 +1,36 @@
-use std::{cell::RefCell, fs};
+use std::fs;
 
 use log::error;
-use petgraph::{
-    dot::{Config, Dot},
-    prelude::DiGraph,
-    stable_graph::{DefaultIx, NodeIndex},
-};
+use petgraph::dot::{Config, Dot};
 
-use super::{CFGEdge, CFGNode, CFG};
+use super::{program::Program, Cfg};
 
-pub fn visualize(cfg: CFG) {
-    let dot = format!("{:?}", Dot::with_config(&cfg, &[]));
+/// Writes the graph of the main chunk to `cfg.dot`, the graph of every other
+/// function to `cfg_<index>.dot` and the call graph to `calls.dot`.
+pub fn visualize(program: &Program) {
+    for function in program.call_graph.node_indices() {
+        let path = if function == program.main {
+            "cfg.dot".to_string()
+        } else {
+            format!("cfg_{}.dot", function.index())
+        };
 
-    if let Err(e) = fs::write("cfg.dot", dot) {
-        error!("could not write cfg.dot: {e}");
+        write(&path, dot(&program.call_graph[function].cfg));
+    }
+
+    let calls = format!(
+        "{:?}",
+        Dot::with_config(&program.call_graph, &[Config::EdgeNoLabel])
+    );
+    write("calls.dot", calls);
+}
+
+fn dot(cfg: &Cfg) -> String {
+    format!("{:?}", Dot::with_config(&cfg.graph, &[]))
+}
+
+fn write(path: &str, dot: String) {
+    if let Err(e) = fs::write(path, dot) {
+        error!("could not write {path}: {e}");
     }
 }
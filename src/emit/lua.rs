@@ -0,0 +1,614 @@
+//! A pretty-printer producing readable, valid Lua.
+//!
+//! Reading the output back yields the same tree: parentheses are only added
+//! where the tree could not be expressed otherwise, so
+//! `parse(print(parse(src))) == parse(src)` holds for every source the parser
+//! accepts.
+
+use std::fmt::Write;
+
+use crate::parser::ast::definition::{
+    AnonFunctionExpression, Attribute, Block, Chunk, CompoundOperator, Expression, ExpressionKind,
+    FunctionTypes, InterpolatedString, LastStatement, LastStatementKind, Parameter, Statement,
+    StatementKind, TableField, Type, Variable,
+};
+
+use super::{
+    binary_operator, binary_priority, needs_parentheses_assertion, needs_parentheses_left,
+    needs_parentheses_prefix, needs_parentheses_right, needs_parentheses_unary, unary_operator,
+    write_escaped, write_float, write_integer, write_string,
+};
+
+const INDENTATION: &str = "    ";
+
+pub fn print(chunk: &Chunk) -> String {
+    print_block(&chunk.block)
+}
+
+pub fn print_block(block: &Block) -> String {
+    let mut printer = Printer::default();
+    printer.block(block);
+    printer.output
+}
+
+pub fn print_statement(stmt: &Statement) -> String {
+    let mut printer = Printer::default();
+    printer.statement(stmt);
+    printer.output
+}
+
+pub fn print_last_statement(stmt: &LastStatement) -> String {
+    let mut printer = Printer::default();
+    printer.last_statement(stmt);
+    printer.output
+}
+
+pub fn print_expression(exp: &Expression) -> String {
+    let mut printer = Printer::default();
+    printer.expression(exp);
+    printer.output
+}
+
+pub fn print_variable(var: &Variable) -> String {
+    let mut printer = Printer::default();
+    printer.variable(var);
+    printer.output
+}
+
+#[derive(Default)]
+struct Printer {
+    output: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn line(&mut self) {
+        self.output.push('\n');
+
+        for _ in 0..self.indent {
+            self.output.push_str(INDENTATION);
+        }
+    }
+
+    fn list<T>(&mut self, items: &[T], mut print: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.output.push_str(", ");
+            }
+
+            print(self, item);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for (i, stmt) in block.statements.iter().enumerate() {
+            if i > 0 {
+                self.line();
+            }
+
+            let start = self.output.len();
+            self.statement(stmt);
+
+            // `a = b\n(f)()` would read as `a = b(f)()`
+            if i > 0 && self.output[start..].starts_with('(') {
+                self.output.insert(start, ';');
+            }
+        }
+
+        if let Some(last) = &block.last_statement {
+            if !block.statements.is_empty() {
+                self.line();
+            }
+
+            self.last_statement(last);
+        }
+    }
+
+    /// Writes a block indented on its own lines, leaving the cursor on a fresh
+    /// line at the outer indentation.
+    fn body(&mut self, block: &Block) {
+        self.indent += 1;
+
+        if !block.statements.is_empty() || block.last_statement.is_some() {
+            self.line();
+            self.block(block);
+        }
+
+        self.indent -= 1;
+        self.line();
+    }
+
+    fn last_statement(&mut self, stmt: &LastStatement) {
+        match &stmt.kind {
+            LastStatementKind::Break => self.output.push_str("break"),
+            LastStatementKind::Continue => self.output.push_str("continue"),
+            LastStatementKind::Return(ret) => {
+                self.output.push_str("return");
+
+                if !ret.expression_list.is_empty() {
+                    self.output.push(' ');
+                    self.list(&ret.expression_list, Self::expression);
+                }
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::Semicolon => self.output.push(';'),
+            StatementKind::LocalDeclaration(stmt) => {
+                self.output.push_str("local ");
+
+                for (i, identifier) in stmt.identifier_list.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+
+                    self.output.push_str(identifier);
+
+                    if let Some(Some(attribute)) = stmt.attribute_list.get(i) {
+                        self.attribute(attribute);
+                    }
+
+                    if let Some(Some(ty)) = stmt.type_list.get(i) {
+                        self.type_annotation(ty);
+                    }
+                }
+
+                if !stmt.expression_list.is_empty() {
+                    self.output.push_str(" = ");
+                    self.list(&stmt.expression_list, Self::expression);
+                }
+            }
+            StatementKind::FunctionCall(stmt) => self.call(&stmt.callee, &stmt.arguments),
+            StatementKind::Assignment(stmt) => {
+                self.list(&stmt.variable_list, Self::variable);
+                self.output.push_str(" = ");
+                self.list(&stmt.expression_list, Self::expression);
+            }
+            StatementKind::CompoundAssignment(stmt) => {
+                self.variable(&stmt.variable);
+                self.output.push(' ');
+                self.compound_operator(&stmt.operator);
+                self.output.push_str("= ");
+                self.expression(&stmt.expression);
+            }
+            StatementKind::Label(label) => write!(self.output, "::{label}::").unwrap(),
+            StatementKind::Break => self.output.push_str("break"),
+            StatementKind::Goto(label) => write!(self.output, "goto {label}").unwrap(),
+            StatementKind::Scope(block) => {
+                self.output.push_str("do");
+                self.body(block);
+                self.output.push_str("end");
+            }
+            StatementKind::While(stmt) => {
+                self.output.push_str("while ");
+                self.expression(&stmt.condition);
+                self.output.push_str(" do");
+                self.body(&stmt.block);
+                self.output.push_str("end");
+            }
+            StatementKind::Repeat(stmt) => {
+                self.output.push_str("repeat");
+                self.body(&stmt.block);
+                self.output.push_str("until ");
+                self.expression(&stmt.condition);
+            }
+            StatementKind::If(stmt) => {
+                self.output.push_str("if ");
+                self.expression(&stmt.condition);
+                self.output.push_str(" then");
+                self.body(&stmt.block);
+
+                for elseif in stmt.elseif_blocks.iter() {
+                    self.output.push_str("elseif ");
+                    self.expression(&elseif.condition);
+                    self.output.push_str(" then");
+                    self.body(&elseif.block);
+                }
+
+                if let Some(block) = &stmt.else_block {
+                    self.output.push_str("else");
+                    self.body(block);
+                }
+
+                self.output.push_str("end");
+            }
+            StatementKind::NumericFor(stmt) => {
+                write!(self.output, "for {}", stmt.identifier).unwrap();
+
+                if let Some(ty) = &stmt.identifier_type {
+                    self.type_annotation(ty);
+                }
+
+                self.output.push_str(" = ");
+                self.expression(&stmt.start);
+                self.output.push_str(", ");
+                self.expression(&stmt.end);
+
+                if let Some(step) = &stmt.step {
+                    self.output.push_str(", ");
+                    self.expression(step);
+                }
+
+                self.output.push_str(" do");
+                self.body(&stmt.block);
+                self.output.push_str("end");
+            }
+            StatementKind::GenericFor(stmt) => {
+                self.output.push_str("for ");
+
+                for (i, identifier) in stmt.identifier_list.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+
+                    self.output.push_str(identifier);
+
+                    if let Some(Some(ty)) = stmt.type_list.get(i) {
+                        self.type_annotation(ty);
+                    }
+                }
+
+                self.output.push_str(" in ");
+                self.list(&stmt.expression_list, Self::expression);
+                self.output.push_str(" do");
+                self.body(&stmt.block);
+                self.output.push_str("end");
+            }
+            StatementKind::FunctionDefinition(stmt) => {
+                self.output.push_str("function ");
+                self.variable(&stmt.identifier);
+                self.function_body(&stmt.parameter_list, stmt.types.as_deref(), &stmt.block);
+            }
+            StatementKind::LocalFunctionDefinition(stmt) => {
+                self.output.push_str("local function ");
+                self.variable(&stmt.identifier);
+                self.function_body(&stmt.parameter_list, stmt.types.as_deref(), &stmt.block);
+            }
+            StatementKind::TypeDeclaration(stmt) => {
+                if stmt.exported {
+                    self.output.push_str("export ");
+                }
+
+                write!(self.output, "type {}", stmt.identifier).unwrap();
+
+                if let Some(generics) = &stmt.generics {
+                    self.output.push_str(&generics.0);
+                }
+
+                write!(self.output, " = {}", stmt.definition.0).unwrap();
+            }
+        }
+    }
+
+    fn attribute(&mut self, attribute: &Attribute) {
+        match attribute {
+            Attribute::Const => self.output.push_str(" <const>"),
+            Attribute::Close => self.output.push_str(" <close>"),
+        }
+    }
+
+    fn type_annotation(&mut self, ty: &Type) {
+        write!(self.output, ": {}", ty.0).unwrap();
+    }
+
+    fn compound_operator(&mut self, operator: &CompoundOperator) {
+        self.output.push_str(match operator {
+            CompoundOperator::Addition => "+",
+            CompoundOperator::Subtraction => "-",
+            CompoundOperator::Multiplication => "*",
+            CompoundOperator::Division => "/",
+            CompoundOperator::Modulo => "%",
+            CompoundOperator::Exponentiation => "^",
+            CompoundOperator::Concatenation => "..",
+        });
+    }
+
+    fn function_body(
+        &mut self,
+        parameters: &[Parameter],
+        types: Option<&FunctionTypes>,
+        block: &Block,
+    ) {
+        if let Some(generics) = types.and_then(|types| types.generics.as_ref()) {
+            self.output.push_str(&generics.0);
+        }
+
+        self.output.push('(');
+
+        for (i, param) in parameters.iter().enumerate() {
+            if i > 0 {
+                self.output.push_str(", ");
+            }
+
+            match param {
+                Parameter::Identifier(identifier) => self.output.push_str(identifier),
+                Parameter::VariableArg => self.output.push_str("..."),
+            }
+
+            let ty = types.and_then(|types| types.parameter_types.get(i));
+
+            if let Some(Some(ty)) = ty {
+                self.type_annotation(ty);
+            }
+        }
+
+        self.output.push(')');
+
+        if let Some(ty) = types.and_then(|types| types.return_type.as_ref()) {
+            self.type_annotation(ty);
+        }
+
+        self.body(block);
+        self.output.push_str("end");
+    }
+
+    fn variable(&mut self, var: &Variable) {
+        match var {
+            Variable::Identifier(identifier) => self.output.push_str(identifier),
+            Variable::TableIndex(index) => {
+                self.prefix(&index.base);
+                self.output.push('[');
+                self.expression(&index.index);
+                self.output.push(']');
+            }
+            Variable::TableMember(member) => {
+                self.prefix(&member.base);
+                write!(self.output, ".{}", member.member).unwrap();
+            }
+            Variable::TableMethod(method) => {
+                self.prefix(&method.base);
+                write!(self.output, ":{}", method.method).unwrap();
+            }
+        }
+    }
+
+    fn call(&mut self, callee: &Expression, arguments: &[Expression]) {
+        self.prefix(callee);
+        self.output.push('(');
+        self.list(arguments, Self::expression);
+        self.output.push(')');
+    }
+
+    fn prefix(&mut self, exp: &Expression) {
+        self.expression_parenthesized(exp, needs_parentheses_prefix(exp));
+    }
+
+    fn expression_parenthesized(&mut self, exp: &Expression, parenthesized: bool) {
+        if parenthesized {
+            self.output.push('(');
+            self.expression(exp);
+            self.output.push(')');
+        } else {
+            self.expression(exp);
+        }
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        if let Some((operator, a, b)) = binary_operator(&exp.kind) {
+            let (left, right) = binary_priority(&exp.kind).unwrap();
+
+            self.expression_parenthesized(a, needs_parentheses_left(left, a));
+            write!(self.output, " {operator} ").unwrap();
+            self.expression_parenthesized(b, needs_parentheses_right(right, b));
+
+            return;
+        }
+
+        if let Some((operator, operand)) = unary_operator(&exp.kind) {
+            self.output.push_str(operator);
+
+            if operator == "not" {
+                self.output.push(' ');
+            }
+
+            let start = self.output.len();
+            self.expression_parenthesized(operand, needs_parentheses_unary(operand));
+
+            // `- -x` must not turn into a comment
+            if operator == "-" && self.output[start..].starts_with('-') {
+                self.output.insert(start, ' ');
+            }
+
+            return;
+        }
+
+        match &exp.kind {
+            ExpressionKind::LiteralInteger(number) => write_integer(&mut self.output, *number),
+            ExpressionKind::LiteralFloat(number) => write_float(&mut self.output, *number),
+            ExpressionKind::LiteralString(string) => write_string(&mut self.output, string),
+            ExpressionKind::True => self.output.push_str("true"),
+            ExpressionKind::False => self.output.push_str("false"),
+            ExpressionKind::Nil => self.output.push_str("nil"),
+            ExpressionKind::VariableArgument => self.output.push_str("..."),
+            ExpressionKind::TableConstructor(fields) => {
+                self.output.push('{');
+                self.list(fields, Self::table_field);
+                self.output.push('}');
+            }
+            ExpressionKind::FunctionCall(call) => self.call(&call.callee, &call.arguments),
+            ExpressionKind::AnonFunctionDefinition(func) => self.anon_function(func),
+            ExpressionKind::Variable(var) => self.variable(var),
+            ExpressionKind::If(exp) => {
+                self.output.push_str("if ");
+                self.expression(&exp.condition);
+                self.output.push_str(" then ");
+                self.expression(&exp.expression);
+
+                for elseif in exp.elseif_expressions.iter() {
+                    self.output.push_str(" elseif ");
+                    self.expression(&elseif.condition);
+                    self.output.push_str(" then ");
+                    self.expression(&elseif.expression);
+                }
+
+                self.output.push_str(" else ");
+                self.expression(&exp.else_expression);
+            }
+            ExpressionKind::InterpolatedString(string) => self.interpolated_string(string),
+            ExpressionKind::TypeAssertion(exp, ty) => {
+                self.expression_parenthesized(exp, needs_parentheses_assertion(exp));
+                write!(self.output, " :: {}", ty.0).unwrap();
+            }
+            ExpressionKind::Parenthesized(exp) => {
+                self.output.push('(');
+                self.expression(exp);
+                self.output.push(')');
+            }
+            _ => unreachable!("operators are handled above"),
+        }
+    }
+
+    fn table_field(&mut self, field: &TableField) {
+        match field {
+            TableField::Value(value) => self.expression(value),
+            TableField::IndexValue(index, value) => {
+                self.output.push('[');
+                self.expression(index);
+                self.output.push_str("] = ");
+                self.expression(value);
+            }
+            TableField::KeyValue(key, value) => {
+                write!(self.output, "{key} = ").unwrap();
+                self.expression(value);
+            }
+        }
+    }
+
+    fn anon_function(&mut self, func: &AnonFunctionExpression) {
+        self.output.push_str("function");
+        self.function_body(&func.parameter_list, func.types.as_deref(), &func.block);
+    }
+
+    fn interpolated_string(&mut self, string: &InterpolatedString) {
+        self.output.push('`');
+
+        for segment in string.segments.iter() {
+            write_escaped(&mut self.output, &segment.literal, &['`', '{', '}']);
+            self.output.push('{');
+
+            // `{{` is rejected as an attempt at escaping the brace
+            let start = self.output.len();
+            self.expression(&segment.expression);
+
+            if self.output[start..].starts_with('{') {
+                self.output.insert(start, ' ');
+            }
+
+            self.output.push('}');
+        }
+
+        write_escaped(&mut self.output, &string.last, &['`', '{', '}']);
+        self.output.push('`');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+
+    use super::print;
+
+    /// Asserts that printing the tree of `source` and parsing it back gives
+    /// the same tree, and returns the printed source.
+    fn round_trip(source: &str) -> String {
+        let chunk = parse(source).unwrap();
+        let printed = print(&chunk);
+
+        assert_eq!(parse(&printed).unwrap(), chunk, "printed as:\n{printed}");
+
+        printed
+    }
+
+    #[test]
+    fn right_associative_operators() {
+        round_trip("x = a .. b .. c");
+        round_trip("x = (a .. b) .. c");
+        round_trip("x = a ^ b ^ c");
+        round_trip("x = (a ^ b) ^ c");
+        round_trip("x = a + b .. c + d");
+        round_trip("x = a .. b == c");
+    }
+
+    #[test]
+    fn precedence() {
+        round_trip("x = a + b * c - d / e");
+        round_trip("x = (a + b) * (c - d)");
+        round_trip("x = a - (b - c)");
+        round_trip("x = not a == b");
+        round_trip("x = not (a == b)");
+        round_trip("x = a or b and c");
+        round_trip("x = (a or b) and c");
+    }
+
+    #[test]
+    fn unary_minus_before_power() {
+        round_trip("x = -a ^ b");
+        round_trip("x = (-a) ^ b");
+        round_trip("x = -a ^ -b");
+        round_trip("x = - -a");
+        round_trip("x = #t ^ 2");
+    }
+
+    #[test]
+    fn negative_literals() {
+        round_trip("x = -1");
+        round_trip("x = -1.5");
+        round_trip("x = -2 ^ 2");
+        round_trip("x = a - -1");
+        round_trip("x = -0x10");
+    }
+
+    #[test]
+    fn wrapped_hexadecimal_literals() {
+        assert_eq!(
+            round_trip("x = 0xffffffffffffffff"),
+            "x = 0xffffffffffffffff"
+        );
+        assert_eq!(
+            round_trip("x = 0x8000000000000000"),
+            "x = 0x8000000000000000"
+        );
+        round_trip("x = -0xffffffffffffffff ^ 2");
+        round_trip("x = a - 0xfffffffffffffffe");
+    }
+
+    #[test]
+    fn string_escapes() {
+        round_trip(r#"x = "a\nb\tc\\d\"e'f""#);
+        round_trip(r"x = '\0\1\127\255'");
+        round_trip(r#"x = "\x41\65""#);
+        round_trip(r#"x = "\u{48}\u{20AC}""#);
+        round_trip("x = 'line\\\nbreak'");
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn skipped_whitespace_escape() {
+        round_trip("x = 'a\\z\n    b'");
+    }
+
+    #[test]
+    fn long_strings() {
+        round_trip("x = [[plain]]");
+        round_trip("x = [[\nleading newline]]");
+        round_trip("x = [==[with ]] and ]=] inside]==]");
+        round_trip("x = [[multi\nline\n\ttext]]");
+    }
+
+    #[test]
+    fn nested_functions() {
+        round_trip(
+            "local function outer(a, ...)
+                local function inner(b)
+                    return function(c) return a + b + c end
+                end
+                return inner, ...
+            end",
+        );
+        round_trip("x = (function() return 1 end)()");
+        round_trip("t = { f = function(self) return self end, [1] = function() end }");
+        round_trip("function a.b.c:d(e) return function() return e end end");
+    }
+}
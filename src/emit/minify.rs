@@ -0,0 +1,884 @@
+//! A minifying emitter producing the smallest Lua it can for a chunk.
+//!
+//! On top of leaving out every optional whitespace, the minifier works on a
+//! copy of the tree that has its Luau types stripped, redundant parentheses
+//! removed and local variables renamed to the shortest free names.
+
+use std::collections::BTreeSet;
+
+use crate::parser::ast::{
+    definition::{
+        AnonFunctionExpression, Attribute, Block, Chunk, CompoundOperator, Expression,
+        ExpressionKind, FunctionDefinitionStatement, GenericForStatement, Identifier,
+        InterpolatedString, LastStatement, LastStatementKind, LocalDeclarationStatement,
+        LocalFunctionDefinitionStatement, LuaString, NumericForStatement, Parameter,
+        RepeatStatement, Statement, StatementKind, TableField, Variable,
+    },
+    luau,
+    visitor::{self, VisitorMut},
+};
+
+use super::{
+    binary_operator, binary_priority, needs_parentheses_assertion, needs_parentheses_left,
+    needs_parentheses_prefix, needs_parentheses_right, needs_parentheses_unary, unary_operator,
+    write_escaped,
+};
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+    // contextual keywords of Luau
+    "continue", "export", "type",
+];
+
+pub fn minify(chunk: &Chunk) -> String {
+    let mut chunk = chunk.clone();
+
+    luau::strip_types(&mut chunk);
+    ParenthesesRemover.visit_chunk_mut(&mut chunk);
+    rename_locals(&mut chunk);
+
+    let mut writer = Writer::default();
+    writer.block(&chunk.block);
+    writer.output
+}
+
+/// Removes parentheses that only group, keeping those that truncate a call or
+/// `...` to a single value. The writer puts back whatever precedence needs.
+struct ParenthesesRemover;
+
+impl VisitorMut for ParenthesesRemover {
+    fn visit_expression_mut(&mut self, exp: &mut Expression) {
+        visitor::walk_expression_mut(self, exp);
+
+        if let ExpressionKind::Parenthesized(inner) = &mut exp.kind {
+            if !matches!(
+                inner.kind,
+                ExpressionKind::FunctionCall(_) | ExpressionKind::VariableArgument
+            ) {
+                let inner = std::mem::replace(
+                    inner.as_mut(),
+                    Expression::new(ExpressionKind::Nil, exp.span),
+                );
+                exp.kind = inner.kind;
+            }
+        }
+    }
+}
+
+/// Renames every local by its depth on the stack of visible locals, so a name
+/// is never shadowed while it is still in use. Globals keep their names and
+/// are never handed out to locals.
+fn rename_locals(chunk: &mut Chunk) {
+    // a dry run on a copy finds every global the chunk refers to
+    let mut collector = Renamer::new(BTreeSet::new());
+    collector.visit_chunk_mut(&mut chunk.clone());
+
+    let mut renamer = Renamer::new(collector.globals);
+    renamer.visit_chunk_mut(chunk);
+}
+
+struct Renamer {
+    /// Pairs of original and new names for every scope, innermost last.
+    scopes: Vec<Vec<(Identifier, Identifier)>>,
+    names: NameGenerator,
+    globals: BTreeSet<Identifier>,
+}
+
+impl Renamer {
+    fn new(reserved: BTreeSet<Identifier>) -> Self {
+        Renamer {
+            scopes: vec![],
+            names: NameGenerator::new(reserved),
+            globals: BTreeSet::new(),
+        }
+    }
+
+    fn declare(&mut self, identifier: &mut Identifier) {
+        let depth = self.scopes.iter().map(Vec::len).sum();
+        let name = self.names.get(depth);
+        let original = std::mem::replace(identifier, name.clone());
+
+        self.bind(original, name);
+    }
+
+    fn bind(&mut self, original: Identifier, name: Identifier) {
+        self.scopes.last_mut().unwrap().push((original, name));
+    }
+
+    fn declare_parameters(&mut self, parameters: &mut [Parameter]) {
+        for param in parameters.iter_mut() {
+            if let Parameter::Identifier(identifier) = param {
+                self.declare(identifier);
+            }
+        }
+    }
+}
+
+impl VisitorMut for Renamer {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        self.scopes.push(vec![]);
+        visitor::walk_block_mut(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_local_declaration_mut(&mut self, stmt: &mut LocalDeclarationStatement) {
+        for exp in stmt.expression_list.iter_mut() {
+            self.visit_expression_mut(exp);
+        }
+
+        for identifier in stmt.identifier_list.iter_mut() {
+            self.declare(identifier);
+        }
+    }
+
+    fn visit_repeat_mut(&mut self, stmt: &mut RepeatStatement) {
+        // the condition still sees the locals of the block
+        self.scopes.push(vec![]);
+        visitor::walk_block_mut(self, &mut stmt.block);
+        self.visit_expression_mut(&mut stmt.condition);
+        self.scopes.pop();
+    }
+
+    fn visit_numeric_for_mut(&mut self, stmt: &mut NumericForStatement) {
+        self.visit_expression_mut(&mut stmt.start);
+        self.visit_expression_mut(&mut stmt.end);
+
+        if let Some(step) = &mut stmt.step {
+            self.visit_expression_mut(step);
+        }
+
+        self.scopes.push(vec![]);
+        self.declare(&mut stmt.identifier);
+        self.visit_block_mut(&mut stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_generic_for_mut(&mut self, stmt: &mut GenericForStatement) {
+        for exp in stmt.expression_list.iter_mut() {
+            self.visit_expression_mut(exp);
+        }
+
+        self.scopes.push(vec![]);
+
+        for identifier in stmt.identifier_list.iter_mut() {
+            self.declare(identifier);
+        }
+
+        self.visit_block_mut(&mut stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_function_definition_mut(&mut self, stmt: &mut FunctionDefinitionStatement) {
+        self.visit_variable_mut(&mut stmt.identifier);
+        self.scopes.push(vec![]);
+
+        // methods receive an implicit `self` that has to keep its name
+        if let Variable::TableMethod(_) = stmt.identifier {
+            self.bind("self".to_string(), "self".to_string());
+        }
+
+        self.declare_parameters(&mut stmt.parameter_list);
+        self.visit_block_mut(&mut stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_local_function_definition_mut(&mut self, stmt: &mut LocalFunctionDefinitionStatement) {
+        if let Variable::Identifier(identifier) = &mut stmt.identifier {
+            self.declare(identifier);
+        }
+
+        self.scopes.push(vec![]);
+        self.declare_parameters(&mut stmt.parameter_list);
+        self.visit_block_mut(&mut stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_anon_function_mut(&mut self, func: &mut AnonFunctionExpression) {
+        self.scopes.push(vec![]);
+        self.declare_parameters(&mut func.parameter_list);
+        self.visit_block_mut(&mut func.block);
+        self.scopes.pop();
+    }
+
+    fn visit_identifier_mut(&mut self, identifier: &mut Identifier) {
+        let binding = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(original, _)| original == identifier);
+
+        match binding {
+            Some((_, name)) => *identifier = name.clone(),
+            None => {
+                self.globals.insert(identifier.clone());
+            }
+        }
+    }
+}
+
+/// Hands out the shortest identifiers that are neither keywords nor reserved.
+struct NameGenerator {
+    reserved: BTreeSet<Identifier>,
+    names: Vec<Identifier>,
+    next: usize,
+}
+
+impl NameGenerator {
+    fn new(mut reserved: BTreeSet<Identifier>) -> Self {
+        reserved.extend(KEYWORDS.iter().map(|keyword| keyword.to_string()));
+        reserved.insert("self".to_string());
+
+        NameGenerator {
+            reserved,
+            names: vec![],
+            next: 0,
+        }
+    }
+
+    fn get(&mut self, index: usize) -> Identifier {
+        while self.names.len() <= index {
+            let name = Self::candidate(self.next);
+            self.next += 1;
+
+            if !self.reserved.contains(&name) {
+                self.names.push(name);
+            }
+        }
+
+        self.names[index].clone()
+    }
+
+    /// The `index`-th identifier, counting through all names of one character
+    /// before those of two, and so on.
+    fn candidate(mut index: usize) -> Identifier {
+        const FIRST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_";
+        const REST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_0123456789";
+
+        let mut name = String::new();
+        name.push(FIRST[index % FIRST.len()] as char);
+        index /= FIRST.len();
+
+        while index > 0 {
+            index -= 1;
+            name.push(REST[index % REST.len()] as char);
+            index /= REST.len();
+        }
+
+        name
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The string an index expression consists of, if it can be written as a
+/// name instead, so that `t["key"]` becomes `t.key`.
+fn as_name(exp: &Expression) -> Option<&str> {
+    let ExpressionKind::LiteralString(string) = &exp.kind else {
+        return None;
+    };
+
+    let name = std::str::from_utf8(string.as_bytes()).ok()?;
+    let valid = name.starts_with(|c: char| !c.is_ascii_digit())
+        && name.chars().all(is_word)
+        && !KEYWORDS.contains(&name);
+
+    valid.then_some(name)
+}
+
+#[derive(Default)]
+struct Writer {
+    output: String,
+    after_number: bool,
+}
+
+impl Writer {
+    /// Appends a token, separating it from the previous one only where the
+    /// two would otherwise read as a different token.
+    fn token(&mut self, token: &str) {
+        let (Some(last), Some(next)) = (self.output.chars().last(), token.chars().next()) else {
+            self.output.push_str(token);
+            return;
+        };
+
+        let separate = (is_word(last) && is_word(next))
+            || (self.after_number && (is_word(next) || next == '.'))
+            || (last == '.' && (next == '.' || next.is_ascii_digit()))
+            || (last == '-' && next == '-')
+            || (last == '[' && (next == '[' || next == '='))
+            || (last == '>' && next == '=');
+
+        if separate {
+            self.output.push(' ');
+        }
+
+        self.output.push_str(token);
+        self.after_number = false;
+    }
+
+    fn number(&mut self, number: &str) {
+        self.token(number);
+        self.after_number = true;
+    }
+
+    fn list<T>(&mut self, items: &[T], mut write: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+            }
+
+            write(self, item);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        let mut first = true;
+
+        for stmt in block.statements.iter() {
+            if let StatementKind::Semicolon = stmt.kind {
+                continue;
+            }
+
+            let start = self.output.len();
+            self.statement(stmt);
+
+            // `a=b(f)()` would read as a single statement
+            if !first && self.output[start..].starts_with('(') {
+                self.output.insert(start, ';');
+            }
+
+            first = false;
+        }
+
+        if let Some(last) = &block.last_statement {
+            self.last_statement(last);
+        }
+    }
+
+    fn body(&mut self, block: &Block) {
+        self.block(block);
+        self.token("end");
+    }
+
+    fn last_statement(&mut self, stmt: &LastStatement) {
+        match &stmt.kind {
+            LastStatementKind::Break => self.token("break"),
+            LastStatementKind::Continue => self.token("continue"),
+            LastStatementKind::Return(ret) => {
+                self.token("return");
+                self.list(&ret.expression_list, Self::expression);
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::Semicolon | StatementKind::TypeDeclaration(_) => {}
+            StatementKind::LocalDeclaration(stmt) => {
+                self.token("local");
+
+                for (i, identifier) in stmt.identifier_list.iter().enumerate() {
+                    if i > 0 {
+                        self.token(",");
+                    }
+
+                    self.token(identifier);
+
+                    match stmt.attribute_list.get(i) {
+                        Some(Some(Attribute::Const)) => self.token("<const>"),
+                        Some(Some(Attribute::Close)) => self.token("<close>"),
+                        _ => {}
+                    }
+                }
+
+                if !stmt.expression_list.is_empty() {
+                    self.token("=");
+                    self.list(&stmt.expression_list, Self::expression);
+                }
+            }
+            StatementKind::FunctionCall(stmt) => self.call(&stmt.callee, &stmt.arguments),
+            StatementKind::Assignment(stmt) => {
+                self.list(&stmt.variable_list, Self::variable);
+                self.token("=");
+                self.list(&stmt.expression_list, Self::expression);
+            }
+            StatementKind::CompoundAssignment(stmt) => {
+                self.variable(&stmt.variable);
+                self.token(match stmt.operator {
+                    CompoundOperator::Addition => "+=",
+                    CompoundOperator::Subtraction => "-=",
+                    CompoundOperator::Multiplication => "*=",
+                    CompoundOperator::Division => "/=",
+                    CompoundOperator::Modulo => "%=",
+                    CompoundOperator::Exponentiation => "^=",
+                    CompoundOperator::Concatenation => "..=",
+                });
+                self.expression(&stmt.expression);
+            }
+            StatementKind::Label(label) => {
+                self.token("::");
+                self.token(label);
+                self.token("::");
+            }
+            StatementKind::Break => self.token("break"),
+            StatementKind::Goto(label) => {
+                self.token("goto");
+                self.token(label);
+            }
+            StatementKind::Scope(block) => {
+                self.token("do");
+                self.body(block);
+            }
+            StatementKind::While(stmt) => {
+                self.token("while");
+                self.expression(&stmt.condition);
+                self.token("do");
+                self.body(&stmt.block);
+            }
+            StatementKind::Repeat(stmt) => {
+                self.token("repeat");
+                self.block(&stmt.block);
+                self.token("until");
+                self.expression(&stmt.condition);
+            }
+            StatementKind::If(stmt) => {
+                self.token("if");
+                self.expression(&stmt.condition);
+                self.token("then");
+                self.block(&stmt.block);
+
+                for elseif in stmt.elseif_blocks.iter() {
+                    self.token("elseif");
+                    self.expression(&elseif.condition);
+                    self.token("then");
+                    self.block(&elseif.block);
+                }
+
+                if let Some(block) = &stmt.else_block {
+                    self.token("else");
+                    self.block(block);
+                }
+
+                self.token("end");
+            }
+            StatementKind::NumericFor(stmt) => {
+                self.token("for");
+                self.token(&stmt.identifier);
+                self.token("=");
+                self.expression(&stmt.start);
+                self.token(",");
+                self.expression(&stmt.end);
+
+                if let Some(step) = &stmt.step {
+                    self.token(",");
+                    self.expression(step);
+                }
+
+                self.token("do");
+                self.body(&stmt.block);
+            }
+            StatementKind::GenericFor(stmt) => {
+                self.token("for");
+                self.list(&stmt.identifier_list, |writer, identifier| {
+                    writer.token(identifier)
+                });
+                self.token("in");
+                self.list(&stmt.expression_list, Self::expression);
+                self.token("do");
+                self.body(&stmt.block);
+            }
+            StatementKind::FunctionDefinition(stmt) => {
+                self.token("function");
+                self.variable(&stmt.identifier);
+                self.function_body(&stmt.parameter_list, &stmt.block);
+            }
+            StatementKind::LocalFunctionDefinition(stmt) => {
+                self.token("local");
+                self.token("function");
+                self.variable(&stmt.identifier);
+                self.function_body(&stmt.parameter_list, &stmt.block);
+            }
+        }
+    }
+
+    fn function_body(&mut self, parameters: &[Parameter], block: &Block) {
+        self.token("(");
+        self.list(parameters, |writer, param| match param {
+            Parameter::Identifier(identifier) => writer.token(identifier),
+            Parameter::VariableArg => writer.token("..."),
+        });
+        self.token(")");
+        self.body(block);
+    }
+
+    fn variable(&mut self, var: &Variable) {
+        match var {
+            Variable::Identifier(identifier) => self.token(identifier),
+            Variable::TableIndex(index) => {
+                self.prefix(&index.base);
+
+                if let Some(name) = as_name(&index.index) {
+                    self.token(".");
+                    self.token(name);
+                    return;
+                }
+
+                self.token("[");
+                self.expression(&index.index);
+                self.token("]");
+            }
+            Variable::TableMember(member) => {
+                self.prefix(&member.base);
+                self.token(".");
+                self.token(&member.member);
+            }
+            Variable::TableMethod(method) => {
+                self.prefix(&method.base);
+                self.token(":");
+                self.token(&method.method);
+            }
+        }
+    }
+
+    fn call(&mut self, callee: &Expression, arguments: &[Expression]) {
+        self.prefix(callee);
+
+        // a lone string or table argument needs no parentheses
+        if let [argument] = arguments {
+            if let ExpressionKind::LiteralString(_) | ExpressionKind::TableConstructor(_) =
+                argument.kind
+            {
+                self.expression(argument);
+                return;
+            }
+        }
+
+        self.token("(");
+        self.list(arguments, Self::expression);
+        self.token(")");
+    }
+
+    fn prefix(&mut self, exp: &Expression) {
+        self.expression_parenthesized(exp, needs_parentheses_prefix(exp));
+    }
+
+    fn expression_parenthesized(&mut self, exp: &Expression, parenthesized: bool) {
+        if parenthesized {
+            self.token("(");
+            self.expression(exp);
+            self.token(")");
+        } else {
+            self.expression(exp);
+        }
+    }
+
+    fn expression(&mut self, exp: &Expression) {
+        if let Some((operator, a, b)) = binary_operator(&exp.kind) {
+            let (left, right) = binary_priority(&exp.kind).unwrap();
+
+            self.expression_parenthesized(a, needs_parentheses_left(left, a));
+            self.token(operator);
+            self.expression_parenthesized(b, needs_parentheses_right(right, b));
+
+            return;
+        }
+
+        if let Some((operator, operand)) = unary_operator(&exp.kind) {
+            self.token(operator);
+            self.expression_parenthesized(operand, needs_parentheses_unary(operand));
+
+            return;
+        }
+
+        match &exp.kind {
+            ExpressionKind::LiteralInteger(number) => self.number(&shortest_integer(*number)),
+            ExpressionKind::LiteralFloat(number) => self.number(&shortest_float(*number)),
+            ExpressionKind::LiteralString(string) => self.token(&shortest_string(string)),
+            ExpressionKind::True => self.token("true"),
+            ExpressionKind::False => self.token("false"),
+            ExpressionKind::Nil => self.token("nil"),
+            ExpressionKind::VariableArgument => self.token("..."),
+            ExpressionKind::TableConstructor(fields) => {
+                self.token("{");
+                self.list(fields, Self::table_field);
+                self.token("}");
+            }
+            ExpressionKind::FunctionCall(call) => self.call(&call.callee, &call.arguments),
+            ExpressionKind::AnonFunctionDefinition(func) => {
+                self.token("function");
+                self.function_body(&func.parameter_list, &func.block);
+            }
+            ExpressionKind::Variable(var) => self.variable(var),
+            ExpressionKind::If(exp) => {
+                self.token("if");
+                self.expression(&exp.condition);
+                self.token("then");
+                self.expression(&exp.expression);
+
+                for elseif in exp.elseif_expressions.iter() {
+                    self.token("elseif");
+                    self.expression(&elseif.condition);
+                    self.token("then");
+                    self.expression(&elseif.expression);
+                }
+
+                self.token("else");
+                self.expression(&exp.else_expression);
+            }
+            ExpressionKind::InterpolatedString(string) => self.interpolated_string(string),
+            ExpressionKind::TypeAssertion(exp, _) => {
+                self.expression_parenthesized(exp, needs_parentheses_assertion(exp))
+            }
+            ExpressionKind::Parenthesized(exp) => {
+                self.token("(");
+                self.expression(exp);
+                self.token(")");
+            }
+            _ => unreachable!("operators are handled above"),
+        }
+    }
+
+    fn table_field(&mut self, field: &TableField) {
+        match field {
+            TableField::Value(value) => self.expression(value),
+            TableField::IndexValue(index, value) if as_name(index).is_some() => {
+                self.token(as_name(index).unwrap());
+                self.token("=");
+                self.expression(value);
+            }
+            TableField::IndexValue(index, value) => {
+                self.token("[");
+                self.expression(index);
+                self.token("]");
+                self.token("=");
+                self.expression(value);
+            }
+            TableField::KeyValue(key, value) => {
+                self.token(key);
+                self.token("=");
+                self.expression(value);
+            }
+        }
+    }
+
+    fn interpolated_string(&mut self, string: &InterpolatedString) {
+        let mut literal = String::from("`");
+
+        for segment in string.segments.iter() {
+            write_escaped(&mut literal, &segment.literal, &['`', '{', '}']);
+            literal.push('{');
+            self.token(&literal);
+
+            // `{{` is rejected as an attempt at escaping the brace
+            if let ExpressionKind::TableConstructor(_) = segment.expression.kind {
+                self.output.push(' ');
+            }
+
+            self.expression(&segment.expression);
+            literal = String::from("}");
+        }
+
+        write_escaped(&mut literal, &string.last, &['`', '{', '}']);
+        literal.push('`');
+        self.token(&literal);
+    }
+}
+
+fn shortest_integer(number: i64) -> String {
+    if number == i64::MIN {
+        return "0x8000000000000000".to_string();
+    }
+
+    let decimal = number.to_string();
+
+    if number < 0 {
+        return decimal;
+    }
+
+    let hexadecimal = format!("0x{number:x}");
+
+    if hexadecimal.len() < decimal.len() {
+        hexadecimal
+    } else {
+        decimal
+    }
+}
+
+/// Writes a float in the fewest characters that still read back as the same
+/// float, e.g. `.5`, `1.` or `15e-8`.
+fn shortest_float(number: f64) -> String {
+    if number.is_nan() {
+        return "(0/0)".to_string();
+    }
+
+    if number.is_infinite() {
+        return if number < 0.0 { "-1e999" } else { "1e999" }.to_string();
+    }
+
+    let sign = if number.is_sign_negative() { "-" } else { "" };
+
+    // the shortest digits that round trip, as in `1.2345e-7`
+    let scientific = format!("{:e}", number.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let digits = mantissa.replace('.', "");
+    let last = digits.len() as i32 - 1;
+
+    let positional = if exponent >= last {
+        format!("{digits}{}.", "0".repeat((exponent - last) as usize))
+    } else if exponent >= 0 {
+        let (integral, fractional) = digits.split_at(exponent as usize + 1);
+        format!("{integral}.{fractional}")
+    } else {
+        format!(".{}{digits}", "0".repeat((-exponent - 1) as usize))
+    };
+
+    let shortest = [
+        positional,
+        format!("{mantissa}e{exponent}"),
+        format!("{digits}e{}", exponent - last),
+    ]
+    .into_iter()
+    .min_by_key(String::len)
+    .unwrap();
+
+    format!("{sign}{shortest}")
+}
+
+fn shortest_string(string: &LuaString) -> String {
+    let quoted = |quote: char| {
+        let mut literal = String::from(quote);
+        write_escaped(&mut literal, string, &[quote]);
+        literal.push(quote);
+        literal
+    };
+
+    let mut candidates = vec![quoted('"'), quoted('\'')];
+
+    if let Some(literal) = long_bracket(string) {
+        candidates.push(literal);
+    }
+
+    candidates.into_iter().min_by_key(String::len).unwrap()
+}
+
+/// Writes a string between long brackets, if it survives being read back. Line
+/// breaks other than `\n` would be normalized, and the text has to be valid
+/// UTF-8 to be written out at all.
+fn long_bracket(string: &LuaString) -> Option<String> {
+    let text = std::str::from_utf8(string.as_bytes()).ok()?;
+
+    if text.contains('\r') {
+        return None;
+    }
+
+    // the closing bracket must not appear in the text, including at its end
+    let terminated = format!("{text}]");
+    let level = (0..)
+        .find(|level| !terminated.contains(&format!("]{}]", "=".repeat(*level))))
+        .unwrap();
+    let equals = "=".repeat(level);
+
+    // a line break right after the opening bracket is skipped
+    let newline = if text.starts_with('\n') { "\n" } else { "" };
+
+    Some(format!("[{equals}[{newline}{text}]{equals}]"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{
+        ast::{luau, visitor::VisitorMut},
+        parse,
+    };
+
+    use super::{minify, rename_locals, ParenthesesRemover};
+
+    /// Asserts that the minified source parses back to the tree the minifier
+    /// printed, and returns it.
+    fn round_trip(source: &str) -> String {
+        let chunk = parse(source).unwrap();
+        let minified = minify(&chunk);
+
+        let mut expected = chunk;
+        luau::strip_types(&mut expected);
+        ParenthesesRemover.visit_chunk_mut(&mut expected);
+        rename_locals(&mut expected);
+
+        // the parentheses the writer needed come back as nodes of their own
+        let mut reparsed = parse(&minified).unwrap();
+        ParenthesesRemover.visit_chunk_mut(&mut reparsed);
+
+        assert_eq!(reparsed, expected, "minified as:\n{minified}");
+
+        minified
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            round_trip("x = (a + b) * c .. d ^ (e ^ f)"),
+            "x=(a+b)*c..d^e^f"
+        );
+        assert_eq!(round_trip("x = (a .. b) .. c"), "x=(a..b)..c");
+        assert_eq!(round_trip("x = -(-1) y = -a ^ 2"), "x=- -1 y=-a^2");
+        assert_eq!(round_trip("x = (...) y = (f())"), "x=(...)y=(f())");
+    }
+
+    #[test]
+    fn statements() {
+        round_trip(
+            "local t = { 1, 2, x = 3, ['y z'] = [[long]] }
+            for k, v in pairs(t) do print(k, v) end
+            while t[1] do t[1] = nil end
+            repeat local done = true until done
+            if a then b() elseif c then d() else e() end",
+        );
+
+        // string keys that are names are written as fields
+        assert_eq!(minify(&parse("t = { ['y'] = 1 }").unwrap()), "t={y=1}");
+    }
+
+    #[test]
+    fn renamed_locals_skip_globals() {
+        assert_eq!(
+            round_trip("local x = 1 print(a, b, x)"),
+            "local c=1 print(a,b,c)"
+        );
+        assert_eq!(
+            round_trip("local a = a local b = function() return a, c end"),
+            "local b=a local d=function()return b,c end"
+        );
+    }
+
+    #[test]
+    fn locals_shadowing_globals() {
+        assert_eq!(
+            round_trip("local print = print print(x)"),
+            "local a=print a(x)"
+        );
+        assert_eq!(
+            round_trip("for i = 1, 10 do local a = i end print(i, a)"),
+            "for b=1,10 do local c=b end print(i,a)"
+        );
+        assert_eq!(
+            round_trip("local f = function(n) return f end"),
+            "local a=function(a)return f end"
+        );
+    }
+
+    #[test]
+    fn nested_scopes() {
+        assert_eq!(
+            round_trip("local x = 1 do local x = x + 1 y = x end z = x"),
+            "local a=1 do local b=a+1 y=b end z=a"
+        );
+        assert_eq!(
+            round_trip("local function f(n) if n > 0 then return f(n - 1) end end"),
+            "local function a(b)if b>0 then return a(b-1)end end"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Conversion of syntax trees back into Lua source code.
+
+use std::fmt::Write;
+
+use crate::parser::ast::definition::{Expression, ExpressionKind, LuaString};
+
+pub mod lua;
+pub mod minify;
+
+/// Binding power of the unary operators, between the arithmetic operators and
+/// exponentiation.
+const UNARY_PRIORITY: u8 = 12;
+
+/// Left and right binding power of a binary operator, taken from the reference
+/// parser. Right associative operators bind weaker on their right side.
+fn binary_priority(kind: &ExpressionKind) -> Option<(u8, u8)> {
+    let priority = match kind {
+        ExpressionKind::Or(..) => (1, 1),
+        ExpressionKind::And(..) => (2, 2),
+        ExpressionKind::LessThan(..)
+        | ExpressionKind::GreaterThan(..)
+        | ExpressionKind::LessThanOrEqual(..)
+        | ExpressionKind::GreaterThanOrEqual(..)
+        | ExpressionKind::NotEqual(..)
+        | ExpressionKind::Equal(..) => (3, 3),
+        ExpressionKind::BitwiseOr(..) => (4, 4),
+        ExpressionKind::BitwiseXor(..) => (5, 5),
+        ExpressionKind::BitwiseAnd(..) => (6, 6),
+        ExpressionKind::ShiftLeft(..) | ExpressionKind::ShiftRight(..) => (7, 7),
+        ExpressionKind::Concatenation(..) => (9, 8),
+        ExpressionKind::Addition(..) | ExpressionKind::Subtraction(..) => (10, 10),
+        ExpressionKind::Multiplication(..)
+        | ExpressionKind::Division(..)
+        | ExpressionKind::FloorDivision(..)
+        | ExpressionKind::Modulo(..) => (11, 11),
+        ExpressionKind::Exponentiation(..) => (14, 13),
+        _ => return None,
+    };
+
+    Some(priority)
+}
+
+fn binary_operator(kind: &ExpressionKind) -> Option<(&'static str, &Expression, &Expression)> {
+    let (operator, a, b) = match kind {
+        ExpressionKind::Exponentiation(a, b) => ("^", a, b),
+        ExpressionKind::Multiplication(a, b) => ("*", a, b),
+        ExpressionKind::Division(a, b) => ("/", a, b),
+        ExpressionKind::FloorDivision(a, b) => ("//", a, b),
+        ExpressionKind::Modulo(a, b) => ("%", a, b),
+        ExpressionKind::Addition(a, b) => ("+", a, b),
+        ExpressionKind::Subtraction(a, b) => ("-", a, b),
+        ExpressionKind::Concatenation(a, b) => ("..", a, b),
+        ExpressionKind::ShiftLeft(a, b) => ("<<", a, b),
+        ExpressionKind::ShiftRight(a, b) => (">>", a, b),
+        ExpressionKind::BitwiseAnd(a, b) => ("&", a, b),
+        ExpressionKind::BitwiseXor(a, b) => ("~", a, b),
+        ExpressionKind::BitwiseOr(a, b) => ("|", a, b),
+        ExpressionKind::LessThan(a, b) => ("<", a, b),
+        ExpressionKind::GreaterThan(a, b) => (">", a, b),
+        ExpressionKind::LessThanOrEqual(a, b) => ("<=", a, b),
+        ExpressionKind::GreaterThanOrEqual(a, b) => (">=", a, b),
+        ExpressionKind::NotEqual(a, b) => ("~=", a, b),
+        ExpressionKind::Equal(a, b) => ("==", a, b),
+        ExpressionKind::And(a, b) => ("and", a, b),
+        ExpressionKind::Or(a, b) => ("or", a, b),
+        _ => return None,
+    };
+
+    Some((operator, a, b))
+}
+
+fn unary_operator(kind: &ExpressionKind) -> Option<(&'static str, &Expression)> {
+    match kind {
+        ExpressionKind::Not(exp) => Some(("not", exp)),
+        ExpressionKind::Negative(exp) => Some(("-", exp)),
+        ExpressionKind::BitwiseNot(exp) => Some(("~", exp)),
+        ExpressionKind::Length(exp) => Some(("#", exp)),
+        _ => None,
+    }
+}
+
+/// Negative floats only come out of later passes, and read back as a unary
+/// minus applied to the literal. Negative integers are written in hexadecimal,
+/// see [`write_integer`], so they never start with a minus.
+fn is_negative_number(kind: &ExpressionKind) -> bool {
+    match kind {
+        ExpressionKind::LiteralFloat(number) => number.is_sign_negative() && !number.is_nan(),
+        _ => false,
+    }
+}
+
+/// The weakest binding power that still extends the expression to the right,
+/// e.g. `a + -b` is closed off by any operator binding weaker than `+`, while
+/// an if-expression absorbs every operator that follows it.
+fn right_priority(exp: &Expression) -> u8 {
+    if let Some((_, right)) = binary_priority(&exp.kind) {
+        let (_, _, b) = binary_operator(&exp.kind).unwrap();
+
+        if needs_parentheses_right(right, b) {
+            return right;
+        }
+
+        return right.min(right_priority(b));
+    }
+
+    if let Some((_, operand)) = unary_operator(&exp.kind) {
+        if needs_parentheses_unary(operand) {
+            return UNARY_PRIORITY;
+        }
+
+        return UNARY_PRIORITY.min(right_priority(operand));
+    }
+
+    match &exp.kind {
+        _ if is_negative_number(&exp.kind) => UNARY_PRIORITY,
+        ExpressionKind::If(_) => 0,
+        _ => u8::MAX,
+    }
+}
+
+fn needs_parentheses_left(left: u8, operand: &Expression) -> bool {
+    right_priority(operand) < left
+}
+
+fn needs_parentheses_right(right: u8, operand: &Expression) -> bool {
+    binary_priority(&operand.kind).is_some_and(|(left, _)| left <= right)
+}
+
+fn needs_parentheses_unary(operand: &Expression) -> bool {
+    binary_priority(&operand.kind).is_some_and(|(left, _)| left <= UNARY_PRIORITY)
+}
+
+/// Only variables, calls and parenthesized expressions may be called or
+/// indexed directly.
+fn needs_parentheses_prefix(exp: &Expression) -> bool {
+    !matches!(
+        exp.kind,
+        ExpressionKind::Variable(_)
+            | ExpressionKind::FunctionCall(_)
+            | ExpressionKind::Parenthesized(_)
+    )
+}
+
+/// A type assertion applies to a single simple expression.
+fn needs_parentheses_assertion(exp: &Expression) -> bool {
+    binary_priority(&exp.kind).is_some()
+        || unary_operator(&exp.kind).is_some()
+        || is_negative_number(&exp.kind)
+        || matches!(exp.kind, ExpressionKind::If(_))
+}
+
+/// Writes negative integers as the hexadecimal literal that wraps around to
+/// them, e.g. `0xffffffffffffffff` for `-1`. Such literals are how negative
+/// integers come out of the parser, and `-1` would read back as a unary minus
+/// applied to `1`.
+fn write_integer(output: &mut String, number: i64) {
+    if number < 0 {
+        write!(output, "0x{:x}", number as u64).unwrap();
+    } else {
+        write!(output, "{number}").unwrap();
+    }
+}
+
+fn write_float(output: &mut String, number: f64) {
+    if number.is_nan() {
+        output.push_str("(0/0)");
+    } else if number.is_infinite() {
+        output.push_str(if number < 0.0 { "-1e9999" } else { "1e9999" });
+    } else {
+        write!(output, "{number:?}").unwrap();
+    }
+}
+
+fn write_string(output: &mut String, string: &LuaString) {
+    output.push('"');
+    write_escaped(output, string, &['"']);
+    output.push('"');
+}
+
+/// Writes the contents of a string literal, escaping the given delimiters,
+/// backslashes and control characters. Invalid UTF-8 is written as decimal
+/// escapes so the bytes survive unchanged.
+fn write_escaped(output: &mut String, string: &LuaString, delimiters: &[char]) {
+    for chunk in string.0.utf8_chunks() {
+        let mut chars = chunk.valid().chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => output.push_str("\\\\"),
+                c if delimiters.contains(&c) => {
+                    output.push('\\');
+                    output.push(c);
+                }
+                '\n' => output.push_str("\\n"),
+                '\r' => output.push_str("\\r"),
+                '\t' => output.push_str("\\t"),
+                // a following digit would otherwise become part of the escape
+                c if c.is_ascii_control() => match chars.peek() {
+                    Some('0'..='9') => write!(output, "\\{:03}", c as u8).unwrap(),
+                    _ => write!(output, "\\{}", c as u8).unwrap(),
+                },
+                c => output.push(c),
+            }
+        }
+
+        for byte in chunk.invalid() {
+            write!(output, "\\{byte:03}").unwrap();
+        }
+    }
+}
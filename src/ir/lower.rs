@@ -0,0 +1,1164 @@
+use std::collections::{HashMap, HashSet};
+
+use petgraph::{stable_graph::NodeIndex, visit::EdgeRef, Direction};
+
+use crate::{
+    cfg::{
+        analysis,
+        program::{self, Program},
+        translator::Options,
+        BasicBlock, CFGEdge, CFGNode, Cfg, Error,
+    },
+    parser::ast::definition::{
+        AssignmentStatement, Chunk, CompoundAssignmentStatement, CompoundOperator, Expression,
+        ExpressionKind, FunctionCallExpression, FunctionCallStatement, FunctionId, Identifier,
+        IfExpression, InterpolatedString, LastStatementKind, LocalDeclarationStatement, LuaString,
+        Parameter, Statement, StatementKind, TableField, Variable,
+    },
+};
+
+use super::{
+    BinaryOperator, Block, Comparison, Constant, Function, Instruction, Label, Module, Operand,
+    Register, Results, Terminator, UnaryOperator, Upvalue, Values,
+};
+
+/// Where an assignment stores its value.
+enum Place {
+    Register(Register),
+    Upvalue(Upvalue),
+    Global(Identifier),
+    Table(Operand, Operand),
+}
+
+struct Lowerer<'a> {
+    program: &'a Program,
+    /// The functions nested in this one, which are lowered first.
+    lowered: &'a [Option<Function>],
+    /// The locals declared in every function.
+    declared: &'a HashMap<NodeIndex, HashSet<Identifier>>,
+    /// The locals that closures capture, which live in cells.
+    captured: &'a mut HashSet<Identifier>,
+    function: NodeIndex,
+    locals: HashMap<Identifier, Register>,
+    upvalues: Vec<Identifier>,
+    registers: usize,
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    /// The block instructions are appended to.
+    current: usize,
+    labels: HashMap<NodeIndex, Label>,
+}
+
+impl Lowerer<'_> {
+    fn temporary(&mut self) -> Register {
+        let register = Register(self.registers);
+        self.registers += 1;
+
+        register
+    }
+
+    fn local(&mut self, name: &Identifier) -> Register {
+        if let Some(&register) = self.locals.get(name) {
+            return register;
+        }
+
+        let register = self.temporary();
+        self.locals.insert(name.clone(), register);
+
+        register
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.blocks[self.current].0.push(instruction);
+    }
+
+    fn new_block(&mut self) -> Label {
+        self.blocks.push((vec![], None));
+
+        Label(self.blocks.len() - 1)
+    }
+
+    /// Ends the current block and continues in `next`.
+    fn terminate(&mut self, terminator: Terminator, next: Label) {
+        self.blocks[self.current].1 = Some(terminator);
+        self.current = next.0;
+    }
+
+    /// The block a node of the graph starts in. The exit gets a block that
+    /// only returns once something jumps to it.
+    fn label(&mut self, node: NodeIndex) -> Label {
+        if let Some(&label) = self.labels.get(&node) {
+            return label;
+        }
+
+        let label = self.new_block();
+        self.blocks[label.0].1 = Some(Terminator::Return(Values::default()));
+        self.labels.insert(node, label);
+
+        label
+    }
+
+    /// Finds a name that is not a local of this function among the locals of
+    /// the enclosing ones.
+    fn upvalue(&mut self, name: &Identifier) -> Option<usize> {
+        if let Some(index) = self.upvalues.iter().position(|upvalue| upvalue == name) {
+            return Some(index);
+        }
+
+        let mut function = self.program.call_graph[self.function].parent;
+
+        while let Some(parent) = function {
+            if self.declared[&parent].contains(name) {
+                self.captured.insert(name.clone());
+                self.upvalues.push(name.clone());
+
+                return Some(self.upvalues.len() - 1);
+            }
+
+            function = self.program.call_graph[parent].parent;
+        }
+
+        None
+    }
+
+    fn is_local(&self, name: &Identifier) -> bool {
+        self.declared[&self.function].contains(name)
+    }
+
+    fn name(&mut self, name: &Identifier) -> Place {
+        if self.is_local(name) {
+            let register = self.local(name);
+
+            return match self.captured.contains(name) {
+                true => Place::Upvalue(Upvalue::Cell(register)),
+                false => Place::Register(register),
+            };
+        }
+
+        match self.upvalue(name) {
+            Some(index) => Place::Upvalue(Upvalue::Captured(index)),
+            None => Place::Global(name.clone()),
+        }
+    }
+
+    fn place(&mut self, var: &Variable) -> Place {
+        let (base, key) = match var {
+            Variable::Identifier(identifier) => return self.name(identifier),
+            Variable::TableIndex(index) => {
+                let base = self.expression(&index.base);
+                (base, self.expression(&index.index))
+            }
+            Variable::TableMember(member) => {
+                (self.expression(&member.base), string(&member.member))
+            }
+            Variable::TableMethod(method) => {
+                (self.expression(&method.base), string(&method.method))
+            }
+        };
+
+        Place::Table(base, key)
+    }
+
+    fn load(&mut self, place: Place) -> Operand {
+        let target = match place {
+            Place::Register(register) => return Operand::Register(register),
+            Place::Upvalue(upvalue) => {
+                let target = self.temporary();
+                self.emit(Instruction::GetUpvalue { target, upvalue });
+                target
+            }
+            Place::Global(name) => {
+                let target = self.temporary();
+                self.emit(Instruction::GetGlobal { target, name });
+                target
+            }
+            Place::Table(table, key) => {
+                let target = self.temporary();
+                self.emit(Instruction::GetTable { target, table, key });
+                target
+            }
+        };
+
+        Operand::Register(target)
+    }
+
+    fn store(&mut self, place: Place, value: Operand) {
+        self.emit(match place {
+            Place::Register(target) => Instruction::Move {
+                target,
+                source: value,
+            },
+            Place::Upvalue(upvalue) => Instruction::SetUpvalue { upvalue, value },
+            Place::Global(name) => Instruction::SetGlobal { name, value },
+            Place::Table(table, key) => Instruction::SetTable { table, key, value },
+        });
+    }
+
+    /// Copies the value of a local into a temporary, so that it survives
+    /// later writes to the local.
+    fn snapshot(&mut self, operand: Operand) -> Operand {
+        match operand {
+            Operand::Register(register) if self.locals.values().any(|&r| r == register) => {
+                let target = self.temporary();
+                self.emit(Instruction::Move {
+                    target,
+                    source: operand,
+                });
+
+                Operand::Register(target)
+            }
+            operand => operand,
+        }
+    }
+
+    fn declare(&mut self, name: &Identifier, value: Operand) {
+        let register = self.local(name);
+
+        if self.captured.contains(name) {
+            self.emit(Instruction::NewCell { target: register });
+            self.emit(Instruction::SetUpvalue {
+                upvalue: Upvalue::Cell(register),
+                value,
+            });
+        } else {
+            self.emit(Instruction::Move {
+                target: register,
+                source: value,
+            });
+        }
+    }
+
+    fn closure(&mut self, target: Register, id: Option<FunctionId>) {
+        let function = id
+            .and_then(|id| self.program.function(id))
+            .expect("every function is numbered when the program is built");
+        let names = self.lowered[function.index()]
+            .as_ref()
+            .expect("nested functions are lowered first")
+            .upvalues
+            .clone();
+
+        let upvalues = names
+            .iter()
+            .map(|name| match self.name(name) {
+                Place::Upvalue(upvalue) => upvalue,
+                _ => unreachable!("a captured local lives in a cell"),
+            })
+            .collect();
+
+        self.emit(Instruction::Closure {
+            target,
+            function: function.index(),
+            upvalues,
+        });
+    }
+
+    fn call(&mut self, callee: &Expression, arguments: &[Expression], results: Results) {
+        let (function, object) = match &callee.kind {
+            // the object is evaluated once, and passed as the first argument
+            ExpressionKind::Variable(Variable::TableMethod(method)) => {
+                let object = self.expression(&method.base);
+                let function = self.load(Place::Table(object.clone(), string(&method.method)));
+
+                (function, Some(object))
+            }
+            _ => (self.expression(callee), None),
+        };
+
+        let mut arguments = self.values(arguments);
+
+        if let Some(object) = object {
+            arguments.fixed.insert(0, object);
+        }
+
+        self.emit(Instruction::Call {
+            function,
+            arguments,
+            results,
+        });
+    }
+
+    /// Evaluates an expression that may produce any number of values into
+    /// `results`. Returns false for expressions with a single value.
+    fn results(&mut self, exp: &Expression, results: Results) -> bool {
+        match &exp.kind {
+            ExpressionKind::FunctionCall(FunctionCallExpression { callee, arguments }) => {
+                self.call(callee, arguments, results);
+                true
+            }
+            ExpressionKind::VariableArgument => {
+                self.emit(Instruction::VarArg { results });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn is_multiple(exp: &Expression) -> bool {
+        matches!(
+            exp.kind,
+            ExpressionKind::FunctionCall(_) | ExpressionKind::VariableArgument
+        )
+    }
+
+    /// Evaluates a list of expressions, the last of which contributes all of
+    /// its values.
+    fn values(&mut self, exps: &[Expression]) -> Values {
+        let mut values = Values::default();
+
+        for (i, exp) in exps.iter().enumerate() {
+            if i + 1 == exps.len() && Self::is_multiple(exp) {
+                let rest = self.temporary();
+                self.results(exp, Results::All(rest));
+                values.rest = Some(rest);
+            } else {
+                let operand = self.expression(exp);
+                values.fixed.push(operand);
+            }
+        }
+
+        values
+    }
+
+    /// Evaluates a list of expressions into exactly `count` values, dropping
+    /// extra values and filling missing ones with nil.
+    fn adjust(&mut self, exps: &[Expression], count: usize) -> Vec<Operand> {
+        let mut operands = vec![];
+
+        for (i, exp) in exps.iter().enumerate() {
+            let missing = count.saturating_sub(operands.len());
+
+            if Self::is_multiple(exp) && (i + 1 == exps.len() || missing == 0) {
+                let registers = match i + 1 == exps.len() {
+                    true => (0..missing).map(|_| self.temporary()).collect::<Vec<_>>(),
+                    false => vec![self.temporary()],
+                };
+
+                self.results(exp, Results::Fixed(registers.clone()));
+                operands.extend(registers.into_iter().map(Operand::Register));
+            } else {
+                let operand = self.expression(exp);
+                operands.push(operand);
+            }
+        }
+
+        operands.resize(count, Operand::Constant(Constant::Nil));
+        operands
+    }
+
+    fn unary(&mut self, operator: UnaryOperator, exp: &Expression) -> Operand {
+        let operand = self.expression(exp);
+        let target = self.temporary();
+
+        self.emit(Instruction::Unary {
+            target,
+            operator,
+            operand,
+        });
+
+        Operand::Register(target)
+    }
+
+    fn binary(&mut self, operator: BinaryOperator, left: Operand, right: Operand) -> Operand {
+        let target = self.temporary();
+
+        self.emit(Instruction::Binary {
+            target,
+            operator,
+            left,
+            right,
+        });
+
+        Operand::Register(target)
+    }
+
+    fn compare(&mut self, operator: Comparison, a: &Expression, b: &Expression) -> Operand {
+        let left = self.expression(a);
+        let right = self.expression(b);
+        let target = self.temporary();
+
+        self.emit(Instruction::Compare {
+            target,
+            operator,
+            left,
+            right,
+        });
+
+        Operand::Register(target)
+    }
+
+    /// `a and b` and `a or b` only evaluate `b` when `a` does not already
+    /// decide the value.
+    fn short_circuit(&mut self, a: &Expression, b: &Expression, and: bool) -> Operand {
+        let target = self.temporary();
+        let source = self.expression(a);
+        self.emit(Instruction::Move { target, source });
+
+        let right = self.new_block();
+        let after = self.new_block();
+        let (then, otherwise) = match and {
+            true => (right, after),
+            false => (after, right),
+        };
+
+        let condition = Operand::Register(target);
+        self.terminate(
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            },
+            right,
+        );
+
+        let source = self.expression(b);
+        self.emit(Instruction::Move { target, source });
+        self.terminate(Terminator::Jump(after), after);
+
+        Operand::Register(target)
+    }
+
+    fn if_expression(&mut self, exp: &IfExpression) -> Operand {
+        let target = self.temporary();
+        let after = self.new_block();
+
+        let branches = std::iter::once((exp.condition.as_ref(), exp.expression.as_ref())).chain(
+            exp.elseif_expressions
+                .iter()
+                .map(|elseif| (&elseif.condition, &elseif.expression)),
+        );
+
+        for (condition, value) in branches {
+            let condition = self.expression(condition);
+            let then = self.new_block();
+            let otherwise = self.new_block();
+
+            self.terminate(
+                Terminator::Branch {
+                    condition,
+                    then,
+                    otherwise,
+                },
+                then,
+            );
+
+            let source = self.expression(value);
+            self.emit(Instruction::Move { target, source });
+            self.terminate(Terminator::Jump(after), otherwise);
+        }
+
+        let source = self.expression(&exp.else_expression);
+        self.emit(Instruction::Move { target, source });
+        self.terminate(Terminator::Jump(after), after);
+
+        Operand::Register(target)
+    }
+
+    /// Concatenates the literal parts with every value converted by the
+    /// global `tostring`.
+    fn interpolated_string(&mut self, string: &InterpolatedString) -> Operand {
+        let mut parts = vec![];
+
+        for segment in string.segments.iter() {
+            if !segment.literal.as_bytes().is_empty() {
+                parts.push(Operand::Constant(Constant::String(segment.literal.clone())));
+            }
+
+            let value = self.expression(&segment.expression);
+            let function = self.load(Place::Global("tostring".to_string()));
+            let target = self.temporary();
+
+            self.emit(Instruction::Call {
+                function,
+                arguments: Values {
+                    fixed: vec![value],
+                    rest: None,
+                },
+                results: Results::Fixed(vec![target]),
+            });
+
+            parts.push(Operand::Register(target));
+        }
+
+        if !string.last.as_bytes().is_empty() || parts.is_empty() {
+            parts.push(Operand::Constant(Constant::String(string.last.clone())));
+        }
+
+        parts
+            .into_iter()
+            .reduce(|left, right| self.binary(BinaryOperator::Concatenate, left, right))
+            .unwrap()
+    }
+
+    fn table(&mut self, fields: &[TableField]) -> Operand {
+        let table = self.temporary();
+        self.emit(Instruction::NewTable { target: table });
+
+        let mut index = 1;
+
+        for (i, field) in fields.iter().enumerate() {
+            let (key, value) = match field {
+                TableField::Value(exp) if i + 1 == fields.len() && Self::is_multiple(exp) => {
+                    let values = self.temporary();
+                    self.results(exp, Results::All(values));
+                    self.emit(Instruction::SetList {
+                        table,
+                        start: index,
+                        values,
+                    });
+
+                    continue;
+                }
+                TableField::Value(exp) => {
+                    let key = Operand::Constant(Constant::Integer(index));
+                    index += 1;
+
+                    (key, self.expression(exp))
+                }
+                TableField::KeyValue(name, exp) => (string(name), self.expression(exp)),
+                TableField::IndexValue(key, exp) => {
+                    let key = self.expression(key);
+                    (key, self.expression(exp))
+                }
+            };
+
+            self.emit(Instruction::SetTable {
+                table: Operand::Register(table),
+                key,
+                value,
+            });
+        }
+
+        Operand::Register(table)
+    }
+
+    /// Evaluates an expression to a single value.
+    fn expression(&mut self, exp: &Expression) -> Operand {
+        use ExpressionKind as E;
+
+        let constant = |constant| Operand::Constant(constant);
+
+        match &exp.kind {
+            E::LiteralInteger(value) => constant(Constant::Integer(*value)),
+            E::LiteralFloat(value) => constant(Constant::Float(*value)),
+            E::LiteralString(value) => constant(Constant::String(value.clone())),
+            E::True => constant(Constant::Boolean(true)),
+            E::False => constant(Constant::Boolean(false)),
+            E::Nil => constant(Constant::Nil),
+            E::TableConstructor(fields) => self.table(fields),
+            E::FunctionCall(_) | E::VariableArgument => {
+                let target = self.temporary();
+                self.results(exp, Results::Fixed(vec![target]));
+
+                Operand::Register(target)
+            }
+            E::AnonFunctionDefinition(func) => {
+                let target = self.temporary();
+                self.closure(target, func.id);
+
+                Operand::Register(target)
+            }
+            E::Variable(var) => {
+                let place = self.place(var);
+                self.load(place)
+            }
+            E::If(exp) => self.if_expression(exp),
+            E::InterpolatedString(string) => self.interpolated_string(string),
+            E::TypeAssertion(exp, _) | E::Parenthesized(exp) => self.expression(exp),
+
+            E::Not(exp) => self.unary(UnaryOperator::Not, exp),
+            E::Negative(exp) => self.unary(UnaryOperator::Negate, exp),
+            E::BitwiseNot(exp) => self.unary(UnaryOperator::BitwiseNot, exp),
+            E::Length(exp) => self.unary(UnaryOperator::Length, exp),
+
+            E::LessThan(a, b) => self.compare(Comparison::LessThan, a, b),
+            E::GreaterThan(a, b) => self.compare(Comparison::GreaterThan, a, b),
+            E::LessThanOrEqual(a, b) => self.compare(Comparison::LessThanOrEqual, a, b),
+            E::GreaterThanOrEqual(a, b) => self.compare(Comparison::GreaterThanOrEqual, a, b),
+            E::NotEqual(a, b) => self.compare(Comparison::NotEqual, a, b),
+            E::Equal(a, b) => self.compare(Comparison::Equal, a, b),
+
+            E::And(a, b) => self.short_circuit(a, b, true),
+            E::Or(a, b) => self.short_circuit(a, b, false),
+
+            E::Exponentiation(a, b)
+            | E::Multiplication(a, b)
+            | E::Division(a, b)
+            | E::FloorDivision(a, b)
+            | E::Modulo(a, b)
+            | E::Addition(a, b)
+            | E::Subtraction(a, b)
+            | E::Concatenation(a, b)
+            | E::ShiftLeft(a, b)
+            | E::ShiftRight(a, b)
+            | E::BitwiseAnd(a, b)
+            | E::BitwiseXor(a, b)
+            | E::BitwiseOr(a, b) => {
+                let operator = match &exp.kind {
+                    E::Exponentiation(..) => BinaryOperator::Power,
+                    E::Multiplication(..) => BinaryOperator::Multiply,
+                    E::Division(..) => BinaryOperator::Divide,
+                    E::FloorDivision(..) => BinaryOperator::FloorDivide,
+                    E::Modulo(..) => BinaryOperator::Modulo,
+                    E::Addition(..) => BinaryOperator::Add,
+                    E::Subtraction(..) => BinaryOperator::Subtract,
+                    E::Concatenation(..) => BinaryOperator::Concatenate,
+                    E::ShiftLeft(..) => BinaryOperator::ShiftLeft,
+                    E::ShiftRight(..) => BinaryOperator::ShiftRight,
+                    E::BitwiseAnd(..) => BinaryOperator::BitwiseAnd,
+                    E::BitwiseXor(..) => BinaryOperator::BitwiseXor,
+                    _ => BinaryOperator::BitwiseOr,
+                };
+
+                let left = self.expression(a);
+                let right = self.expression(b);
+
+                self.binary(operator, left, right)
+            }
+        }
+    }
+
+    fn local_declaration(&mut self, stmt: &LocalDeclarationStatement) {
+        let values = self.adjust(&stmt.expression_list, stmt.identifier_list.len());
+
+        for (identifier, value) in stmt.identifier_list.iter().zip(values) {
+            self.declare(identifier, value);
+        }
+    }
+
+    /// Every table and key on the left, and every value on the right, is
+    /// evaluated before the first store.
+    fn assignment(&mut self, stmt: &AssignmentStatement) {
+        let several = stmt.variable_list.len() > 1;
+        let mut places = vec![];
+
+        for var in stmt.variable_list.iter() {
+            let place = match self.place(var) {
+                Place::Table(table, key) if several => {
+                    let table = self.snapshot(table);
+                    Place::Table(table, self.snapshot(key))
+                }
+                place => place,
+            };
+
+            places.push(place);
+        }
+
+        let values = self.adjust(&stmt.expression_list, places.len());
+
+        let values = match several {
+            true => values
+                .into_iter()
+                .map(|value| self.snapshot(value))
+                .collect(),
+            false => values,
+        };
+
+        for (place, value) in places.into_iter().zip(values) {
+            self.store(place, value);
+        }
+    }
+
+    fn compound_assignment(&mut self, stmt: &CompoundAssignmentStatement) {
+        let operator = match stmt.operator {
+            CompoundOperator::Addition => BinaryOperator::Add,
+            CompoundOperator::Subtraction => BinaryOperator::Subtract,
+            CompoundOperator::Multiplication => BinaryOperator::Multiply,
+            CompoundOperator::Division => BinaryOperator::Divide,
+            CompoundOperator::Modulo => BinaryOperator::Modulo,
+            CompoundOperator::Exponentiation => BinaryOperator::Power,
+            CompoundOperator::Concatenation => BinaryOperator::Concatenate,
+        };
+
+        // the table and key are only evaluated once
+        let (read, write) = match self.place(&stmt.variable) {
+            Place::Table(table, key) => (
+                Place::Table(table.clone(), key.clone()),
+                Place::Table(table, key),
+            ),
+            Place::Register(register) => (Place::Register(register), Place::Register(register)),
+            Place::Upvalue(upvalue) => (Place::Upvalue(upvalue), Place::Upvalue(upvalue)),
+            Place::Global(name) => (Place::Global(name.clone()), Place::Global(name)),
+        };
+
+        let left = self.load(read);
+        let right = self.expression(&stmt.expression);
+        let value = self.binary(operator, left, right);
+
+        self.store(write, value);
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::LocalDeclaration(decl) => self.local_declaration(decl),
+            StatementKind::FunctionCall(FunctionCallStatement { callee, arguments }) => {
+                self.call(callee, arguments, Results::Fixed(vec![]))
+            }
+            StatementKind::Assignment(assignment) => self.assignment(assignment),
+            StatementKind::CompoundAssignment(assignment) => self.compound_assignment(assignment),
+            StatementKind::FunctionDefinition(def) => {
+                let place = self.place(&def.identifier);
+                let target = self.temporary();
+
+                self.closure(target, def.id);
+                self.store(place, Operand::Register(target));
+            }
+            StatementKind::LocalFunctionDefinition(def) => {
+                let Variable::Identifier(identifier) = &def.identifier else {
+                    return;
+                };
+
+                let register = self.local(identifier);
+
+                // the function may refer to itself, so its cell has to exist
+                // before the closure captures it
+                if self.captured.contains(identifier) {
+                    let target = self.temporary();
+                    let upvalue = Upvalue::Cell(register);
+
+                    self.emit(Instruction::NewCell { target: register });
+                    self.closure(target, def.id);
+                    self.emit(Instruction::SetUpvalue {
+                        upvalue,
+                        value: Operand::Register(target),
+                    });
+                } else {
+                    self.closure(register, def.id);
+                }
+            }
+            // control flow is already part of the graph
+            _ => {}
+        }
+    }
+
+    /// Lowers the statements of a node, and the edges leaving it.
+    fn node(&mut self, cfg: &Cfg, node: NodeIndex) {
+        self.current = self.label(node).0;
+
+        let empty = BasicBlock::default();
+        let block = match &cfg.graph[node] {
+            CFGNode::Block(block) => block.as_ref(),
+            _ => &empty,
+        };
+
+        for stmt in block.statements.iter() {
+            self.statement(stmt);
+        }
+
+        if let Some(LastStatementKind::Return(ret)) = block.last_statement.as_ref().map(|s| &s.kind)
+        {
+            let values = self.values(&ret.expression_list);
+            self.blocks[self.current].1 = Some(Terminator::Return(values));
+            return;
+        }
+
+        let edges = cfg
+            .graph
+            .edges_directed(node, Direction::Outgoing)
+            .map(|edge| (*edge.weight(), edge.target()))
+            .collect::<Vec<_>>();
+
+        let terminator = match &block.condition {
+            Some(condition) => {
+                let condition = self.expression(condition);
+                let target = |kind| {
+                    edges
+                        .iter()
+                        .find(|(edge, _)| *edge == kind)
+                        .map(|&(_, target)| target)
+                        .expect("a test has a true and a false edge")
+                };
+                let (then, otherwise) = (target(CFGEdge::True), target(CFGEdge::False));
+
+                Terminator::Branch {
+                    condition,
+                    then: self.label(then),
+                    otherwise: self.label(otherwise),
+                }
+            }
+            None => match edges.first() {
+                Some(&(_, target)) if target != cfg.exit => Terminator::Jump(self.label(target)),
+                _ => Terminator::Return(Values::default()),
+            },
+        };
+
+        self.blocks[self.current].1 = Some(terminator);
+    }
+}
+
+fn string(name: &str) -> Operand {
+    Operand::Constant(Constant::String(LuaString::from(name)))
+}
+
+/// The parameters and locals of a function.
+fn declared(function: &program::Function) -> HashSet<Identifier> {
+    let mut declared = HashSet::new();
+
+    for param in function.parameter_list.iter() {
+        if let Parameter::Identifier(identifier) = param {
+            declared.insert(identifier.clone());
+        }
+    }
+
+    let cfg = &function.cfg;
+
+    for node in cfg.graph.node_indices() {
+        for stmt in cfg.graph[node]
+            .block()
+            .into_iter()
+            .flat_map(|b| &b.statements)
+        {
+            match &stmt.kind {
+                StatementKind::LocalDeclaration(decl) => {
+                    declared.extend(decl.identifier_list.iter().cloned())
+                }
+                StatementKind::LocalFunctionDefinition(def) => {
+                    if let Variable::Identifier(identifier) = &def.identifier {
+                        declared.insert(identifier.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    declared
+}
+
+fn lower_function(
+    program: &Program,
+    node: NodeIndex,
+    lowered: &[Option<Function>],
+    declared: &HashMap<NodeIndex, HashSet<Identifier>>,
+    captured: &mut HashSet<Identifier>,
+) -> Function {
+    let function = &program.call_graph[node];
+    let cfg = &function.cfg;
+
+    let mut lowerer = Lowerer {
+        program,
+        lowered,
+        declared,
+        captured,
+        function: node,
+        locals: HashMap::new(),
+        upvalues: vec![],
+        registers: 0,
+        blocks: vec![],
+        current: 0,
+        labels: HashMap::new(),
+    };
+
+    // the blocks of the graph come first, in the order they execute
+    let order = analysis::reverse_postorder(cfg)
+        .into_iter()
+        .filter(|&node| node != cfg.exit)
+        .collect::<Vec<_>>();
+
+    for &node in order.iter() {
+        let label = lowerer.new_block();
+        lowerer.labels.insert(node, label);
+    }
+
+    // arguments arrive in registers, so captured parameters are moved into
+    // their cells first
+    let mut parameters = vec![];
+    let mut cells = vec![];
+
+    for param in function.parameter_list.iter() {
+        if let Parameter::Identifier(identifier) = param {
+            match lowerer.captured.contains(identifier) {
+                true => {
+                    let register = lowerer.temporary();
+                    parameters.push(register);
+                    cells.push((identifier, register));
+                }
+                false => parameters.push(lowerer.local(identifier)),
+            }
+        }
+    }
+
+    for (identifier, register) in cells {
+        lowerer.declare(identifier, Operand::Register(register));
+    }
+
+    for node in order {
+        lowerer.node(cfg, node);
+    }
+
+    let blocks = lowerer
+        .blocks
+        .into_iter()
+        .map(|(instructions, terminator)| Block {
+            instructions,
+            terminator: terminator.expect("every block is terminated"),
+        })
+        .collect();
+
+    Function {
+        name: function.name.clone(),
+        parameters,
+        is_variadic: function.parameter_list.contains(&Parameter::VariableArg),
+        upvalues: lowerer.upvalues,
+        blocks,
+        registers: lowerer.registers,
+    }
+}
+
+pub(super) fn lower(chunk: &mut Chunk, options: Options) -> Result<Module, Vec<Error>> {
+    let program = program::build(chunk, options)?;
+
+    let declared = program
+        .call_graph
+        .node_indices()
+        .map(|node| (node, declared(&program.call_graph[node])))
+        .collect::<HashMap<_, _>>();
+
+    let mut lowered = program
+        .call_graph
+        .node_indices()
+        .map(|_| None)
+        .collect::<Vec<_>>();
+    let mut captured = HashSet::new();
+
+    // nested functions are added after the function they are nested in, so
+    // going backwards lowers them first, and finds every captured local
+    // before the function declaring it
+    for node in program.call_graph.node_indices().rev() {
+        let function = lower_function(&program, node, &lowered, &declared, &mut captured);
+        lowered[node.index()] = Some(function);
+    }
+
+    Ok(Module {
+        functions: lowered.into_iter().map(Option::unwrap).collect(),
+        main: program.main.index(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ir, parser::parse};
+
+    fn lowered(source: &str) -> String {
+        let chunk = parse(source).unwrap();
+        let module = ir::lower(&chunk, Default::default()).unwrap();
+
+        format!("{module:?}")
+    }
+
+    #[test]
+    fn multiple_results() {
+        assert_eq!(
+            lowered(
+                "local function f() return 1, 2 end
+                local a, b, c = f()
+                local t = { f() }
+                local u = { f(), 3 }
+                return (f()), f()"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r0 = closure f1()
+  r1, r2, r3 = call r0()
+  r4 = r1
+  r5 = r2
+  r6 = r3
+  r7 = {}
+  ...r8 = call r0()
+  r7[1...] = ...r8
+  r9 = r7
+  r10 = {}
+  r11 = call r0()
+  r10[1] = r11
+  r10[2] = 3
+  r12 = r10
+  r13 = call r0()
+  ...r14 = call r0()
+  return r13, ...r14
+
+f1: function f@1()
+L0:
+  jump L1
+L1:
+  return 1, 2
+"
+        );
+    }
+
+    #[test]
+    fn captured_locals() {
+        assert_eq!(
+            lowered(
+                "local n = 0
+                local function inc() n = n + 1 return n end
+                inc()
+                return n"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r0 = cell
+  cell r0 = 0
+  r1 = closure f1(cell r0)
+  call r1()
+  r2 = cell r0
+  return r2
+
+f1: function inc@1()
+  upvalue 0: n@1
+L0:
+  jump L1
+L1:
+  r0 = upvalue 0
+  r1 = Add r0, 1
+  upvalue 0 = r1
+  r2 = upvalue 0
+  return r2
+"
+        );
+    }
+
+    #[test]
+    fn method_calls() {
+        assert_eq!(
+            lowered(
+                "local obj = {}
+                function obj:get(k) return self[k] end
+                return obj:get(\"x\"), s:upper()"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r0 = {}
+  r1 = r0
+  r2 = closure f1()
+  r1[\"get\"] = r2
+  r4 = r1[\"get\"]
+  r3 = call r4(r1, \"x\")
+  r6 = global s
+  r7 = r6[\"upper\"]
+  ...r5 = call r7(r6)
+  return r3, ...r5
+
+f1: function obj@1:get(r0, r1)
+L0:
+  jump L1
+L1:
+  r2 = r0[r1]
+  return r2
+"
+        );
+    }
+
+    #[test]
+    fn numeric_for() {
+        assert_eq!(
+            lowered(
+                "local s = 0
+                for i = 1, 10, 2 do s = s + i end
+                return s"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r0 = 0
+  r1 = 1
+  r2 = 10
+  r3 = 2
+  jump L2
+L2:
+  r4 = LessThanOrEqual r1, r2
+  branch r4, L4, L3
+L3:
+  return r0
+L4:
+  r5 = r1
+  r6 = Add r0, r5
+  r0 = r6
+  r7 = Add r1, r3
+  r1 = r7
+  jump L2
+"
+        );
+    }
+
+    #[test]
+    fn generic_for() {
+        assert_eq!(
+            lowered("for k, v in pairs(t) do print(k, v) end"),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r3 = global pairs
+  r4 = global t
+  r0, r1, r2 = call r3(r4)
+  r5 = r0
+  r6 = r1
+  r7 = r2
+  jump L2
+L2:
+  r8, r9 = call r5(r6, r7)
+  r10 = r8
+  r11 = r9
+  r12 = NotEqual r10, nil
+  branch r12, L3, L4
+L3:
+  r7 = r10
+  r13 = global print
+  call r13(r10, r11)
+  jump L2
+L4:
+  return 
+"
+        );
+    }
+
+    #[test]
+    fn captured_loop_variable() {
+        // every iteration gets a fresh cell, so each closure sees its own `i`
+        assert_eq!(
+            lowered(
+                "local fs = {}
+                for i = 1, 3 do fs[i] = function() return i end end"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r0 = {}
+  r1 = r0
+  r2 = 1
+  r3 = 3
+  r4 = 1
+  jump L2
+L2:
+  r5 = LessThanOrEqual r2, r3
+  branch r5, L3, L4
+L3:
+  r6 = cell
+  cell r6 = r2
+  r7 = cell r6
+  r8 = closure f1(cell r6)
+  r1[r7] = r8
+  r9 = Add r2, r4
+  r2 = r9
+  jump L2
+L4:
+  return 
+
+f1: function anonymous()
+  upvalue 0: i@1
+L0:
+  jump L1
+L1:
+  r0 = upvalue 0
+  return r0
+"
+        );
+    }
+}
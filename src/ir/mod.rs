@@ -0,0 +1,711 @@
+//! A linear three-address form of every function, between the control flow
+//! graph and the VM.
+//!
+//! Expressions are flattened into instructions on numbered registers, each of
+//! which holds a local or a temporary. A register may also hold a list of
+//! values, the open result of a call or of `...`, which is only ever spread
+//! into the end of an argument list, a return or a table constructor.
+
+use std::fmt;
+
+use petgraph::{stable_graph::NodeIndex, Graph};
+
+use crate::{
+    cfg::{translator::Options, CFGEdge, CFGNode, Cfg, Error},
+    parser::ast::definition::{Chunk, Identifier, LuaString},
+    scope,
+};
+
+mod lower;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Register(pub usize);
+
+impl fmt::Debug for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+/// A block of a function, as an index into its blocks.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Label(pub usize);
+
+impl Label {
+    /// The node standing for the block in [`Function::cfg`], which comes
+    /// after the entry and the exit.
+    pub fn node(self) -> NodeIndex {
+        NodeIndex::new(self.0 + 2)
+    }
+
+    /// The block a node of [`Function::cfg`] stands for, unless it is the
+    /// entry or the exit.
+    pub fn from_node(node: NodeIndex) -> Option<Label> {
+        node.index().checked_sub(2).map(Label)
+    }
+}
+
+impl fmt::Debug for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Constant {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(LuaString),
+}
+
+impl fmt::Debug for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Nil => write!(f, "nil"),
+            Constant::Boolean(value) => write!(f, "{value}"),
+            Constant::Integer(value) => write!(f, "{value}"),
+            Constant::Float(value) => write!(f, "{value:?}"),
+            Constant::String(value) => write!(f, "{value:?}"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Operand {
+    Register(Register),
+    Constant(Constant),
+}
+
+impl Operand {
+    pub fn register(&self) -> Option<Register> {
+        match self {
+            Operand::Register(register) => Some(*register),
+            Operand::Constant(_) => None,
+        }
+    }
+
+    fn register_mut(&mut self) -> Option<&mut Register> {
+        match self {
+            Operand::Register(register) => Some(register),
+            Operand::Constant(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{register:?}"),
+            Operand::Constant(constant) => write!(f, "{constant:?}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+    BitwiseNot,
+    Length,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Modulo,
+    Power,
+    Concatenate,
+    ShiftLeft,
+    ShiftRight,
+    BitwiseAnd,
+    BitwiseXor,
+    BitwiseOr,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+/// Where a closure finds a captured local.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Upvalue {
+    /// The cell a local of the current function lives in.
+    Cell(Register),
+    /// An upvalue of the current function, by its index.
+    Captured(usize),
+}
+
+impl Upvalue {
+    fn register_mut(&mut self) -> Option<&mut Register> {
+        match self {
+            Upvalue::Cell(register) => Some(register),
+            Upvalue::Captured(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Upvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upvalue::Cell(register) => write!(f, "cell {register:?}"),
+            Upvalue::Captured(index) => write!(f, "upvalue {index}"),
+        }
+    }
+}
+
+/// The values an instruction produces when there may be any number of them.
+#[derive(Clone, PartialEq)]
+pub enum Results {
+    /// Exactly as many values as there are registers, padded with nil.
+    Fixed(Vec<Register>),
+    /// Every value, as a list in a single register.
+    All(Register),
+}
+
+impl Results {
+    fn registers_mut(&mut self) -> Vec<&mut Register> {
+        match self {
+            Results::Fixed(registers) => registers.iter_mut().collect(),
+            Results::All(register) => vec![register],
+        }
+    }
+}
+
+impl fmt::Debug for Results {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Results::Fixed(registers) => write!(f, "{}", list(registers, None)),
+            Results::All(register) => write!(f, "...{register:?}"),
+        }
+    }
+}
+
+/// A list of values that may end with a list register spread into it.
+#[derive(Clone, Default, PartialEq)]
+pub struct Values {
+    pub fixed: Vec<Operand>,
+    pub rest: Option<Register>,
+}
+
+impl Values {
+    /// The registers the values are read from.
+    pub fn registers(&self) -> Vec<Register> {
+        self.fixed
+            .iter()
+            .filter_map(Operand::register)
+            .chain(self.rest)
+            .collect()
+    }
+
+    fn registers_mut(&mut self) -> Vec<&mut Register> {
+        self.fixed
+            .iter_mut()
+            .filter_map(Operand::register_mut)
+            .chain(self.rest.as_mut())
+            .collect()
+    }
+}
+
+impl fmt::Debug for Values {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", list(&self.fixed, self.rest))
+    }
+}
+
+fn list<T: fmt::Debug>(items: &[T], rest: Option<Register>) -> String {
+    items
+        .iter()
+        .map(|item| format!("{item:?}"))
+        .chain(rest.map(|rest| format!("...{rest:?}")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Instruction {
+    Move {
+        target: Register,
+        source: Operand,
+    },
+    GetGlobal {
+        target: Register,
+        name: Identifier,
+    },
+    SetGlobal {
+        name: Identifier,
+        value: Operand,
+    },
+    /// Creates the cell of a local that closures capture. A local declared
+    /// in a loop gets a new cell on every iteration.
+    NewCell {
+        target: Register,
+    },
+    GetUpvalue {
+        target: Register,
+        upvalue: Upvalue,
+    },
+    SetUpvalue {
+        upvalue: Upvalue,
+        value: Operand,
+    },
+    NewTable {
+        target: Register,
+    },
+    GetTable {
+        target: Register,
+        table: Operand,
+        key: Operand,
+    },
+    SetTable {
+        table: Operand,
+        key: Operand,
+        value: Operand,
+    },
+    /// Stores a list of values at consecutive integer keys from `start` on.
+    SetList {
+        table: Register,
+        start: i64,
+        values: Register,
+    },
+    Unary {
+        target: Register,
+        operator: UnaryOperator,
+        operand: Operand,
+    },
+    Binary {
+        target: Register,
+        operator: BinaryOperator,
+        left: Operand,
+        right: Operand,
+    },
+    Compare {
+        target: Register,
+        operator: Comparison,
+        left: Operand,
+        right: Operand,
+    },
+    Call {
+        function: Operand,
+        arguments: Values,
+        results: Results,
+    },
+    VarArg {
+        results: Results,
+    },
+    /// Instantiates a function of the module with the given upvalues.
+    Closure {
+        target: Register,
+        function: usize,
+        upvalues: Vec<Upvalue>,
+    },
+    /// Hands a list of values on the way a return hands them to a call, cut
+    /// off or padded with nil for fixed results.
+    Adjust {
+        results: Results,
+        values: Values,
+    },
+}
+
+impl Instruction {
+    /// The registers written and read by the instruction, in that order, and
+    /// how many of them are written. A cell counts as read, since it is the
+    /// cell rather than the register that changes.
+    fn registers_mut(&mut self) -> (Vec<&mut Register>, usize) {
+        let (targets, sources) = match self {
+            Instruction::Move { target, source } => {
+                (vec![target], source.register_mut().into_iter().collect())
+            }
+            Instruction::GetGlobal { target, .. }
+            | Instruction::NewCell { target }
+            | Instruction::NewTable { target } => (vec![target], vec![]),
+            Instruction::SetGlobal { value, .. } => {
+                (vec![], value.register_mut().into_iter().collect())
+            }
+            Instruction::GetUpvalue { target, upvalue } => {
+                (vec![target], upvalue.register_mut().into_iter().collect())
+            }
+            Instruction::SetUpvalue { upvalue, value } => (
+                vec![],
+                upvalue
+                    .register_mut()
+                    .into_iter()
+                    .chain(value.register_mut())
+                    .collect(),
+            ),
+            Instruction::GetTable { target, table, key } => (
+                vec![target],
+                table
+                    .register_mut()
+                    .into_iter()
+                    .chain(key.register_mut())
+                    .collect(),
+            ),
+            Instruction::SetTable { table, key, value } => (
+                vec![],
+                [table, key, value]
+                    .into_iter()
+                    .filter_map(Operand::register_mut)
+                    .collect(),
+            ),
+            Instruction::SetList { table, values, .. } => (vec![], vec![table, values]),
+            Instruction::Unary {
+                target, operand, ..
+            } => (vec![target], operand.register_mut().into_iter().collect()),
+            Instruction::Binary {
+                target,
+                left,
+                right,
+                ..
+            }
+            | Instruction::Compare {
+                target,
+                left,
+                right,
+                ..
+            } => (
+                vec![target],
+                left.register_mut()
+                    .into_iter()
+                    .chain(right.register_mut())
+                    .collect(),
+            ),
+            Instruction::Call {
+                function,
+                arguments,
+                results,
+            } => (
+                results.registers_mut(),
+                function
+                    .register_mut()
+                    .into_iter()
+                    .chain(arguments.registers_mut())
+                    .collect(),
+            ),
+            Instruction::VarArg { results } => (results.registers_mut(), vec![]),
+            Instruction::Closure {
+                target, upvalues, ..
+            } => (
+                vec![target],
+                upvalues
+                    .iter_mut()
+                    .filter_map(Upvalue::register_mut)
+                    .collect(),
+            ),
+            Instruction::Adjust { results, values } => {
+                (results.registers_mut(), values.registers_mut())
+            }
+        };
+
+        let count = targets.len();
+        (targets.into_iter().chain(sources).collect(), count)
+    }
+
+    /// The registers the instruction writes.
+    pub fn targets(&self) -> Vec<Register> {
+        let mut instruction = self.clone();
+        let (registers, count) = instruction.registers_mut();
+        registers
+            .into_iter()
+            .take(count)
+            .map(|register| *register)
+            .collect()
+    }
+
+    /// The registers the instruction reads.
+    pub fn sources(&self) -> Vec<Register> {
+        let mut instruction = self.clone();
+        let (registers, count) = instruction.registers_mut();
+        registers
+            .into_iter()
+            .skip(count)
+            .map(|register| *register)
+            .collect()
+    }
+
+    /// Replaces every register the instruction reads or writes.
+    pub fn map_registers(&mut self, mut f: impl FnMut(Register) -> Register) {
+        for register in self.registers_mut().0 {
+            *register = f(*register);
+        }
+    }
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Move { target, source } => write!(f, "{target:?} = {source:?}"),
+            Instruction::GetGlobal { target, name } => write!(f, "{target:?} = global {name}"),
+            Instruction::SetGlobal { name, value } => write!(f, "global {name} = {value:?}"),
+            Instruction::NewCell { target } => write!(f, "{target:?} = cell"),
+            Instruction::GetUpvalue { target, upvalue } => {
+                write!(f, "{target:?} = {upvalue:?}")
+            }
+            Instruction::SetUpvalue { upvalue, value } => write!(f, "{upvalue:?} = {value:?}"),
+            Instruction::NewTable { target } => write!(f, "{target:?} = {{}}"),
+            Instruction::GetTable { target, table, key } => {
+                write!(f, "{target:?} = {table:?}[{key:?}]")
+            }
+            Instruction::SetTable { table, key, value } => {
+                write!(f, "{table:?}[{key:?}] = {value:?}")
+            }
+            Instruction::SetList {
+                table,
+                start,
+                values,
+            } => write!(f, "{table:?}[{start}...] = ...{values:?}"),
+            Instruction::Unary {
+                target,
+                operator,
+                operand,
+            } => write!(f, "{target:?} = {operator:?} {operand:?}"),
+            Instruction::Binary {
+                target,
+                operator,
+                left,
+                right,
+            } => write!(f, "{target:?} = {operator:?} {left:?}, {right:?}"),
+            Instruction::Compare {
+                target,
+                operator,
+                left,
+                right,
+            } => write!(f, "{target:?} = {operator:?} {left:?}, {right:?}"),
+            Instruction::Call {
+                function,
+                arguments,
+                results,
+            } => match results {
+                Results::Fixed(registers) if registers.is_empty() => {
+                    write!(f, "call {function:?}({arguments:?})")
+                }
+                _ => write!(f, "{results:?} = call {function:?}({arguments:?})"),
+            },
+            Instruction::VarArg { results } => write!(f, "{results:?} = vararg"),
+            Instruction::Closure {
+                target,
+                function,
+                upvalues,
+            } => write!(
+                f,
+                "{target:?} = closure f{function}({})",
+                list(upvalues, None)
+            ),
+            Instruction::Adjust { results, values } => write!(f, "{results:?} = {values:?}"),
+        }
+    }
+}
+
+/// How a block hands control on.
+#[derive(Clone, PartialEq)]
+pub enum Terminator {
+    Jump(Label),
+    /// Goes to `then` unless the condition is `false` or `nil`.
+    Branch {
+        condition: Operand,
+        then: Label,
+        otherwise: Label,
+    },
+    Return(Values),
+}
+
+impl Terminator {
+    /// The registers the terminator reads.
+    pub fn sources(&self) -> Vec<Register> {
+        let mut terminator = self.clone();
+        let registers = terminator.registers_mut();
+        registers.into_iter().map(|register| *register).collect()
+    }
+
+    fn registers_mut(&mut self) -> Vec<&mut Register> {
+        match self {
+            Terminator::Jump(_) => vec![],
+            Terminator::Branch { condition, .. } => condition.register_mut().into_iter().collect(),
+            Terminator::Return(values) => values.registers_mut(),
+        }
+    }
+
+    /// Replaces every register the terminator reads.
+    pub fn map_registers(&mut self, mut f: impl FnMut(Register) -> Register) {
+        for register in self.registers_mut() {
+            *register = f(*register);
+        }
+    }
+
+    /// The blocks control can go on to.
+    pub fn successors(&self) -> Vec<Label> {
+        match self {
+            Terminator::Jump(label) => vec![*label],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Return(_) => vec![],
+        }
+    }
+
+    /// Replaces every block the terminator can go on to.
+    pub fn map_labels(&mut self, mut f: impl FnMut(Label) -> Label) {
+        match self {
+            Terminator::Jump(label) => *label = f(*label),
+            Terminator::Branch {
+                then, otherwise, ..
+            } => {
+                *then = f(*then);
+                *otherwise = f(*otherwise);
+            }
+            Terminator::Return(_) => {}
+        }
+    }
+}
+
+impl fmt::Debug for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(label) => write!(f, "jump {label:?}"),
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => write!(f, "branch {condition:?}, {then:?}, {otherwise:?}"),
+            Terminator::Return(values) => write!(f, "return {values:?}"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+pub struct Function {
+    pub name: Option<String>,
+    /// The registers the arguments arrive in.
+    pub parameters: Vec<Register>,
+    pub is_variadic: bool,
+    /// The names of the captured locals, in the order the closure receives
+    /// them.
+    pub upvalues: Vec<Identifier>,
+    /// Execution starts at the first block.
+    pub blocks: Vec<Block>,
+    /// How many registers the function uses.
+    pub registers: usize,
+}
+
+impl Function {
+    /// The shape of the function as a control flow graph, for the analyses of
+    /// [`crate::cfg::analysis`]. Its blocks are empty, and stand for the
+    /// blocks of the function by [`Label::node`].
+    pub fn cfg(&self) -> Cfg {
+        let mut graph = Graph::new();
+        let entry = graph.add_node(CFGNode::Entry);
+        let exit = graph.add_node(CFGNode::Exit);
+
+        for _ in self.blocks.iter() {
+            graph.add_node(CFGNode::Block(Box::default()));
+        }
+
+        if !self.blocks.is_empty() {
+            graph.add_edge(entry, Label(0).node(), CFGEdge::Fallthrough);
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let node = Label(i).node();
+
+            match &block.terminator {
+                Terminator::Jump(label) => {
+                    graph.add_edge(node, label.node(), CFGEdge::Fallthrough);
+                }
+                Terminator::Branch {
+                    then, otherwise, ..
+                } => {
+                    graph.add_edge(node, then.node(), CFGEdge::True);
+                    graph.add_edge(node, otherwise.node(), CFGEdge::False);
+                }
+                Terminator::Return(_) => {
+                    graph.add_edge(node, exit, CFGEdge::Fallthrough);
+                }
+            }
+        }
+
+        Cfg { graph, entry, exit }
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "function {}(",
+            self.name.as_deref().unwrap_or("anonymous")
+        )?;
+        write!(f, "{}", list(&self.parameters, None))?;
+
+        if self.is_variadic {
+            if !self.parameters.is_empty() {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "...")?;
+        }
+
+        writeln!(f, ")")?;
+
+        for (i, upvalue) in self.upvalues.iter().enumerate() {
+            writeln!(f, "  upvalue {i}: {upvalue}")?;
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{:?}:", Label(i))?;
+
+            for instruction in block.instructions.iter() {
+                writeln!(f, "  {instruction:?}")?;
+            }
+
+            writeln!(f, "  {:?}", block.terminator)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every function of a chunk, where closures refer to functions by their
+/// index.
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub main: usize,
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "f{i}: {function:?}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lowers the main chunk and every function nested in it. Fails where the
+/// chunk cannot be translated into control flow graphs.
+pub fn lower(chunk: &Chunk, options: Options) -> Result<Module, Vec<Error>> {
+    let mut chunk = chunk.clone();
+    scope::rename(&mut [], &mut chunk.block);
+
+    lower::lower(&mut chunk, options)
+}
@@ -0,0 +1,9 @@
+pub mod cfg;
+pub mod emit;
+pub mod ir;
+pub mod lint;
+pub mod optimize;
+pub mod parser;
+pub mod scope;
+pub mod ssa;
+pub mod vm;
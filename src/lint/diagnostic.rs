@@ -0,0 +1,130 @@
+use std::fmt::Write;
+
+use crate::parser::ast::definition::Span;
+
+use super::Lint;
+
+/// A warning about a span of the source, printed like the diagnostics of
+/// rustc.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    pub message: String,
+    pub span: Span,
+    /// Shown next to the underlined source.
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(lint: Lint, message: String, span: Span) -> Self {
+        Diagnostic {
+            lint,
+            message,
+            span,
+            label: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn with_label(mut self, label: String) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    /// Formats the diagnostic with the line it points at. A span over several
+    /// lines is underlined up to the end of its first line.
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let start = self.span.start;
+        let line_start = source[..start.offset.min(source.len())]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line = source[line_start..].lines().next().unwrap_or_default();
+
+        let line_end = line_start + line.len();
+        let from = start.offset.clamp(line_start, line_end);
+        let to = self.span.end.offset.clamp(from, line_end);
+
+        // tabs stay tabs, so the underline lines up with the source
+        let indent = source[line_start..from]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let width = source[from..to].chars().count().max(1);
+
+        let number = start.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        let mut out = String::new();
+        let _ = writeln!(out, "warning[{}]: {}", self.lint.name(), self.message);
+        let _ = writeln!(out, "{gutter}--> {path}:{}:{}", start.line, start.column);
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{number} | {line}");
+
+        let _ = write!(out, "{gutter} | {indent}{}", "^".repeat(width));
+
+        match &self.label {
+            Some(label) => {
+                let _ = writeln!(out, " {label}");
+            }
+            None => out.push('\n'),
+        }
+
+        if !self.notes.is_empty() {
+            let _ = writeln!(out, "{gutter} |");
+        }
+
+        for note in self.notes.iter() {
+            let _ = writeln!(out, "{gutter} = note: {note}");
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::ast::definition::{Position, Span};
+
+    use super::{Diagnostic, Lint};
+
+    #[test]
+    fn render() {
+        let source = "local a = 1\n\tlocal unused = 2\n";
+        let start = Position {
+            line: 2,
+            column: 8,
+            offset: 19,
+        };
+        let end = Position {
+            line: 2,
+            column: 14,
+            offset: 25,
+        };
+
+        let diagnostic = Diagnostic::new(
+            Lint::UnusedVariable,
+            "unused variable `unused`".to_string(),
+            Span::new(start, end),
+        )
+        .with_label("never read".to_string())
+        .with_note("prefix it with an underscore".to_string());
+
+        assert_eq!(
+            diagnostic.render("test.lua", source),
+            "warning[unused_variable]: unused variable `unused`
+ --> test.lua:2:8
+  |
+2 | \tlocal unused = 2
+  | \t      ^^^^^^ never read
+  |
+  = note: prefix it with an underscore
+"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Warnings about likely mistakes: locals that are never read or that hide
+//! other locals, globals that are never defined, and code that never runs.
+
+use std::collections::HashSet;
+
+use crate::{
+    cfg::{program, Error},
+    parser::ast::definition::{Chunk, Position, Span},
+    scope::{self, Access, BindingKind, Resolution},
+};
+
+mod diagnostic;
+mod unreachable;
+
+pub use diagnostic::Diagnostic;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    Shadowing,
+    UndefinedGlobal,
+    GlobalAssignment,
+    UnreachableCode,
+}
+
+impl Lint {
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedParameter => "unused_parameter",
+            Lint::Shadowing => "shadowing",
+            Lint::UndefinedGlobal => "undefined_global",
+            Lint::GlobalAssignment => "global_assignment",
+            Lint::UnreachableCode => "unreachable_code",
+        }
+    }
+}
+
+/// The globals of the standard libraries of every supported Lua version.
+const STANDARD_GLOBALS: &[&str] = &[
+    "_G",
+    "_VERSION",
+    "assert",
+    "bit32",
+    "collectgarbage",
+    "coroutine",
+    "debug",
+    "dofile",
+    "error",
+    "getfenv",
+    "getmetatable",
+    "io",
+    "ipairs",
+    "load",
+    "loadfile",
+    "loadstring",
+    "math",
+    "module",
+    "next",
+    "os",
+    "package",
+    "pairs",
+    "pcall",
+    "print",
+    "rawequal",
+    "rawget",
+    "rawlen",
+    "rawset",
+    "require",
+    "select",
+    "setfenv",
+    "setmetatable",
+    "string",
+    "table",
+    "tonumber",
+    "tostring",
+    "type",
+    "unpack",
+    "utf8",
+    "xpcall",
+    #[cfg(feature = "luau")]
+    "typeof",
+    #[cfg(feature = "luau")]
+    "buffer",
+    #[cfg(feature = "luau")]
+    "task",
+];
+
+pub struct Options {
+    /// The globals that may be read and written without a warning.
+    pub globals: HashSet<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            globals: STANDARD_GLOBALS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Narrows a span to the first occurrence of `name` as a whole word in it, so
+/// a diagnostic about a name points at the name rather than its statement.
+fn name_span(source: &str, span: Span, name: &str) -> Span {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let Some(text) = source.get(span.start.offset..span.end.offset) else {
+        return span;
+    };
+
+    let found = text.match_indices(name).find(|&(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + name.len()..].chars().next();
+
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    });
+
+    let Some((i, _)) = found else {
+        return span;
+    };
+
+    let prefix = &text[..i];
+    let line = span.start.line + prefix.matches('\n').count();
+    let column = match prefix.rfind('\n') {
+        Some(newline) => prefix[newline + 1..].chars().count() + 1,
+        None => span.start.column + prefix.chars().count(),
+    };
+
+    let start = Position {
+        line,
+        column,
+        offset: span.start.offset + i,
+    };
+    let end = Position {
+        line,
+        column: column + name.chars().count(),
+        offset: start.offset + name.len(),
+    };
+
+    Span::new(start, end)
+}
+
+/// Checks a chunk parsed from `source`, and returns the diagnostics in the
+/// order of the source. Fails when the chunk has jumps that Lua would reject.
+pub fn lint(source: &str, chunk: &Chunk, options: &Options) -> Result<Vec<Diagnostic>, Vec<Error>> {
+    let scopes = scope::resolve(&[], &chunk.block);
+    let mut diagnostics = vec![];
+
+    let read = scopes
+        .references
+        .iter()
+        .filter(|reference| reference.access == Access::Read)
+        .filter_map(|reference| reference.resolution.binding())
+        .collect::<HashSet<_>>();
+
+    for (i, binding) in scopes.bindings.iter().enumerate() {
+        // a leading underscore marks a name that is unused on purpose
+        if binding.kind == BindingKind::SelfParameter || binding.name.starts_with('_') {
+            continue;
+        }
+
+        let name = &binding.name;
+        let span = name_span(source, binding.span, name);
+
+        if !read.contains(&scope::BindingId(i)) {
+            let (lint, message) = match binding.kind {
+                BindingKind::Parameter => (Lint::UnusedParameter, "unused parameter"),
+                BindingKind::LocalFunction => (Lint::UnusedVariable, "unused local function"),
+                _ => (Lint::UnusedVariable, "unused variable"),
+            };
+
+            let mut diagnostic = Diagnostic::new(lint, format!("{message} `{name}`"), span);
+
+            if scopes.references_to(scope::BindingId(i)).next().is_some() {
+                diagnostic = diagnostic.with_note(format!("`{name}` is written, but never read"));
+            }
+
+            diagnostics.push(diagnostic.with_note(format!(
+                "if this is intentional, prefix it with an underscore: `_{name}`"
+            )));
+        }
+
+        if let Some(shadowed) = binding.shadows {
+            let shadowed = scopes.binding(shadowed);
+
+            if shadowed.kind != BindingKind::SelfParameter {
+                let declared = name_span(source, shadowed.span, name).start;
+
+                diagnostics.push(
+                    Diagnostic::new(
+                        Lint::Shadowing,
+                        format!("`{name}` shadows a local of the same name"),
+                        span,
+                    )
+                    .with_note(format!("the shadowed `{name}` is declared at {declared}")),
+                );
+            }
+        }
+    }
+
+    let assigned = scopes
+        .references
+        .iter()
+        .filter(|reference| reference.resolution == Resolution::Global)
+        .filter(|reference| reference.access == Access::Write)
+        .map(|reference| &reference.name)
+        .collect::<HashSet<_>>();
+
+    for reference in scopes.references.iter() {
+        let name = &reference.name;
+
+        if reference.resolution != Resolution::Global || options.globals.contains(name) {
+            continue;
+        }
+
+        let span = name_span(source, reference.span, name);
+
+        match reference.access {
+            // a global that the chunk defines itself is only reported where
+            // it is defined
+            Access::Read if !assigned.contains(name) => diagnostics.push(
+                Diagnostic::new(
+                    Lint::UndefinedGlobal,
+                    format!("use of undefined global `{name}`"),
+                    span,
+                )
+                .with_label("not found in this scope".to_string()),
+            ),
+            Access::Read => {}
+            Access::Write => diagnostics.push(
+                Diagnostic::new(
+                    Lint::GlobalAssignment,
+                    format!("assignment to undeclared global `{name}`"),
+                    span,
+                )
+                .with_note(format!(
+                    "declare it with `local {name}`, or allow it as a global"
+                )),
+            ),
+        }
+    }
+
+    // building numbers the functions, which the caller has no use for
+    let program = program::build(&mut chunk.clone(), Default::default())?;
+    diagnostics.extend(unreachable::check(&program, &chunk.block));
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+
+    use super::{lint, Options};
+
+    /// The lints reported for a source, as `line:column name: message`.
+    fn lints(source: &str) -> Vec<String> {
+        let chunk = parse(source).unwrap();
+
+        lint(source, &chunk, &Options::default())
+            .unwrap()
+            .iter()
+            .map(|diagnostic| {
+                format!(
+                    "{} {}: {}",
+                    diagnostic.span.start,
+                    diagnostic.lint.name(),
+                    diagnostic.message
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unused_variable() {
+        assert_eq!(
+            lints("local a, b = 1, 2\nlocal _c = 3\nprint(b)"),
+            ["1:7 unused_variable: unused variable `a`"]
+        );
+        assert_eq!(
+            lints("local x = 1\nx = 2"),
+            ["1:7 unused_variable: unused variable `x`"]
+        );
+        assert_eq!(
+            lints("local function g() end"),
+            ["1:16 unused_variable: unused local function `g`"]
+        );
+        assert_eq!(lints("local x = 1\nprint(x)"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn unused_parameter() {
+        assert_eq!(
+            lints("local function f(a, _b, c) return c end\nf()"),
+            ["1:18 unused_parameter: unused parameter `a`"]
+        );
+        // the implicit `self` of a method is never reported
+        assert_eq!(
+            lints("local t = {}\nfunction t:m(x) return x end\nreturn t"),
+            [] as [&str; 0]
+        );
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            lints("local x = 1\ndo local x = 2 print(x) end\nprint(x)"),
+            ["2:10 shadowing: `x` shadows a local of the same name"]
+        );
+        assert_eq!(
+            lints("local x = 1\nlocal y = 2\nprint(x, y)"),
+            [] as [&str; 0]
+        );
+    }
+
+    #[test]
+    fn undefined_global() {
+        assert_eq!(
+            lints("print(undefined, string)"),
+            ["1:7 undefined_global: use of undefined global `undefined`"]
+        );
+        // a global the chunk assigns is only reported where it is assigned
+        assert_eq!(
+            lints("print(foo)\nfoo = 1"),
+            ["2:1 global_assignment: assignment to undeclared global `foo`"]
+        );
+    }
+
+    #[test]
+    fn global_assignment() {
+        assert_eq!(
+            lints("function helper() end\nhelper()"),
+            ["1:10 global_assignment: assignment to undeclared global `helper`"]
+        );
+        assert_eq!(
+            lints("local function helper() end\nhelper()"),
+            [] as [&str; 0]
+        );
+    }
+
+    #[test]
+    fn unreachable_code() {
+        // only the first statement of an unreachable run is reported
+        assert_eq!(
+            lints("local function f() do return 1 end print(1) print(2) end\nf()"),
+            ["1:36 unreachable_code: unreachable statement"]
+        );
+        assert_eq!(
+            lints("for i = 1, 2 do do break end print(i) end"),
+            ["1:30 unreachable_code: unreachable statement"]
+        );
+        assert_eq!(
+            lints("local function f(x) if x then return 1 else return 2 end return 3 end\nf()"),
+            ["1:58 unreachable_code: unreachable statement"]
+        );
+        assert_eq!(
+            lints("local function f(x) if x then return 1 end return 2 end\nf()"),
+            [] as [&str; 0]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "lua52")]
+    fn skipped_by_goto() {
+        assert_eq!(
+            lints("goto skip\nprint(1)\n::skip::\nprint(2)"),
+            ["2:1 unreachable_code: unreachable statement"]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+
+use crate::{
+    cfg::program::Program,
+    parser::ast::{
+        definition::{Block, LastStatementKind, Span, Statement, StatementKind},
+        visitor::Visitor,
+    },
+};
+
+use super::{Diagnostic, Lint};
+
+/// Finds the statements that are missing from the graphs of their
+/// functions, which drop every node without a path from the entry.
+struct Unreachable {
+    /// The spans of every statement and condition left in a graph.
+    reachable: HashSet<Span>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Unreachable {
+    /// Whether the statement is executed at all. Control flow statements are
+    /// not kept in the graph as such, so they are found by their condition or
+    /// by the hidden locals of a loop. `None` for statements that have no
+    /// trace in the graph, whose reachability does not matter.
+    fn is_reachable(&self, stmt: &Statement) -> Option<bool> {
+        let span = match &stmt.kind {
+            StatementKind::LocalDeclaration(_)
+            | StatementKind::FunctionCall(_)
+            | StatementKind::Assignment(_)
+            | StatementKind::CompoundAssignment(_)
+            | StatementKind::FunctionDefinition(_)
+            | StatementKind::LocalFunctionDefinition(_)
+            | StatementKind::NumericFor(_)
+            | StatementKind::GenericFor(_) => stmt.span,
+            StatementKind::If(stmt) => stmt.condition.span,
+            StatementKind::While(stmt) => stmt.condition.span,
+            _ => return None,
+        };
+
+        Some(self.reachable.contains(&span))
+    }
+
+    fn report(&mut self, span: Span) {
+        self.diagnostics.push(
+            Diagnostic::new(
+                Lint::UnreachableCode,
+                "unreachable statement".to_string(),
+                span,
+            )
+            .with_label("unreachable statement".to_string())
+            .with_note(
+                "no path leads here past a `return`, `break`, `goto` or endless loop".to_string(),
+            ),
+        );
+    }
+}
+
+impl Visitor for Unreachable {
+    fn visit_block(&mut self, block: &Block) {
+        // only the first statement of a run that is never executed is reported
+        let mut reported = false;
+
+        for stmt in block.statements.iter() {
+            match self.is_reachable(stmt) {
+                Some(false) => {
+                    if !reported {
+                        self.report(stmt.span);
+                        reported = true;
+                    }
+                }
+                Some(true) => {
+                    reported = false;
+                    self.visit_statement(stmt);
+                }
+                None => self.visit_statement(stmt),
+            }
+        }
+
+        if let Some(stmt) = &block.last_statement {
+            if let LastStatementKind::Return(_) = stmt.kind {
+                match self.reachable.contains(&stmt.span) {
+                    true => self.visit_last_statement(stmt),
+                    false if !reported => self.report(stmt.span),
+                    false => {}
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn check(program: &Program, block: &Block) -> Vec<Diagnostic> {
+    let mut reachable = HashSet::new();
+
+    for function in program.call_graph.node_weights() {
+        for node in function.cfg.graph.node_weights() {
+            let Some(block) = node.block() else {
+                continue;
+            };
+
+            reachable.extend(block.statements.iter().map(|stmt| stmt.span));
+            reachable.extend(block.last_statement.iter().map(|stmt| stmt.span));
+            reachable.extend(block.condition.iter().map(|exp| exp.span));
+        }
+    }
+
+    let mut unreachable = Unreachable {
+        reachable,
+        diagnostics: vec![],
+    };
+
+    unreachable.visit_block(block);
+    unreachable.diagnostics
+}
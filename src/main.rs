@@ -1,18 +1,143 @@
-use std::fs;
+use std::{env, fs, process::ExitCode};
 
 extern crate log;
 extern crate pretty_env_logger;
 
-mod cfg;
-mod parser;
-mod vm;
+use dolos::{cfg, emit, lint, optimize, parser};
+use log::error;
 
-fn main() {
+const LINT_USAGE: &str = "usage: dolos lint [--global <name>]... <file>...";
+
+fn main() -> ExitCode {
     pretty_env_logger::init();
 
-    let source_code = fs::read_to_string("test.lua").unwrap();
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    match args.first().map(String::as_str) {
+        Some("lint") => run_lint(&args[1..]),
+        _ => compile(),
+    }
+}
+
+fn compile() -> ExitCode {
+    let source_code = match fs::read_to_string("test.lua") {
+        Ok(source_code) => source_code,
+        Err(e) => {
+            error!("could not read test.lua: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut ast = match parser::parse(&source_code) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            for e in errors {
+                error!("could not parse test.lua: {e}");
+            }
+
+            return ExitCode::FAILURE;
+        }
+    };
+
+    parser::ast::luau::strip_types(&mut ast);
+    optimize::fold_constants(&mut ast);
+
+    if let Err(e) = fs::write("out.lua", emit::lua::print(&ast)) {
+        error!("could not write out.lua: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    let program = match cfg::program::build(&mut ast, Default::default()) {
+        Ok(program) => program,
+        Err(errors) => {
+            for e in errors {
+                error!("could not compile test.lua: {e}");
+            }
+
+            return ExitCode::FAILURE;
+        }
+    };
+
+    cfg::visualization::visualize(&program);
+
+    ExitCode::SUCCESS
+}
+
+/// Prints the diagnostics of every file. Fails only when a file cannot be
+/// read, parsed or compiled, since warnings alone do not stop anything.
+fn run_lint(args: &[String]) -> ExitCode {
+    let mut options = lint::Options::default();
+    let mut paths = vec![];
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--global" => match args.next() {
+                Some(name) => {
+                    options.globals.insert(name.clone());
+                }
+                None => {
+                    eprintln!("{LINT_USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("{LINT_USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut status = ExitCode::SUCCESS;
+
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("error: could not read {path}: {e}");
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        let mut ast = match parser::parse(&source) {
+            Ok(ast) => ast,
+            Err(errors) => {
+                for e in errors {
+                    eprintln!("error: could not parse {path}: {e}");
+                }
+
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        parser::ast::luau::strip_types(&mut ast);
+
+        let diagnostics = match lint::lint(&source, &ast, &options) {
+            Ok(diagnostics) => diagnostics,
+            Err(errors) => {
+                for e in errors {
+                    eprintln!("error: could not compile {path}: {e}");
+                }
+
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+
+        for diagnostic in diagnostics.iter() {
+            eprintln!("{}", diagnostic.render(path, &source));
+        }
+
+        match diagnostics.len() {
+            0 => {}
+            1 => eprintln!("warning: `{path}` generated 1 warning"),
+            n => eprintln!("warning: `{path}` generated {n} warnings"),
+        }
+    }
 
-    let ast = parser::parse(&source_code).unwrap();
-    let cfg = cfg::translator::translate(&ast.block);
-    cfg::visualization::visualize(cfg);
+    status
 }
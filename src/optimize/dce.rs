@@ -0,0 +1,515 @@
+use std::collections::HashSet;
+
+use petgraph::{
+    stable_graph::NodeIndex,
+    visit::{Dfs, EdgeRef},
+    Direction,
+};
+
+use crate::{
+    cfg::{CFGEdge, CFGNode},
+    parser::ast::{
+        definition::{
+            AnonFunctionExpression, Expression, ExpressionKind, FunctionCallStatement, Identifier,
+            Statement, StatementKind, TableField, Variable,
+        },
+        visitor::{self, Visitor},
+    },
+    ssa::Ssa,
+};
+
+/// Whether evaluating an expression can do nothing but produce its value.
+/// Calls and indexing may run arbitrary code through metamethods, and so may
+/// reading a global, which indexes the environment. Operators are left alone
+/// too, since they raise an error on operands of the wrong type or call a
+/// metamethod on tables.
+fn is_pure(ssa: &Ssa, exp: &Expression) -> bool {
+    match &exp.kind {
+        ExpressionKind::Nil
+        | ExpressionKind::True
+        | ExpressionKind::False
+        | ExpressionKind::LiteralInteger(_)
+        | ExpressionKind::LiteralFloat(_)
+        | ExpressionKind::LiteralString(_)
+        | ExpressionKind::VariableArgument
+        | ExpressionKind::AnonFunctionDefinition(_) => true,
+        ExpressionKind::Variable(Variable::Identifier(identifier)) => {
+            ssa.is_value(identifier) || ssa.pinned.contains(identifier)
+        }
+        ExpressionKind::TableConstructor(fields) => fields.iter().all(|field| match field {
+            TableField::Value(value) | TableField::KeyValue(_, value) => is_pure(ssa, value),
+            // a nil or NaN key raises an error
+            TableField::IndexValue(key, value) => {
+                let is_valid_key = match key.kind {
+                    ExpressionKind::LiteralFloat(number) => !number.is_nan(),
+                    ExpressionKind::LiteralInteger(_)
+                    | ExpressionKind::LiteralString(_)
+                    | ExpressionKind::True
+                    | ExpressionKind::False => true,
+                    _ => false,
+                };
+
+                is_valid_key && is_pure(ssa, value)
+            }
+        }),
+        ExpressionKind::Parenthesized(exp)
+        | ExpressionKind::TypeAssertion(exp, _)
+        | ExpressionKind::Not(exp) => is_pure(ssa, exp),
+        ExpressionKind::And(a, b) | ExpressionKind::Or(a, b) => is_pure(ssa, a) && is_pure(ssa, b),
+        ExpressionKind::If(exp) => {
+            is_pure(ssa, &exp.condition)
+                && is_pure(ssa, &exp.expression)
+                && exp.elseif_expressions.iter().all(|elseif| {
+                    is_pure(ssa, &elseif.condition) && is_pure(ssa, &elseif.expression)
+                })
+                && is_pure(ssa, &exp.else_expression)
+        }
+        _ => false,
+    }
+}
+
+fn is_multiple(exp: &Expression) -> bool {
+    matches!(
+        exp.kind,
+        ExpressionKind::FunctionCall(_) | ExpressionKind::VariableArgument
+    )
+}
+
+/// The statement that makes the same call as an expression, if it is one.
+fn call_statement(exp: &Expression) -> Option<Statement> {
+    match &exp.kind {
+        ExpressionKind::FunctionCall(call) => Some(Statement::new(
+            StatementKind::FunctionCall(FunctionCallStatement {
+                callee: call.callee.clone(),
+                arguments: call.arguments.clone(),
+            }),
+            exp.span,
+        )),
+        ExpressionKind::Parenthesized(exp) => call_statement(exp),
+        _ => None,
+    }
+}
+
+/// Collects the versioned locals an expression reads. Nested functions only
+/// refer to pinned locals, so they are skipped.
+struct Reads<'a> {
+    ssa: &'a Ssa,
+    reads: Vec<Identifier>,
+}
+
+impl Visitor for Reads<'_> {
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        if self.ssa.is_value(identifier) {
+            self.reads.push(identifier.clone());
+        }
+    }
+
+    fn visit_anon_function(&mut self, _func: &AnonFunctionExpression) {}
+}
+
+/// The locals a statement writes, where `None` stands for a target that is
+/// not a versioned local, and the values assigned to them.
+fn assignment<'a>(
+    ssa: &Ssa,
+    stmt: &'a Statement,
+) -> Option<(Vec<Option<&'a Identifier>>, Vec<&'a Expression>)> {
+    let local = |var: &'a Variable| match var {
+        Variable::Identifier(identifier) if ssa.is_value(identifier) => Some(identifier),
+        _ => None,
+    };
+
+    match &stmt.kind {
+        StatementKind::LocalDeclaration(decl) => Some((
+            decl.identifier_list
+                .iter()
+                .map(|identifier| Some(identifier).filter(|name| ssa.is_value(name)))
+                .collect(),
+            decl.expression_list.iter().collect(),
+        )),
+        StatementKind::Assignment(assignment) => Some((
+            assignment.variable_list.iter().map(local).collect(),
+            assignment.expression_list.iter().collect(),
+        )),
+        StatementKind::FunctionDefinition(def) => Some((vec![local(&def.identifier)], vec![])),
+        StatementKind::LocalFunctionDefinition(def) => Some((vec![local(&def.identifier)], vec![])),
+        _ => None,
+    }
+}
+
+/// Whether a statement has to run even when none of the locals it writes
+/// are read.
+fn is_needed(ssa: &Ssa, live: &HashSet<Identifier>, stmt: &Statement) -> bool {
+    let Some((targets, values)) = assignment(ssa, stmt) else {
+        return true;
+    };
+
+    targets
+        .iter()
+        .any(|target| target.is_none_or(|name| live.contains(name)))
+        || values.iter().any(|exp| !is_pure(ssa, exp))
+}
+
+fn statement_reads(ssa: &Ssa, stmt: &Statement) -> Vec<Identifier> {
+    let mut reads = Reads { ssa, reads: vec![] };
+
+    match &stmt.kind {
+        StatementKind::LocalDeclaration(decl) => {
+            for exp in decl.expression_list.iter() {
+                reads.visit_expression(exp);
+            }
+        }
+        StatementKind::Assignment(assignment) => {
+            for var in assignment.variable_list.iter() {
+                if !matches!(var, Variable::Identifier(_)) {
+                    reads.visit_variable(var);
+                }
+            }
+
+            for exp in assignment.expression_list.iter() {
+                reads.visit_expression(exp);
+            }
+        }
+        StatementKind::FunctionDefinition(def) => {
+            if !matches!(def.identifier, Variable::Identifier(_)) {
+                reads.visit_variable(&def.identifier);
+            }
+        }
+        StatementKind::LocalFunctionDefinition(_) => {}
+        _ => visitor::walk_statement(&mut reads, stmt),
+    }
+
+    reads.reads
+}
+
+/// Finds the versioned locals whose value can still be observed: the ones
+/// read by a statement that has to run, a condition or a return, and the
+/// arguments of the phis of such locals.
+fn live_values(ssa: &Ssa) -> HashSet<Identifier> {
+    let mut live = HashSet::new();
+
+    loop {
+        let mut worklist = vec![];
+
+        for node in ssa.cfg.graph.node_indices() {
+            let Some(block) = ssa.cfg.graph[node].block() else {
+                continue;
+            };
+
+            for stmt in block.statements.iter() {
+                if is_needed(ssa, &live, stmt) {
+                    worklist.extend(statement_reads(ssa, stmt));
+                }
+            }
+
+            let mut reads = Reads { ssa, reads: vec![] };
+
+            if let Some(stmt) = &block.last_statement {
+                visitor::walk_last_statement(&mut reads, stmt);
+            }
+
+            if let Some(exp) = &block.condition {
+                reads.visit_expression(exp);
+            }
+
+            worklist.extend(reads.reads);
+        }
+
+        let count = live.len();
+
+        while let Some(name) = worklist.pop() {
+            if !live.insert(name.clone()) {
+                continue;
+            }
+
+            for phi in ssa.phis.values().flatten() {
+                if phi.target == name {
+                    worklist.extend(phi.arguments.iter().map(|(_, argument)| argument.clone()));
+                }
+            }
+        }
+
+        // a new live local can make the statement writing it needed
+        if live.len() == count {
+            return live;
+        }
+    }
+}
+
+/// Drops the entries whose flag in `remove` is set.
+fn remove_flagged<T>(list: &mut Vec<T>, remove: &[bool]) {
+    let mut i = 0;
+
+    list.retain(|_| {
+        i += 1;
+        !remove.get(i - 1).copied().unwrap_or(false)
+    });
+}
+
+/// Rewrites a statement without the locals that are never read, or returns
+/// the statements that replace it.
+fn sweep(ssa: &Ssa, live: &HashSet<Identifier>, mut stmt: Statement) -> Vec<Statement> {
+    let Some((targets, values)) = assignment(ssa, &stmt) else {
+        return vec![stmt];
+    };
+
+    let dead = targets
+        .iter()
+        .map(|target| target.is_some_and(|name| !live.contains(name)))
+        .collect::<Vec<_>>();
+
+    if matches!(
+        stmt.kind,
+        StatementKind::FunctionDefinition(_) | StatementKind::LocalFunctionDefinition(_)
+    ) {
+        return match dead[0] {
+            true => vec![],
+            false => vec![stmt],
+        };
+    }
+
+    // when nothing is read, only the calls among the values have to stay
+    if dead.iter().all(|&dead| dead) {
+        let calls = values
+            .iter()
+            .filter(|exp| !is_pure(ssa, exp))
+            .map(|exp| call_statement(exp))
+            .collect::<Option<Vec<_>>>();
+
+        if let Some(calls) = calls {
+            return calls;
+        }
+    }
+
+    // a value can go with its target unless it is the last one, spreading
+    // over several targets
+    let multiple = values.last().is_some_and(|exp| is_multiple(exp));
+    let remove = dead
+        .iter()
+        .enumerate()
+        .map(|(i, &dead)| {
+            dead && match values.get(i) {
+                Some(exp) => is_pure(ssa, exp) && !(multiple && i + 1 == values.len()),
+                None => !multiple,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if remove.iter().all(|&remove| remove) || !remove.contains(&true) {
+        return vec![stmt];
+    }
+
+    match &mut stmt.kind {
+        StatementKind::LocalDeclaration(decl) => {
+            if decl.attribute_list.len() == decl.identifier_list.len() {
+                remove_flagged(&mut decl.attribute_list, &remove);
+            }
+
+            if decl.type_list.len() == decl.identifier_list.len() {
+                remove_flagged(&mut decl.type_list, &remove);
+            }
+
+            remove_flagged(&mut decl.identifier_list, &remove);
+            remove_flagged(&mut decl.expression_list, &remove);
+        }
+        StatementKind::Assignment(assignment) => {
+            remove_flagged(&mut assignment.variable_list, &remove);
+            remove_flagged(&mut assignment.expression_list, &remove);
+        }
+        _ => unreachable!(),
+    }
+
+    vec![stmt]
+}
+
+/// Removes code whose effect can never be observed: the blocks no path from
+/// the entry leads to, the assignments to locals that are never read, along
+/// with the pure values assigned to them, the phis of such locals, and the
+/// tests of pure conditions whose edges all lead to the same block.
+///
+/// Only versioned locals are considered. Writes to globals, to table fields
+/// and to locals a closure captures always stay, and so do the calls among
+/// the values of a removed assignment.
+pub fn eliminate_dead_code(ssa: &mut Ssa) {
+    let mut reachable = HashSet::new();
+    let mut dfs = Dfs::new(&ssa.cfg.graph, ssa.cfg.entry);
+
+    while let Some(node) = dfs.next(&ssa.cfg.graph) {
+        reachable.insert(node);
+    }
+
+    let unreachable = ssa
+        .cfg
+        .graph
+        .node_indices()
+        .filter(|node| {
+            matches!(ssa.cfg.graph[*node], CFGNode::Block(_)) && !reachable.contains(node)
+        })
+        .collect::<Vec<_>>();
+
+    ssa.remove_blocks(unreachable);
+
+    let live = live_values(ssa);
+
+    for node in ssa.cfg.graph.node_indices().collect::<Vec<_>>() {
+        let Some(block) = ssa.cfg.graph[node].block() else {
+            continue;
+        };
+
+        let statements = block
+            .statements
+            .iter()
+            .flat_map(|stmt| sweep(ssa, &live, stmt.clone()))
+            .collect();
+
+        let targets = ssa
+            .cfg
+            .graph
+            .edges_directed(node, Direction::Outgoing)
+            .map(|edge| edge.target())
+            .collect::<HashSet<NodeIndex>>();
+
+        let is_pointless = block
+            .condition
+            .as_ref()
+            .is_some_and(|exp| targets.len() == 1 && is_pure(ssa, exp));
+
+        let block = ssa.cfg.graph[node].block_mut().unwrap();
+        block.statements = statements;
+
+        if is_pointless {
+            block.condition = None;
+
+            let mut edges = ssa
+                .cfg
+                .graph
+                .edges_directed(node, Direction::Outgoing)
+                .map(|edge| edge.id())
+                .collect::<Vec<_>>();
+            edges.sort();
+
+            ssa.cfg.graph[edges[0]] = CFGEdge::Fallthrough;
+            ssa.remove_edges(edges.split_off(1));
+        }
+    }
+
+    for phis in ssa.phis.values_mut() {
+        phis.retain(|phi| live.contains(&phi.target));
+    }
+
+    ssa.phis.retain(|_, phis| !phis.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{ast::definition::Parameter, parse},
+        ssa,
+    };
+
+    use super::eliminate_dead_code;
+
+    /// The statements of the function body after the pass, one per line.
+    fn eliminated(source: &str) -> Vec<String> {
+        let chunk = parse(source).unwrap();
+        let parameters = [Parameter::Identifier("p".to_string())];
+        let mut ssa = ssa::construct(&parameters, &chunk.block, Default::default()).unwrap();
+
+        eliminate_dead_code(&mut ssa);
+
+        ssa.cfg
+            .graph
+            .node_weights()
+            .filter_map(|node| node.block())
+            .flat_map(|block| {
+                format!("{block:?}")
+                    .lines()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn pure_values_and_dead_stores_removed() {
+        assert_eq!(
+            eliminated(
+                "local s = { 1, x = 'a' }
+                local b = not p and 1
+                local dead = 3
+                dead = 4
+                local f = function() end"
+            ),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn calls_kept() {
+        assert_eq!(
+            eliminated(
+                "local r = f()
+                local a, b = 1, g(p)
+                print(p)"
+            ),
+            vec!["f()", "g(p@1#0)", "print(p@1#0)"]
+        );
+    }
+
+    #[test]
+    fn table_indexing_kept() {
+        // indexing and arithmetic on unknown values may run a metamethod
+        assert_eq!(
+            eliminated(
+                "local t = {}
+                local n = #t
+                local k = t.x
+                local q = p.q
+                local sum = p + 1"
+            ),
+            vec![
+                "local t@1#1 = {}",
+                "local n@1#1 = #t@1#1",
+                "local k@1#1 = t@1#1.x",
+                "local q@1#1 = p@1#0.q",
+                "local sum@1#1 = p@1#0 + 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn global_and_field_writes_kept() {
+        assert_eq!(
+            eliminated(
+                "local t = {}
+                t.y = 1
+                t[p] = 2
+                g = 3"
+            ),
+            vec![
+                "local t@1#1 = {}",
+                "t@1#1.y = 1",
+                "t@1#1[p@1#0] = 2",
+                "g = 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn captured_locals_kept() {
+        assert_eq!(
+            eliminated(
+                "local x = 1
+                x = 2
+                return function() return x end"
+            ),
+            vec![
+                "local x@1 = 1",
+                "x@1 = 2",
+                "return function()",
+                "    return x@1",
+                "end",
+            ]
+        );
+    }
+}
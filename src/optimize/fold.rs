@@ -0,0 +1,613 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    parser::{
+        ast::{
+            definition::{
+                Attribute, Chunk, Expression, ExpressionKind, Identifier,
+                LocalDeclarationStatement, LuaString, Variable,
+            },
+            visitor::{self, VisitorMut},
+        },
+        number::{self, Number},
+    },
+    scope::{self, Access, BindingKind, Scopes},
+};
+
+/// Integers beyond this magnitude are not exact as doubles, which is all Lua
+/// 5.1 and 5.2 have, so results past it are left to the runtime.
+pub(super) const MAX_EXACT_INTEGER: i64 = 1 << 53;
+
+/// Lua 5.1 turns numbers into strings with `%.14g`, which only prints
+/// integers below this exactly.
+const MAX_PRINTED_INTEGER: i64 = 100_000_000_000_000;
+
+#[derive(Clone, PartialEq, Debug)]
+enum Value {
+    Nil,
+    Boolean(bool),
+    Number(Number),
+    String(LuaString),
+}
+
+impl Value {
+    fn of(exp: &Expression) -> Option<Value> {
+        let value = match &exp.kind {
+            ExpressionKind::Nil => Value::Nil,
+            ExpressionKind::True => Value::Boolean(true),
+            ExpressionKind::False => Value::Boolean(false),
+            // a negative literal is a hexadecimal one that wraps around, which
+            // only does so in the versions with integers
+            ExpressionKind::LiteralInteger(number) if *number < 0 => return None,
+            ExpressionKind::LiteralInteger(number) => Value::Number(Number::Integer(*number)),
+            ExpressionKind::LiteralFloat(number) => Value::Number(Number::Float(*number)),
+            ExpressionKind::LiteralString(string) => Value::String(string.clone()),
+            ExpressionKind::Negative(inner) => match inner.kind {
+                ExpressionKind::LiteralInteger(number)
+                    if number > 0 && number <= MAX_EXACT_INTEGER =>
+                {
+                    Value::Number(Number::Integer(-number))
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    fn into_kind(self) -> ExpressionKind {
+        match self {
+            Value::Nil => ExpressionKind::Nil,
+            Value::Boolean(true) => ExpressionKind::True,
+            Value::Boolean(false) => ExpressionKind::False,
+            // written as a negation, since a negative integer literal stands
+            // for a wrapped hexadecimal one
+            Value::Number(Number::Integer(number)) if number < 0 => {
+                ExpressionKind::Negative(Box::new(Expression::new(
+                    ExpressionKind::LiteralInteger(-number),
+                    Default::default(),
+                )))
+            }
+            Value::Number(Number::Integer(number)) => ExpressionKind::LiteralInteger(number),
+            Value::Number(Number::Float(number)) => ExpressionKind::LiteralFloat(number),
+            Value::String(string) => ExpressionKind::LiteralString(string),
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    /// The number an arithmetic operator sees. Strings are converted like
+    /// `tonumber` does, which fails on anything that is not a numeral.
+    fn to_number(&self) -> Option<Number> {
+        match self {
+            Value::Number(number) => Some(*number),
+            Value::String(string) => {
+                let text = std::str::from_utf8(string.as_bytes()).ok()?;
+                let text = text.trim_matches(|c: char| c.is_ascii_whitespace());
+
+                // digit separators and binary numerals are Luau syntax only
+                let (negative, digits) = match text.as_bytes().first() {
+                    Some(b'-') => (true, &text[1..]),
+                    Some(b'+') => (false, &text[1..]),
+                    _ => (false, text),
+                };
+
+                if digits.contains('_') || digits.starts_with("0b") || digits.starts_with("0B") {
+                    return None;
+                }
+
+                match number::parse(digits)? {
+                    // `"-0"` is a negative zero in the versions without
+                    // integers
+                    Number::Integer(0) if negative => None,
+                    Number::Integer(number) if negative => {
+                        Some(Number::Integer(number.wrapping_neg()))
+                    }
+                    Number::Float(number) if negative => Some(Number::Float(-number)),
+                    number => Some(number),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The integer a bitwise operator sees, which a float only has when it
+    /// has no fractional part.
+    fn to_integer(&self) -> Option<i64> {
+        match self.to_number()? {
+            Number::Integer(number) => Some(number),
+            Number::Float(number) => float_to_integer(number),
+        }
+    }
+
+    /// The text a concatenation sees. Floats are formatted differently by
+    /// every version, so they are not folded.
+    fn to_text(&self) -> Option<Vec<u8>> {
+        match self {
+            Value::String(string) => Some(string.0.clone()),
+            Value::Number(Number::Integer(number)) if number.abs() < MAX_PRINTED_INTEGER => {
+                Some(number.to_string().into_bytes())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn float_to_integer(number: f64) -> Option<i64> {
+    let fits = (-(2f64.powi(63))..2f64.powi(63)).contains(&number);
+    (fits && number.fract() == 0.0).then_some(number as i64)
+}
+
+fn exact_integer(number: i64) -> Option<Number> {
+    (number.abs() <= MAX_EXACT_INTEGER).then_some(Number::Integer(number))
+}
+
+/// Lua 5.1, 5.2 and Luau compute a zero product or quotient of operands with
+/// different signs as `-0`, which no integer stands for, and which makes
+/// `1 / x` negative infinity.
+fn without_negative_zero(result: i64, a: i64, b: i64) -> Option<i64> {
+    (result != 0 || (a < 0) == (b < 0)).then_some(result)
+}
+
+fn to_float(number: Number) -> Option<f64> {
+    match number {
+        Number::Integer(number) if number.abs() <= MAX_EXACT_INTEGER => Some(number as f64),
+        Number::Integer(_) => None,
+        Number::Float(number) => Some(number),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    FloorDivide,
+    Modulo,
+    Power,
+}
+
+/// Computes an arithmetic operation the way Lua 5.3 and later do, as long as
+/// the result is the same in the versions without integers.
+fn arithmetic(operator: Arithmetic, a: Number, b: Number) -> Option<Number> {
+    use Arithmetic as A;
+
+    if let (Number::Integer(a), Number::Integer(b)) = (a, b) {
+        match operator {
+            A::Add => return exact_integer(a.checked_add(b)?),
+            A::Subtract => return exact_integer(a.checked_sub(b)?),
+            A::Multiply => return exact_integer(without_negative_zero(a.checked_mul(b)?, a, b)?),
+            // integer division by zero raises an error
+            A::FloorDivide => {
+                let quotient = a.checked_div(b)?;
+                let floor = match a % b != 0 && (a < 0) != (b < 0) {
+                    true => quotient - 1,
+                    false => quotient,
+                };
+
+                return exact_integer(without_negative_zero(floor, a, b)?);
+            }
+            A::Modulo => {
+                let remainder = a.checked_rem(b)?;
+                let modulo = match remainder != 0 && (remainder < 0) != (b < 0) {
+                    true => remainder + b,
+                    false => remainder,
+                };
+
+                return exact_integer(modulo);
+            }
+            A::Divide | A::Power => {}
+        }
+    }
+
+    let (a, b) = (to_float(a)?, to_float(b)?);
+
+    let result = match operator {
+        A::Add => a + b,
+        A::Subtract => a - b,
+        A::Multiply => a * b,
+        A::Divide => a / b,
+        A::FloorDivide => (a / b).floor(),
+        // older versions compute `a - floor(a / b) * b`, which only agrees
+        // for finite operands
+        A::Modulo if !a.is_finite() || !b.is_finite() || b == 0.0 => return None,
+        A::Modulo => {
+            let modulo = a % b;
+
+            match modulo != 0.0 && (modulo < 0.0) != (b < 0.0) {
+                true => modulo + b,
+                false => modulo,
+            }
+        }
+        A::Power => a.powf(b),
+    };
+
+    Some(Number::Float(result))
+}
+
+#[derive(Clone, Copy)]
+enum Bitwise {
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+fn shift_left(a: i64, b: i64) -> i64 {
+    match b {
+        b if b <= -64 || b >= 64 => 0,
+        b if b >= 0 => ((a as u64) << b) as i64,
+        b => ((a as u64) >> -b) as i64,
+    }
+}
+
+fn bitwise(operator: Bitwise, a: i64, b: i64) -> i64 {
+    match operator {
+        Bitwise::And => a & b,
+        Bitwise::Or => a | b,
+        Bitwise::Xor => a ^ b,
+        Bitwise::ShiftLeft => shift_left(a, b),
+        Bitwise::ShiftRight => shift_left(a, b.checked_neg().unwrap_or(64)),
+    }
+}
+
+/// Orders two numbers by their mathematical value, even when one of them is
+/// an integer that a float cannot represent.
+fn compare_numbers(a: Number, b: Number) -> Option<Ordering> {
+    match (a, b) {
+        (Number::Integer(a), Number::Integer(b)) => Some(a.cmp(&b)),
+        (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+        (Number::Integer(a), Number::Float(b)) => compare_integer_float(a, b),
+        (Number::Float(a), Number::Integer(b)) => {
+            compare_integer_float(b, a).map(Ordering::reverse)
+        }
+    }
+}
+
+fn compare_integer_float(a: i64, b: f64) -> Option<Ordering> {
+    if b.is_nan() {
+        return None;
+    }
+
+    match float_to_integer(b.floor()) {
+        Some(floor) => Some(a.cmp(&floor).then(match b.fract() == 0.0 {
+            true => Ordering::Equal,
+            false => Ordering::Less,
+        })),
+        None if b > 0.0 => Some(Ordering::Less),
+        None => Some(Ordering::Greater),
+    }
+}
+
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => compare_numbers(*a, *b) == Some(Ordering::Equal),
+        (a, b) => a == b,
+    }
+}
+
+/// Orders numbers with numbers and strings with strings. Anything else
+/// raises an error.
+fn compare(a: &Value, b: &Value) -> Option<Option<Ordering>> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Some(compare_numbers(*a, *b)),
+        (Value::String(a), Value::String(b)) => Some(Some(a.cmp(b))),
+        _ => None,
+    }
+}
+
+/// A value that may only be the first of several has to be kept in
+/// parentheses when it takes the place of a single value.
+fn single(exp: &Expression) -> ExpressionKind {
+    match exp.kind {
+        ExpressionKind::FunctionCall(_) | ExpressionKind::VariableArgument => {
+            ExpressionKind::Parenthesized(Box::new(exp.clone()))
+        }
+        _ => exp.kind.clone(),
+    }
+}
+
+/// Whether the expression is a literal nil, boolean, number or string.
+pub(super) fn is_constant(exp: &Expression) -> bool {
+    Value::of(exp).is_some()
+}
+
+/// Whether a constant counts as true in a condition.
+pub(super) fn is_truthy(exp: &Expression) -> bool {
+    Value::of(exp).is_some_and(|value| value.is_truthy())
+}
+
+/// Evaluates an expression whose operands are constants. Returns `None` when
+/// the expression is not constant, or would raise an error at runtime.
+pub(super) fn fold(exp: &Expression) -> Option<ExpressionKind> {
+    use ExpressionKind as E;
+
+    let number = |value: Number| Some(Value::Number(value).into_kind());
+    let boolean = |value: bool| Some(Value::Boolean(value).into_kind());
+
+    let arithmetic_operator = match &exp.kind {
+        E::Addition(..) => Some(Arithmetic::Add),
+        E::Subtraction(..) => Some(Arithmetic::Subtract),
+        E::Multiplication(..) => Some(Arithmetic::Multiply),
+        E::Division(..) => Some(Arithmetic::Divide),
+        E::FloorDivision(..) => Some(Arithmetic::FloorDivide),
+        E::Modulo(..) => Some(Arithmetic::Modulo),
+        E::Exponentiation(..) => Some(Arithmetic::Power),
+        _ => None,
+    };
+
+    let bitwise_operator = match &exp.kind {
+        E::BitwiseAnd(..) => Some(Bitwise::And),
+        E::BitwiseOr(..) => Some(Bitwise::Or),
+        E::BitwiseXor(..) => Some(Bitwise::Xor),
+        E::ShiftLeft(..) => Some(Bitwise::ShiftLeft),
+        E::ShiftRight(..) => Some(Bitwise::ShiftRight),
+        _ => None,
+    };
+
+    match &exp.kind {
+        E::Parenthesized(inner) => Value::of(inner).map(Value::into_kind),
+        E::Not(inner) => boolean(!Value::of(inner)?.is_truthy()),
+        E::Negative(inner) => match Value::of(inner)?.to_number()? {
+            // `-0` is a negative zero in the versions without integers
+            Number::Integer(0) => None,
+            Number::Integer(value) => number(exact_integer(value.checked_neg()?)?),
+            Number::Float(value) => number(Number::Float(-value)),
+        },
+        E::BitwiseNot(inner) => number(Number::Integer(!Value::of(inner)?.to_integer()?)),
+        E::Length(inner) => match Value::of(inner)? {
+            Value::String(string) => number(Number::Integer(string.as_bytes().len() as i64)),
+            _ => None,
+        },
+        // the right operand is kept when the left one does not decide
+        E::And(a, b) => match Value::of(a)? {
+            value if value.is_truthy() => Some(single(b)),
+            value => Some(value.into_kind()),
+        },
+        E::Or(a, b) => match Value::of(a)? {
+            value if value.is_truthy() => Some(value.into_kind()),
+            _ => Some(single(b)),
+        },
+        // a false condition followed by `elseif` branches is left as it is
+        E::If(exp) => match Value::of(&exp.condition)?.is_truthy() {
+            true => Some(single(&exp.expression)),
+            false if exp.elseif_expressions.is_empty() => Some(single(&exp.else_expression)),
+            false => None,
+        },
+        E::Concatenation(a, b) => {
+            let mut text = Value::of(a)?.to_text()?;
+            text.extend(Value::of(b)?.to_text()?);
+
+            Some(E::LiteralString(LuaString(text)))
+        }
+        E::Equal(a, b) => boolean(equals(&Value::of(a)?, &Value::of(b)?)),
+        E::NotEqual(a, b) => boolean(!equals(&Value::of(a)?, &Value::of(b)?)),
+        E::LessThan(a, b) => {
+            boolean(compare(&Value::of(a)?, &Value::of(b)?)? == Some(Ordering::Less))
+        }
+        E::GreaterThan(a, b) => {
+            boolean(compare(&Value::of(a)?, &Value::of(b)?)? == Some(Ordering::Greater))
+        }
+        E::LessThanOrEqual(a, b) => boolean(matches!(
+            compare(&Value::of(a)?, &Value::of(b)?)?,
+            Some(Ordering::Less | Ordering::Equal)
+        )),
+        E::GreaterThanOrEqual(a, b) => boolean(matches!(
+            compare(&Value::of(a)?, &Value::of(b)?)?,
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        E::Addition(a, b)
+        | E::Subtraction(a, b)
+        | E::Multiplication(a, b)
+        | E::Division(a, b)
+        | E::FloorDivision(a, b)
+        | E::Modulo(a, b)
+        | E::Exponentiation(a, b) => {
+            let a = Value::of(a)?.to_number()?;
+            let b = Value::of(b)?.to_number()?;
+
+            number(arithmetic(arithmetic_operator?, a, b)?)
+        }
+        E::BitwiseAnd(a, b)
+        | E::BitwiseOr(a, b)
+        | E::BitwiseXor(a, b)
+        | E::ShiftLeft(a, b)
+        | E::ShiftRight(a, b) => {
+            let a = Value::of(a)?.to_integer()?;
+            let b = Value::of(b)?.to_integer()?;
+
+            number(Number::Integer(bitwise(bitwise_operator?, a, b)))
+        }
+        _ => None,
+    }
+}
+
+/// Folds expressions bottom up, replacing every read of a local that holds a
+/// constant for its whole lifetime with that constant.
+struct Folder<'a> {
+    scopes: &'a Scopes,
+    written: HashSet<scope::BindingId>,
+    /// The constants of the locals seen so far, by unique name.
+    constants: HashMap<Identifier, ExpressionKind>,
+}
+
+impl VisitorMut for Folder<'_> {
+    fn visit_local_declaration_mut(&mut self, stmt: &mut LocalDeclarationStatement) {
+        visitor::walk_local_declaration_mut(self, stmt);
+
+        let count = stmt.expression_list.len();
+        let is_multiple = stmt.expression_list.last().is_some_and(|exp| {
+            matches!(
+                exp.kind,
+                ExpressionKind::FunctionCall(_) | ExpressionKind::VariableArgument
+            )
+        });
+
+        for (i, identifier) in stmt.identifier_list.iter().enumerate() {
+            let Some(id) = self.scopes.lookup(identifier) else {
+                continue;
+            };
+
+            let binding = self.scopes.binding(id);
+
+            if binding.kind != BindingKind::Local
+                || binding.attribute == Some(Attribute::Close)
+                || self.written.contains(&id)
+            {
+                continue;
+            }
+
+            let value = match stmt.expression_list.get(i) {
+                Some(exp) => Value::of(exp),
+                // missing values are nil, unless a call fills them in
+                None if !is_multiple || count == 0 => Some(Value::Nil),
+                None => None,
+            };
+
+            if let Some(value) = value {
+                self.constants.insert(identifier.clone(), value.into_kind());
+            }
+        }
+    }
+
+    fn visit_expression_mut(&mut self, exp: &mut Expression) {
+        visitor::walk_expression_mut(self, exp);
+
+        if let ExpressionKind::Variable(Variable::Identifier(identifier)) = &exp.kind {
+            if let Some(constant) = self.constants.get(identifier) {
+                exp.kind = constant.clone();
+            }
+
+            return;
+        }
+
+        if let Some(kind) = fold(exp) {
+            exp.kind = kind;
+        }
+    }
+}
+
+/// Gives every local its name from the source again.
+struct Restorer<'a> {
+    scopes: &'a Scopes,
+}
+
+impl VisitorMut for Restorer<'_> {
+    fn visit_identifier_mut(&mut self, identifier: &mut Identifier) {
+        if let Some(id) = self.scopes.lookup(identifier) {
+            *identifier = self.scopes.binding(id).name.clone();
+        }
+    }
+}
+
+/// Evaluates constant expressions following the coercions of Lua: strings
+/// in arithmetic are converted to numbers, and integers in concatenations to
+/// strings. Expressions that would raise an error, like `17 + "klema"`, are
+/// left for the runtime to raise.
+///
+/// Locals that are never assigned after their declaration are replaced by
+/// their value where it is a constant.
+pub fn fold_constants(chunk: &mut Chunk) {
+    // locals are told apart by their unique names while folding
+    let scopes = scope::rename(&mut [], &mut chunk.block);
+
+    let written = scopes
+        .references
+        .iter()
+        .filter(|reference| reference.access == Access::Write)
+        .filter_map(|reference| reference.resolution.binding())
+        .collect();
+
+    let mut folder = Folder {
+        scopes: &scopes,
+        written,
+        constants: HashMap::new(),
+    };
+
+    folder.visit_block_mut(&mut chunk.block);
+
+    Restorer { scopes: &scopes }.visit_block_mut(&mut chunk.block);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{emit::lua::print, parser::parse};
+
+    use super::fold_constants;
+
+    fn folded(source: &str) -> String {
+        let mut chunk = parse(source).unwrap();
+        fold_constants(&mut chunk);
+
+        print(&chunk)
+    }
+
+    #[test]
+    fn coercion() {
+        // floats are formatted differently by every version, so they are
+        // not concatenated
+        assert_eq!(
+            folded(
+                r#"return "10" + 1, "0x10" * 2, " 5 " - 1, 10 / "4", 1 .. 2, "3" .. 4, 1.5 .. """#
+            ),
+            r#"return 11, 32, 4, 2.5, "12", "34", 1.5 .. """#
+        );
+    }
+
+    #[test]
+    fn runtime_errors_left() {
+        let source = r#"return 17 + "klema", {} .. "", "a" < 1, #nil, -"x", nil .. "a", 1 < nil"#;
+        assert_eq!(folded(source), source);
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(
+            folded(
+                r#"local a = 2
+                local b = a * 3
+                local c = 2 - 3
+                return (b + 1) .. "x", not (1 < 2 and nil), c * 2, -(-c)"#
+            ),
+            r#"local a = 2
+local b = 6
+local c = -1
+return "7x", true, -2, -1"#
+        );
+    }
+
+    #[test]
+    fn negative_zero_left() {
+        // these are `-0` in the versions without integers, where `1 / -0`
+        // is negative infinity
+        assert_eq!(
+            folded(r#"return -0, 0 * -1, -1 * 0, -"0", "-0" * 1, 1 / (0 * -1), 0 * 1, 0 % -1"#),
+            r#"return -0, 0 * -1, -1 * 0, -"0", "-0" * 1, 1 / (0 * -1), 0, 0"#
+        );
+    }
+
+    #[test]
+    fn wrapped_literals_left() {
+        let source = "return 0xffffffffffffffff + 1";
+        assert_eq!(folded(source), source);
+    }
+
+    #[test]
+    #[cfg(feature = "lua53")]
+    fn integer_operators() {
+        assert_eq!(
+            folded(
+                r#"return 7 // 2, -7 // 2, 7 % -3, 3 & "5", 1 << 64, 0 // -1, 1 // 0, 1 % 0, 1 & 1.5"#
+            ),
+            r#"return 3, -4, -2, 1, 0, 0 // -1, 1 // 0, 1 % 0, 1 & 1.5"#
+        );
+    }
+}
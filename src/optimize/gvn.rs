@@ -0,0 +1,362 @@
+use std::collections::{HashMap, HashSet};
+
+use petgraph::stable_graph::NodeIndex;
+
+use crate::{
+    cfg::analysis::{self, DominatorTree},
+    ir::{
+        BinaryOperator, Comparison, Constant, Function, Instruction, Label, Module, Operand,
+        Register, UnaryOperator,
+    },
+};
+
+use super::types::{self, Type, Types};
+
+/// An operand compared by its value. Floats are compared by their bits.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+enum Value {
+    Register(Register),
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Float(u64),
+    String(Vec<u8>),
+}
+
+/// A computation, identified by its operator and the values it works on.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum Key {
+    Unary(UnaryOperator, Value),
+    Binary(BinaryOperator, Value, Value),
+    Compare(Comparison, Value, Value),
+    /// A field of a table, after the given number of writes to it.
+    GetTable(Register, Value, usize),
+    /// The length of a table, after the given number of writes to it.
+    Length(Register, usize),
+}
+
+fn is_commutative(operator: BinaryOperator) -> bool {
+    matches!(
+        operator,
+        BinaryOperator::Add
+            | BinaryOperator::Multiply
+            | BinaryOperator::BitwiseAnd
+            | BinaryOperator::BitwiseOr
+            | BinaryOperator::BitwiseXor
+    )
+}
+
+struct Numbering<'a> {
+    function: &'a mut Function,
+    stable: HashSet<Register>,
+    types: Types,
+    /// The register first known to hold the value of a stable register.
+    numbers: HashMap<Register, Register>,
+    /// The computations done in the blocks dominating the current one.
+    available: HashMap<Key, Register>,
+    /// How many times each local table was written so far.
+    writes: HashMap<Register, usize>,
+}
+
+impl Numbering<'_> {
+    /// `None` for a register that may hold different values over time.
+    fn value(&self, operand: &Operand) -> Option<Value> {
+        let value = match operand {
+            Operand::Register(register) if self.stable.contains(register) => {
+                Value::Register(self.numbers.get(register).copied().unwrap_or(*register))
+            }
+            Operand::Register(_) => return None,
+            Operand::Constant(Constant::Nil) => Value::Nil,
+            Operand::Constant(Constant::Boolean(value)) => Value::Boolean(*value),
+            Operand::Constant(Constant::Integer(value)) => Value::Integer(*value),
+            Operand::Constant(Constant::Float(value)) => Value::Float(value.to_bits()),
+            Operand::Constant(Constant::String(value)) => Value::String(value.0.clone()),
+        };
+
+        Some(value)
+    }
+
+    /// The computation an instruction does, if it cannot run a metamethod,
+    /// and so gives the same result every time. Errors do not matter, since
+    /// a computation that raises one is never followed by the one it would
+    /// replace.
+    fn key(&self, instruction: &Instruction) -> Option<Key> {
+        if let Instruction::GetTable { table, key, .. } = instruction {
+            let (Some(Type::Table), Some(Value::Register(table))) =
+                (self.types.of(table), self.value(table))
+            else {
+                return None;
+            };
+
+            let writes = self.writes.get(&table).copied().unwrap_or(0);
+            return Some(Key::GetTable(table, self.value(key)?, writes));
+        }
+
+        if let Instruction::Unary {
+            operator: UnaryOperator::Length,
+            operand,
+            ..
+        } = instruction
+        {
+            if let (Some(Type::Table), Some(Value::Register(table))) =
+                (self.types.of(operand), self.value(operand))
+            {
+                let writes = self.writes.get(&table).copied().unwrap_or(0);
+                return Some(Key::Length(table, writes));
+            }
+        }
+
+        self.types.result(instruction)?;
+
+        match instruction {
+            Instruction::Unary {
+                operator, operand, ..
+            } => Some(Key::Unary(*operator, self.value(operand)?)),
+            Instruction::Binary {
+                operator,
+                left,
+                right,
+                ..
+            } => {
+                let (mut left, mut right) = (self.value(left)?, self.value(right)?);
+
+                if is_commutative(*operator) && right < left {
+                    std::mem::swap(&mut left, &mut right);
+                }
+
+                Some(Key::Binary(*operator, left, right))
+            }
+            Instruction::Compare {
+                operator,
+                left,
+                right,
+                ..
+            } => {
+                let (operator, left, right) = match operator {
+                    Comparison::GreaterThan => (Comparison::LessThan, right, left),
+                    Comparison::GreaterThanOrEqual => (Comparison::LessThanOrEqual, right, left),
+                    _ => (*operator, left, right),
+                };
+
+                let (mut left, mut right) = (self.value(left)?, self.value(right)?);
+
+                if matches!(operator, Comparison::Equal | Comparison::NotEqual) && right < left {
+                    std::mem::swap(&mut left, &mut right);
+                }
+
+                Some(Key::Compare(operator, left, right))
+            }
+            _ => None,
+        }
+    }
+
+    /// Numbers the instructions of a block, replacing the computations that
+    /// are available with a copy. Returns the computations it made
+    /// available.
+    fn block(&mut self, b: usize) -> Vec<Key> {
+        let mut added = vec![];
+
+        for i in 0..self.function.blocks[b].instructions.len() {
+            let instruction = &self.function.blocks[b].instructions[i];
+
+            match instruction {
+                Instruction::Move { target, source } => {
+                    if self.stable.contains(target) {
+                        if let Some(Value::Register(register)) = self.value(source) {
+                            self.numbers.insert(*target, register);
+                        }
+                    }
+
+                    continue;
+                }
+                Instruction::SetTable {
+                    table: Operand::Register(table),
+                    ..
+                }
+                | Instruction::SetList { table, .. } => {
+                    if let Some(Value::Register(table)) = self.value(&Operand::Register(*table)) {
+                        *self.writes.entry(table).or_default() += 1;
+                    }
+
+                    continue;
+                }
+                _ => {}
+            }
+
+            let Some(key) = self.key(instruction) else {
+                continue;
+            };
+
+            let target = instruction.targets()[0];
+
+            let number = match self.available.get(&key) {
+                Some(&leader) => {
+                    self.function.blocks[b].instructions[i] = Instruction::Move {
+                        target,
+                        source: Operand::Register(leader),
+                    };
+
+                    leader
+                }
+                None if self.stable.contains(&target) => {
+                    self.available.insert(key.clone(), target);
+                    added.push(key);
+
+                    target
+                }
+                None => continue,
+            };
+
+            if self.stable.contains(&target) {
+                self.numbers.insert(target, number);
+            }
+        }
+
+        added
+    }
+
+    /// Numbers the blocks below `node` in the dominator tree, where the
+    /// computations of the block stay available.
+    fn visit(&mut self, dominators: &DominatorTree, node: NodeIndex) {
+        let added = match Label::from_node(node) {
+            Some(label) => self.block(label.0),
+            None => vec![],
+        };
+
+        for &child in dominators.children(node) {
+            self.visit(dominators, child);
+        }
+
+        for key in added {
+            self.available.remove(&key);
+        }
+    }
+}
+
+/// Computes every repeated computation only once, in the block that
+/// dominates the others, which copy its result instead. Only computations
+/// on registers written once are considered, and only when they cannot run
+/// a metamethod: arithmetic on numbers, concatenation of strings and
+/// numbers, comparisons that never reach `__eq`, `__lt` or `__le`, `not`,
+/// and the length and fields of tables that nothing else can see.
+pub fn eliminate_common_subexpressions(module: &mut Module) {
+    for function in module.functions.iter_mut() {
+        let cfg = function.cfg();
+        let dominators = analysis::dominators(&cfg);
+
+        let stable = types::stable_registers(function);
+        let types = types::infer(function, types::local_tables(function, &stable));
+
+        let mut numbering = Numbering {
+            function,
+            stable,
+            types,
+            numbers: HashMap::new(),
+            available: HashMap::new(),
+            writes: HashMap::new(),
+        };
+
+        numbering.visit(&dominators, cfg.entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ir, parser::parse};
+
+    use super::eliminate_common_subexpressions;
+
+    fn numbered(source: &str) -> String {
+        let chunk = parse(source).unwrap();
+        let mut module = ir::lower(&chunk, Default::default()).unwrap();
+
+        eliminate_common_subexpressions(&mut module);
+        format!("{module:?}")
+    }
+
+    fn count(module: &str, operator: &str) -> usize {
+        module.matches(&format!(" = {operator} ")).count()
+    }
+
+    #[test]
+    fn length_of_local_table_reused() {
+        let module = numbered(
+            "local t = { 1, 2 }
+            return #t, #t",
+        );
+
+        assert_eq!(count(&module, "Length"), 1, "{module}");
+    }
+
+    #[test]
+    fn length_of_escaping_table_kept() {
+        // `g` may change the table or give it a metatable with `__len`
+        let module = numbered(
+            "local t = {}
+            g(t)
+            return #t, #t",
+        );
+
+        assert_eq!(count(&module, "Length"), 2, "{module}");
+    }
+
+    #[test]
+    fn length_of_table_with_metatable_kept() {
+        let module = numbered(
+            "local t = setmetatable({}, m)
+            return #t, #t",
+        );
+
+        assert_eq!(count(&module, "Length"), 2, "{module}");
+    }
+
+    #[test]
+    fn length_of_table_written_elsewhere_kept() {
+        let module = numbered(
+            "local t = {}
+            local a = #t
+            if p then t[1] = 1 end
+            return a, #t",
+        );
+
+        assert_eq!(count(&module, "Length"), 2, "{module}");
+    }
+
+    #[test]
+    fn length_after_write_recomputed() {
+        // `a` is 1 and `b` is 2, while `c` is still `b`
+        let module = numbered(
+            "local t = {}
+            t[1] = 1
+            local a = #t
+            t[2] = 2
+            local b = #t
+            local c = #t
+            print(a, b, c)",
+        );
+
+        assert_eq!(count(&module, "Length"), 2, "{module}");
+    }
+
+    #[test]
+    fn arithmetic_on_numbers_reused() {
+        let module = numbered(
+            "local x = 1
+            for i = 1, 2 do
+                print(i * 2 + x, i * 2 + x)
+            end",
+        );
+
+        assert_eq!(count(&module, "Multiply"), 1, "{module}");
+        // the other addition steps the loop
+        assert_eq!(count(&module, "Add"), 2, "{module}");
+    }
+
+    #[test]
+    fn arithmetic_on_unknown_values_kept() {
+        // a global may hold a table with `__add`
+        let module = numbered("return p + 1, p + 1");
+
+        assert_eq!(count(&module, "Add"), 2, "{module}");
+    }
+}
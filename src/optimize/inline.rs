@@ -0,0 +1,477 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{
+    Block, Function, Instruction, Label, Module, Operand, Register, Results, Terminator, Upvalue,
+    Values,
+};
+
+pub struct Options {
+    /// Functions of at most this many instructions are inlined at every
+    /// call, larger ones only where they are called once. With `usize::MAX`,
+    /// every function that can be inlined is, which leaves little trace of
+    /// where one function ends and another begins.
+    pub max_instructions: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            max_instructions: 32,
+        }
+    }
+}
+
+/// A call to a closure that is created once and never used as a value.
+struct Candidate {
+    function: usize,
+    upvalues: Vec<Upvalue>,
+    /// The block and the index of the call.
+    call: (usize, usize),
+}
+
+fn size(function: &Function) -> usize {
+    function
+        .blocks
+        .iter()
+        .map(|block| block.instructions.len())
+        .sum()
+}
+
+fn uses_vararg(function: &Function) -> bool {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .any(|instruction| matches!(instruction, Instruction::VarArg { .. }))
+}
+
+/// Whether the arguments can be bound to the parameters without running the
+/// callee. The values of a list spread over the parameters are only known at
+/// runtime, so the ones left over for `...` cannot be told apart.
+fn can_bind(callee: &Function, arguments: &Values) -> bool {
+    arguments.fixed.len() >= callee.parameters.len()
+        || arguments.rest.is_none()
+        || !uses_vararg(callee)
+}
+
+/// Finds a call in `caller` to a local function that does not escape. Such
+/// a function cannot refer to itself either, since that needs a cell.
+fn find(module: &Module, caller: usize, options: &Options) -> Option<Candidate> {
+    let function = &module.functions[caller];
+
+    let mut definitions = HashMap::<Register, usize>::new();
+    let mut calls = HashMap::<Register, Vec<(usize, usize)>>::new();
+    let mut escaping = HashSet::new();
+
+    for (b, block) in function.blocks.iter().enumerate() {
+        for (i, instruction) in block.instructions.iter().enumerate() {
+            for target in instruction.targets() {
+                *definitions.entry(target).or_default() += 1;
+            }
+
+            match instruction {
+                Instruction::Call {
+                    function: Operand::Register(register),
+                    arguments,
+                    ..
+                } => {
+                    calls.entry(*register).or_default().push((b, i));
+                    escaping.extend(arguments.registers());
+                }
+                _ => escaping.extend(instruction.sources()),
+            }
+        }
+
+        escaping.extend(block.terminator.sources());
+    }
+
+    for instruction in function.blocks.iter().flat_map(|block| &block.instructions) {
+        let Instruction::Closure {
+            target,
+            function: index,
+            upvalues,
+        } = instruction
+        else {
+            continue;
+        };
+
+        if definitions[target] != 1 || escaping.contains(target) || *index == caller {
+            continue;
+        }
+
+        let Some(sites) = calls.get(target) else {
+            continue;
+        };
+
+        let callee = &module.functions[*index];
+
+        if sites.len() > 1 && size(callee) > options.max_instructions {
+            continue;
+        }
+
+        for &(b, i) in sites.iter() {
+            let Instruction::Call { arguments, .. } = &function.blocks[b].instructions[i] else {
+                unreachable!();
+            };
+
+            if can_bind(callee, arguments) {
+                return Some(Candidate {
+                    function: *index,
+                    upvalues: upvalues.clone(),
+                    call: (b, i),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Replaces a call with the blocks of the callee. The instructions after the
+/// call move to a block of their own, which every return of the callee
+/// jumps to once it has handed its values to the results of the call.
+fn inline(module: &mut Module, caller: usize, candidate: Candidate) {
+    let callee = &module.functions[candidate.function];
+    let callee_registers = callee.registers;
+    let parameters = callee.parameters.clone();
+    let mut blocks = callee.blocks.clone();
+
+    let function = &mut module.functions[caller];
+    let (b, i) = candidate.call;
+
+    let offset = function.registers;
+    function.registers += callee_registers;
+    let register = |register: Register| Register(register.0 + offset);
+
+    let continuation = Label(function.blocks.len());
+    let start = Label(continuation.0 + 1);
+
+    let block = &mut function.blocks[b];
+    let after = block.instructions.split_off(i + 1);
+    let Some(Instruction::Call {
+        arguments, results, ..
+    }) = block.instructions.pop()
+    else {
+        unreachable!();
+    };
+
+    let terminator = std::mem::replace(&mut block.terminator, Terminator::Jump(start));
+
+    // arguments are bound in order, and any left over are the varargs
+    let parameters = parameters.into_iter().map(register).collect::<Vec<_>>();
+    let fixed = arguments.fixed.len().min(parameters.len());
+
+    for (parameter, argument) in parameters.iter().zip(arguments.fixed.iter()) {
+        block.instructions.push(Instruction::Move {
+            target: *parameter,
+            source: argument.clone(),
+        });
+    }
+
+    if fixed < parameters.len() {
+        block.instructions.push(Instruction::Adjust {
+            results: Results::Fixed(parameters[fixed..].to_vec()),
+            values: Values {
+                fixed: vec![],
+                rest: arguments.rest,
+            },
+        });
+    }
+
+    let varargs = match arguments.fixed.len() >= parameters.len() {
+        true => Values {
+            fixed: arguments.fixed[parameters.len()..].to_vec(),
+            rest: arguments.rest,
+        },
+        false => Values::default(),
+    };
+
+    function.blocks.push(Block {
+        instructions: after,
+        terminator,
+    });
+
+    for block in blocks.iter_mut() {
+        for instruction in block.instructions.iter_mut() {
+            instruction.map_registers(register);
+
+            // the upvalues of the callee are whatever the closure captured
+            let upvalues = match instruction {
+                Instruction::GetUpvalue { upvalue, .. }
+                | Instruction::SetUpvalue { upvalue, .. } => {
+                    vec![upvalue]
+                }
+                Instruction::Closure { upvalues, .. } => upvalues.iter_mut().collect(),
+                Instruction::VarArg { results } => {
+                    *instruction = Instruction::Adjust {
+                        results: results.clone(),
+                        values: varargs.clone(),
+                    };
+                    vec![]
+                }
+                _ => vec![],
+            };
+
+            for upvalue in upvalues {
+                if let Upvalue::Captured(index) = *upvalue {
+                    *upvalue = candidate.upvalues[index];
+                }
+            }
+        }
+
+        block.terminator.map_registers(register);
+        block
+            .terminator
+            .map_labels(|label| Label(label.0 + start.0));
+
+        if let Terminator::Return(values) = &block.terminator {
+            if results != Results::Fixed(vec![]) {
+                block.instructions.push(Instruction::Adjust {
+                    results: results.clone(),
+                    values: values.clone(),
+                });
+            }
+
+            block.terminator = Terminator::Jump(continuation);
+        }
+    }
+
+    function.blocks.extend(blocks);
+}
+
+/// Removes the closures that nothing refers to any more.
+fn remove_unused_closures(function: &mut Function, inlined: &HashSet<Register>) {
+    let used = function
+        .blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .instructions
+                .iter()
+                .flat_map(Instruction::sources)
+                .chain(block.terminator.sources())
+        })
+        .collect::<HashSet<_>>();
+
+    for block in function.blocks.iter_mut() {
+        block.instructions.retain(|instruction| match instruction {
+            Instruction::Closure { target, .. } => {
+                !inlined.contains(target) || used.contains(target)
+            }
+            _ => true,
+        });
+    }
+}
+
+/// Drops the functions no closure is created for any more, and renumbers
+/// the rest.
+fn remove_unused_functions(module: &mut Module) {
+    let mut reachable = HashSet::from([module.main]);
+    let mut worklist = vec![module.main];
+
+    while let Some(index) = worklist.pop() {
+        for instruction in module.functions[index]
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+        {
+            if let Instruction::Closure { function, .. } = instruction {
+                if reachable.insert(*function) {
+                    worklist.push(*function);
+                }
+            }
+        }
+    }
+
+    let mut indices = HashMap::new();
+    let mut functions = vec![];
+
+    for (index, function) in std::mem::take(&mut module.functions)
+        .into_iter()
+        .enumerate()
+    {
+        if reachable.contains(&index) {
+            indices.insert(index, functions.len());
+            functions.push(function);
+        }
+    }
+
+    for instruction in functions
+        .iter_mut()
+        .flat_map(|function| &mut function.blocks)
+        .flat_map(|block| &mut block.instructions)
+    {
+        if let Instruction::Closure { function, .. } = instruction {
+            *function = indices[function];
+        }
+    }
+
+    module.functions = functions;
+    module.main = indices[&module.main];
+}
+
+/// Replaces the calls to local functions with their bodies, as long as the
+/// function is never used as a value: not passed on, stored, returned or
+/// captured, which also rules out a function calling itself. Functions
+/// nested in others are inlined into those first, so a chain of helpers
+/// collapses into its outermost caller.
+///
+/// Arguments are moved into the parameters of the callee, and whatever
+/// is left over makes up its varargs. Every return becomes a jump to the
+/// code after the call, handing its values to the results of the call.
+pub fn inline_functions(module: &mut Module, options: Options) {
+    for caller in (0..module.functions.len()).rev() {
+        let mut inlined = HashSet::new();
+
+        while let Some(candidate) = find(module, caller, &options) {
+            let (b, i) = candidate.call;
+
+            if let Instruction::Call {
+                function: Operand::Register(register),
+                ..
+            } = module.functions[caller].blocks[b].instructions[i]
+            {
+                inlined.insert(register);
+            }
+
+            inline(module, caller, candidate);
+        }
+
+        remove_unused_closures(&mut module.functions[caller], &inlined);
+    }
+
+    remove_unused_functions(module);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ir, parser::parse};
+
+    use super::inline_functions;
+
+    fn inlined(source: &str) -> String {
+        let chunk = parse(source).unwrap();
+        let mut module = ir::lower(&chunk, Default::default()).unwrap();
+
+        inline_functions(&mut module, Default::default());
+        format!("{module:?}")
+    }
+
+    #[test]
+    fn varargs() {
+        assert_eq!(
+            inlined(
+                "local function count(...)
+                    return select('#', ...)
+                end
+                print(count(1, 2, 3))"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r1 = global print
+  jump L3
+L2:
+  call r1(...r2)
+  return 
+L3:
+  jump L4
+L4:
+  r4 = global select
+  ...r5 = 1, 2, 3
+  ...r3 = call r4(\"#\", ...r5)
+  ...r2 = ...r3
+  jump L2
+"
+        );
+    }
+
+    #[test]
+    fn multiple_returns() {
+        // the missing third value is filled in with nil, while a call as the
+        // last argument passes on every value
+        assert_eq!(
+            inlined(
+                "local function two()
+                    return 1, 2
+                end
+                local a, b, c = two()
+                print(two())
+                return a, b, c"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  jump L3
+L2:
+  r4 = r1
+  r5 = r2
+  r6 = r3
+  r7 = global print
+  jump L6
+L3:
+  jump L4
+L4:
+  r1, r2, r3 = 1, 2
+  jump L2
+L5:
+  call r7(...r8)
+  return r4, r5, r6
+L6:
+  jump L7
+L7:
+  ...r8 = 1, 2
+  jump L5
+"
+        );
+    }
+
+    #[test]
+    fn early_returns() {
+        // every return hands its value to the result and jumps past the call
+        assert_eq!(
+            inlined(
+                "local function f(x)
+                    if x then return 1 end
+                    return 2
+                end
+                return f(p) + 1"
+            ),
+            "f0: function anonymous(...)
+L0:
+  jump L1
+L1:
+  r2 = global p
+  r4 = r2
+  jump L3
+L2:
+  r3 = Add r1, 1
+  return r3
+L3:
+  jump L4
+L4:
+  branch r4, L6, L5
+L5:
+  r1 = 2
+  jump L2
+L6:
+  r1 = 1
+  jump L2
+"
+        );
+    }
+
+    #[test]
+    fn functions_used_as_values_kept() {
+        let module = inlined(
+            "local function f() return 1 end
+            local function g() return g() end
+            print(f(), g(), f)",
+        );
+
+        assert!(module.contains("closure f1()"), "{module}");
+        assert!(module.contains("f2: function g@1()"), "{module}");
+    }
+}
@@ -0,0 +1,694 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    cfg::analysis,
+    ir::{
+        BinaryOperator, Block, Comparison, Constant, Function, Instruction, Label, Module, Operand,
+        Register, Terminator, UnaryOperator,
+    },
+};
+
+use super::{
+    fold::MAX_EXACT_INTEGER,
+    types::{self, Type, Types},
+};
+
+/// Where an instruction is: the block and its index in the block.
+type Position = (usize, usize);
+
+/// A register that goes from one integer to the next by a fixed step on
+/// every iteration, until the test of the loop header fails.
+struct Induction {
+    register: Register,
+    start: i64,
+    step: i64,
+    /// The one definition in the loop, which adds the step.
+    increment: Position,
+    /// The largest magnitude the register can reach, the value that fails
+    /// the test included.
+    bound: i64,
+}
+
+struct Loop<'a> {
+    function: &'a mut Function,
+    header: usize,
+    body: HashSet<usize>,
+    preheader: usize,
+    definitions: HashMap<Register, Vec<Position>>,
+}
+
+fn definitions(function: &Function) -> HashMap<Register, Vec<Position>> {
+    let mut definitions = HashMap::<Register, Vec<Position>>::new();
+
+    for (b, block) in function.blocks.iter().enumerate() {
+        for (i, instruction) in block.instructions.iter().enumerate() {
+            for target in instruction.targets() {
+                definitions.entry(target).or_default().push((b, i));
+            }
+        }
+    }
+
+    definitions
+}
+
+/// Finds the block every entry into the loop passes right before the
+/// header, or makes one. The blocks outside the loop that go to the header
+/// are redirected to the new block, which jumps to the header. There is
+/// none for a loop around the first block, where the function starts.
+fn preheader(function: &mut Function, header: usize, body: &HashSet<usize>) -> Option<usize> {
+    if header == 0 {
+        return None;
+    }
+
+    let entries = (0..function.blocks.len())
+        .filter(|b| !body.contains(b))
+        .filter(|&b| {
+            function.blocks[b]
+                .terminator
+                .successors()
+                .contains(&Label(header))
+        })
+        .collect::<Vec<_>>();
+
+    if let [entry] = entries[..] {
+        if function.blocks[entry].terminator == Terminator::Jump(Label(header)) {
+            return Some(entry);
+        }
+    }
+
+    let preheader = function.blocks.len();
+
+    for b in entries {
+        function.blocks[b]
+            .terminator
+            .map_labels(|label| match label {
+                Label(b) if b == header => Label(preheader),
+                _ => label,
+            });
+    }
+
+    function.blocks.push(Block {
+        instructions: vec![],
+        terminator: Terminator::Jump(Label(header)),
+    });
+
+    Some(preheader)
+}
+
+impl Loop<'_> {
+    /// The sorted blocks of the loop, so that instructions are moved in the
+    /// same order every time.
+    fn blocks(&self) -> Vec<usize> {
+        let mut blocks = self.body.iter().copied().collect::<Vec<_>>();
+        blocks.sort_unstable();
+        blocks
+    }
+
+    fn instruction(&self, (b, i): Position) -> &Instruction {
+        &self.function.blocks[b].instructions[i]
+    }
+
+    /// The integer an operand always holds, which for a register means that
+    /// it is only ever set to that integer.
+    fn integer(&self, operand: &Operand) -> Option<i64> {
+        let mut visited = HashSet::new();
+        let mut operand = operand;
+
+        loop {
+            let register = match operand {
+                Operand::Constant(Constant::Integer(value)) => return Some(*value),
+                Operand::Constant(_) => return None,
+                Operand::Register(register) => *register,
+            };
+
+            if !visited.insert(register) {
+                return None;
+            }
+
+            let [position] = self.definitions.get(&register)?[..] else {
+                return None;
+            };
+
+            match self.instruction(position) {
+                Instruction::Move { source, .. } => operand = source,
+                instruction => return self.integer_of(instruction),
+            }
+        }
+    }
+
+    /// The integer an instruction sets its target to, if it is always the
+    /// same.
+    fn integer_of(&self, instruction: &Instruction) -> Option<i64> {
+        match instruction {
+            Instruction::Move { source, .. } => self.integer(source),
+            Instruction::Unary {
+                operator: UnaryOperator::Negate,
+                operand: Operand::Constant(Constant::Integer(value)),
+                ..
+            } => value.checked_neg(),
+            _ => None,
+        }
+    }
+
+    /// The integer a register the loop does not write holds when the loop
+    /// starts. The locals of a loop may share their register with those of
+    /// an earlier one, so it is whatever the preheader sets it to last.
+    fn integer_on_entry(&self, operand: &Operand) -> Option<i64> {
+        let Operand::Register(register) = operand else {
+            return self.integer(operand);
+        };
+
+        let positions = self.definitions.get(register)?;
+
+        if positions.iter().any(|(b, _)| self.body.contains(b)) {
+            return None;
+        }
+
+        match positions.iter().filter(|(b, _)| *b == self.preheader).max() {
+            Some(&position) => self.integer_of(self.instruction(position)),
+            None => self.integer(operand),
+        }
+    }
+
+    /// Moves the instructions that give the same value on every iteration
+    /// into the preheader: those that only read registers the loop does not
+    /// write, and can neither run a metamethod nor raise an error, since
+    /// the loop might not have run them at all.
+    fn hoist(&mut self, stable: &HashSet<Register>, types: &Types) {
+        let mut variant = self
+            .blocks()
+            .into_iter()
+            .flat_map(|b| &self.function.blocks[b].instructions)
+            .flat_map(Instruction::targets)
+            .collect::<HashSet<_>>();
+
+        let is_invariant = |instruction: &Instruction, variant: &HashSet<Register>| {
+            let is_safe = match instruction {
+                Instruction::Move { .. } => true,
+                Instruction::GetTable { table, .. } => types.of(table) == Some(Type::Table),
+                Instruction::Unary { .. }
+                | Instruction::Binary { .. }
+                | Instruction::Compare { .. } => {
+                    types.result(instruction).is_some() && !types.may_raise(instruction)
+                }
+                _ => false,
+            };
+
+            is_safe
+                && instruction.targets().iter().all(|t| stable.contains(t))
+                && instruction.sources().iter().all(|s| !variant.contains(s))
+        };
+
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for b in self.blocks() {
+                let mut i = 0;
+
+                while i < self.function.blocks[b].instructions.len() {
+                    if !is_invariant(&self.function.blocks[b].instructions[i], &variant) {
+                        i += 1;
+                        continue;
+                    }
+
+                    let instruction = self.function.blocks[b].instructions.remove(i);
+
+                    for target in instruction.targets() {
+                        variant.remove(&target);
+                    }
+
+                    self.function.blocks[self.preheader]
+                        .instructions
+                        .push(instruction);
+
+                    changed = true;
+                }
+            }
+        }
+
+        self.definitions = definitions(self.function);
+    }
+
+    /// Finds the register the header tests against a limit, like the counter
+    /// of a numeric `for` loop, when it starts at an integer and only ever
+    /// adds an integer step that moves it towards the limit.
+    fn induction(&self) -> Option<Induction> {
+        let header = &self.function.blocks[self.header];
+
+        let Terminator::Branch {
+            condition: Operand::Register(condition),
+            then,
+            otherwise,
+        } = &header.terminator
+        else {
+            return None;
+        };
+
+        if !self.body.contains(&then.0) || self.body.contains(&otherwise.0) {
+            return None;
+        }
+
+        let Some(Instruction::Compare {
+            operator,
+            left,
+            right,
+            ..
+        }) = header
+            .instructions
+            .iter()
+            .rev()
+            .find(|instruction| instruction.targets().contains(condition))
+        else {
+            return None;
+        };
+
+        // whether the loop goes on while the register is below the limit,
+        // with the register on the left
+        let (upwards, register, limit) = match (operator, left, right) {
+            (Comparison::LessThan | Comparison::LessThanOrEqual, Operand::Register(r), limit) => {
+                (true, *r, limit)
+            }
+            (
+                Comparison::GreaterThan | Comparison::GreaterThanOrEqual,
+                Operand::Register(r),
+                limit,
+            ) => (false, *r, limit),
+            (Comparison::LessThan | Comparison::LessThanOrEqual, limit, Operand::Register(r)) => {
+                (false, *r, limit)
+            }
+            (
+                Comparison::GreaterThan | Comparison::GreaterThanOrEqual,
+                limit,
+                Operand::Register(r),
+            ) => (true, *r, limit),
+            _ => return None,
+        };
+
+        let limit = self.integer_on_entry(limit)?;
+
+        let positions = self.definitions.get(&register)?;
+
+        let [increment] = positions
+            .iter()
+            .copied()
+            .filter(|(b, _)| self.body.contains(b))
+            .collect::<Vec<_>>()[..]
+        else {
+            return None;
+        };
+
+        let start = positions
+            .iter()
+            .filter(|(b, _)| *b == self.preheader)
+            .max()
+            .and_then(|&position| self.integer_of(self.instruction(position)))?;
+
+        // either `i = i + step`, or a copy of a temporary holding that
+        let sum = match self.instruction(increment) {
+            Instruction::Move {
+                source: Operand::Register(temporary),
+                ..
+            } => match self.definitions.get(temporary)?[..] {
+                [position] => self.instruction(position),
+                _ => return None,
+            },
+            sum => sum,
+        };
+
+        let Instruction::Binary {
+            operator: BinaryOperator::Add,
+            left,
+            right,
+            ..
+        } = sum
+        else {
+            return None;
+        };
+
+        let step = match (left, right) {
+            (Operand::Register(r), step) | (step, Operand::Register(r)) if *r == register => {
+                self.integer_on_entry(step)?
+            }
+            _ => return None,
+        };
+
+        if step == 0 || (step > 0) != upwards {
+            return None;
+        }
+
+        let bound = start
+            .checked_abs()?
+            .max(limit.checked_abs()?.checked_add(step.checked_abs()?)?);
+
+        Some(Induction {
+            register,
+            start,
+            step,
+            increment,
+            bound,
+        })
+    }
+
+    /// Turns multiplications of the induction register by an integer into a
+    /// register of their own, which starts at the product of the start and
+    /// grows by the product of the step wherever the induction register
+    /// does. Only products that stay exact in every version of Lua are
+    /// reduced, since the versions without integers would round them.
+    fn reduce(&mut self, induction: &Induction) {
+        let (increment_block, increment_index) = induction.increment;
+
+        // the blocks the loop can reach from the increment on, before it
+        // starts over
+        let mut after = HashSet::new();
+        let mut stack = self.function.blocks[increment_block]
+            .terminator
+            .successors();
+
+        while let Some(Label(b)) = stack.pop() {
+            if b != self.header && self.body.contains(&b) && after.insert(b) {
+                stack.extend(self.function.blocks[b].terminator.successors());
+            }
+        }
+
+        let is_current =
+            |(b, i): Position| !after.contains(&b) && (b != increment_block || i < increment_index);
+
+        // copies of the induction register made in the same iteration, before
+        // it grows, which only stand for it up to the increment as well
+        let copies = self
+            .definitions
+            .iter()
+            .filter_map(|(register, positions)| match positions[..] {
+                [position] if self.body.contains(&position.0) && is_current(position) => {
+                    match self.instruction(position) {
+                        Instruction::Move {
+                            source: Operand::Register(source),
+                            ..
+                        } if *source == induction.register => Some(*register),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let mut products = vec![];
+
+        for b in self.blocks() {
+            for (i, instruction) in self.function.blocks[b].instructions.iter().enumerate() {
+                let Instruction::Binary {
+                    target,
+                    operator: BinaryOperator::Multiply,
+                    left,
+                    right,
+                } = instruction
+                else {
+                    continue;
+                };
+
+                let factor = match (left, right) {
+                    (Operand::Register(r), factor) | (factor, Operand::Register(r))
+                        if (copies.contains(r) || *r == induction.register)
+                            && is_current((b, i)) =>
+                    {
+                        self.integer(factor)
+                    }
+                    _ => None,
+                };
+
+                let Some(factor) = factor else {
+                    continue;
+                };
+
+                let is_exact = induction
+                    .bound
+                    .checked_mul(factor)
+                    .is_some_and(|product| product.abs() <= MAX_EXACT_INTEGER);
+
+                if is_exact {
+                    products.push(((b, i), *target, factor));
+                }
+            }
+        }
+
+        let mut registers = HashMap::new();
+
+        for ((b, i), target, factor) in products {
+            let register = *registers.entry(factor).or_insert_with(|| {
+                let register = Register(self.function.registers);
+                self.function.registers += 1;
+
+                register
+            });
+
+            self.function.blocks[b].instructions[i] = Instruction::Move {
+                target,
+                source: Operand::Register(register),
+            };
+        }
+
+        let mut factors = registers.into_iter().collect::<Vec<_>>();
+        factors.sort_unstable_by_key(|(_, register)| *register);
+
+        for (n, (factor, register)) in factors.into_iter().enumerate() {
+            self.function.blocks[self.preheader]
+                .instructions
+                .push(Instruction::Move {
+                    target: register,
+                    source: Operand::Constant(Constant::Integer(induction.start * factor)),
+                });
+
+            self.function.blocks[increment_block].instructions.insert(
+                increment_index + 1 + n,
+                Instruction::Binary {
+                    target: register,
+                    operator: BinaryOperator::Add,
+                    left: Operand::Register(register),
+                    right: Operand::Constant(Constant::Integer(induction.step * factor)),
+                },
+            );
+        }
+    }
+}
+
+/// Moves the computations that do not change from one iteration of a loop
+/// to the next out of it, into a block that runs once before the loop, and
+/// replaces multiplications of the counter of a numeric `for` loop by an
+/// integer with a register that adds the step times the integer on every
+/// iteration. Nested loops go first, so that what they hoist can move on
+/// out of the loops around them.
+pub fn optimize_loops(module: &mut Module) {
+    for function in module.functions.iter_mut() {
+        let stable = types::stable_registers(function);
+        let types = types::infer(function, types::local_tables(function, &stable));
+
+        let cfg = function.cfg();
+        let dominators = analysis::dominators(&cfg);
+
+        let headers = analysis::natural_loops(&cfg, &dominators)
+            .into_iter()
+            .rev()
+            .filter_map(|l| Label::from_node(l.header))
+            .collect::<Vec<_>>();
+
+        for Label(header) in headers {
+            // every preheader made changes the graph
+            let cfg = function.cfg();
+            let dominators = analysis::dominators(&cfg);
+
+            let Some(l) = analysis::natural_loops(&cfg, &dominators)
+                .into_iter()
+                .find(|l| l.header == Label(header).node())
+            else {
+                continue;
+            };
+
+            let body = l
+                .body
+                .iter()
+                .filter_map(|node| Label::from_node(*node))
+                .map(|Label(b)| b)
+                .collect::<HashSet<_>>();
+
+            let Some(preheader) = preheader(function, header, &body) else {
+                continue;
+            };
+
+            let definitions = definitions(function);
+
+            let mut l = Loop {
+                function,
+                header,
+                body,
+                preheader,
+                definitions,
+            };
+
+            l.hoist(&stable, &types);
+
+            if let Some(induction) = l.induction() {
+                l.reduce(&induction);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cfg::analysis,
+        ir::{self, Label, Module},
+        parser::parse,
+    };
+
+    use super::optimize_loops;
+
+    fn optimized(source: &str) -> Module {
+        let chunk = parse(source).unwrap();
+        let mut module = ir::lower(&chunk, Default::default()).unwrap();
+
+        optimize_loops(&mut module);
+        module
+    }
+
+    /// The instructions of the main function inside and outside of its
+    /// loops.
+    fn split(module: &Module) -> (Vec<String>, Vec<String>) {
+        let function = &module.functions[module.main];
+        let cfg = function.cfg();
+        let dominators = analysis::dominators(&cfg);
+        let loops = analysis::natural_loops(&cfg, &dominators);
+
+        let (mut inside, mut outside) = (vec![], vec![]);
+
+        for (b, block) in function.blocks.iter().enumerate() {
+            let is_inside = loops.iter().any(|l| l.body.contains(&Label(b).node()));
+            let instructions = block.instructions.iter().map(|i| format!("{i:?}"));
+
+            match is_inside {
+                true => inside.extend(instructions),
+                false => outside.extend(instructions),
+            }
+        }
+
+        (inside, outside)
+    }
+
+    fn any(instructions: &[String], text: &str) -> bool {
+        instructions
+            .iter()
+            .any(|instruction| instruction.contains(text))
+    }
+
+    #[test]
+    fn invariant_field_hoisted() {
+        let module = optimized(
+            "local t = { x = 2 }
+            local s = 0
+            for i = 1, 10 do s = s + t.x end
+            return s",
+        );
+        let (inside, outside) = split(&module);
+
+        assert!(!any(&inside, "[\"x\"]"), "{module:?}");
+        assert!(any(&outside, " = r1[\"x\"]"), "{module:?}");
+    }
+
+    #[test]
+    fn field_of_table_written_in_loop_kept() {
+        let module = optimized(
+            "local t = { x = 1 }
+            local s = 0
+            for i = 1, 10 do
+                s = s + t.x
+                t.x = i
+            end
+            return s",
+        );
+        let (inside, outside) = split(&module);
+
+        assert!(any(&inside, " = r1[\"x\"]"), "{module:?}");
+        assert!(!any(&outside, " = r1[\"x\"]"), "{module:?}");
+    }
+
+    #[test]
+    fn field_of_escaping_table_kept() {
+        let module = optimized(
+            "local t = { x = 1 }
+            local s = 0
+            for i = 1, 10 do
+                s = s + t.x
+                g(t)
+            end
+            return s",
+        );
+        let (inside, _) = split(&module);
+
+        assert!(any(&inside, "[\"x\"]"), "{module:?}");
+    }
+
+    #[test]
+    fn product_reduced() {
+        let module = optimized("for i = 1, 10 do print(i * 4) end");
+        let (inside, _) = split(&module);
+
+        assert!(!any(&inside, "Multiply"), "{module:?}");
+        assert!(any(&inside, ", 4"), "{module:?}");
+    }
+
+    #[test]
+    fn product_reduced_with_negative_step() {
+        let module = optimized("for i = 10, 1, -2 do print(i * 4) end");
+        let (inside, outside) = split(&module);
+
+        assert!(!any(&inside, "Multiply"), "{module:?}");
+        assert!(any(&outside, " = 40"), "{module:?}");
+        assert!(any(&inside, ", -8"), "{module:?}");
+    }
+
+    #[test]
+    fn product_of_float_step_kept() {
+        let module = optimized("for i = 1, 10, 0.5 do print(i * 4) end");
+        let (inside, _) = split(&module);
+
+        assert!(any(&inside, "Multiply"), "{module:?}");
+    }
+
+    #[test]
+    fn product_of_copy_reduced() {
+        let module = optimized(
+            "local i = 0
+            local s = 0
+            while i < 10 do
+                local j = i
+                s = s + j * 2
+                i = i + 1
+            end
+            return s",
+        );
+        let (inside, _) = split(&module);
+
+        assert!(!any(&inside, "Multiply"), "{module:?}");
+    }
+
+    #[test]
+    fn product_of_copy_after_increment_kept() {
+        // `j` still holds the counter from before the increment, which the
+        // reduced register has already left behind
+        let module = optimized(
+            "local i = 0
+            local s = 0
+            while i < 10 do
+                local j = i
+                i = i + 1
+                s = s + j * 2
+            end
+            return s",
+        );
+        let (inside, _) = split(&module);
+
+        assert!(any(&inside, "Multiply"), "{module:?}");
+    }
+}
@@ -0,0 +1,17 @@
+//! Transformations that keep the behaviour of a program while making it
+//! simpler.
+
+mod dce;
+mod fold;
+mod gvn;
+mod inline;
+mod licm;
+mod sccp;
+mod types;
+
+pub use dce::eliminate_dead_code;
+pub use fold::fold_constants;
+pub use gvn::eliminate_common_subexpressions;
+pub use inline::{inline_functions, Options as InlineOptions};
+pub use licm::optimize_loops;
+pub use sccp::propagate_constants;
@@ -0,0 +1,606 @@
+use std::collections::{HashMap, HashSet};
+
+use petgraph::{graph::EdgeIndex, stable_graph::NodeIndex, visit::EdgeRef, Direction};
+
+use crate::{
+    cfg::{CFGEdge, CFGNode},
+    parser::ast::{
+        definition::{
+            AnonFunctionExpression, Expression, ExpressionKind, Identifier, Statement,
+            StatementKind, Variable,
+        },
+        visitor::{self, Visitor, VisitorMut},
+    },
+    ssa::Ssa,
+};
+
+use super::fold;
+
+/// What is known about the value of a name. Values only ever move down,
+/// from not yet defined to a constant to anything.
+#[derive(Clone, Debug)]
+enum Lattice {
+    /// No definition has been found to run yet.
+    Undefined,
+    Constant(Expression),
+    Overdefined,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Undefined, value) | (value, Lattice::Undefined) => value.clone(),
+            (Lattice::Constant(a), Lattice::Constant(b)) if same_constant(a, b) => self.clone(),
+            _ => Lattice::Overdefined,
+        }
+    }
+
+    fn is_same(&self, other: &Lattice) -> bool {
+        match (self, other) {
+            (Lattice::Undefined, Lattice::Undefined)
+            | (Lattice::Overdefined, Lattice::Overdefined) => true,
+            (Lattice::Constant(a), Lattice::Constant(b)) => same_constant(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Compares floats by their bits, so that `0.0` and `-0.0` stay apart.
+fn same_constant(a: &Expression, b: &Expression) -> bool {
+    match (&a.kind, &b.kind) {
+        (ExpressionKind::LiteralFloat(a), ExpressionKind::LiteralFloat(b)) => {
+            a.to_bits() == b.to_bits()
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn is_multiple(exp: &Expression) -> bool {
+    matches!(
+        exp.kind,
+        ExpressionKind::FunctionCall(_) | ExpressionKind::VariableArgument
+    )
+}
+
+/// Replaces the values known to be constant and folds what becomes constant.
+/// Nested functions only refer to pinned locals, so they are skipped.
+struct Substitution<'a> {
+    lattice: &'a HashMap<Identifier, Lattice>,
+    /// Whether a value without a definition yet was read.
+    undefined: bool,
+}
+
+impl VisitorMut for Substitution<'_> {
+    fn visit_expression_mut(&mut self, exp: &mut Expression) {
+        visitor::walk_expression_mut(self, exp);
+
+        if let ExpressionKind::Variable(Variable::Identifier(identifier)) = &exp.kind {
+            match self.lattice.get(identifier) {
+                Some(Lattice::Constant(constant)) => exp.kind = constant.kind.clone(),
+                Some(Lattice::Undefined) => self.undefined = true,
+                _ => {}
+            }
+
+            return;
+        }
+
+        if let Some(kind) = fold::fold(exp) {
+            exp.kind = kind;
+        }
+    }
+
+    fn visit_anon_function_mut(&mut self, _func: &mut AnonFunctionExpression) {}
+}
+
+/// A place that reads versioned locals, and is evaluated again whenever the
+/// value of one of them changes.
+#[derive(Clone, Copy)]
+enum Use {
+    Phi(NodeIndex),
+    Statement(NodeIndex, usize),
+    Condition(NodeIndex),
+}
+
+/// Collects the names a statement or expression refers to. Nested functions
+/// only refer to pinned locals, so they are skipped.
+#[derive(Default)]
+struct Names(Vec<Identifier>);
+
+impl Visitor for Names {
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        self.0.push(identifier.clone());
+    }
+
+    fn visit_anon_function(&mut self, _func: &AnonFunctionExpression) {}
+}
+
+/// The places that read every versioned local.
+fn uses(ssa: &Ssa) -> HashMap<Identifier, Vec<Use>> {
+    let mut uses = HashMap::<Identifier, Vec<Use>>::new();
+    let mut add = |names: Vec<Identifier>, place: Use| {
+        for name in names.into_iter().filter(|name| ssa.is_value(name)) {
+            uses.entry(name).or_default().push(place);
+        }
+    };
+
+    for (&node, phis) in ssa.phis.iter() {
+        let arguments = phis.iter().flat_map(|phi| &phi.arguments);
+        add(
+            arguments.map(|(_, name)| name.clone()).collect(),
+            Use::Phi(node),
+        );
+    }
+
+    for node in ssa.cfg.graph.node_indices() {
+        let Some(block) = ssa.cfg.graph[node].block() else {
+            continue;
+        };
+
+        for (i, stmt) in block.statements.iter().enumerate() {
+            let mut names = Names::default();
+            names.visit_statement(stmt);
+            add(names.0, Use::Statement(node, i));
+        }
+
+        if let Some(exp) = &block.condition {
+            let mut names = Names::default();
+            names.visit_expression(exp);
+            add(names.0, Use::Condition(node));
+        }
+    }
+
+    uses
+}
+
+struct Propagation<'a> {
+    ssa: &'a Ssa,
+    uses: HashMap<Identifier, Vec<Use>>,
+    lattice: HashMap<Identifier, Lattice>,
+    /// The edges found to be taken on some execution.
+    executable: HashSet<EdgeIndex>,
+    /// The blocks with an executable edge into them, and the entry.
+    reached: HashSet<NodeIndex>,
+    /// The edges found to be taken whose target has not seen them yet.
+    cfg_worklist: Vec<EdgeIndex>,
+    /// The names whose value changed since their uses were evaluated.
+    ssa_worklist: Vec<Identifier>,
+}
+
+impl Propagation<'_> {
+    fn evaluate(&self, exp: &Expression) -> Lattice {
+        let mut exp = exp.clone();
+        let mut substitution = Substitution {
+            lattice: &self.lattice,
+            undefined: false,
+        };
+
+        substitution.visit_expression_mut(&mut exp);
+
+        match substitution.undefined {
+            true => Lattice::Undefined,
+            false if fold::is_constant(&exp) => Lattice::Constant(exp),
+            false => Lattice::Overdefined,
+        }
+    }
+
+    /// Lowers the value of a name, and queues its uses when it changed.
+    fn set(&mut self, name: &Identifier, value: Lattice) {
+        let Some(old) = self.lattice.get(name) else {
+            return;
+        };
+
+        let new = old.meet(&value);
+
+        if !new.is_same(old) {
+            self.lattice.insert(name.clone(), new);
+            self.ssa_worklist.push(name.clone());
+        }
+    }
+
+    /// Gives every versioned name in `targets` the value assigned to it, or
+    /// nil for the ones past the end of `values`.
+    fn assign<'b>(
+        &mut self,
+        targets: impl Iterator<Item = Option<&'b Identifier>>,
+        values: &[Expression],
+    ) {
+        for (i, target) in targets.enumerate() {
+            let Some(target) = target else {
+                continue;
+            };
+
+            let value = match values.get(i) {
+                Some(exp) => self.evaluate(exp),
+                None if values.last().is_some_and(is_multiple) => Lattice::Overdefined,
+                None => Lattice::Constant(Expression::new(ExpressionKind::Nil, Default::default())),
+            };
+
+            self.set(target, value);
+        }
+    }
+
+    /// A function is never constant.
+    fn define(&mut self, var: &Variable) {
+        if let Variable::Identifier(identifier) = var {
+            self.set(identifier, Lattice::Overdefined);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StatementKind::LocalDeclaration(decl) => {
+                self.assign(decl.identifier_list.iter().map(Some), &decl.expression_list)
+            }
+            StatementKind::Assignment(assignment) => self.assign(
+                assignment.variable_list.iter().map(|var| match var {
+                    Variable::Identifier(identifier) => Some(identifier),
+                    _ => None,
+                }),
+                &assignment.expression_list,
+            ),
+            StatementKind::FunctionDefinition(def) => self.define(&def.identifier),
+            StatementKind::LocalFunctionDefinition(def) => self.define(&def.identifier),
+            _ => {}
+        }
+    }
+
+    /// Merges the arguments of the phis of a block that flow in along an
+    /// executable edge.
+    fn phis(&mut self, node: NodeIndex) {
+        let graph = &self.ssa.cfg.graph;
+
+        for phi in self.ssa.phis.get(&node).into_iter().flatten() {
+            let mut value = Lattice::Undefined;
+
+            for (pred, argument) in phi.arguments.iter() {
+                let taken = graph
+                    .edges_connecting(*pred, node)
+                    .any(|edge| self.executable.contains(&edge.id()));
+
+                if taken {
+                    value = value.meet(&self.lattice[argument]);
+                }
+            }
+
+            self.set(&phi.target, value);
+        }
+    }
+
+    /// Queues the edges the condition of a block can take.
+    fn condition(&mut self, node: NodeIndex) {
+        let graph = &self.ssa.cfg.graph;
+        let condition = graph[node]
+            .block()
+            .and_then(|block| block.condition.as_ref())
+            .map(|exp| self.evaluate(exp));
+
+        for edge in graph.edges_directed(node, Direction::Outgoing) {
+            let taken = match (&condition, edge.weight()) {
+                (None | Some(Lattice::Overdefined), _) => true,
+                (Some(Lattice::Undefined), _) => false,
+                (Some(Lattice::Constant(exp)), CFGEdge::True) => fold::is_truthy(exp),
+                (Some(Lattice::Constant(exp)), CFGEdge::False) => !fold::is_truthy(exp),
+                (Some(Lattice::Constant(_)), CFGEdge::Fallthrough) => true,
+            };
+
+            if taken && !self.executable.contains(&edge.id()) {
+                self.cfg_worklist.push(edge.id());
+            }
+        }
+    }
+
+    /// Evaluates a block the first time an executable edge leads to it.
+    fn reach(&mut self, node: NodeIndex) {
+        self.reached.insert(node);
+        self.phis(node);
+
+        let ssa = self.ssa;
+
+        for stmt in ssa.cfg.graph[node]
+            .block()
+            .into_iter()
+            .flat_map(|block| &block.statements)
+        {
+            self.statement(stmt);
+        }
+
+        self.condition(node);
+    }
+
+    /// Evaluates a use of a name whose value changed, unless its block is
+    /// not known to run yet.
+    fn reevaluate(&mut self, place: Use) {
+        let ssa = self.ssa;
+
+        match place {
+            Use::Phi(node) if self.reached.contains(&node) => self.phis(node),
+            Use::Statement(node, i) if self.reached.contains(&node) => {
+                self.statement(&ssa.cfg.graph[node].block().unwrap().statements[i])
+            }
+            Use::Condition(node) if self.reached.contains(&node) => self.condition(node),
+            _ => {}
+        }
+    }
+}
+
+/// Finds the versioned locals that hold the same constant on every
+/// execution, and the branches that always go the same way, assuming nothing
+/// runs until an executable edge leads to it. This is the sparse conditional
+/// constant propagation of Wegman and Zadeck: a statement is only evaluated
+/// again when a local it reads changes, and a block when a new edge into it
+/// is found to be taken. Constants replace the reads of
+/// such locals, conditions that are always true or false become jumps, and
+/// the blocks no executable edge leads to are removed along with the phi
+/// arguments they feed. Phis that became constant are no longer read, and
+/// are dropped when leaving SSA form.
+///
+/// Expressions are folded as on the syntax tree, so an expression that would
+/// raise an error, or whose value depends on anything not known here, is
+/// never considered constant.
+pub fn propagate_constants(ssa: &mut Ssa) {
+    // version 0 of a local is an argument, or never read
+    let lattice = ssa
+        .values
+        .iter()
+        .map(|(name, value)| {
+            let initial = match value.version {
+                0 => Lattice::Overdefined,
+                _ => Lattice::Undefined,
+            };
+
+            (name.clone(), initial)
+        })
+        .collect();
+
+    let mut propagation = Propagation {
+        ssa,
+        uses: uses(ssa),
+        lattice,
+        executable: HashSet::new(),
+        reached: HashSet::new(),
+        cfg_worklist: vec![],
+        ssa_worklist: vec![],
+    };
+
+    propagation.reach(ssa.cfg.entry);
+
+    loop {
+        if let Some(edge) = propagation.cfg_worklist.pop() {
+            if !propagation.executable.insert(edge) {
+                continue;
+            }
+
+            // a block that already ran only has new arguments for its phis
+            let (_, node) = ssa.cfg.graph.edge_endpoints(edge).unwrap();
+
+            match propagation.reached.contains(&node) {
+                true => propagation.phis(node),
+                false => propagation.reach(node),
+            }
+        } else if let Some(name) = propagation.ssa_worklist.pop() {
+            for place in propagation.uses.get(&name).cloned().unwrap_or_default() {
+                propagation.reevaluate(place);
+            }
+        } else {
+            break;
+        }
+    }
+
+    let Propagation {
+        lattice,
+        executable,
+        reached,
+        ..
+    } = propagation;
+
+    let mut substitution = Substitution {
+        lattice: &lattice,
+        undefined: false,
+    };
+
+    let graph = &mut ssa.cfg.graph;
+    let mut dead_edges = vec![];
+
+    for node in graph.node_indices().collect::<Vec<_>>() {
+        if !reached.contains(&node) {
+            continue;
+        }
+
+        let Some(block) = graph[node].block_mut() else {
+            continue;
+        };
+
+        for stmt in block.statements.iter_mut() {
+            visitor::walk_statement_mut(&mut substitution, stmt);
+        }
+
+        if let Some(stmt) = &mut block.last_statement {
+            visitor::walk_last_statement_mut(&mut substitution, stmt);
+        }
+
+        if let Some(exp) = &mut block.condition {
+            substitution.visit_expression_mut(exp);
+
+            if !fold::is_constant(exp) {
+                continue;
+            }
+
+            block.condition = None;
+
+            let edges = graph
+                .edges_directed(node, Direction::Outgoing)
+                .map(|edge| edge.id())
+                .collect::<Vec<_>>();
+
+            for edge in edges {
+                match executable.contains(&edge) {
+                    true => graph[edge] = CFGEdge::Fallthrough,
+                    false => dead_edges.push(edge),
+                }
+            }
+        }
+    }
+
+    ssa.remove_edges(dead_edges);
+
+    let dead_blocks = ssa
+        .cfg
+        .graph
+        .node_indices()
+        .filter(|node| matches!(ssa.cfg.graph[*node], CFGNode::Block(_)) && !reached.contains(node))
+        .collect();
+
+    ssa.remove_blocks(dead_blocks);
+    ssa.phis.retain(|_, phis| !phis.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::Direction;
+
+    use crate::{
+        parser::{ast::definition::Parameter, parse},
+        ssa::{self, Ssa},
+    };
+
+    use super::propagate_constants;
+
+    fn propagated(source: &str) -> Ssa {
+        let chunk = parse(source).unwrap();
+        let parameters = [Parameter::Identifier("p".to_string())];
+        let mut ssa = ssa::construct(&parameters, &chunk.block, Default::default()).unwrap();
+
+        propagate_constants(&mut ssa);
+        ssa
+    }
+
+    fn blocks(ssa: &Ssa) -> Vec<String> {
+        ssa.cfg
+            .graph
+            .node_weights()
+            .map(|node| format!("{node:?}"))
+            .collect()
+    }
+
+    /// Every phi has one argument per predecessor, and only from them.
+    fn assert_phis_match_predecessors(ssa: &Ssa) {
+        for (&node, phis) in ssa.phis.iter() {
+            let predecessors = ssa
+                .cfg
+                .graph
+                .neighbors_directed(node, Direction::Incoming)
+                .count();
+
+            for phi in phis {
+                assert_eq!(phi.arguments.len(), predecessors, "{phi:?}");
+
+                for (pred, _) in phi.arguments.iter() {
+                    assert!(ssa.cfg.graph.contains_edge(*pred, node), "{phi:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn constant_branch_pruned() {
+        let ssa = propagated(
+            "local x = 1
+            local y
+            if x == 1 then y = 2 else y = p end
+            return y",
+        );
+
+        let blocks = blocks(&ssa);
+
+        assert!(
+            !blocks.iter().any(|block| block.contains("= p")),
+            "{blocks:?}"
+        );
+        assert!(blocks.iter().any(|block| block == "return 2"), "{blocks:?}");
+        assert!(
+            !blocks.iter().any(|block| block.contains("test")),
+            "{blocks:?}"
+        );
+
+        // the phi at the join only keeps the argument of the branch taken
+        let [phi] = ssa.phis.values().flatten().collect::<Vec<_>>()[..] else {
+            panic!("one phi expected: {:?}", ssa.phis);
+        };
+        let [(pred, argument)] = phi.arguments.as_slice() else {
+            panic!("one argument expected: {phi:?}");
+        };
+
+        assert_eq!(
+            format!("{:?}", ssa.cfg.graph[*pred]),
+            format!("{argument} = 2")
+        );
+        assert_phis_match_predecessors(&ssa);
+    }
+
+    #[test]
+    fn phi_arguments_of_pruned_branch_removed() {
+        let ssa = propagated(
+            "local x = 1
+            while p do
+                local z
+                if x > 0 then z = p else z = 5 end
+                print(z)
+                x = 1
+            end",
+        );
+
+        let blocks = blocks(&ssa);
+
+        assert!(
+            !blocks.iter().any(|block| block.contains("= 5")),
+            "{blocks:?}"
+        );
+        assert!(
+            !blocks.iter().any(|block| block.contains("> 0")),
+            "{blocks:?}"
+        );
+        assert_phis_match_predecessors(&ssa);
+    }
+
+    #[test]
+    fn unknown_branch_kept() {
+        let ssa = propagated(
+            "local y
+            if p then y = 2 else y = 3 end
+            return y",
+        );
+
+        let blocks = blocks(&ssa);
+
+        assert!(
+            blocks.iter().any(|block| block.ends_with("test p@1#0")),
+            "{blocks:?}"
+        );
+        assert_eq!(ssa.phis.len(), 1);
+        assert_phis_match_predecessors(&ssa);
+    }
+
+    #[test]
+    fn values_around_loops() {
+        // a value that stays the same around the back edge is constant, one
+        // that changes is not
+        let ssa = propagated(
+            "local x, y = 1, 1
+            while p do
+                x = 1
+                y = y + 1
+            end
+            return x, y",
+        );
+
+        let blocks = blocks(&ssa);
+
+        assert!(
+            blocks
+                .iter()
+                .any(|block| block.starts_with("return 1, y@1#")),
+            "{blocks:?}"
+        );
+        assert_phis_match_predecessors(&ssa);
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{
+    BinaryOperator, Comparison, Constant, Function, Instruction, Operand, Register, UnaryOperator,
+};
+
+/// What is known about the type of a value, which decides whether an
+/// operator on it can run a metamethod.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Type {
+    Nil,
+    Boolean,
+    Number,
+    String,
+    /// A table created in the function that never leaves it, so nothing can
+    /// give it a metatable, and that is only written where it is created.
+    Table,
+}
+
+/// The registers written exactly once, and the parameters that are never
+/// written. Their definition dominates every use, so they hold the same
+/// value wherever the definition dominates.
+pub(super) fn stable_registers(function: &Function) -> HashSet<Register> {
+    let mut definitions = HashMap::<Register, usize>::new();
+
+    for instruction in function.blocks.iter().flat_map(|block| &block.instructions) {
+        for target in instruction.targets() {
+            *definitions.entry(target).or_default() += 1;
+        }
+    }
+
+    let parameters = function.parameters.iter().collect::<HashSet<_>>();
+
+    (0..function.registers)
+        .map(Register)
+        .filter(|register| {
+            let count = definitions.get(register).copied().unwrap_or(0);
+
+            match parameters.contains(register) {
+                true => count == 0,
+                false => count == 1,
+            }
+        })
+        .collect()
+}
+
+/// Finds the tables that are created in the function, are only indexed,
+/// measured, written and copied into registers written once, never passed
+/// anywhere, and are only written in the block that creates them.
+pub(super) fn local_tables(function: &Function, stable: &HashSet<Register>) -> HashSet<Register> {
+    let instructions = || function.blocks.iter().flat_map(|block| &block.instructions);
+
+    // the register a copy was made from, so that a local holding a table
+    // refers to the same table as the temporary it was created in
+    let copies = instructions()
+        .filter_map(|instruction| match instruction {
+            Instruction::Move {
+                target,
+                source: Operand::Register(source),
+            } if stable.contains(target) => Some((*target, *source)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let origin = |mut register: Register| {
+        for _ in 0..copies.len() {
+            match copies.get(&register) {
+                Some(source) => register = *source,
+                None => break,
+            }
+        }
+
+        register
+    };
+
+    let mut created = HashMap::new();
+    let mut written = HashMap::<Register, HashSet<usize>>::new();
+    let mut escaping = HashSet::new();
+
+    for (b, block) in function.blocks.iter().enumerate() {
+        for instruction in block.instructions.iter() {
+            match instruction {
+                Instruction::NewTable { target } => {
+                    created.insert(*target, b);
+                }
+                Instruction::Move { target, .. } if copies.contains_key(target) => {}
+                Instruction::GetTable { table, key, .. } => {
+                    escaping.extend(key.register().map(origin));
+
+                    if table.register().is_none() {
+                        escaping.extend(instruction.sources().into_iter().map(origin));
+                    }
+                }
+                Instruction::SetTable { table, key, value } => {
+                    escaping.extend(key.register().map(origin));
+                    escaping.extend(value.register().map(origin));
+
+                    if let Some(table) = table.register() {
+                        written.entry(origin(table)).or_default().insert(b);
+                    }
+                }
+                Instruction::SetList { table, values, .. } => {
+                    escaping.insert(origin(*values));
+                    written.entry(origin(*table)).or_default().insert(b);
+                }
+                Instruction::Unary {
+                    operator: UnaryOperator::Length,
+                    ..
+                } => {}
+                _ => escaping.extend(instruction.sources().into_iter().map(origin)),
+            }
+        }
+
+        escaping.extend(block.terminator.sources().into_iter().map(origin));
+    }
+
+    created
+        .into_iter()
+        .filter(|(table, block)| {
+            stable.contains(table)
+                && !escaping.contains(table)
+                && written
+                    .get(table)
+                    .is_none_or(|blocks| blocks.iter().all(|b| b == block))
+        })
+        .map(|(table, _)| table)
+        .collect()
+}
+
+/// The types of the registers of a function, as far as they are known.
+pub(super) struct Types {
+    /// `None` for a register that may hold values of different types, or of
+    /// an unknown one.
+    registers: HashMap<Register, Option<Type>>,
+    tables: HashSet<Register>,
+}
+
+impl Types {
+    pub(super) fn of(&self, operand: &Operand) -> Option<Type> {
+        match operand {
+            Operand::Register(register) => self.registers.get(register).copied().flatten(),
+            Operand::Constant(Constant::Nil) => Some(Type::Nil),
+            Operand::Constant(Constant::Boolean(_)) => Some(Type::Boolean),
+            Operand::Constant(Constant::Integer(_) | Constant::Float(_)) => Some(Type::Number),
+            Operand::Constant(Constant::String(_)) => Some(Type::String),
+        }
+    }
+
+    /// The type of the value an instruction gives, if the instruction cannot
+    /// run a metamethod. Comparisons of numbers with strings, which raise an
+    /// error rather than run one, are left out as well.
+    pub(super) fn result(&self, instruction: &Instruction) -> Option<Type> {
+        let is_number = |operand| self.of(operand) == Some(Type::Number);
+
+        match instruction {
+            Instruction::Move { source, .. } => self.of(source),
+            Instruction::NewTable { target } if self.tables.contains(target) => Some(Type::Table),
+            Instruction::Unary {
+                operator, operand, ..
+            } => match operator {
+                UnaryOperator::Not => Some(Type::Boolean),
+                UnaryOperator::Negate | UnaryOperator::BitwiseNot if is_number(operand) => {
+                    Some(Type::Number)
+                }
+                UnaryOperator::Length
+                    if matches!(self.of(operand), Some(Type::String | Type::Table)) =>
+                {
+                    Some(Type::Number)
+                }
+                _ => None,
+            },
+            Instruction::Binary {
+                operator: BinaryOperator::Concatenate,
+                left,
+                right,
+                ..
+            } => {
+                let is_text =
+                    |operand| matches!(self.of(operand), Some(Type::String | Type::Number));
+
+                (is_text(left) && is_text(right)).then_some(Type::String)
+            }
+            Instruction::Binary { left, right, .. } => {
+                (is_number(left) && is_number(right)).then_some(Type::Number)
+            }
+            Instruction::Compare {
+                operator,
+                left,
+                right,
+                ..
+            } => {
+                let (left, right) = (self.of(left), self.of(right));
+
+                // only two tables or two userdata are compared by `__eq`
+                let is_primitive = |ty| {
+                    matches!(
+                        ty,
+                        Some(Type::Nil | Type::Boolean | Type::Number | Type::String)
+                    )
+                };
+
+                let is_plain = match operator {
+                    Comparison::Equal | Comparison::NotEqual => {
+                        is_primitive(left) || is_primitive(right)
+                    }
+                    _ => left == right && matches!(left, Some(Type::Number | Type::String)),
+                };
+
+                is_plain.then_some(Type::Boolean)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether an instruction that cannot run a metamethod may still raise
+    /// an error: integer division by zero, and bitwise operators on floats
+    /// without an integer value.
+    pub(super) fn may_raise(&self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::Unary {
+                operator: UnaryOperator::BitwiseNot,
+                ..
+            } => true,
+            Instruction::Binary { operator, .. } => matches!(
+                operator,
+                BinaryOperator::FloorDivide
+                    | BinaryOperator::Modulo
+                    | BinaryOperator::ShiftLeft
+                    | BinaryOperator::ShiftRight
+                    | BinaryOperator::BitwiseAnd
+                    | BinaryOperator::BitwiseXor
+                    | BinaryOperator::BitwiseOr
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Infers the types of the registers of a function. Every register starts
+/// out as whatever its first definition gives, and becomes unknown once two
+/// definitions disagree, until nothing changes any more. Definitions that
+/// read a register nothing is known about yet wait for it, so that a
+/// counter that only ever adds numbers to itself stays a number.
+pub(super) fn infer(function: &Function, tables: HashSet<Register>) -> Types {
+    let instructions = function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .collect::<Vec<_>>();
+
+    let defined = instructions
+        .iter()
+        .flat_map(|instruction| instruction.targets())
+        .collect::<HashSet<_>>();
+
+    // nothing is known about the arguments
+    let mut types = Types {
+        registers: (0..function.registers)
+            .map(Register)
+            .filter(|register| !defined.contains(register))
+            .chain(function.parameters.iter().copied())
+            .map(|register| (register, None))
+            .collect(),
+        tables,
+    };
+
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for instruction in instructions.iter() {
+            let sources = instruction.sources();
+
+            if sources
+                .iter()
+                .any(|source| !types.registers.contains_key(source))
+            {
+                continue;
+            }
+
+            let targets = instruction.targets();
+
+            let ty = match targets.len() {
+                1 => types.result(instruction),
+                _ => None,
+            };
+
+            for target in targets {
+                let joined = match types.registers.get(&target) {
+                    None => ty,
+                    Some(&old) if old == ty => continue,
+                    Some(_) => None,
+                };
+
+                if types.registers.insert(target, joined) != Some(joined) {
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    types
+}
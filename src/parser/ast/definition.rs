@@ -5,15 +5,92 @@ pub struct Chunk {
 
 pub type Identifier = String;
 
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LuaString(pub Vec<u8>);
+
+impl LuaString {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<&str> for LuaString {
+    fn from(value: &str) -> Self {
+        LuaString(value.as_bytes().to_vec())
+    }
+}
+
+impl std::fmt::Debug for LuaString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+/// Identifies a function definition, so that its graph in the program can be
+/// found from the tree. Ids are handed out when the program is built, see
+/// [`crate::cfg::program::build`], and like spans they are not part of the
+/// structure of the tree.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct FunctionId(pub usize);
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+/// The source range a node was parsed from. Spans are not part of the
+/// structure of the tree, so nodes compare equal regardless of where they came
+/// from.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Block {
     pub statements: Vec<Statement>,
     pub last_statement: Option<LastStatement>,
 }
 
+/// A Luau type, kept as its source text. Types carry no meaning for the
+/// later stages and are removed by [`super::luau::strip_types`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Type(pub String);
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct FunctionTypes {
+    pub generics: Option<Type>,
+    pub parameter_types: Vec<Option<Type>>,
+    pub return_type: Option<Type>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Attribute {
+    Const,
+    Close,
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct LocalDeclarationStatement {
     pub identifier_list: Vec<Identifier>,
+    pub attribute_list: Vec<Option<Attribute>>,
+    pub type_list: Vec<Option<Type>>,
     pub expression_list: Vec<Expression>,
 }
 
@@ -29,6 +106,32 @@ pub struct AssignmentStatement {
     pub expression_list: Vec<Expression>,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompoundOperator {
+    Addition,
+    Subtraction,
+    Multiplication,
+    Division,
+    Modulo,
+    Exponentiation,
+    Concatenation,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CompoundAssignmentStatement {
+    pub variable: Variable,
+    pub operator: CompoundOperator,
+    pub expression: Expression,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TypeDeclarationStatement {
+    pub exported: bool,
+    pub identifier: Identifier,
+    pub generics: Option<Type>,
+    pub definition: Type,
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct LabelStatement {
     pub identifier: Identifier,
@@ -68,6 +171,7 @@ pub struct ElseIf {
 #[derive(Clone, PartialEq, Debug)]
 pub struct NumericForStatement {
     pub identifier: Identifier,
+    pub identifier_type: Option<Type>,
     pub start: Expression,
     pub end: Expression,
     pub step: Option<Expression>,
@@ -77,41 +181,97 @@ pub struct NumericForStatement {
 #[derive(Clone, PartialEq, Debug)]
 pub struct GenericForStatement {
     pub identifier_list: Vec<Identifier>,
+    pub type_list: Vec<Option<Type>>,
     pub expression_list: Vec<Expression>,
     pub block: Block,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Debug)]
 pub struct FunctionDefinitionStatement {
+    pub id: Option<FunctionId>,
     pub identifier: Variable,
     pub parameter_list: Vec<Parameter>,
+    pub types: Option<Box<FunctionTypes>>,
     pub block: Block,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+impl PartialEq for FunctionDefinitionStatement {
+    fn eq(&self, other: &Self) -> bool {
+        self.identifier == other.identifier
+            && self.parameter_list == other.parameter_list
+            && self.types == other.types
+            && self.block == other.block
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct LocalFunctionDefinitionStatement {
+    pub id: Option<FunctionId>,
     pub identifier: Variable,
     pub parameter_list: Vec<Parameter>,
+    pub types: Option<Box<FunctionTypes>>,
     pub block: Block,
 }
 
+impl PartialEq for LocalFunctionDefinitionStatement {
+    fn eq(&self, other: &Self) -> bool {
+        self.identifier == other.identifier
+            && self.parameter_list == other.parameter_list
+            && self.types == other.types
+            && self.block == other.block
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct ReturnStatement {
     pub expression_list: Vec<Expression>,
 }
 
+#[derive(Clone, Debug)]
+pub struct LastStatement {
+    pub kind: LastStatementKind,
+    pub span: Span,
+}
+
+impl PartialEq for LastStatement {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
-pub enum LastStatement {
+pub enum LastStatementKind {
     Break,
+    Continue,
     Return(ReturnStatement),
 }
 
+#[derive(Clone, Debug)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl PartialEq for Statement {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, PartialEq, Debug)]
-pub enum Statement {
+pub enum StatementKind {
     Semicolon,
     LocalDeclaration(LocalDeclarationStatement),
     FunctionCall(FunctionCallStatement),
     Assignment(AssignmentStatement),
+    CompoundAssignment(CompoundAssignmentStatement),
     Label(Identifier),
     Break,
     Goto(Identifier),
@@ -123,6 +283,7 @@ pub enum Statement {
     GenericFor(GenericForStatement),
     FunctionDefinition(FunctionDefinitionStatement),
     LocalFunctionDefinition(LocalFunctionDefinitionStatement),
+    TypeDeclaration(TypeDeclarationStatement),
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -170,16 +331,71 @@ pub struct FunctionCallExpression {
     pub arguments: Vec<Expression>,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Debug)]
 pub struct AnonFunctionExpression {
+    pub id: Option<FunctionId>,
     pub parameter_list: Vec<Parameter>,
+    pub types: Option<Box<FunctionTypes>>,
     pub block: Block,
 }
 
+impl PartialEq for AnonFunctionExpression {
+    fn eq(&self, other: &Self) -> bool {
+        self.parameter_list == other.parameter_list
+            && self.types == other.types
+            && self.block == other.block
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ElseIfExpression {
+    pub condition: Expression,
+    pub expression: Expression,
+}
+
 #[derive(Clone, PartialEq, Debug)]
-pub enum Expression {
-    LiteralNumber(f64),
-    LiteralString(String),
+pub struct IfExpression {
+    pub condition: Box<Expression>,
+    pub expression: Box<Expression>,
+    pub elseif_expressions: Vec<ElseIfExpression>,
+    pub else_expression: Box<Expression>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct InterpolatedStringSegment {
+    pub literal: LuaString,
+    pub expression: Expression,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct InterpolatedString {
+    pub segments: Vec<InterpolatedStringSegment>,
+    pub last: LuaString,
+}
+
+#[derive(Clone, Debug)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression { kind, span }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ExpressionKind {
+    LiteralInteger(i64),
+    LiteralFloat(f64),
+    LiteralString(LuaString),
     True,
     False,
     Nil,
@@ -188,6 +404,9 @@ pub enum Expression {
     AnonFunctionDefinition(AnonFunctionExpression),
     Variable(Variable),
     VariableArgument,
+    If(IfExpression),
+    InterpolatedString(InterpolatedString),
+    TypeAssertion(Box<Expression>, Type),
 
     Parenthesized(Box<Expression>),
 
@@ -195,9 +414,11 @@ pub enum Expression {
 
     Not(Box<Expression>),
     Negative(Box<Expression>),
+    BitwiseNot(Box<Expression>),
 
     Multiplication(Box<Expression>, Box<Expression>),
     Division(Box<Expression>, Box<Expression>),
+    FloorDivision(Box<Expression>, Box<Expression>),
     Modulo(Box<Expression>, Box<Expression>),
 
     Addition(Box<Expression>, Box<Expression>),
@@ -205,6 +426,13 @@ pub enum Expression {
 
     Concatenation(Box<Expression>, Box<Expression>),
 
+    ShiftLeft(Box<Expression>, Box<Expression>),
+    ShiftRight(Box<Expression>, Box<Expression>),
+
+    BitwiseAnd(Box<Expression>, Box<Expression>),
+    BitwiseXor(Box<Expression>, Box<Expression>),
+    BitwiseOr(Box<Expression>, Box<Expression>),
+
     LessThan(Box<Expression>, Box<Expression>),
     GreaterThan(Box<Expression>, Box<Expression>),
     LessThanOrEqual(Box<Expression>, Box<Expression>),
@@ -1,246 +1,433 @@
-use crate::parser::ast::definition::{
-    FunctionCallExpression, IfStatement, TableField, TableIndex, TableMember,
+use full_moon::{node::Node, tokenizer::TokenReference};
+
+use crate::parser::{
+    ast::definition::{FunctionCallExpression, IfStatement, TableField, TableIndex, TableMember},
+    number::{self, Number},
+    string, Error,
 };
 
 use super::definition::{
-    AnonFunctionExpression, AssignmentStatement, Block, ElseIf, Expression, FunctionCallStatement,
-    FunctionDefinitionStatement, GenericForStatement, LastStatement, LocalDeclarationStatement,
-    LocalFunctionDefinitionStatement, NumericForStatement, Parameter, RepeatStatement,
-    ReturnStatement, Statement, TableMethod, Variable, WhileStatement,
+    AnonFunctionExpression, AssignmentStatement, Block, ElseIf, Expression, ExpressionKind,
+    FunctionCallStatement, FunctionDefinitionStatement, FunctionTypes, GenericForStatement,
+    Identifier, LastStatement, LastStatementKind, LocalDeclarationStatement,
+    LocalFunctionDefinitionStatement, LuaString, NumericForStatement, Parameter, Position,
+    RepeatStatement, ReturnStatement, Span, Statement, StatementKind, TableMethod, Variable,
+    WhileStatement,
+};
+
+#[cfg(feature = "lua54")]
+use super::definition::Attribute;
+
+#[cfg(feature = "luau")]
+use super::definition::{
+    CompoundAssignmentStatement, CompoundOperator, ElseIfExpression, IfExpression,
+    InterpolatedString, InterpolatedStringSegment, Type, TypeDeclarationStatement,
 };
 
-fn get_args(args: &full_moon::ast::FunctionArgs) -> Vec<Expression> {
+impl From<full_moon::tokenizer::Position> for Position {
+    fn from(value: full_moon::tokenizer::Position) -> Self {
+        Position {
+            line: value.line(),
+            column: value.character(),
+            offset: value.bytes(),
+        }
+    }
+}
+
+/// The span covering every token of a node. [`Node::range`] is not used, as
+/// it ends one byte early on nodes closed by a bracket index.
+fn span(node: impl Node) -> Span {
+    node.tokens()
+        .filter_map(|token| {
+            Some(Span::new(
+                token.start_position()?.into(),
+                token.end_position()?.into(),
+            ))
+        })
+        .reduce(Span::to)
+        .unwrap_or_default()
+}
+
+fn identifier(token: &TokenReference) -> Identifier {
+    token.token().to_string()
+}
+
+fn string_literal(token: &TokenReference) -> Result<LuaString, Error> {
+    let bytes = match token.token_type() {
+        full_moon::tokenizer::TokenType::StringLiteral {
+            literal,
+            quote_type: full_moon::tokenizer::StringLiteralQuoteType::Brackets,
+            ..
+        } => string::long_bracket(literal),
+        full_moon::tokenizer::TokenType::StringLiteral { literal, .. } => string::unescape(literal)
+            .map_err(|message| Error::MalformedString {
+                message,
+                position: Some(span(token).start),
+            })?,
+        _ => return Err(unsupported("string literal", token)),
+    };
+
+    Ok(LuaString(bytes))
+}
+
+#[cfg(feature = "luau")]
+fn luau_type(node: &impl std::fmt::Display) -> Type {
+    Type(node.to_string().trim().to_string())
+}
+
+#[cfg(feature = "luau")]
+fn type_list<'a>(
+    specifiers: impl Iterator<Item = Option<&'a full_moon::ast::types::TypeSpecifier>>,
+    len: usize,
+) -> Vec<Option<Type>> {
+    let mut types: Vec<Option<Type>> = specifiers
+        .map(|specifier| specifier.map(|specifier| luau_type(specifier.type_info())))
+        .collect();
+
+    types.resize(len, None);
+    types
+}
+
+#[cfg(feature = "luau")]
+fn function_types(body: &full_moon::ast::FunctionBody) -> Option<Box<FunctionTypes>> {
+    let types = FunctionTypes {
+        generics: body.generics().map(luau_type),
+        parameter_types: type_list(body.type_specifiers(), body.parameters().len()),
+        return_type: body
+            .return_type()
+            .map(|specifier| luau_type(specifier.type_info())),
+    };
+
+    let annotated = types.generics.is_some()
+        || types.return_type.is_some()
+        || types.parameter_types.iter().any(Option::is_some);
+
+    annotated.then(|| Box::new(types))
+}
+
+#[cfg(not(feature = "luau"))]
+fn function_types(_body: &full_moon::ast::FunctionBody) -> Option<Box<FunctionTypes>> {
+    None
+}
+
+#[cfg(feature = "luau")]
+fn interpolated_literal(token: &TokenReference) -> Result<LuaString, Error> {
+    match token.token_type() {
+        full_moon::tokenizer::TokenType::InterpolatedString { literal, .. } => {
+            string::unescape_interpolated(literal)
+                .map(LuaString)
+                .map_err(|message| Error::MalformedString {
+                    message,
+                    position: Some(span(token).start),
+                })
+        }
+        _ => Err(unsupported("interpolated string", token)),
+    }
+}
+
+#[cfg(feature = "luau")]
+fn type_declaration(
+    declaration: &full_moon::ast::types::TypeDeclaration,
+    exported: bool,
+) -> StatementKind {
+    StatementKind::TypeDeclaration(TypeDeclarationStatement {
+        exported,
+        identifier: identifier(declaration.type_name()),
+        generics: declaration.generics().map(luau_type),
+        definition: luau_type(declaration.type_definition()),
+    })
+}
+
+fn unsupported(construct: &'static str, node: impl Node) -> Error {
+    Error::Unsupported {
+        construct,
+        position: node.start_position().map(Position::from),
+    }
+}
+
+fn get_args(args: &full_moon::ast::FunctionArgs) -> Result<Vec<Expression>, Vec<Error>> {
     match args {
-        full_moon::ast::FunctionArgs::Parentheses {
-            parentheses,
-            arguments,
-        } => arguments.iter().map(|x| Expression::from(x)).collect(),
-        full_moon::ast::FunctionArgs::String(token) => {
-            vec![Expression::LiteralString(token.to_string())]
+        full_moon::ast::FunctionArgs::Parentheses { arguments, .. } => {
+            arguments.iter().map(Expression::try_from).collect()
         }
+        full_moon::ast::FunctionArgs::String(token) => Ok(vec![Expression::new(
+            ExpressionKind::LiteralString(string_literal(token).map_err(|error| vec![error])?),
+            span(token),
+        )]),
         full_moon::ast::FunctionArgs::TableConstructor(table) => {
-            vec![Expression::from(table)]
+            Ok(vec![Expression::try_from(table)?])
         }
-        _ => panic!("unexpected FunctionArgs"),
+        _ => Err(vec![unsupported("function arguments", args)]),
     }
 }
 
-fn expression_prefix_suffixes(
+fn expression_prefix_suffixes<'a>(
     prefix: &full_moon::ast::Prefix,
-    suffixes: Vec<&full_moon::ast::Suffix>,
-) -> Expression {
+    suffixes: impl Iterator<Item = &'a full_moon::ast::Suffix>,
+) -> Result<Expression, Vec<Error>> {
     let mut exp = match prefix {
-        full_moon::ast::Prefix::Expression(exp) => Expression::from(exp),
-        full_moon::ast::Prefix::Name(token) => {
-            Expression::Variable(Variable::Identifier(token.to_string()))
-        }
-        _ => panic!("unexpected Prefix"),
+        full_moon::ast::Prefix::Expression(exp) => Expression::try_from(exp)?,
+        full_moon::ast::Prefix::Name(token) => Expression::new(
+            ExpressionKind::Variable(Variable::Identifier(identifier(token))),
+            span(token),
+        ),
+        _ => return Err(vec![unsupported("prefix", prefix)]),
     };
 
     for suffix in suffixes {
-        match suffix {
+        let exp_span = exp.span.to(span(suffix));
+
+        let kind = match suffix {
             full_moon::ast::Suffix::Call(call) => match call {
                 full_moon::ast::Call::AnonymousCall(args) => {
-                    exp = Expression::FunctionCall(FunctionCallExpression {
+                    ExpressionKind::FunctionCall(FunctionCallExpression {
                         callee: Box::new(exp),
-                        arguments: get_args(&args),
-                    });
+                        arguments: get_args(args)?,
+                    })
                 }
                 full_moon::ast::Call::MethodCall(call) => {
-                    let method = call.name().to_string();
-                    let args = get_args(call.args());
+                    let method = identifier(call.name());
+                    let args = get_args(call.args())?;
+                    let callee_span = exp.span.to(span(call.name()));
 
-                    exp = Expression::FunctionCall(FunctionCallExpression {
-                        callee: Box::new(Expression::Variable(Variable::TableMethod(
-                            TableMethod {
+                    ExpressionKind::FunctionCall(FunctionCallExpression {
+                        callee: Box::new(Expression::new(
+                            ExpressionKind::Variable(Variable::TableMethod(TableMethod {
                                 base: Box::new(exp),
                                 method,
-                            },
-                        ))),
+                            })),
+                            callee_span,
+                        )),
                         arguments: args,
-                    });
+                    })
                 }
-                _ => panic!("unexpected Suffix::Call"),
+                _ => return Err(vec![unsupported("call", call)]),
             },
             full_moon::ast::Suffix::Index(idx) => match idx {
-                full_moon::ast::Index::Brackets {
-                    brackets,
-                    expression,
-                } => {
-                    exp = Expression::Variable(Variable::TableIndex(TableIndex {
+                full_moon::ast::Index::Brackets { expression, .. } => {
+                    ExpressionKind::Variable(Variable::TableIndex(TableIndex {
                         base: Box::new(exp),
-                        index: Box::new(Expression::from(expression)),
-                    }));
+                        index: Box::new(Expression::try_from(expression)?),
+                    }))
                 }
-                full_moon::ast::Index::Dot { dot, name } => {
-                    exp = Expression::Variable(Variable::TableMember(TableMember {
+                full_moon::ast::Index::Dot { name, .. } => {
+                    ExpressionKind::Variable(Variable::TableMember(TableMember {
                         base: Box::new(exp),
-                        member: name.to_string(),
-                    }));
+                        member: identifier(name),
+                    }))
                 }
-                _ => panic!("unexpected Suffix::Index"),
+                _ => return Err(vec![unsupported("index", idx)]),
             },
-            _ => panic!("unexpected Suffix"),
-        }
+            _ => return Err(vec![unsupported("suffix", suffix)]),
+        };
+
+        exp = Expression::new(kind, exp_span);
     }
 
-    exp
+    Ok(exp)
 }
 
-fn variable_prefix_suffixes(
+fn variable_prefix_suffixes<'a>(
     prefix: &full_moon::ast::Prefix,
-    suffixes: Vec<&full_moon::ast::Suffix>,
-) -> Variable {
-    let mut var = match prefix {
-        full_moon::ast::Prefix::Name(token) => Variable::Identifier(token.to_string()),
-        _ => panic!("unexpected Prefix"),
+    suffixes: impl Iterator<Item = &'a full_moon::ast::Suffix>,
+) -> Result<Variable, Vec<Error>> {
+    let (mut var, mut var_span) = match prefix {
+        full_moon::ast::Prefix::Name(token) => {
+            (Variable::Identifier(identifier(token)), span(token))
+        }
+        _ => return Err(vec![unsupported("assignment target prefix", prefix)]),
     };
 
     for suffix in suffixes {
+        let base = Box::new(Expression::new(ExpressionKind::Variable(var), var_span));
+        var_span = var_span.to(span(suffix));
+
         match suffix {
             full_moon::ast::Suffix::Index(idx) => match idx {
-                full_moon::ast::Index::Brackets {
-                    brackets,
-                    expression,
-                } => {
-                    // FIXME: this shit is retarded!!!!
-                    // it will probably cause issues down the line
-                    // maybe create a separate struct for assignment variables?
-
+                full_moon::ast::Index::Brackets { expression, .. } => {
+                    // assignment targets reuse the expression variable node,
+                    // so every prefix but the last is wrapped back into an
+                    // expression; a dedicated assignment target type would
+                    // avoid the round trip
                     var = Variable::TableIndex(TableIndex {
-                        base: Box::new(Expression::Variable(var)),
-                        index: Box::new(Expression::from(expression)),
+                        base,
+                        index: Box::new(Expression::try_from(expression)?),
                     });
                 }
-                full_moon::ast::Index::Dot { dot, name } => {
+                full_moon::ast::Index::Dot { name, .. } => {
                     var = Variable::TableMember(TableMember {
-                        base: Box::new(Expression::Variable(var)),
-                        member: name.to_string(),
+                        base,
+                        member: identifier(name),
                     });
                 }
-                _ => panic!("unexpected Suffix::Index"),
+                _ => return Err(vec![unsupported("index", idx)]),
             },
-            _ => panic!("unexpected Suffix"),
+            _ => return Err(vec![unsupported("assignment target suffix", suffix)]),
         }
     }
 
-    var
+    Ok(var)
 }
 
-impl From<full_moon::ast::Block> for Block {
-    fn from(value: full_moon::ast::Block) -> Self {
-        Block {
-            statements: value
-                .stmts()
-                .map(|stmt| Statement::from(stmt.clone()))
-                .collect(),
-            last_statement: value.last_stmt().map(|stmt| match stmt {
-                full_moon::ast::LastStmt::Break(_) => LastStatement::Break,
-                full_moon::ast::LastStmt::Return(ret) => LastStatement::Return(ReturnStatement {
-                    expression_list: ret.returns().iter().map(|x| x.into()).collect(),
-                }),
-                _ => panic!("unexpected LastStmt"),
-            }),
+impl TryFrom<&full_moon::ast::Block> for Block {
+    type Error = Vec<Error>;
+
+    /// Converts every statement even after one fails, so that the errors of
+    /// all of them are reported together.
+    fn try_from(value: &full_moon::ast::Block) -> Result<Self, Self::Error> {
+        let mut statements = vec![];
+        let mut errors = vec![];
+
+        for stmt in value.stmts() {
+            match Statement::try_from(stmt) {
+                Ok(stmt) => statements.push(stmt),
+                Err(stmt_errors) => errors.extend(stmt_errors),
+            }
         }
-    }
-}
 
-impl From<&full_moon::ast::Block> for Block {
-    fn from(value: &full_moon::ast::Block) -> Self {
-        Block::from(value.clone())
+        let last_statement = match value.last_stmt() {
+            Some(stmt) => {
+                let kind = match stmt {
+                    full_moon::ast::LastStmt::Break(_) => Ok(LastStatementKind::Break),
+                    #[cfg(feature = "luau")]
+                    full_moon::ast::LastStmt::Continue(_) => Ok(LastStatementKind::Continue),
+                    full_moon::ast::LastStmt::Return(ret) => ret
+                        .returns()
+                        .iter()
+                        .map(Expression::try_from)
+                        .collect::<Result<_, _>>()
+                        .map(|expression_list| {
+                            LastStatementKind::Return(ReturnStatement { expression_list })
+                        }),
+                    _ => Err(vec![unsupported("last statement", stmt)]),
+                };
+
+                match kind {
+                    Ok(kind) => Some(LastStatement {
+                        kind,
+                        span: span(stmt),
+                    }),
+                    Err(stmt_errors) => {
+                        errors.extend(stmt_errors);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Block {
+            statements,
+            last_statement,
+        })
     }
 }
 
-impl From<full_moon::ast::VarExpression> for Expression {
-    fn from(value: full_moon::ast::VarExpression) -> Self {
-        expression_prefix_suffixes(value.prefix(), value.suffixes().collect())
+impl TryFrom<full_moon::ast::Block> for Block {
+    type Error = Vec<Error>;
+
+    fn try_from(value: full_moon::ast::Block) -> Result<Self, Self::Error> {
+        Block::try_from(&value)
     }
 }
 
-impl From<&full_moon::ast::VarExpression> for Expression {
-    fn from(value: &full_moon::ast::VarExpression) -> Self {
-        Expression::from(value.clone())
+impl TryFrom<&full_moon::ast::VarExpression> for Expression {
+    type Error = Vec<Error>;
+
+    fn try_from(value: &full_moon::ast::VarExpression) -> Result<Self, Self::Error> {
+        expression_prefix_suffixes(value.prefix(), value.suffixes())
     }
 }
 
-impl From<full_moon::ast::Var> for Variable {
-    fn from(value: full_moon::ast::Var) -> Self {
+impl TryFrom<&full_moon::ast::Var> for Variable {
+    type Error = Vec<Error>;
+
+    fn try_from(value: &full_moon::ast::Var) -> Result<Self, Self::Error> {
         match value {
             full_moon::ast::Var::Expression(exp) => {
-                variable_prefix_suffixes(exp.prefix(), exp.suffixes().collect())
+                variable_prefix_suffixes(exp.prefix(), exp.suffixes())
             }
-            full_moon::ast::Var::Name(token) => Variable::Identifier(token.to_string()),
-            _ => panic!("unexpected Var"),
+            full_moon::ast::Var::Name(token) => Ok(Variable::Identifier(identifier(token))),
+            _ => Err(vec![unsupported("variable", value)]),
         }
     }
 }
 
-impl From<&full_moon::ast::Var> for Variable {
-    fn from(value: &full_moon::ast::Var) -> Self {
-        Variable::from(value.clone())
-    }
-}
+impl TryFrom<&full_moon::ast::ElseIf> for ElseIf {
+    type Error = Vec<Error>;
 
-impl From<full_moon::ast::ElseIf> for ElseIf {
-    fn from(value: full_moon::ast::ElseIf) -> Self {
-        ElseIf {
-            condition: Expression::from(value.condition().clone()),
-            block: Block::from(value.block().clone()),
-        }
+    fn try_from(value: &full_moon::ast::ElseIf) -> Result<Self, Self::Error> {
+        Ok(ElseIf {
+            condition: Expression::try_from(value.condition())?,
+            block: Block::try_from(value.block())?,
+        })
     }
 }
 
-impl From<full_moon::ast::Stmt> for Statement {
-    fn from(value: full_moon::ast::Stmt) -> Self {
-        match value {
+impl TryFrom<&full_moon::ast::Stmt> for Statement {
+    type Error = Vec<Error>;
+
+    fn try_from(value: &full_moon::ast::Stmt) -> Result<Self, Self::Error> {
+        let kind = match value {
             full_moon::ast::Stmt::Assignment(stmt) => {
                 let variables = stmt
                     .variables()
                     .iter()
-                    .map(|var| Variable::from(var))
-                    .collect();
+                    .map(Variable::try_from)
+                    .collect::<Result<_, _>>()?;
 
                 let expressions = stmt
                     .expressions()
                     .iter()
-                    .map(|exp| Expression::from(exp))
-                    .collect();
+                    .map(Expression::try_from)
+                    .collect::<Result<_, _>>()?;
 
-                Statement::Assignment(AssignmentStatement {
+                StatementKind::Assignment(AssignmentStatement {
                     variable_list: variables,
                     expression_list: expressions,
                 })
             }
-            full_moon::ast::Stmt::Do(stmt) => Statement::Scope(Block::from(stmt.block())),
+            full_moon::ast::Stmt::Do(stmt) => StatementKind::Scope(Block::try_from(stmt.block())?),
             full_moon::ast::Stmt::FunctionCall(stmt) => {
-                let exp = expression_prefix_suffixes(stmt.prefix(), stmt.suffixes().collect());
-
-                match exp {
-                    Expression::FunctionCall(call) => {
-                        Statement::FunctionCall(FunctionCallStatement {
+                match expression_prefix_suffixes(stmt.prefix(), stmt.suffixes())?.kind {
+                    ExpressionKind::FunctionCall(call) => {
+                        StatementKind::FunctionCall(FunctionCallStatement {
                             callee: call.callee,
-                            arguments: call.arguments
+                            arguments: call.arguments,
                         })
-                    },
-                    _ => panic!("unexpected Expression type while translating full_moon::ast::Stmt::FunctionCall, expected Expression::FunctionCall, got: {:#?}", exp)
+                    }
+                    _ => return Err(vec![unsupported("function call statement", stmt)]),
                 }
             }
             full_moon::ast::Stmt::FunctionDeclaration(stmt) => {
                 let mut names = stmt.name().names().iter();
 
-                let mut identifier = Variable::Identifier(names.next().expect("unexpected empty names list while translating full_moon::ast::Stmt::FunctionDeclaration").to_string());
+                let (mut name, mut name_span) = match names.next() {
+                    Some(name) => (Variable::Identifier(identifier(name)), span(name)),
+                    None => {
+                        return Err(vec![unsupported(
+                            "function declaration without a name",
+                            stmt,
+                        )])
+                    }
+                };
 
-                while let Some(name) = names.next() {
-                    identifier = Variable::TableMember(TableMember {
-                        base: Box::new(Expression::Variable(identifier)),
-                        member: name.to_string(),
+                for member in names {
+                    name = Variable::TableMember(TableMember {
+                        base: Box::new(Expression::new(ExpressionKind::Variable(name), name_span)),
+                        member: identifier(member),
                     });
+                    name_span = name_span.to(span(member));
                 }
 
                 if let Some(method) = stmt.name().method_name() {
-                    identifier = Variable::TableMethod(TableMethod {
-                        base: Box::new(Expression::Variable(identifier)),
-                        method: method.to_string(),
+                    name = Variable::TableMethod(TableMethod {
+                        base: Box::new(Expression::new(ExpressionKind::Variable(name), name_span)),
+                        method: identifier(method),
                     })
                 }
 
@@ -248,51 +435,80 @@ impl From<full_moon::ast::Stmt> for Statement {
                     .body()
                     .parameters()
                     .iter()
-                    .map(|x| Parameter::from(x))
-                    .collect();
+                    .map(Parameter::try_from)
+                    .collect::<Result<_, _>>()?;
 
-                Statement::FunctionDefinition(FunctionDefinitionStatement {
-                    identifier,
+                StatementKind::FunctionDefinition(FunctionDefinitionStatement {
+                    id: None,
+                    identifier: name,
                     parameter_list,
-                    block: Block::from(stmt.body().block()),
+                    types: function_types(stmt.body()),
+                    block: Block::try_from(stmt.body().block())?,
                 })
             }
             full_moon::ast::Stmt::GenericFor(stmt) => {
-                let identifier_list = stmt.names().iter().map(|x| x.to_string()).collect();
+                let identifier_list: Vec<Identifier> =
+                    stmt.names().iter().map(identifier).collect();
+
+                #[cfg(feature = "luau")]
+                let type_list = type_list(stmt.type_specifiers(), identifier_list.len());
+
+                #[cfg(not(feature = "luau"))]
+                let type_list = vec![None; identifier_list.len()];
+
                 let expression_list = stmt
                     .expressions()
                     .iter()
-                    .map(|x| Expression::from(x))
-                    .collect();
+                    .map(Expression::try_from)
+                    .collect::<Result<_, _>>()?;
 
-                Statement::GenericFor(GenericForStatement {
+                StatementKind::GenericFor(GenericForStatement {
                     identifier_list,
+                    type_list,
                     expression_list,
-                    block: Block::from(stmt.block()),
+                    block: Block::try_from(stmt.block())?,
                 })
             }
-            full_moon::ast::Stmt::If(stmt) => Statement::If(IfStatement {
-                condition: Expression::from(stmt.condition()),
-                block: Block::from(stmt.block()),
-                elseif_blocks: stmt.else_if().map_or(vec![], |elseif| {
-                    elseif
+            full_moon::ast::Stmt::If(stmt) => StatementKind::If(IfStatement {
+                condition: Expression::try_from(stmt.condition())?,
+                block: Block::try_from(stmt.block())?,
+                elseif_blocks: match stmt.else_if() {
+                    Some(elseif) => elseif
                         .iter()
-                        .map(|elseif| ElseIf::from(elseif.clone()))
-                        .collect()
-                }),
-                else_block: stmt.else_block().map(|block| Block::from(block)),
+                        .map(ElseIf::try_from)
+                        .collect::<Result<_, _>>()?,
+                    None => vec![],
+                },
+                else_block: stmt.else_block().map(Block::try_from).transpose()?,
             }),
             full_moon::ast::Stmt::LocalAssignment(stmt) => {
-                let identifiers = stmt.names().iter().map(|token| token.to_string()).collect();
+                let identifiers: Vec<Identifier> = stmt.names().iter().map(identifier).collect();
+
+                #[cfg(feature = "lua54")]
+                let attributes = stmt
+                    .attributes()
+                    .map(|attribute| attribute.map(Attribute::try_from).transpose())
+                    .collect::<Result<_, _>>()?;
+
+                #[cfg(not(feature = "lua54"))]
+                let attributes = vec![None; identifiers.len()];
+
+                #[cfg(feature = "luau")]
+                let types = type_list(stmt.type_specifiers(), identifiers.len());
+
+                #[cfg(not(feature = "luau"))]
+                let types = vec![None; identifiers.len()];
 
                 let expressions = stmt
                     .expressions()
                     .iter()
-                    .map(|exp| Expression::from(exp))
-                    .collect();
+                    .map(Expression::try_from)
+                    .collect::<Result<_, _>>()?;
 
-                Statement::LocalDeclaration(LocalDeclarationStatement {
+                StatementKind::LocalDeclaration(LocalDeclarationStatement {
                     identifier_list: identifiers,
+                    attribute_list: attributes,
+                    type_list: types,
                     expression_list: expressions,
                 })
             }
@@ -301,202 +517,308 @@ impl From<full_moon::ast::Stmt> for Statement {
                     .body()
                     .parameters()
                     .iter()
-                    .map(|x| Parameter::from(x))
-                    .collect();
+                    .map(Parameter::try_from)
+                    .collect::<Result<_, _>>()?;
 
-                let block = stmt.body().block().into();
+                let block = stmt.body().block().try_into()?;
 
-                Statement::LocalFunctionDefinition(LocalFunctionDefinitionStatement {
-                    identifier: Variable::Identifier(stmt.name().to_string()),
+                StatementKind::LocalFunctionDefinition(LocalFunctionDefinitionStatement {
+                    id: None,
+                    identifier: Variable::Identifier(identifier(stmt.name())),
                     parameter_list,
+                    types: function_types(stmt.body()),
                     block,
                 })
             }
-            full_moon::ast::Stmt::NumericFor(stmt) => Statement::NumericFor(NumericForStatement {
-                identifier: stmt.index_variable().to_string(),
-                start: stmt.start().into(),
-                end: stmt.end().into(),
-                step: stmt.step().map(|x| x.into()),
-                block: stmt.block().into(),
-            }),
-            full_moon::ast::Stmt::Repeat(stmt) => Statement::Repeat(RepeatStatement {
-                block: stmt.block().into(),
-                condition: stmt.until().into(),
+            full_moon::ast::Stmt::NumericFor(stmt) => {
+                StatementKind::NumericFor(NumericForStatement {
+                    identifier: identifier(stmt.index_variable()),
+                    #[cfg(feature = "luau")]
+                    identifier_type: stmt
+                        .type_specifier()
+                        .map(|specifier| luau_type(specifier.type_info())),
+                    #[cfg(not(feature = "luau"))]
+                    identifier_type: None,
+                    start: stmt.start().try_into()?,
+                    end: stmt.end().try_into()?,
+                    step: stmt.step().map(Expression::try_from).transpose()?,
+                    block: stmt.block().try_into()?,
+                })
+            }
+            full_moon::ast::Stmt::Repeat(stmt) => StatementKind::Repeat(RepeatStatement {
+                block: stmt.block().try_into()?,
+                condition: stmt.until().try_into()?,
             }),
-            full_moon::ast::Stmt::While(stmt) => Statement::While(WhileStatement {
-                condition: stmt.condition().into(),
-                block: stmt.block().into(),
+            full_moon::ast::Stmt::While(stmt) => StatementKind::While(WhileStatement {
+                condition: stmt.condition().try_into()?,
+                block: stmt.block().try_into()?,
             }),
-            _ => panic!("unexpected Stmt"),
-        }
-    }
-}
+            #[cfg(feature = "lua52")]
+            full_moon::ast::Stmt::Goto(stmt) => StatementKind::Goto(identifier(stmt.label_name())),
+            #[cfg(feature = "lua52")]
+            full_moon::ast::Stmt::Label(stmt) => StatementKind::Label(identifier(stmt.name())),
+            #[cfg(feature = "luau")]
+            full_moon::ast::Stmt::CompoundAssignment(stmt) => {
+                use full_moon::ast::types::CompoundOp;
+
+                let operator = match stmt.compound_operator() {
+                    CompoundOp::PlusEqual(_) => CompoundOperator::Addition,
+                    CompoundOp::MinusEqual(_) => CompoundOperator::Subtraction,
+                    CompoundOp::StarEqual(_) => CompoundOperator::Multiplication,
+                    CompoundOp::SlashEqual(_) => CompoundOperator::Division,
+                    CompoundOp::PercentEqual(_) => CompoundOperator::Modulo,
+                    CompoundOp::CaretEqual(_) => CompoundOperator::Exponentiation,
+                    CompoundOp::TwoDotsEqual(_) => CompoundOperator::Concatenation,
+                    operator => return Err(vec![unsupported("compound operator", operator)]),
+                };
+
+                StatementKind::CompoundAssignment(CompoundAssignmentStatement {
+                    variable: Variable::try_from(stmt.lhs())?,
+                    operator,
+                    expression: Expression::try_from(stmt.rhs())?,
+                })
+            }
+            #[cfg(feature = "luau")]
+            full_moon::ast::Stmt::TypeDeclaration(stmt) => type_declaration(stmt, false),
+            #[cfg(feature = "luau")]
+            full_moon::ast::Stmt::ExportedTypeDeclaration(stmt) => {
+                type_declaration(stmt.type_declaration(), true)
+            }
+            _ => return Err(vec![unsupported("statement", value)]),
+        };
 
-impl From<&full_moon::ast::Stmt> for Statement {
-    fn from(value: &full_moon::ast::Stmt) -> Self {
-        Statement::from(value.clone())
+        Ok(Statement::new(kind, span(value)))
     }
 }
 
-impl From<full_moon::ast::Expression> for Expression {
-    fn from(value: full_moon::ast::Expression) -> Self {
-        match value {
+impl TryFrom<&full_moon::ast::Expression> for Expression {
+    type Error = Vec<Error>;
+
+    fn try_from(value: &full_moon::ast::Expression) -> Result<Self, Self::Error> {
+        let kind = match value {
             full_moon::ast::Expression::BinaryOperator { lhs, binop, rhs } => {
-                let lhs = Box::new(Expression::from(lhs));
-                let rhs = Box::new(Expression::from(rhs));
+                let lhs = Box::new(Expression::try_from(lhs.as_ref())?);
+                let rhs = Box::new(Expression::try_from(rhs.as_ref())?);
 
                 use full_moon::ast::BinOp;
 
                 match binop {
-                    BinOp::And(_) => Expression::And(lhs, rhs),
-                    BinOp::Caret(_) => Expression::Exponentiation(lhs, rhs),
-                    BinOp::GreaterThan(_) => Expression::GreaterThan(lhs, rhs),
-                    BinOp::GreaterThanEqual(_) => Expression::GreaterThanOrEqual(lhs, rhs),
-                    BinOp::LessThan(_) => Expression::LessThan(lhs, rhs),
-                    BinOp::LessThanEqual(_) => Expression::LessThanOrEqual(lhs, rhs),
-                    BinOp::Minus(_) => Expression::Subtraction(lhs, rhs),
-                    BinOp::Or(_) => Expression::Or(lhs, rhs),
-                    BinOp::Percent(_) => Expression::Modulo(lhs, rhs),
-                    BinOp::Plus(_) => Expression::Addition(lhs, rhs),
-                    BinOp::Slash(_) => Expression::Division(lhs, rhs),
-                    BinOp::Star(_) => Expression::Multiplication(lhs, rhs),
-                    BinOp::TildeEqual(_) => Expression::NotEqual(lhs, rhs),
-                    BinOp::TwoDots(_) => Expression::Concatenation(lhs, rhs),
-                    BinOp::TwoEqual(_) => Expression::Equal(lhs, rhs),
-                    _ => panic!("unexpected BinOp"),
+                    BinOp::And(_) => ExpressionKind::And(lhs, rhs),
+                    BinOp::Caret(_) => ExpressionKind::Exponentiation(lhs, rhs),
+                    BinOp::GreaterThan(_) => ExpressionKind::GreaterThan(lhs, rhs),
+                    BinOp::GreaterThanEqual(_) => ExpressionKind::GreaterThanOrEqual(lhs, rhs),
+                    BinOp::LessThan(_) => ExpressionKind::LessThan(lhs, rhs),
+                    BinOp::LessThanEqual(_) => ExpressionKind::LessThanOrEqual(lhs, rhs),
+                    BinOp::Minus(_) => ExpressionKind::Subtraction(lhs, rhs),
+                    BinOp::Or(_) => ExpressionKind::Or(lhs, rhs),
+                    BinOp::Percent(_) => ExpressionKind::Modulo(lhs, rhs),
+                    BinOp::Plus(_) => ExpressionKind::Addition(lhs, rhs),
+                    BinOp::Slash(_) => ExpressionKind::Division(lhs, rhs),
+                    BinOp::Star(_) => ExpressionKind::Multiplication(lhs, rhs),
+                    BinOp::TildeEqual(_) => ExpressionKind::NotEqual(lhs, rhs),
+                    BinOp::TwoDots(_) => ExpressionKind::Concatenation(lhs, rhs),
+                    BinOp::TwoEqual(_) => ExpressionKind::Equal(lhs, rhs),
+                    #[cfg(feature = "lua53")]
+                    BinOp::Ampersand(_) => ExpressionKind::BitwiseAnd(lhs, rhs),
+                    #[cfg(feature = "lua53")]
+                    BinOp::DoubleSlash(_) => ExpressionKind::FloorDivision(lhs, rhs),
+                    #[cfg(feature = "lua53")]
+                    BinOp::DoubleLessThan(_) => ExpressionKind::ShiftLeft(lhs, rhs),
+                    #[cfg(feature = "lua53")]
+                    BinOp::Pipe(_) => ExpressionKind::BitwiseOr(lhs, rhs),
+                    #[cfg(feature = "lua53")]
+                    BinOp::DoubleGreaterThan(_) => ExpressionKind::ShiftRight(lhs, rhs),
+                    #[cfg(feature = "lua53")]
+                    BinOp::Tilde(_) => ExpressionKind::BitwiseXor(lhs, rhs),
+                    _ => return Err(vec![unsupported("binary operator", binop)]),
                 }
             }
-            full_moon::ast::Expression::Parentheses {
-                contained,
-                expression,
-            } => Expression::Parenthesized(Box::new(Expression::from(expression))),
+            full_moon::ast::Expression::Parentheses { expression, .. } => {
+                ExpressionKind::Parenthesized(Box::new(Expression::try_from(expression.as_ref())?))
+            }
             full_moon::ast::Expression::UnaryOperator { unop, expression } => {
-                let expr = Box::new(Expression::from(expression));
+                let expr = Box::new(Expression::try_from(expression.as_ref())?);
 
                 use full_moon::ast::UnOp;
 
                 match unop {
-                    UnOp::Minus(_) => Expression::Negative(expr),
-                    UnOp::Not(_) => Expression::Not(expr),
-                    UnOp::Hash(_) => Expression::Length(expr),
-                    _ => panic!("unexpected UnOp"),
+                    UnOp::Minus(_) => ExpressionKind::Negative(expr),
+                    UnOp::Not(_) => ExpressionKind::Not(expr),
+                    UnOp::Hash(_) => ExpressionKind::Length(expr),
+                    #[cfg(feature = "lua53")]
+                    UnOp::Tilde(_) => ExpressionKind::BitwiseNot(expr),
+                    _ => return Err(vec![unsupported("unary operator", unop)]),
                 }
             }
-            full_moon::ast::Expression::Value { value } => match *value {
-                full_moon::ast::Value::Function((_, func)) => {
-                    let params = func
-                        .parameters()
-                        .iter()
-                        .map(|x| Parameter::from(x))
-                        .collect();
-
-                    Expression::AnonFunctionDefinition(AnonFunctionExpression {
-                        parameter_list: params,
-                        block: Block::from(func.block()),
-                    })
-                }
-                full_moon::ast::Value::FunctionCall(call) => {
-                    expression_prefix_suffixes(call.prefix(), call.suffixes().collect())
-                }
-                full_moon::ast::Value::TableConstructor(table) => Expression::from(table),
-                full_moon::ast::Value::Number(token) => {
-                    Expression::LiteralNumber(token.to_string().trim().parse::<f64>().unwrap())
-                }
-                full_moon::ast::Value::ParenthesesExpression(expr) => {
-                    Expression::Parenthesized(Box::new(Expression::from(expr)))
-                }
-                full_moon::ast::Value::String(token) => {
-                    Expression::LiteralString(token.to_string())
-                }
-                full_moon::ast::Value::Symbol(token) => match token.token_type() {
-                    full_moon::tokenizer::TokenType::Symbol { symbol } => match symbol {
-                        full_moon::tokenizer::Symbol::False => Expression::False,
-                        full_moon::tokenizer::Symbol::Nil => Expression::Nil,
-                        full_moon::tokenizer::Symbol::True => Expression::True,
-                        _ => panic!("unexpected symbol: {}", symbol),
-                    },
-                    _ => panic!(
-                        "unexpected Value::Symbol that is not a TokenType::Symbol: {:#?}",
-                        token
+            #[cfg(not(feature = "luau"))]
+            full_moon::ast::Expression::Value { value, .. } => value_kind(value)?,
+            #[cfg(feature = "luau")]
+            full_moon::ast::Expression::Value {
+                value,
+                type_assertion,
+            } => {
+                let kind = value_kind(value)?;
+
+                match type_assertion {
+                    Some(assertion) => ExpressionKind::TypeAssertion(
+                        Box::new(Expression::new(kind, span(value))),
+                        luau_type(assertion.cast_to()),
                     ),
-                },
-                full_moon::ast::Value::Var(var) => match var {
-                    full_moon::ast::Var::Expression(expr) => {
-                        expression_prefix_suffixes(expr.prefix(), expr.suffixes().collect())
-                    }
-                    full_moon::ast::Var::Name(token) => {
-                        Expression::Variable(Variable::Identifier(token.to_string()))
-                    }
-                    _ => panic!("unexpected Var"),
-                },
-                _ => panic!("unexpected Expression::Value"),
-            },
-            _ => panic!("unexpected Expression"),
-        }
-    }
-}
+                    None => kind,
+                }
+            }
+            _ => return Err(vec![unsupported("expression", value)]),
+        };
 
-impl From<Box<full_moon::ast::Expression>> for Expression {
-    fn from(value: Box<full_moon::ast::Expression>) -> Self {
-        Expression::from(*value)
+        Ok(Expression::new(kind, span(value)))
     }
 }
 
-impl From<&full_moon::ast::Expression> for Expression {
-    fn from(value: &full_moon::ast::Expression) -> Self {
-        Expression::from(value.clone())
-    }
+fn value_kind(value: &full_moon::ast::Value) -> Result<ExpressionKind, Vec<Error>> {
+    let kind = match value {
+        full_moon::ast::Value::Function((_, func)) => {
+            let params = func
+                .parameters()
+                .iter()
+                .map(Parameter::try_from)
+                .collect::<Result<_, _>>()?;
+
+            ExpressionKind::AnonFunctionDefinition(AnonFunctionExpression {
+                id: None,
+                parameter_list: params,
+                types: function_types(func),
+                block: Block::try_from(func.block())?,
+            })
+        }
+        full_moon::ast::Value::FunctionCall(call) => {
+            expression_prefix_suffixes(call.prefix(), call.suffixes())?.kind
+        }
+        full_moon::ast::Value::TableConstructor(table) => Expression::try_from(table)?.kind,
+        full_moon::ast::Value::Number(token) => match number::parse(&token.token().to_string()) {
+            Some(Number::Integer(number)) => ExpressionKind::LiteralInteger(number),
+            Some(Number::Float(number)) => ExpressionKind::LiteralFloat(number),
+            None => {
+                return Err(vec![Error::MalformedNumber {
+                    literal: token.token().to_string(),
+                    position: Some(span(token).start),
+                }])
+            }
+        },
+        full_moon::ast::Value::ParenthesesExpression(expr) => Expression::try_from(expr)?.kind,
+        full_moon::ast::Value::String(token) => {
+            ExpressionKind::LiteralString(string_literal(token).map_err(|error| vec![error])?)
+        }
+        full_moon::ast::Value::Symbol(token) => match token.token_type() {
+            full_moon::tokenizer::TokenType::Symbol { symbol } => match symbol {
+                full_moon::tokenizer::Symbol::False => ExpressionKind::False,
+                full_moon::tokenizer::Symbol::Nil => ExpressionKind::Nil,
+                full_moon::tokenizer::Symbol::True => ExpressionKind::True,
+                full_moon::tokenizer::Symbol::Ellipse => ExpressionKind::VariableArgument,
+                _ => return Err(vec![unsupported("symbol", token)]),
+            },
+            _ => return Err(vec![unsupported("symbol", token)]),
+        },
+        full_moon::ast::Value::Var(var) => match var {
+            full_moon::ast::Var::Expression(expr) => {
+                expression_prefix_suffixes(expr.prefix(), expr.suffixes())?.kind
+            }
+            full_moon::ast::Var::Name(token) => {
+                ExpressionKind::Variable(Variable::Identifier(identifier(token)))
+            }
+            _ => return Err(vec![unsupported("variable", var)]),
+        },
+        #[cfg(feature = "luau")]
+        full_moon::ast::Value::IfExpression(exp) => ExpressionKind::If(IfExpression {
+            condition: Box::new(Expression::try_from(exp.condition())?),
+            expression: Box::new(Expression::try_from(exp.if_expression())?),
+            elseif_expressions: exp
+                .else_if_expressions()
+                .map_or(&[][..], Vec::as_slice)
+                .iter()
+                .map(|elseif| {
+                    Ok(ElseIfExpression {
+                        condition: Expression::try_from(elseif.condition())?,
+                        expression: Expression::try_from(elseif.expression())?,
+                    })
+                })
+                .collect::<Result<_, Vec<Error>>>()?,
+            else_expression: Box::new(Expression::try_from(exp.else_expression())?),
+        }),
+        #[cfg(feature = "luau")]
+        full_moon::ast::Value::InterpolatedString(string) => {
+            ExpressionKind::InterpolatedString(InterpolatedString {
+                segments: string
+                    .segments()
+                    .map(|segment| {
+                        Ok(InterpolatedStringSegment {
+                            literal: interpolated_literal(&segment.literal)
+                                .map_err(|error| vec![error])?,
+                            expression: Expression::try_from(&segment.expression)?,
+                        })
+                    })
+                    .collect::<Result<_, Vec<Error>>>()?,
+                last: interpolated_literal(string.last_string()).map_err(|error| vec![error])?,
+            })
+        }
+        _ => return Err(vec![unsupported("value", value)]),
+    };
+
+    Ok(kind)
 }
 
-impl From<full_moon::ast::Parameter> for Parameter {
-    fn from(value: full_moon::ast::Parameter) -> Self {
+impl TryFrom<&full_moon::ast::Parameter> for Parameter {
+    type Error = Vec<Error>;
+
+    fn try_from(value: &full_moon::ast::Parameter) -> Result<Self, Self::Error> {
         match value {
-            full_moon::ast::Parameter::Ellipse(_) => Parameter::VariableArg,
-            full_moon::ast::Parameter::Name(token) => Parameter::Identifier(token.to_string()),
-            _ => panic!("unexpected Parameter"),
+            full_moon::ast::Parameter::Ellipse(_) => Ok(Parameter::VariableArg),
+            full_moon::ast::Parameter::Name(token) => Ok(Parameter::Identifier(identifier(token))),
+            _ => Err(vec![unsupported("parameter", value)]),
         }
     }
 }
 
-impl From<&full_moon::ast::Parameter> for Parameter {
-    fn from(value: &full_moon::ast::Parameter) -> Self {
-        Parameter::from(value.clone())
+impl TryFrom<&full_moon::ast::TableConstructor> for Expression {
+    type Error = Vec<Error>;
+
+    fn try_from(value: &full_moon::ast::TableConstructor) -> Result<Self, Self::Error> {
+        let fields = value
+            .fields()
+            .iter()
+            .map(|field| match field {
+                full_moon::ast::Field::ExpressionKey { key, value, .. } => {
+                    Ok(TableField::IndexValue(
+                        Expression::try_from(key)?,
+                        Expression::try_from(value)?,
+                    ))
+                }
+                full_moon::ast::Field::NameKey { key, value, .. } => Ok(TableField::KeyValue(
+                    identifier(key),
+                    Expression::try_from(value)?,
+                )),
+                full_moon::ast::Field::NoKey(value) => {
+                    Ok(TableField::Value(Expression::try_from(value)?))
+                }
+                _ => Err(vec![unsupported("table field", field)]),
+            })
+            .collect::<Result<Vec<TableField>, Vec<Error>>>()?;
+
+        Ok(Expression::new(
+            ExpressionKind::TableConstructor(fields),
+            span(value),
+        ))
     }
 }
 
-impl From<full_moon::ast::TableConstructor> for Expression {
-    fn from(value: full_moon::ast::TableConstructor) -> Self {
-        let fields = value.fields().iter();
-
-        Expression::TableConstructor(
-            fields
-                .map(|field| match field {
-                    full_moon::ast::Field::ExpressionKey {
-                        brackets,
-                        key,
-                        equal,
-                        value,
-                    } => {
-                        let index = Expression::from(key);
-                        let value = Expression::from(value);
-
-                        TableField::IndexValue(index, value)
-                    }
-                    full_moon::ast::Field::NameKey { key, equal, value } => {
-                        TableField::KeyValue(key.to_string(), Expression::from(value))
-                    }
-                    full_moon::ast::Field::NoKey(value) => {
-                        TableField::Value(Expression::from(value))
-                    }
-                    _ => panic!("unexpected Field"),
-                })
-                .collect::<Vec<TableField>>(),
-        )
-    }
-}
+#[cfg(feature = "lua54")]
+impl TryFrom<&full_moon::ast::lua54::Attribute> for Attribute {
+    type Error = Vec<Error>;
 
-impl From<&full_moon::ast::TableConstructor> for Expression {
-    fn from(value: &full_moon::ast::TableConstructor) -> Self {
-        Expression::from(value.clone())
+    fn try_from(value: &full_moon::ast::lua54::Attribute) -> Result<Self, Self::Error> {
+        match identifier(value.name()).as_str() {
+            "const" => Ok(Attribute::Const),
+            "close" => Ok(Attribute::Close),
+            _ => Err(vec![unsupported("local attribute", value)]),
+        }
     }
 }
@@ -0,0 +1,197 @@
+use super::{
+    definition::{
+        AnonFunctionExpression, Block, Chunk, Expression, ExpressionKind,
+        FunctionDefinitionStatement, GenericForStatement, LocalDeclarationStatement,
+        LocalFunctionDefinitionStatement, NumericForStatement, StatementKind,
+    },
+    visitor::{self, VisitorMut},
+};
+
+/// Removes every Luau type annotation, type assertion and type declaration
+/// from the chunk, leaving plain Lua semantics behind.
+///
+/// A type assertion also truncates its operand to a single value, so
+/// `f() :: T` turns into `(f())` rather than `f()`.
+pub fn strip_types(chunk: &mut Chunk) {
+    TypeStripper.visit_chunk_mut(chunk);
+}
+
+struct TypeStripper;
+
+impl VisitorMut for TypeStripper {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        block
+            .statements
+            .retain(|stmt| !matches!(stmt.kind, StatementKind::TypeDeclaration(_)));
+
+        visitor::walk_block_mut(self, block);
+    }
+
+    fn visit_local_declaration_mut(&mut self, stmt: &mut LocalDeclarationStatement) {
+        stmt.type_list.iter_mut().for_each(|ty| *ty = None);
+        visitor::walk_local_declaration_mut(self, stmt);
+    }
+
+    fn visit_numeric_for_mut(&mut self, stmt: &mut NumericForStatement) {
+        stmt.identifier_type = None;
+        visitor::walk_numeric_for_mut(self, stmt);
+    }
+
+    fn visit_generic_for_mut(&mut self, stmt: &mut GenericForStatement) {
+        stmt.type_list.iter_mut().for_each(|ty| *ty = None);
+        visitor::walk_generic_for_mut(self, stmt);
+    }
+
+    fn visit_function_definition_mut(&mut self, stmt: &mut FunctionDefinitionStatement) {
+        stmt.types = None;
+        visitor::walk_function_definition_mut(self, stmt);
+    }
+
+    fn visit_local_function_definition_mut(&mut self, stmt: &mut LocalFunctionDefinitionStatement) {
+        stmt.types = None;
+        visitor::walk_local_function_definition_mut(self, stmt);
+    }
+
+    fn visit_anon_function_mut(&mut self, func: &mut AnonFunctionExpression) {
+        func.types = None;
+        visitor::walk_anon_function_mut(self, func);
+    }
+
+    fn visit_expression_mut(&mut self, exp: &mut Expression) {
+        let ExpressionKind::TypeAssertion(inner, _) = &mut exp.kind else {
+            visitor::walk_expression_mut(self, exp);
+            return;
+        };
+
+        let mut inner = std::mem::replace(
+            inner.as_mut(),
+            Expression::new(ExpressionKind::Nil, exp.span),
+        );
+        self.visit_expression_mut(&mut inner);
+
+        exp.kind = match inner.kind {
+            ExpressionKind::FunctionCall(_) | ExpressionKind::VariableArgument => {
+                ExpressionKind::Parenthesized(Box::new(inner))
+            }
+            kind => kind,
+        };
+    }
+}
+
+#[cfg(all(test, feature = "luau"))]
+mod tests {
+    use super::strip_types;
+    use crate::parser::{
+        ast::definition::{
+            Chunk, CompoundOperator, ExpressionKind, LastStatementKind, StatementKind,
+        },
+        parse,
+    };
+
+    fn stripped(source: &str) -> Chunk {
+        let mut chunk = parse(source).unwrap();
+        strip_types(&mut chunk);
+        chunk
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(
+            stripped(
+                "local x: number, y = 1, 2
+                function f(a: string, ...: any): boolean return a end
+                local function g<T>(b: T): T return b end
+                local h = function(c: number): () end
+                for i: number = 1, 2 do end
+                for k: string, v: any in pairs(t) do end"
+            ),
+            parse(
+                "local x, y = 1, 2
+                function f(a, ...) return a end
+                local function g(b) return b end
+                local h = function(c) end
+                for i = 1, 2 do end
+                for k, v in pairs(t) do end"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn type_declarations() {
+        assert_eq!(
+            stripped("type Point = { x: number } local a = 1 export type List<T> = { T }"),
+            parse("local a = 1").unwrap()
+        );
+    }
+
+    #[test]
+    fn assertions_truncate_to_one_value() {
+        assert_eq!(
+            stripped("local a, b, c = f() :: number, ... :: any, x :: number"),
+            parse("local a, b, c = (f()), (...), x").unwrap()
+        );
+    }
+
+    #[test]
+    fn nested_assertion() {
+        assert_eq!(
+            stripped("print((g() :: any) :: number)"),
+            parse("print(((g())))").unwrap()
+        );
+    }
+
+    #[test]
+    fn luau_statements_kept() {
+        let source = "while x do
+            x -= 1
+            y ..= \"a\"
+            if x then continue end
+        end
+        local z = if a then 1 elseif b then 2 else 3";
+        let chunk = stripped(source);
+        assert_eq!(chunk, parse(source).unwrap());
+
+        let StatementKind::While(while_statement) = &chunk.block.statements[0].kind else {
+            panic!("expected a while loop");
+        };
+        let operators = while_statement.block.statements[..2]
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StatementKind::CompoundAssignment(stmt) => stmt.operator,
+                kind => panic!("expected a compound assignment, got {kind:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            operators,
+            vec![
+                CompoundOperator::Subtraction,
+                CompoundOperator::Concatenation
+            ]
+        );
+
+        let StatementKind::If(if_statement) = &while_statement.block.statements[2].kind else {
+            panic!("expected an if statement");
+        };
+        assert!(matches!(
+            if_statement
+                .block
+                .last_statement
+                .as_ref()
+                .map(|last| &last.kind),
+            Some(LastStatementKind::Continue)
+        ));
+
+        let StatementKind::LocalDeclaration(local) = &chunk.block.statements[1].kind else {
+            panic!("expected a local declaration");
+        };
+        let ExpressionKind::If(if_expression) = &local.expression_list[0].kind else {
+            panic!("expected an if expression");
+        };
+        assert_eq!(if_expression.elseif_expressions.len(), 1);
+        assert_eq!(
+            if_expression.else_expression.kind,
+            ExpressionKind::LiteralInteger(3)
+        );
+    }
+}
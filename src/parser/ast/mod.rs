@@ -1,3 +1,5 @@
 pub mod definition;
 mod display;
 mod full_moon;
+pub mod luau;
+pub mod visitor;
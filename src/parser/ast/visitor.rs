@@ -0,0 +1,1290 @@
+//! Traversal of the syntax tree.
+//!
+//! [`Visitor`] walks a tree by shared reference and [`VisitorMut`] walks it by
+//! mutable reference so nodes can be rewritten in place. Every method defaults
+//! to the matching `walk_*` function, which visits the children of the node;
+//! an implementation overrides the nodes it cares about and calls the `walk_*`
+//! function itself when it still wants to descend.
+//!
+//! Children are visited in evaluation order rather than source order where the
+//! two differ, so the expressions of `local x = x` are visited before the name
+//! they declare and the condition of `repeat ... until cond` comes after its
+//! block.
+//!
+//! [`Visitor::visit_identifier`] is only called for names of variables, such as
+//! locals, parameters and loop variables. Table member and method names, labels
+//! and type names each have their own method.
+
+use super::definition::{
+    AnonFunctionExpression, AssignmentStatement, Attribute, Block, Chunk,
+    CompoundAssignmentStatement, CompoundOperator, ElseIf, ElseIfExpression, Expression,
+    ExpressionKind, FunctionCallExpression, FunctionCallStatement, FunctionDefinitionStatement,
+    FunctionTypes, GenericForStatement, Identifier, IfExpression, IfStatement, InterpolatedString,
+    InterpolatedStringSegment, LastStatement, LastStatementKind, LocalDeclarationStatement,
+    LocalFunctionDefinitionStatement, LuaString, NumericForStatement, Parameter, Position,
+    RepeatStatement, ReturnStatement, Span, Statement, StatementKind, TableField, TableIndex,
+    TableMember, TableMethod, Type, TypeDeclarationStatement, Variable, WhileStatement,
+};
+
+pub trait Visitor {
+    fn visit_chunk(&mut self, chunk: &Chunk) {
+        walk_chunk(self, chunk);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement) {
+        walk_statement(self, stmt);
+    }
+
+    fn visit_last_statement(&mut self, stmt: &LastStatement) {
+        walk_last_statement(self, stmt);
+    }
+
+    fn visit_local_declaration(&mut self, stmt: &LocalDeclarationStatement) {
+        walk_local_declaration(self, stmt);
+    }
+
+    fn visit_function_call_statement(&mut self, stmt: &FunctionCallStatement) {
+        walk_function_call_statement(self, stmt);
+    }
+
+    fn visit_assignment(&mut self, stmt: &AssignmentStatement) {
+        walk_assignment(self, stmt);
+    }
+
+    fn visit_compound_assignment(&mut self, stmt: &CompoundAssignmentStatement) {
+        walk_compound_assignment(self, stmt);
+    }
+
+    fn visit_label(&mut self, _label: &Identifier) {}
+
+    fn visit_goto(&mut self, _label: &Identifier) {}
+
+    fn visit_while(&mut self, stmt: &WhileStatement) {
+        walk_while(self, stmt);
+    }
+
+    fn visit_repeat(&mut self, stmt: &RepeatStatement) {
+        walk_repeat(self, stmt);
+    }
+
+    fn visit_if(&mut self, stmt: &IfStatement) {
+        walk_if(self, stmt);
+    }
+
+    fn visit_else_if(&mut self, elseif: &ElseIf) {
+        walk_else_if(self, elseif);
+    }
+
+    fn visit_numeric_for(&mut self, stmt: &NumericForStatement) {
+        walk_numeric_for(self, stmt);
+    }
+
+    fn visit_generic_for(&mut self, stmt: &GenericForStatement) {
+        walk_generic_for(self, stmt);
+    }
+
+    fn visit_function_definition(&mut self, stmt: &FunctionDefinitionStatement) {
+        walk_function_definition(self, stmt);
+    }
+
+    fn visit_local_function_definition(&mut self, stmt: &LocalFunctionDefinitionStatement) {
+        walk_local_function_definition(self, stmt);
+    }
+
+    fn visit_type_declaration(&mut self, stmt: &TypeDeclarationStatement) {
+        walk_type_declaration(self, stmt);
+    }
+
+    fn visit_return(&mut self, stmt: &ReturnStatement) {
+        walk_return(self, stmt);
+    }
+
+    fn visit_expression(&mut self, exp: &Expression) {
+        walk_expression(self, exp);
+    }
+
+    fn visit_table_field(&mut self, field: &TableField) {
+        walk_table_field(self, field);
+    }
+
+    fn visit_function_call(&mut self, call: &FunctionCallExpression) {
+        walk_function_call(self, call);
+    }
+
+    fn visit_anon_function(&mut self, func: &AnonFunctionExpression) {
+        walk_anon_function(self, func);
+    }
+
+    fn visit_if_expression(&mut self, exp: &IfExpression) {
+        walk_if_expression(self, exp);
+    }
+
+    fn visit_else_if_expression(&mut self, elseif: &ElseIfExpression) {
+        walk_else_if_expression(self, elseif);
+    }
+
+    fn visit_interpolated_string(&mut self, string: &InterpolatedString) {
+        walk_interpolated_string(self, string);
+    }
+
+    fn visit_interpolated_string_segment(&mut self, segment: &InterpolatedStringSegment) {
+        walk_interpolated_string_segment(self, segment);
+    }
+
+    fn visit_variable(&mut self, var: &Variable) {
+        walk_variable(self, var);
+    }
+
+    fn visit_table_index(&mut self, index: &TableIndex) {
+        walk_table_index(self, index);
+    }
+
+    fn visit_table_member(&mut self, member: &TableMember) {
+        walk_table_member(self, member);
+    }
+
+    fn visit_table_method(&mut self, method: &TableMethod) {
+        walk_table_method(self, method);
+    }
+
+    fn visit_parameter(&mut self, param: &Parameter) {
+        walk_parameter(self, param);
+    }
+
+    fn visit_identifier(&mut self, _identifier: &Identifier) {}
+
+    fn visit_lua_string(&mut self, _string: &LuaString) {}
+
+    fn visit_attribute(&mut self, _attribute: &Attribute) {}
+
+    fn visit_compound_operator(&mut self, _operator: &CompoundOperator) {}
+
+    fn visit_type(&mut self, _ty: &Type) {}
+
+    fn visit_function_types(&mut self, types: &FunctionTypes) {
+        walk_function_types(self, types);
+    }
+
+    fn visit_span(&mut self, span: &Span) {
+        walk_span(self, span);
+    }
+
+    fn visit_position(&mut self, _position: &Position) {}
+}
+
+pub fn walk_chunk<V: Visitor + ?Sized>(visitor: &mut V, chunk: &Chunk) {
+    visitor.visit_block(&chunk.block);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    for stmt in block.statements.iter() {
+        visitor.visit_statement(stmt);
+    }
+
+    if let Some(last) = &block.last_statement {
+        visitor.visit_last_statement(last);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Statement) {
+    visitor.visit_span(&stmt.span);
+
+    match &stmt.kind {
+        StatementKind::Semicolon | StatementKind::Break => {}
+        StatementKind::LocalDeclaration(stmt) => visitor.visit_local_declaration(stmt),
+        StatementKind::FunctionCall(stmt) => visitor.visit_function_call_statement(stmt),
+        StatementKind::Assignment(stmt) => visitor.visit_assignment(stmt),
+        StatementKind::CompoundAssignment(stmt) => visitor.visit_compound_assignment(stmt),
+        StatementKind::Label(label) => visitor.visit_label(label),
+        StatementKind::Goto(label) => visitor.visit_goto(label),
+        StatementKind::Scope(block) => visitor.visit_block(block),
+        StatementKind::While(stmt) => visitor.visit_while(stmt),
+        StatementKind::Repeat(stmt) => visitor.visit_repeat(stmt),
+        StatementKind::If(stmt) => visitor.visit_if(stmt),
+        StatementKind::NumericFor(stmt) => visitor.visit_numeric_for(stmt),
+        StatementKind::GenericFor(stmt) => visitor.visit_generic_for(stmt),
+        StatementKind::FunctionDefinition(stmt) => visitor.visit_function_definition(stmt),
+        StatementKind::LocalFunctionDefinition(stmt) => {
+            visitor.visit_local_function_definition(stmt)
+        }
+        StatementKind::TypeDeclaration(stmt) => visitor.visit_type_declaration(stmt),
+    }
+}
+
+pub fn walk_last_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &LastStatement) {
+    visitor.visit_span(&stmt.span);
+
+    match &stmt.kind {
+        LastStatementKind::Break | LastStatementKind::Continue => {}
+        LastStatementKind::Return(stmt) => visitor.visit_return(stmt),
+    }
+}
+
+pub fn walk_local_declaration<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    stmt: &LocalDeclarationStatement,
+) {
+    for exp in stmt.expression_list.iter() {
+        visitor.visit_expression(exp);
+    }
+
+    for identifier in stmt.identifier_list.iter() {
+        visitor.visit_identifier(identifier);
+    }
+
+    for attribute in stmt.attribute_list.iter().flatten() {
+        visitor.visit_attribute(attribute);
+    }
+
+    for ty in stmt.type_list.iter().flatten() {
+        visitor.visit_type(ty);
+    }
+}
+
+pub fn walk_function_call_statement<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    stmt: &FunctionCallStatement,
+) {
+    visitor.visit_expression(&stmt.callee);
+
+    for exp in stmt.arguments.iter() {
+        visitor.visit_expression(exp);
+    }
+}
+
+pub fn walk_assignment<V: Visitor + ?Sized>(visitor: &mut V, stmt: &AssignmentStatement) {
+    for var in stmt.variable_list.iter() {
+        visitor.visit_variable(var);
+    }
+
+    for exp in stmt.expression_list.iter() {
+        visitor.visit_expression(exp);
+    }
+}
+
+pub fn walk_compound_assignment<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    stmt: &CompoundAssignmentStatement,
+) {
+    visitor.visit_variable(&stmt.variable);
+    visitor.visit_compound_operator(&stmt.operator);
+    visitor.visit_expression(&stmt.expression);
+}
+
+pub fn walk_while<V: Visitor + ?Sized>(visitor: &mut V, stmt: &WhileStatement) {
+    visitor.visit_expression(&stmt.condition);
+    visitor.visit_block(&stmt.block);
+}
+
+pub fn walk_repeat<V: Visitor + ?Sized>(visitor: &mut V, stmt: &RepeatStatement) {
+    visitor.visit_block(&stmt.block);
+    visitor.visit_expression(&stmt.condition);
+}
+
+pub fn walk_if<V: Visitor + ?Sized>(visitor: &mut V, stmt: &IfStatement) {
+    visitor.visit_expression(&stmt.condition);
+    visitor.visit_block(&stmt.block);
+
+    for elseif in stmt.elseif_blocks.iter() {
+        visitor.visit_else_if(elseif);
+    }
+
+    if let Some(block) = &stmt.else_block {
+        visitor.visit_block(block);
+    }
+}
+
+pub fn walk_else_if<V: Visitor + ?Sized>(visitor: &mut V, elseif: &ElseIf) {
+    visitor.visit_expression(&elseif.condition);
+    visitor.visit_block(&elseif.block);
+}
+
+pub fn walk_numeric_for<V: Visitor + ?Sized>(visitor: &mut V, stmt: &NumericForStatement) {
+    visitor.visit_expression(&stmt.start);
+    visitor.visit_expression(&stmt.end);
+
+    if let Some(step) = &stmt.step {
+        visitor.visit_expression(step);
+    }
+
+    visitor.visit_identifier(&stmt.identifier);
+
+    if let Some(ty) = &stmt.identifier_type {
+        visitor.visit_type(ty);
+    }
+
+    visitor.visit_block(&stmt.block);
+}
+
+pub fn walk_generic_for<V: Visitor + ?Sized>(visitor: &mut V, stmt: &GenericForStatement) {
+    for exp in stmt.expression_list.iter() {
+        visitor.visit_expression(exp);
+    }
+
+    for identifier in stmt.identifier_list.iter() {
+        visitor.visit_identifier(identifier);
+    }
+
+    for ty in stmt.type_list.iter().flatten() {
+        visitor.visit_type(ty);
+    }
+
+    visitor.visit_block(&stmt.block);
+}
+
+pub fn walk_function_definition<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    stmt: &FunctionDefinitionStatement,
+) {
+    visitor.visit_variable(&stmt.identifier);
+
+    for param in stmt.parameter_list.iter() {
+        visitor.visit_parameter(param);
+    }
+
+    if let Some(types) = &stmt.types {
+        visitor.visit_function_types(types);
+    }
+
+    visitor.visit_block(&stmt.block);
+}
+
+pub fn walk_local_function_definition<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    stmt: &LocalFunctionDefinitionStatement,
+) {
+    visitor.visit_variable(&stmt.identifier);
+
+    for param in stmt.parameter_list.iter() {
+        visitor.visit_parameter(param);
+    }
+
+    if let Some(types) = &stmt.types {
+        visitor.visit_function_types(types);
+    }
+
+    visitor.visit_block(&stmt.block);
+}
+
+pub fn walk_type_declaration<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    stmt: &TypeDeclarationStatement,
+) {
+    if let Some(generics) = &stmt.generics {
+        visitor.visit_type(generics);
+    }
+
+    visitor.visit_type(&stmt.definition);
+}
+
+pub fn walk_return<V: Visitor + ?Sized>(visitor: &mut V, stmt: &ReturnStatement) {
+    for exp in stmt.expression_list.iter() {
+        visitor.visit_expression(exp);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, exp: &Expression) {
+    visitor.visit_span(&exp.span);
+
+    match &exp.kind {
+        ExpressionKind::LiteralInteger(_)
+        | ExpressionKind::LiteralFloat(_)
+        | ExpressionKind::True
+        | ExpressionKind::False
+        | ExpressionKind::Nil
+        | ExpressionKind::VariableArgument => {}
+        ExpressionKind::LiteralString(string) => visitor.visit_lua_string(string),
+        ExpressionKind::TableConstructor(fields) => {
+            for field in fields.iter() {
+                visitor.visit_table_field(field);
+            }
+        }
+        ExpressionKind::FunctionCall(call) => visitor.visit_function_call(call),
+        ExpressionKind::AnonFunctionDefinition(func) => visitor.visit_anon_function(func),
+        ExpressionKind::Variable(var) => visitor.visit_variable(var),
+        ExpressionKind::If(exp) => visitor.visit_if_expression(exp),
+        ExpressionKind::InterpolatedString(string) => visitor.visit_interpolated_string(string),
+        ExpressionKind::TypeAssertion(exp, ty) => {
+            visitor.visit_expression(exp);
+            visitor.visit_type(ty);
+        }
+        ExpressionKind::Parenthesized(exp)
+        | ExpressionKind::Not(exp)
+        | ExpressionKind::Negative(exp)
+        | ExpressionKind::BitwiseNot(exp)
+        | ExpressionKind::Length(exp) => visitor.visit_expression(exp),
+        ExpressionKind::Exponentiation(a, b)
+        | ExpressionKind::Multiplication(a, b)
+        | ExpressionKind::Division(a, b)
+        | ExpressionKind::FloorDivision(a, b)
+        | ExpressionKind::Modulo(a, b)
+        | ExpressionKind::Addition(a, b)
+        | ExpressionKind::Subtraction(a, b)
+        | ExpressionKind::Concatenation(a, b)
+        | ExpressionKind::ShiftLeft(a, b)
+        | ExpressionKind::ShiftRight(a, b)
+        | ExpressionKind::BitwiseAnd(a, b)
+        | ExpressionKind::BitwiseXor(a, b)
+        | ExpressionKind::BitwiseOr(a, b)
+        | ExpressionKind::LessThan(a, b)
+        | ExpressionKind::GreaterThan(a, b)
+        | ExpressionKind::LessThanOrEqual(a, b)
+        | ExpressionKind::GreaterThanOrEqual(a, b)
+        | ExpressionKind::NotEqual(a, b)
+        | ExpressionKind::Equal(a, b)
+        | ExpressionKind::And(a, b)
+        | ExpressionKind::Or(a, b) => {
+            visitor.visit_expression(a);
+            visitor.visit_expression(b);
+        }
+    }
+}
+
+pub fn walk_table_field<V: Visitor + ?Sized>(visitor: &mut V, field: &TableField) {
+    match field {
+        TableField::Value(value) => visitor.visit_expression(value),
+        TableField::IndexValue(index, value) => {
+            visitor.visit_expression(index);
+            visitor.visit_expression(value);
+        }
+        TableField::KeyValue(_, value) => visitor.visit_expression(value),
+    }
+}
+
+pub fn walk_function_call<V: Visitor + ?Sized>(visitor: &mut V, call: &FunctionCallExpression) {
+    visitor.visit_expression(&call.callee);
+
+    for exp in call.arguments.iter() {
+        visitor.visit_expression(exp);
+    }
+}
+
+pub fn walk_anon_function<V: Visitor + ?Sized>(visitor: &mut V, func: &AnonFunctionExpression) {
+    for param in func.parameter_list.iter() {
+        visitor.visit_parameter(param);
+    }
+
+    if let Some(types) = &func.types {
+        visitor.visit_function_types(types);
+    }
+
+    visitor.visit_block(&func.block);
+}
+
+pub fn walk_if_expression<V: Visitor + ?Sized>(visitor: &mut V, exp: &IfExpression) {
+    visitor.visit_expression(&exp.condition);
+    visitor.visit_expression(&exp.expression);
+
+    for elseif in exp.elseif_expressions.iter() {
+        visitor.visit_else_if_expression(elseif);
+    }
+
+    visitor.visit_expression(&exp.else_expression);
+}
+
+pub fn walk_else_if_expression<V: Visitor + ?Sized>(visitor: &mut V, elseif: &ElseIfExpression) {
+    visitor.visit_expression(&elseif.condition);
+    visitor.visit_expression(&elseif.expression);
+}
+
+pub fn walk_interpolated_string<V: Visitor + ?Sized>(visitor: &mut V, string: &InterpolatedString) {
+    for segment in string.segments.iter() {
+        visitor.visit_interpolated_string_segment(segment);
+    }
+
+    visitor.visit_lua_string(&string.last);
+}
+
+pub fn walk_interpolated_string_segment<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    segment: &InterpolatedStringSegment,
+) {
+    visitor.visit_lua_string(&segment.literal);
+    visitor.visit_expression(&segment.expression);
+}
+
+pub fn walk_variable<V: Visitor + ?Sized>(visitor: &mut V, var: &Variable) {
+    match var {
+        Variable::Identifier(identifier) => visitor.visit_identifier(identifier),
+        Variable::TableIndex(index) => visitor.visit_table_index(index),
+        Variable::TableMember(member) => visitor.visit_table_member(member),
+        Variable::TableMethod(method) => visitor.visit_table_method(method),
+    }
+}
+
+pub fn walk_table_index<V: Visitor + ?Sized>(visitor: &mut V, index: &TableIndex) {
+    visitor.visit_expression(&index.base);
+    visitor.visit_expression(&index.index);
+}
+
+pub fn walk_table_member<V: Visitor + ?Sized>(visitor: &mut V, member: &TableMember) {
+    visitor.visit_expression(&member.base);
+}
+
+pub fn walk_table_method<V: Visitor + ?Sized>(visitor: &mut V, method: &TableMethod) {
+    visitor.visit_expression(&method.base);
+}
+
+pub fn walk_parameter<V: Visitor + ?Sized>(visitor: &mut V, param: &Parameter) {
+    match param {
+        Parameter::Identifier(identifier) => visitor.visit_identifier(identifier),
+        Parameter::VariableArg => {}
+    }
+}
+
+pub fn walk_function_types<V: Visitor + ?Sized>(visitor: &mut V, types: &FunctionTypes) {
+    if let Some(generics) = &types.generics {
+        visitor.visit_type(generics);
+    }
+
+    for ty in types.parameter_types.iter().flatten() {
+        visitor.visit_type(ty);
+    }
+
+    if let Some(ty) = &types.return_type {
+        visitor.visit_type(ty);
+    }
+}
+
+pub fn walk_span<V: Visitor + ?Sized>(visitor: &mut V, span: &Span) {
+    visitor.visit_position(&span.start);
+    visitor.visit_position(&span.end);
+}
+
+pub trait VisitorMut {
+    fn visit_chunk_mut(&mut self, chunk: &mut Chunk) {
+        walk_chunk_mut(self, chunk);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        walk_statement_mut(self, stmt);
+    }
+
+    fn visit_last_statement_mut(&mut self, stmt: &mut LastStatement) {
+        walk_last_statement_mut(self, stmt);
+    }
+
+    fn visit_local_declaration_mut(&mut self, stmt: &mut LocalDeclarationStatement) {
+        walk_local_declaration_mut(self, stmt);
+    }
+
+    fn visit_function_call_statement_mut(&mut self, stmt: &mut FunctionCallStatement) {
+        walk_function_call_statement_mut(self, stmt);
+    }
+
+    fn visit_assignment_mut(&mut self, stmt: &mut AssignmentStatement) {
+        walk_assignment_mut(self, stmt);
+    }
+
+    fn visit_compound_assignment_mut(&mut self, stmt: &mut CompoundAssignmentStatement) {
+        walk_compound_assignment_mut(self, stmt);
+    }
+
+    fn visit_label_mut(&mut self, _label: &mut Identifier) {}
+
+    fn visit_goto_mut(&mut self, _label: &mut Identifier) {}
+
+    fn visit_while_mut(&mut self, stmt: &mut WhileStatement) {
+        walk_while_mut(self, stmt);
+    }
+
+    fn visit_repeat_mut(&mut self, stmt: &mut RepeatStatement) {
+        walk_repeat_mut(self, stmt);
+    }
+
+    fn visit_if_mut(&mut self, stmt: &mut IfStatement) {
+        walk_if_mut(self, stmt);
+    }
+
+    fn visit_else_if_mut(&mut self, elseif: &mut ElseIf) {
+        walk_else_if_mut(self, elseif);
+    }
+
+    fn visit_numeric_for_mut(&mut self, stmt: &mut NumericForStatement) {
+        walk_numeric_for_mut(self, stmt);
+    }
+
+    fn visit_generic_for_mut(&mut self, stmt: &mut GenericForStatement) {
+        walk_generic_for_mut(self, stmt);
+    }
+
+    fn visit_function_definition_mut(&mut self, stmt: &mut FunctionDefinitionStatement) {
+        walk_function_definition_mut(self, stmt);
+    }
+
+    fn visit_local_function_definition_mut(&mut self, stmt: &mut LocalFunctionDefinitionStatement) {
+        walk_local_function_definition_mut(self, stmt);
+    }
+
+    fn visit_type_declaration_mut(&mut self, stmt: &mut TypeDeclarationStatement) {
+        walk_type_declaration_mut(self, stmt);
+    }
+
+    fn visit_return_mut(&mut self, stmt: &mut ReturnStatement) {
+        walk_return_mut(self, stmt);
+    }
+
+    fn visit_expression_mut(&mut self, exp: &mut Expression) {
+        walk_expression_mut(self, exp);
+    }
+
+    fn visit_table_field_mut(&mut self, field: &mut TableField) {
+        walk_table_field_mut(self, field);
+    }
+
+    fn visit_function_call_mut(&mut self, call: &mut FunctionCallExpression) {
+        walk_function_call_mut(self, call);
+    }
+
+    fn visit_anon_function_mut(&mut self, func: &mut AnonFunctionExpression) {
+        walk_anon_function_mut(self, func);
+    }
+
+    fn visit_if_expression_mut(&mut self, exp: &mut IfExpression) {
+        walk_if_expression_mut(self, exp);
+    }
+
+    fn visit_else_if_expression_mut(&mut self, elseif: &mut ElseIfExpression) {
+        walk_else_if_expression_mut(self, elseif);
+    }
+
+    fn visit_interpolated_string_mut(&mut self, string: &mut InterpolatedString) {
+        walk_interpolated_string_mut(self, string);
+    }
+
+    fn visit_interpolated_string_segment_mut(&mut self, segment: &mut InterpolatedStringSegment) {
+        walk_interpolated_string_segment_mut(self, segment);
+    }
+
+    fn visit_variable_mut(&mut self, var: &mut Variable) {
+        walk_variable_mut(self, var);
+    }
+
+    fn visit_table_index_mut(&mut self, index: &mut TableIndex) {
+        walk_table_index_mut(self, index);
+    }
+
+    fn visit_table_member_mut(&mut self, member: &mut TableMember) {
+        walk_table_member_mut(self, member);
+    }
+
+    fn visit_table_method_mut(&mut self, method: &mut TableMethod) {
+        walk_table_method_mut(self, method);
+    }
+
+    fn visit_parameter_mut(&mut self, param: &mut Parameter) {
+        walk_parameter_mut(self, param);
+    }
+
+    fn visit_identifier_mut(&mut self, _identifier: &mut Identifier) {}
+
+    fn visit_lua_string_mut(&mut self, _string: &mut LuaString) {}
+
+    fn visit_attribute_mut(&mut self, _attribute: &mut Attribute) {}
+
+    fn visit_compound_operator_mut(&mut self, _operator: &mut CompoundOperator) {}
+
+    fn visit_type_mut(&mut self, _ty: &mut Type) {}
+
+    fn visit_function_types_mut(&mut self, types: &mut FunctionTypes) {
+        walk_function_types_mut(self, types);
+    }
+
+    fn visit_span_mut(&mut self, span: &mut Span) {
+        walk_span_mut(self, span);
+    }
+
+    fn visit_position_mut(&mut self, _position: &mut Position) {}
+}
+
+pub fn walk_chunk_mut<V: VisitorMut + ?Sized>(visitor: &mut V, chunk: &mut Chunk) {
+    visitor.visit_block_mut(&mut chunk.block);
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for stmt in block.statements.iter_mut() {
+        visitor.visit_statement_mut(stmt);
+    }
+
+    if let Some(last) = &mut block.last_statement {
+        visitor.visit_last_statement_mut(last);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Statement) {
+    visitor.visit_span_mut(&mut stmt.span);
+
+    match &mut stmt.kind {
+        StatementKind::Semicolon | StatementKind::Break => {}
+        StatementKind::LocalDeclaration(stmt) => visitor.visit_local_declaration_mut(stmt),
+        StatementKind::FunctionCall(stmt) => visitor.visit_function_call_statement_mut(stmt),
+        StatementKind::Assignment(stmt) => visitor.visit_assignment_mut(stmt),
+        StatementKind::CompoundAssignment(stmt) => visitor.visit_compound_assignment_mut(stmt),
+        StatementKind::Label(label) => visitor.visit_label_mut(label),
+        StatementKind::Goto(label) => visitor.visit_goto_mut(label),
+        StatementKind::Scope(block) => visitor.visit_block_mut(block),
+        StatementKind::While(stmt) => visitor.visit_while_mut(stmt),
+        StatementKind::Repeat(stmt) => visitor.visit_repeat_mut(stmt),
+        StatementKind::If(stmt) => visitor.visit_if_mut(stmt),
+        StatementKind::NumericFor(stmt) => visitor.visit_numeric_for_mut(stmt),
+        StatementKind::GenericFor(stmt) => visitor.visit_generic_for_mut(stmt),
+        StatementKind::FunctionDefinition(stmt) => visitor.visit_function_definition_mut(stmt),
+        StatementKind::LocalFunctionDefinition(stmt) => {
+            visitor.visit_local_function_definition_mut(stmt)
+        }
+        StatementKind::TypeDeclaration(stmt) => visitor.visit_type_declaration_mut(stmt),
+    }
+}
+
+pub fn walk_last_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut LastStatement) {
+    visitor.visit_span_mut(&mut stmt.span);
+
+    match &mut stmt.kind {
+        LastStatementKind::Break | LastStatementKind::Continue => {}
+        LastStatementKind::Return(stmt) => visitor.visit_return_mut(stmt),
+    }
+}
+
+pub fn walk_local_declaration_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut LocalDeclarationStatement,
+) {
+    for exp in stmt.expression_list.iter_mut() {
+        visitor.visit_expression_mut(exp);
+    }
+
+    for identifier in stmt.identifier_list.iter_mut() {
+        visitor.visit_identifier_mut(identifier);
+    }
+
+    for attribute in stmt.attribute_list.iter_mut().flatten() {
+        visitor.visit_attribute_mut(attribute);
+    }
+
+    for ty in stmt.type_list.iter_mut().flatten() {
+        visitor.visit_type_mut(ty);
+    }
+}
+
+pub fn walk_function_call_statement_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut FunctionCallStatement,
+) {
+    visitor.visit_expression_mut(&mut stmt.callee);
+
+    for exp in stmt.arguments.iter_mut() {
+        visitor.visit_expression_mut(exp);
+    }
+}
+
+pub fn walk_assignment_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut AssignmentStatement,
+) {
+    for var in stmt.variable_list.iter_mut() {
+        visitor.visit_variable_mut(var);
+    }
+
+    for exp in stmt.expression_list.iter_mut() {
+        visitor.visit_expression_mut(exp);
+    }
+}
+
+pub fn walk_compound_assignment_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut CompoundAssignmentStatement,
+) {
+    visitor.visit_variable_mut(&mut stmt.variable);
+    visitor.visit_compound_operator_mut(&mut stmt.operator);
+    visitor.visit_expression_mut(&mut stmt.expression);
+}
+
+pub fn walk_while_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut WhileStatement) {
+    visitor.visit_expression_mut(&mut stmt.condition);
+    visitor.visit_block_mut(&mut stmt.block);
+}
+
+pub fn walk_repeat_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut RepeatStatement) {
+    visitor.visit_block_mut(&mut stmt.block);
+    visitor.visit_expression_mut(&mut stmt.condition);
+}
+
+pub fn walk_if_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut IfStatement) {
+    visitor.visit_expression_mut(&mut stmt.condition);
+    visitor.visit_block_mut(&mut stmt.block);
+
+    for elseif in stmt.elseif_blocks.iter_mut() {
+        visitor.visit_else_if_mut(elseif);
+    }
+
+    if let Some(block) = &mut stmt.else_block {
+        visitor.visit_block_mut(block);
+    }
+}
+
+pub fn walk_else_if_mut<V: VisitorMut + ?Sized>(visitor: &mut V, elseif: &mut ElseIf) {
+    visitor.visit_expression_mut(&mut elseif.condition);
+    visitor.visit_block_mut(&mut elseif.block);
+}
+
+pub fn walk_numeric_for_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut NumericForStatement,
+) {
+    visitor.visit_expression_mut(&mut stmt.start);
+    visitor.visit_expression_mut(&mut stmt.end);
+
+    if let Some(step) = &mut stmt.step {
+        visitor.visit_expression_mut(step);
+    }
+
+    visitor.visit_identifier_mut(&mut stmt.identifier);
+
+    if let Some(ty) = &mut stmt.identifier_type {
+        visitor.visit_type_mut(ty);
+    }
+
+    visitor.visit_block_mut(&mut stmt.block);
+}
+
+pub fn walk_generic_for_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut GenericForStatement,
+) {
+    for exp in stmt.expression_list.iter_mut() {
+        visitor.visit_expression_mut(exp);
+    }
+
+    for identifier in stmt.identifier_list.iter_mut() {
+        visitor.visit_identifier_mut(identifier);
+    }
+
+    for ty in stmt.type_list.iter_mut().flatten() {
+        visitor.visit_type_mut(ty);
+    }
+
+    visitor.visit_block_mut(&mut stmt.block);
+}
+
+pub fn walk_function_definition_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut FunctionDefinitionStatement,
+) {
+    visitor.visit_variable_mut(&mut stmt.identifier);
+
+    for param in stmt.parameter_list.iter_mut() {
+        visitor.visit_parameter_mut(param);
+    }
+
+    if let Some(types) = &mut stmt.types {
+        visitor.visit_function_types_mut(types);
+    }
+
+    visitor.visit_block_mut(&mut stmt.block);
+}
+
+pub fn walk_local_function_definition_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut LocalFunctionDefinitionStatement,
+) {
+    visitor.visit_variable_mut(&mut stmt.identifier);
+
+    for param in stmt.parameter_list.iter_mut() {
+        visitor.visit_parameter_mut(param);
+    }
+
+    if let Some(types) = &mut stmt.types {
+        visitor.visit_function_types_mut(types);
+    }
+
+    visitor.visit_block_mut(&mut stmt.block);
+}
+
+pub fn walk_type_declaration_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut TypeDeclarationStatement,
+) {
+    if let Some(generics) = &mut stmt.generics {
+        visitor.visit_type_mut(generics);
+    }
+
+    visitor.visit_type_mut(&mut stmt.definition);
+}
+
+pub fn walk_return_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut ReturnStatement) {
+    for exp in stmt.expression_list.iter_mut() {
+        visitor.visit_expression_mut(exp);
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, exp: &mut Expression) {
+    visitor.visit_span_mut(&mut exp.span);
+
+    match &mut exp.kind {
+        ExpressionKind::LiteralInteger(_)
+        | ExpressionKind::LiteralFloat(_)
+        | ExpressionKind::True
+        | ExpressionKind::False
+        | ExpressionKind::Nil
+        | ExpressionKind::VariableArgument => {}
+        ExpressionKind::LiteralString(string) => visitor.visit_lua_string_mut(string),
+        ExpressionKind::TableConstructor(fields) => {
+            for field in fields.iter_mut() {
+                visitor.visit_table_field_mut(field);
+            }
+        }
+        ExpressionKind::FunctionCall(call) => visitor.visit_function_call_mut(call),
+        ExpressionKind::AnonFunctionDefinition(func) => visitor.visit_anon_function_mut(func),
+        ExpressionKind::Variable(var) => visitor.visit_variable_mut(var),
+        ExpressionKind::If(exp) => visitor.visit_if_expression_mut(exp),
+        ExpressionKind::InterpolatedString(string) => visitor.visit_interpolated_string_mut(string),
+        ExpressionKind::TypeAssertion(exp, ty) => {
+            visitor.visit_expression_mut(exp);
+            visitor.visit_type_mut(ty);
+        }
+        ExpressionKind::Parenthesized(exp)
+        | ExpressionKind::Not(exp)
+        | ExpressionKind::Negative(exp)
+        | ExpressionKind::BitwiseNot(exp)
+        | ExpressionKind::Length(exp) => visitor.visit_expression_mut(exp),
+        ExpressionKind::Exponentiation(a, b)
+        | ExpressionKind::Multiplication(a, b)
+        | ExpressionKind::Division(a, b)
+        | ExpressionKind::FloorDivision(a, b)
+        | ExpressionKind::Modulo(a, b)
+        | ExpressionKind::Addition(a, b)
+        | ExpressionKind::Subtraction(a, b)
+        | ExpressionKind::Concatenation(a, b)
+        | ExpressionKind::ShiftLeft(a, b)
+        | ExpressionKind::ShiftRight(a, b)
+        | ExpressionKind::BitwiseAnd(a, b)
+        | ExpressionKind::BitwiseXor(a, b)
+        | ExpressionKind::BitwiseOr(a, b)
+        | ExpressionKind::LessThan(a, b)
+        | ExpressionKind::GreaterThan(a, b)
+        | ExpressionKind::LessThanOrEqual(a, b)
+        | ExpressionKind::GreaterThanOrEqual(a, b)
+        | ExpressionKind::NotEqual(a, b)
+        | ExpressionKind::Equal(a, b)
+        | ExpressionKind::And(a, b)
+        | ExpressionKind::Or(a, b) => {
+            visitor.visit_expression_mut(a);
+            visitor.visit_expression_mut(b);
+        }
+    }
+}
+
+pub fn walk_table_field_mut<V: VisitorMut + ?Sized>(visitor: &mut V, field: &mut TableField) {
+    match field {
+        TableField::Value(value) => visitor.visit_expression_mut(value),
+        TableField::IndexValue(index, value) => {
+            visitor.visit_expression_mut(index);
+            visitor.visit_expression_mut(value);
+        }
+        TableField::KeyValue(_, value) => visitor.visit_expression_mut(value),
+    }
+}
+
+pub fn walk_function_call_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    call: &mut FunctionCallExpression,
+) {
+    visitor.visit_expression_mut(&mut call.callee);
+
+    for exp in call.arguments.iter_mut() {
+        visitor.visit_expression_mut(exp);
+    }
+}
+
+pub fn walk_anon_function_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    func: &mut AnonFunctionExpression,
+) {
+    for param in func.parameter_list.iter_mut() {
+        visitor.visit_parameter_mut(param);
+    }
+
+    if let Some(types) = &mut func.types {
+        visitor.visit_function_types_mut(types);
+    }
+
+    visitor.visit_block_mut(&mut func.block);
+}
+
+pub fn walk_if_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, exp: &mut IfExpression) {
+    visitor.visit_expression_mut(&mut exp.condition);
+    visitor.visit_expression_mut(&mut exp.expression);
+
+    for elseif in exp.elseif_expressions.iter_mut() {
+        visitor.visit_else_if_expression_mut(elseif);
+    }
+
+    visitor.visit_expression_mut(&mut exp.else_expression);
+}
+
+pub fn walk_else_if_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    elseif: &mut ElseIfExpression,
+) {
+    visitor.visit_expression_mut(&mut elseif.condition);
+    visitor.visit_expression_mut(&mut elseif.expression);
+}
+
+pub fn walk_interpolated_string_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    string: &mut InterpolatedString,
+) {
+    for segment in string.segments.iter_mut() {
+        visitor.visit_interpolated_string_segment_mut(segment);
+    }
+
+    visitor.visit_lua_string_mut(&mut string.last);
+}
+
+pub fn walk_interpolated_string_segment_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    segment: &mut InterpolatedStringSegment,
+) {
+    visitor.visit_lua_string_mut(&mut segment.literal);
+    visitor.visit_expression_mut(&mut segment.expression);
+}
+
+pub fn walk_variable_mut<V: VisitorMut + ?Sized>(visitor: &mut V, var: &mut Variable) {
+    match var {
+        Variable::Identifier(identifier) => visitor.visit_identifier_mut(identifier),
+        Variable::TableIndex(index) => visitor.visit_table_index_mut(index),
+        Variable::TableMember(member) => visitor.visit_table_member_mut(member),
+        Variable::TableMethod(method) => visitor.visit_table_method_mut(method),
+    }
+}
+
+pub fn walk_table_index_mut<V: VisitorMut + ?Sized>(visitor: &mut V, index: &mut TableIndex) {
+    visitor.visit_expression_mut(&mut index.base);
+    visitor.visit_expression_mut(&mut index.index);
+}
+
+pub fn walk_table_member_mut<V: VisitorMut + ?Sized>(visitor: &mut V, member: &mut TableMember) {
+    visitor.visit_expression_mut(&mut member.base);
+}
+
+pub fn walk_table_method_mut<V: VisitorMut + ?Sized>(visitor: &mut V, method: &mut TableMethod) {
+    visitor.visit_expression_mut(&mut method.base);
+}
+
+pub fn walk_parameter_mut<V: VisitorMut + ?Sized>(visitor: &mut V, param: &mut Parameter) {
+    match param {
+        Parameter::Identifier(identifier) => visitor.visit_identifier_mut(identifier),
+        Parameter::VariableArg => {}
+    }
+}
+
+pub fn walk_function_types_mut<V: VisitorMut + ?Sized>(visitor: &mut V, types: &mut FunctionTypes) {
+    if let Some(generics) = &mut types.generics {
+        visitor.visit_type_mut(generics);
+    }
+
+    for ty in types.parameter_types.iter_mut().flatten() {
+        visitor.visit_type_mut(ty);
+    }
+
+    if let Some(ty) = &mut types.return_type {
+        visitor.visit_type_mut(ty);
+    }
+}
+
+pub fn walk_span_mut<V: VisitorMut + ?Sized>(visitor: &mut V, span: &mut Span) {
+    visitor.visit_position_mut(&mut span.start);
+    visitor.visit_position_mut(&mut span.end);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{
+        walk_expression, walk_expression_mut, walk_last_statement, walk_last_statement_mut,
+        walk_statement, walk_statement_mut, Visitor, VisitorMut,
+    };
+    use crate::parser::{
+        ast::definition::{Expression, LastStatement, Span, Statement, StatementKind},
+        parse,
+    };
+
+    /// Records the name of the kind of every statement and expression, in the
+    /// order they are visited.
+    #[derive(Default)]
+    struct Kinds(Vec<String>);
+
+    impl Kinds {
+        fn record(&mut self, kind: &impl std::fmt::Debug) {
+            let kind = format!("{kind:?}");
+            let name = kind.split(['(', ' ']).next().unwrap();
+
+            self.0.push(name.to_string());
+        }
+    }
+
+    impl Visitor for Kinds {
+        fn visit_statement(&mut self, stmt: &Statement) {
+            self.record(&stmt.kind);
+            walk_statement(self, stmt);
+        }
+
+        fn visit_last_statement(&mut self, stmt: &LastStatement) {
+            self.record(&stmt.kind);
+            walk_last_statement(self, stmt);
+        }
+
+        fn visit_expression(&mut self, exp: &Expression) {
+            self.record(&exp.kind);
+            walk_expression(self, exp);
+        }
+    }
+
+    impl VisitorMut for Kinds {
+        fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+            self.record(&stmt.kind);
+            walk_statement_mut(self, stmt);
+        }
+
+        fn visit_last_statement_mut(&mut self, stmt: &mut LastStatement) {
+            self.record(&stmt.kind);
+            walk_last_statement_mut(self, stmt);
+        }
+
+        fn visit_expression_mut(&mut self, exp: &mut Expression) {
+            self.record(&exp.kind);
+            walk_expression_mut(self, exp);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(any(feature = "lua52", feature = "luau")), allow(unused_mut))]
+    fn every_kind_visited() {
+        let mut source = "
+            local a, b = 1, 2.5
+            f('s', ...)
+            x.y, t[k] = true, false
+            do
+                while nil do break end
+            end
+            repeat local c = not a until #b
+            if a < b then elseif a > b then elseif a <= b then else end
+            for i = -a, a ^ 2 do end
+            for k, v in pairs({ 1, y = 2, [3] = 4 }) do end
+            function m.n:o(p, ...) return (p * p) / p % p + p - p .. p end
+            local function g() return a == b, a ~= b, a >= b, a and b or obj:method() end
+            local h = function() end
+        "
+        .to_string();
+        let mut expected = vec![
+            "Semicolon",
+            "LocalDeclaration",
+            "FunctionCall",
+            "Assignment",
+            "Break",
+            "Scope",
+            "While",
+            "Repeat",
+            "If",
+            "NumericFor",
+            "GenericFor",
+            "FunctionDefinition",
+            "LocalFunctionDefinition",
+            "Return",
+            "LiteralInteger",
+            "LiteralFloat",
+            "LiteralString",
+            "True",
+            "False",
+            "Nil",
+            "TableConstructor",
+            "AnonFunctionDefinition",
+            "Variable",
+            "VariableArgument",
+            "Parenthesized",
+            "Exponentiation",
+            "Not",
+            "Negative",
+            "Multiplication",
+            "Division",
+            "Modulo",
+            "Addition",
+            "Subtraction",
+            "Concatenation",
+            "LessThan",
+            "GreaterThan",
+            "LessThanOrEqual",
+            "GreaterThanOrEqual",
+            "NotEqual",
+            "Equal",
+            "And",
+            "Or",
+            "Length",
+        ];
+
+        #[cfg(feature = "lua52")]
+        {
+            source.push_str("goto done ::done::\n");
+            expected.extend(["Goto", "Label"]);
+        }
+
+        #[cfg(feature = "lua53")]
+        {
+            source.push_str("local z = a // b & b | b ~ b << b >> ~b\n");
+            expected.extend([
+                "FloorDivision",
+                "BitwiseAnd",
+                "BitwiseOr",
+                "BitwiseXor",
+                "ShiftLeft",
+                "ShiftRight",
+                "BitwiseNot",
+            ]);
+        }
+
+        #[cfg(feature = "luau")]
+        {
+            source.push_str(
+                "a += 1
+                type T = number
+                local w = if a then b elseif b then a else `x{a}` :: string
+                while a do continue end\n",
+            );
+            expected.extend([
+                "CompoundAssignment",
+                "TypeDeclaration",
+                "If",
+                "InterpolatedString",
+                "TypeAssertion",
+                "Continue",
+            ]);
+        }
+
+        // the parser drops semicolons, but the tree can still hold them
+        let mut chunk = parse(&source).unwrap();
+        chunk
+            .block
+            .statements
+            .push(Statement::new(StatementKind::Semicolon, Span::default()));
+
+        let mut kinds = Kinds::default();
+        kinds.visit_chunk(&chunk);
+        assert_eq!(
+            kinds.0.iter().map(String::as_str).collect::<BTreeSet<_>>(),
+            expected.into_iter().collect::<BTreeSet<_>>()
+        );
+
+        let mut kinds_mut = Kinds::default();
+        kinds_mut.visit_chunk_mut(&mut chunk);
+        assert_eq!(kinds_mut.0, kinds.0);
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use super::ast::definition::Position;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    Syntax {
+        message: String,
+        position: Option<Position>,
+    },
+    Unsupported {
+        construct: &'static str,
+        position: Option<Position>,
+    },
+    MalformedNumber {
+        literal: String,
+        position: Option<Position>,
+    },
+    MalformedString {
+        message: &'static str,
+        position: Option<Position>,
+    },
+}
+
+impl Error {
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Error::Syntax { position, .. }
+            | Error::Unsupported { position, .. }
+            | Error::MalformedNumber { position, .. }
+            | Error::MalformedString { position, .. } => *position,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax { message, .. } => write!(f, "syntax error: {message}")?,
+            Error::Unsupported { construct, .. } => {
+                write!(f, "unsupported construct: {construct}")?
+            }
+            Error::MalformedNumber { literal, .. } => write!(f, "malformed number: {literal}")?,
+            Error::MalformedString { message, .. } => write!(f, "malformed string: {message}")?,
+        }
+
+        if let Some(position) = self.position() {
+            write!(f, " at {position}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<full_moon::Error> for Error {
+    fn from(value: full_moon::Error) -> Self {
+        match value {
+            full_moon::Error::AstError(full_moon::ast::AstError::UnexpectedToken {
+                token,
+                additional,
+            }) => Error::Syntax {
+                message: match additional {
+                    Some(additional) => format!("unexpected token `{token}`, {additional}"),
+                    None => format!("unexpected token `{token}`"),
+                },
+                position: Some(token.start_position().into()),
+            },
+            full_moon::Error::AstError(error) => Error::Syntax {
+                message: error.to_string(),
+                position: None,
+            },
+            full_moon::Error::TokenizerError(error) => Error::Syntax {
+                message: error.error().to_string(),
+                position: Some(error.position().into()),
+            },
+        }
+    }
+}
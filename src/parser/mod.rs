@@ -3,12 +3,148 @@ use crate::parser::ast::definition::Chunk;
 use self::ast::definition::Block;
 
 pub mod ast;
+mod error;
+pub mod number;
+pub mod string;
 
-pub fn parse(source: &str) -> Result<Chunk, full_moon::Error> {
-    let ast = full_moon::parse(source)?;
+pub use error::Error;
+
+/// Parses a chunk, reporting every statement that cannot be converted
+/// rather than stopping at the first.
+pub fn parse(source: &str) -> Result<Chunk, Vec<Error>> {
+    let ast = full_moon::parse(source).map_err(|error| vec![Error::from(error)])?;
     let main_block = ast.nodes();
 
     Ok(Chunk {
-        block: Block::from(main_block),
+        block: Block::try_from(main_block)?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ast::definition::{ExpressionKind, Position, Span, StatementKind},
+        parse, Error,
+    };
+
+    fn position(line: usize, column: usize, offset: usize) -> Option<Position> {
+        Some(Position {
+            line,
+            column,
+            offset,
+        })
+    }
+
+    #[cfg(feature = "lua54")]
+    #[test]
+    fn unsupported_attribute() {
+        assert_eq!(
+            parse("local x <foo> = 1").unwrap_err(),
+            vec![Error::Unsupported {
+                construct: "local attribute",
+                position: position(1, 9, 8),
+            }]
+        );
+    }
+
+    #[test]
+    fn malformed_string() {
+        assert_eq!(
+            parse("x = 1\ny = \"\\q\"").unwrap_err(),
+            vec![Error::MalformedString {
+                message: "invalid escape sequence",
+                position: position(2, 5, 10),
+            }]
+        );
+    }
+
+    #[test]
+    fn malformed_decimal_escape() {
+        assert_eq!(
+            parse("print('\\400')").unwrap_err(),
+            vec![Error::MalformedString {
+                message: "decimal escape too large",
+                position: position(1, 7, 6),
+            }]
+        );
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn malformed_number() {
+        assert_eq!(
+            parse("local a = 0x1p99999999999999999999").unwrap_err(),
+            vec![Error::MalformedNumber {
+                literal: "0x1p99999999999999999999".to_string(),
+                position: position(1, 11, 10),
+            }]
+        );
+    }
+
+    #[test]
+    fn every_statement_reported() {
+        let errors = parse("local a = '\\q'\nlocal b = 1\nlocal c = '\\400'").unwrap_err();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.position())
+                .collect::<Vec<_>>(),
+            vec![position(1, 11, 10), position(3, 11, 37)]
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        let errors = parse("local = 1").unwrap_err();
+
+        assert!(matches!(errors.as_slice(), [Error::Syntax { .. }]));
+    }
+
+    fn text(source: &str, span: Span) -> &str {
+        &source[span.start.offset..span.end.offset]
+    }
+
+    #[test]
+    fn spans() {
+        let source = "local a = 1 + f(2)\nwhile a do\n  a = a.b[3]\nend\nreturn a";
+        let chunk = parse(source).unwrap();
+        let statements = &chunk.block.statements;
+
+        assert_eq!(text(source, statements[0].span), "local a = 1 + f(2)");
+        assert_eq!(
+            text(source, statements[1].span),
+            "while a do\n  a = a.b[3]\nend"
+        );
+        assert_eq!(
+            text(source, chunk.block.last_statement.as_ref().unwrap().span),
+            "return a"
+        );
+
+        let StatementKind::LocalDeclaration(local) = &statements[0].kind else {
+            panic!("expected a local declaration");
+        };
+        let addition = &local.expression_list[0];
+        assert_eq!(text(source, addition.span), "1 + f(2)");
+        let ExpressionKind::Addition(left, right) = &addition.kind else {
+            panic!("expected an addition");
+        };
+        assert_eq!(text(source, left.span), "1");
+        assert_eq!(text(source, right.span), "f(2)");
+
+        let StatementKind::While(while_statement) = &statements[1].kind else {
+            panic!("expected a while loop");
+        };
+        assert_eq!(text(source, while_statement.condition.span), "a");
+        let assignment = &while_statement.block.statements[0];
+        assert_eq!(text(source, assignment.span), "a = a.b[3]");
+        assert_eq!(
+            assignment.span.start,
+            Position {
+                line: 3,
+                column: 3,
+                offset: 32
+            }
+        );
+    }
+}
@@ -0,0 +1,251 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+fn digit_value(c: u8) -> Option<u32> {
+    (c as char).to_digit(16)
+}
+
+/// Converts the text of a Lua numeral into its value, following the rules of
+/// `lua_stringtonumber`: decimal integers that overflow become floats, while
+/// hexadecimal integers wrap around modulo 2^64.
+///
+/// Digit separators (`1_000`) and binary literals (`0b101`) are accepted as
+/// well, since Luau allows them.
+pub fn parse(text: &str) -> Option<Number> {
+    let text = text.as_bytes();
+
+    match text {
+        [b'0', b'x' | b'X', rest @ ..] => parse_hexadecimal(rest),
+        [b'0', b'b' | b'B', rest @ ..] => parse_binary(rest),
+        _ => parse_decimal(text),
+    }
+}
+
+fn parse_decimal(text: &[u8]) -> Option<Number> {
+    let mut digits = String::with_capacity(text.len());
+    let mut is_float = false;
+    let mut seen_digit = false;
+    let mut seen_dot = false;
+    let mut seen_exponent = false;
+
+    let mut i = 0;
+    while i < text.len() {
+        let c = text[i];
+
+        match c {
+            b'0'..=b'9' => {
+                seen_digit = true;
+                digits.push(c as char);
+            }
+            b'_' => {}
+            b'.' if !seen_dot && !seen_exponent => {
+                seen_dot = true;
+                is_float = true;
+                digits.push('.');
+            }
+            b'e' | b'E' if seen_digit && !seen_exponent => {
+                seen_exponent = true;
+                is_float = true;
+                digits.push('e');
+
+                if let Some(sign @ (b'+' | b'-')) = text.get(i + 1) {
+                    digits.push(*sign as char);
+                    i += 1;
+                }
+
+                // the exponent needs at least one digit of its own
+                if !matches!(text.get(i + 1), Some(b'0'..=b'9')) {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+
+        i += 1;
+    }
+
+    if !seen_digit {
+        return None;
+    }
+
+    if !is_float {
+        if let Ok(integer) = digits.parse::<i64>() {
+            return Some(Number::Integer(integer));
+        }
+    }
+
+    digits.parse::<f64>().ok().map(Number::Float)
+}
+
+fn parse_hexadecimal(text: &[u8]) -> Option<Number> {
+    let mut integer: u64 = 0;
+    let mut mantissa: f64 = 0.0;
+    let mut exponent: i64 = 0;
+    let mut significant_digits = 0;
+    let mut seen_digit = false;
+    let mut seen_dot = false;
+
+    let mut i = 0;
+    while i < text.len() {
+        let c = text[i];
+
+        match c {
+            b'_' => {}
+            b'.' if !seen_dot => seen_dot = true,
+            b'p' | b'P' => break,
+            _ => {
+                let digit = digit_value(c)?;
+                seen_digit = true;
+
+                integer = integer.wrapping_mul(16).wrapping_add(digit as u64);
+
+                // digits beyond the precision of a double only shift the exponent
+                if significant_digits == 0 && digit == 0 {
+                    if seen_dot {
+                        exponent -= 4;
+                    }
+                } else if significant_digits < 30 {
+                    significant_digits += 1;
+                    mantissa = mantissa * 16.0 + digit as f64;
+
+                    if seen_dot {
+                        exponent -= 4;
+                    }
+                } else if !seen_dot {
+                    exponent += 4;
+                }
+            }
+        }
+
+        i += 1;
+    }
+
+    if !seen_digit {
+        return None;
+    }
+
+    let has_exponent = i < text.len();
+
+    if !seen_dot && !has_exponent {
+        return Some(Number::Integer(integer as i64));
+    }
+
+    if has_exponent {
+        let rest = std::str::from_utf8(&text[i + 1..]).ok()?.replace('_', "");
+        let digits = rest.strip_prefix('+').unwrap_or(&rest);
+
+        if !digits
+            .strip_prefix('-')
+            .unwrap_or(digits)
+            .bytes()
+            .next()
+            .is_some_and(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        exponent = exponent.saturating_add(digits.parse::<i64>().ok()?);
+    }
+
+    Some(Number::Float(scale_by_power_of_two(mantissa, exponent)))
+}
+
+fn parse_binary(text: &[u8]) -> Option<Number> {
+    let mut integer: u64 = 0;
+    let mut seen_digit = false;
+
+    for c in text {
+        match c {
+            b'0' | b'1' => {
+                seen_digit = true;
+                integer = integer.wrapping_mul(2).wrapping_add((c - b'0') as u64);
+            }
+            b'_' => {}
+            _ => return None,
+        }
+    }
+
+    seen_digit.then_some(Number::Integer(integer as i64))
+}
+
+fn scale_by_power_of_two(mut value: f64, mut exponent: i64) -> f64 {
+    // step in chunks so that intermediate powers never overflow on their own
+    while exponent > 1000 {
+        value *= 2f64.powi(1000);
+        exponent -= 1000;
+    }
+
+    while exponent < -1000 {
+        value *= 2f64.powi(-1000);
+        exponent += 1000;
+    }
+
+    value * 2f64.powi(exponent as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Number};
+
+    #[test]
+    fn decimal_integers() {
+        assert_eq!(parse("0"), Some(Number::Integer(0)));
+        assert_eq!(parse("1_000"), Some(Number::Integer(1000)));
+        assert_eq!(
+            parse("9223372036854775807"),
+            Some(Number::Integer(i64::MAX))
+        );
+    }
+
+    #[test]
+    fn decimal_overflow_becomes_float() {
+        assert_eq!(
+            parse("9223372036854775808"),
+            Some(Number::Float(9223372036854775808.0))
+        );
+        assert_eq!(parse("1e400"), Some(Number::Float(f64::INFINITY)));
+    }
+
+    #[test]
+    fn decimal_floats() {
+        assert_eq!(parse("1.5"), Some(Number::Float(1.5)));
+        assert_eq!(parse(".5"), Some(Number::Float(0.5)));
+        assert_eq!(parse("1e-2"), Some(Number::Float(0.01)));
+        assert_eq!(parse("1e"), None);
+        assert_eq!(parse("."), None);
+    }
+
+    #[test]
+    fn hexadecimal_integers_wrap_around() {
+        assert_eq!(parse("0xff"), Some(Number::Integer(255)));
+        assert_eq!(parse("0xffffffffffffffff"), Some(Number::Integer(-1)));
+        assert_eq!(parse("0x10000000000000000"), Some(Number::Integer(0)));
+        assert_eq!(parse("0x7fffffffffffffff"), Some(Number::Integer(i64::MAX)));
+    }
+
+    #[test]
+    fn binary_integers() {
+        assert_eq!(parse("0b101"), Some(Number::Integer(5)));
+        assert_eq!(parse("0b"), None);
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn hexadecimal_floats() {
+        assert_eq!(parse("0x1p4"), Some(Number::Float(16.0)));
+        assert_eq!(parse("0x1P-1"), Some(Number::Float(0.5)));
+        assert_eq!(parse("0x.8"), Some(Number::Float(0.5)));
+        assert_eq!(parse("0xA.8p+1"), Some(Number::Float(21.0)));
+        assert_eq!(parse("0x1p99999"), Some(Number::Float(f64::INFINITY)));
+        assert_eq!(parse("0x1p"), None);
+    }
+
+    #[cfg(feature = "lua52")]
+    #[test]
+    fn hexadecimal_exponent_too_large() {
+        assert_eq!(parse("0x1p99999999999999999999"), None);
+    }
+}
@@ -0,0 +1,274 @@
+/// Decodes the body of a quoted string literal, without its surrounding
+/// quotes, into the bytes it denotes.
+pub fn unescape(literal: &str) -> Result<Vec<u8>, &'static str> {
+    unescape_with(literal, false)
+}
+
+/// Decodes a literal section of a Luau interpolated string, which may also
+/// escape braces and backticks.
+pub fn unescape_interpolated(literal: &str) -> Result<Vec<u8>, &'static str> {
+    unescape_with(literal, true)
+}
+
+fn unescape_with(literal: &str, interpolated: bool) -> Result<Vec<u8>, &'static str> {
+    let bytes = literal.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            result.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        i += 1;
+        let Some(&escape) = bytes.get(i) else {
+            return Err("unfinished escape sequence");
+        };
+        i += 1;
+
+        match escape {
+            b'a' => result.push(0x07),
+            b'b' => result.push(0x08),
+            b'f' => result.push(0x0c),
+            b'n' => result.push(b'\n'),
+            b'r' => result.push(b'\r'),
+            b't' => result.push(b'\t'),
+            b'v' => result.push(0x0b),
+            b'\\' | b'"' | b'\'' => result.push(escape),
+            b'{' | b'}' | b'`' if interpolated => result.push(escape),
+            b'\n' | b'\r' => {
+                // `\r\n` and `\n\r` count as a single line break
+                if let Some(&next @ (b'\n' | b'\r')) = bytes.get(i) {
+                    if next != escape {
+                        i += 1;
+                    }
+                }
+
+                result.push(b'\n');
+            }
+            b'z' => {
+                while bytes
+                    .get(i)
+                    .is_some_and(|c| c.is_ascii_whitespace() || *c == 0x0b)
+                {
+                    i += 1;
+                }
+            }
+            b'x' => {
+                let digits = bytes
+                    .get(i..i + 2)
+                    .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+                    .ok_or("hexadecimal digit expected")?;
+                let digits = std::str::from_utf8(digits).unwrap();
+
+                result.push(u8::from_str_radix(digits, 16).unwrap());
+                i += 2;
+            }
+            b'0'..=b'9' => {
+                let mut value: u32 = (escape - b'0') as u32;
+                let mut count = 1;
+
+                while count < 3 {
+                    match bytes.get(i) {
+                        Some(c @ b'0'..=b'9') => {
+                            value = value * 10 + (c - b'0') as u32;
+                            count += 1;
+                            i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+
+                result.push(u8::try_from(value).map_err(|_| "decimal escape too large")?);
+            }
+            b'u' => {
+                if bytes.get(i) != Some(&b'{') {
+                    return Err("missing '{' in \\u{xxxx}");
+                }
+                i += 1;
+
+                let start = i;
+                while bytes.get(i).is_some_and(|c| c.is_ascii_hexdigit()) {
+                    i += 1;
+                }
+
+                if start == i {
+                    return Err("hexadecimal digit expected");
+                }
+
+                if bytes.get(i) != Some(&b'}') {
+                    return Err("missing '}' in \\u{xxxx}");
+                }
+
+                let digits = std::str::from_utf8(&bytes[start..i]).unwrap();
+                let value = u32::from_str_radix(digits, 16)
+                    .ok()
+                    .filter(|value| *value < 0x8000_0000)
+                    .ok_or("UTF-8 value too large")?;
+
+                encode_utf8(value, &mut result);
+                i += 1;
+            }
+            _ => return Err("invalid escape sequence"),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Decodes the body of a long bracket string such as `[==[ text ]==]`. Lua
+/// skips a line break directly after the opening bracket and normalizes every
+/// line break sequence to `\n`.
+pub fn long_bracket(literal: &str) -> Vec<u8> {
+    let bytes = literal.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+
+    let mut i = match bytes {
+        [b'\r', b'\n', ..] | [b'\n', b'\r', ..] => 2,
+        [b'\n' | b'\r', ..] => 1,
+        _ => 0,
+    };
+
+    while i < bytes.len() {
+        match bytes[i] {
+            c @ (b'\n' | b'\r') => {
+                if let Some(&next @ (b'\n' | b'\r')) = bytes.get(i + 1) {
+                    if next != c {
+                        i += 1;
+                    }
+                }
+
+                result.push(b'\n');
+            }
+            c => result.push(c),
+        }
+
+        i += 1;
+    }
+
+    result
+}
+
+/// Encodes a code point the way Lua 5.4 does, which allows values up to
+/// 2^31 using the original, longer UTF-8 sequences.
+fn encode_utf8(value: u32, result: &mut Vec<u8>) {
+    if value < 0x80 {
+        result.push(value as u8);
+        return;
+    }
+
+    let mut buffer = [0u8; 6];
+    let mut index = buffer.len();
+    let mut max_first = 0x3f;
+    let mut value = value;
+
+    loop {
+        index -= 1;
+        buffer[index] = 0x80 | (value & 0x3f) as u8;
+        value >>= 6;
+        max_first >>= 1;
+
+        if value <= max_first {
+            break;
+        }
+    }
+
+    index -= 1;
+    buffer[index] = ((!max_first << 1) as u8) | value as u8;
+
+    result.extend_from_slice(&buffer[index..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{long_bracket, unescape, unescape_interpolated};
+    use crate::parser::{
+        ast::definition::{ExpressionKind, LuaString, StatementKind},
+        parse,
+    };
+
+    #[test]
+    fn simple_escapes() {
+        assert_eq!(
+            unescape(r#"\a\b\f\n\r\t\v\\\"\'"#).unwrap(),
+            b"\x07\x08\x0c\n\r\t\x0b\\\"'"
+        );
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(unescape("a\\\nb").unwrap(), b"a\nb");
+        assert_eq!(unescape("a\\\r\nb").unwrap(), b"a\nb");
+        assert_eq!(unescape("a\\\n\rb").unwrap(), b"a\nb");
+        assert_eq!(unescape("a\\\n\nb").unwrap(), b"a\n\nb");
+    }
+
+    #[test]
+    fn skip_whitespace() {
+        assert_eq!(unescape("a\\z  \n\t\x0b b").unwrap(), b"ab");
+        assert_eq!(unescape("a\\zb").unwrap(), b"ab");
+    }
+
+    #[test]
+    fn hexadecimal() {
+        assert_eq!(unescape(r"\x41\xff\x0a").unwrap(), b"A\xff\n");
+        assert_eq!(unescape(r"\x4"), Err("hexadecimal digit expected"));
+        assert_eq!(unescape(r"\xg0"), Err("hexadecimal digit expected"));
+    }
+
+    #[test]
+    fn decimal() {
+        assert_eq!(unescape(r"\65\0\255").unwrap(), b"A\0\xff");
+        assert_eq!(unescape(r"\0651").unwrap(), b"A1");
+        assert_eq!(unescape(r"\256"), Err("decimal escape too large"));
+    }
+
+    #[test]
+    fn unicode() {
+        assert_eq!(unescape(r"\u{41}").unwrap(), b"A");
+        assert_eq!(unescape(r"\u{e9}").unwrap(), "é".as_bytes());
+        assert_eq!(unescape(r"\u{1F600}").unwrap(), "😀".as_bytes());
+        assert_eq!(
+            unescape(r"\u{7FFFFFFF}").unwrap(),
+            b"\xfd\xbf\xbf\xbf\xbf\xbf"
+        );
+        assert_eq!(unescape(r"\u{80000000}"), Err("UTF-8 value too large"));
+        assert_eq!(unescape(r"\u41"), Err("missing '{' in \\u{xxxx}"));
+        assert_eq!(unescape(r"\u{}"), Err("hexadecimal digit expected"));
+        assert_eq!(unescape(r"\u{41"), Err("missing '}' in \\u{xxxx}"));
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        assert_eq!(unescape(r"\q"), Err("invalid escape sequence"));
+        assert_eq!(unescape(r"\{"), Err("invalid escape sequence"));
+        assert_eq!(unescape("a\\"), Err("unfinished escape sequence"));
+    }
+
+    #[test]
+    fn interpolated() {
+        assert_eq!(unescape_interpolated(r"\{\}\`").unwrap(), b"{}`");
+    }
+
+    #[test]
+    fn long_brackets() {
+        assert_eq!(long_bracket("\nfirst\r\nsecond"), b"first\nsecond");
+        assert_eq!(long_bracket("\r\n\\n"), b"\\n");
+        assert_eq!(long_bracket("\n\nblank"), b"\nblank");
+    }
+
+    #[test]
+    fn long_bracket_levels() {
+        let chunk = parse("local a = [==[\n]] ]=] ]==]").unwrap();
+        let StatementKind::LocalDeclaration(local) = &chunk.block.statements[0].kind else {
+            panic!("expected a local declaration");
+        };
+
+        assert_eq!(
+            local.expression_list[0].kind,
+            ExpressionKind::LiteralString(LuaString::from("]] ]=] "))
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Resolution of names to the declarations they refer to.
+//!
+//! Every local, parameter and loop variable is a binding of its own, and
+//! every use of a name resolves to the innermost binding visible at that
+//! point, or to a global when there is none.
+
+use std::collections::HashMap;
+
+use crate::parser::ast::definition::{Attribute, Block, Identifier, Parameter, Span};
+
+mod resolver;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct BindingId(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BindingKind {
+    Local,
+    LocalFunction,
+    Parameter,
+    /// The implicit first parameter of a method.
+    SelfParameter,
+    ForVariable,
+}
+
+#[derive(Clone, Debug)]
+pub struct Binding {
+    /// The name in the source.
+    pub name: Identifier,
+    /// A name no other binding has, e.g. `x@2` for the second local called
+    /// `x`. The implicit `self` of a method keeps its name, since it is not
+    /// part of any parameter list.
+    pub unique_name: Identifier,
+    pub kind: BindingKind,
+    pub attribute: Option<Attribute>,
+    /// The statement or function that declares the binding.
+    pub span: Span,
+    /// How many functions deep the binding is declared; 0 for the body that
+    /// was resolved.
+    pub depth: usize,
+    /// Whether a nested function refers to the binding.
+    pub captured: bool,
+    /// The binding with the same name that was visible where this one is
+    /// declared, and is hidden by it.
+    pub shadows: Option<BindingId>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resolution {
+    /// A binding of the function the name is used in.
+    Local(BindingId),
+    /// A binding of an enclosing function.
+    Upvalue(BindingId),
+    Global,
+}
+
+impl Resolution {
+    pub fn binding(self) -> Option<BindingId> {
+        match self {
+            Resolution::Local(id) | Resolution::Upvalue(id) => Some(id),
+            Resolution::Global => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A use of a name, in the order they appear in the source. A compound
+/// assignment reads and then writes its variable.
+#[derive(Clone, Debug)]
+pub struct Reference {
+    /// The name in the source.
+    pub name: Identifier,
+    pub resolution: Resolution,
+    pub access: Access,
+    /// The innermost expression or statement containing the use.
+    pub span: Span,
+    /// How many functions deep the use is.
+    pub depth: usize,
+}
+
+pub struct Scopes {
+    pub bindings: Vec<Binding>,
+    pub references: Vec<Reference>,
+    unique_names: HashMap<Identifier, BindingId>,
+}
+
+impl Scopes {
+    pub fn binding(&self, id: BindingId) -> &Binding {
+        &self.bindings[id.0]
+    }
+
+    /// The binding a renamed identifier belongs to. The `self` of a method is
+    /// not unique, so it is never found.
+    pub fn lookup(&self, unique_name: &str) -> Option<BindingId> {
+        self.unique_names.get(unique_name).copied()
+    }
+
+    pub fn references_to(&self, id: BindingId) -> impl Iterator<Item = &Reference> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.resolution.binding() == Some(id))
+    }
+}
+
+/// Resolves every name in a function body, leaving it unchanged.
+pub fn resolve(parameter_list: &[Parameter], block: &Block) -> Scopes {
+    let mut parameter_list = parameter_list.to_vec();
+    let mut block = block.clone();
+
+    resolver::resolve(&mut parameter_list, &mut block, false)
+}
+
+/// Resolves every name in a function body and replaces the name of every
+/// binding, and of every use of it, with its unique name. Globals keep their
+/// names.
+pub fn rename(parameter_list: &mut [Parameter], block: &mut Block) -> Scopes {
+    resolver::resolve(parameter_list, block, true)
+}
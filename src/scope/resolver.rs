@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+
+use crate::parser::ast::{
+    definition::{
+        AnonFunctionExpression, AssignmentStatement, Attribute, Block, CompoundAssignmentStatement,
+        Expression, FunctionDefinitionStatement, GenericForStatement, Identifier,
+        LocalDeclarationStatement, LocalFunctionDefinitionStatement, NumericForStatement,
+        Parameter, RepeatStatement, Span, Statement, Variable,
+    },
+    visitor::{self, VisitorMut},
+};
+
+use super::{Access, Binding, BindingId, BindingKind, Reference, Resolution, Scopes};
+
+struct Resolver {
+    rename: bool,
+    /// The bindings of every enclosing block, innermost last.
+    scopes: Vec<Vec<BindingId>>,
+    /// The innermost expression or statement being visited, last.
+    spans: Vec<Span>,
+    depth: usize,
+    counts: HashMap<Identifier, usize>,
+    bindings: Vec<Binding>,
+    references: Vec<Reference>,
+}
+
+impl Resolver {
+    fn span(&self) -> Span {
+        self.spans.last().copied().unwrap_or_default()
+    }
+
+    fn lookup(&self, identifier: &Identifier) -> Option<BindingId> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .copied()
+            .find(|&id| self.bindings[id.0].name == *identifier)
+    }
+
+    fn declare(
+        &mut self,
+        identifier: &mut Identifier,
+        kind: BindingKind,
+        attribute: Option<Attribute>,
+    ) {
+        let unique_name = match kind {
+            BindingKind::SelfParameter => identifier.clone(),
+            _ => {
+                let count = self.counts.entry(identifier.clone()).or_default();
+                *count += 1;
+                format!("{identifier}@{count}")
+            }
+        };
+
+        let id = BindingId(self.bindings.len());
+        self.bindings.push(Binding {
+            name: identifier.clone(),
+            unique_name: unique_name.clone(),
+            kind,
+            attribute,
+            span: self.span(),
+            depth: self.depth,
+            captured: false,
+            shadows: self.lookup(identifier),
+        });
+        self.scopes.last_mut().unwrap().push(id);
+
+        if self.rename {
+            *identifier = unique_name;
+        }
+    }
+
+    fn reference(&mut self, identifier: &mut Identifier, access: Access) {
+        let id = self.lookup(identifier);
+        let resolution = match id {
+            Some(id) if self.bindings[id.0].depth == self.depth => Resolution::Local(id),
+            Some(id) => {
+                self.bindings[id.0].captured = true;
+                Resolution::Upvalue(id)
+            }
+            None => Resolution::Global,
+        };
+
+        self.references.push(Reference {
+            name: identifier.clone(),
+            resolution,
+            access,
+            span: self.span(),
+            depth: self.depth,
+        });
+
+        if let (true, Some(id)) = (self.rename, id) {
+            *identifier = self.bindings[id.0].unique_name.clone();
+        }
+    }
+
+    /// Visits a variable that is assigned to. Only a plain name is written;
+    /// the table and key of a field are read.
+    fn target(&mut self, var: &mut Variable) {
+        match var {
+            Variable::Identifier(identifier) => self.reference(identifier, Access::Write),
+            _ => self.visit_variable_mut(var),
+        }
+    }
+
+    fn declare_parameters(&mut self, parameters: &mut [Parameter]) {
+        for param in parameters.iter_mut() {
+            if let Parameter::Identifier(identifier) = param {
+                self.declare(identifier, BindingKind::Parameter, None);
+            }
+        }
+    }
+
+    fn function(&mut self, parameters: &mut [Parameter], block: &mut Block, method: bool) {
+        self.depth += 1;
+        self.scopes.push(vec![]);
+
+        if method {
+            self.declare(&mut "self".to_string(), BindingKind::SelfParameter, None);
+        }
+
+        self.declare_parameters(parameters);
+        self.visit_block_mut(block);
+        self.scopes.pop();
+        self.depth -= 1;
+    }
+}
+
+impl VisitorMut for Resolver {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        self.scopes.push(vec![]);
+        visitor::walk_block_mut(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        self.spans.push(stmt.span);
+        visitor::walk_statement_mut(self, stmt);
+        self.spans.pop();
+    }
+
+    fn visit_expression_mut(&mut self, exp: &mut Expression) {
+        self.spans.push(exp.span);
+        visitor::walk_expression_mut(self, exp);
+        self.spans.pop();
+    }
+
+    fn visit_local_declaration_mut(&mut self, stmt: &mut LocalDeclarationStatement) {
+        // the values are evaluated before the new locals come into scope, so
+        // `local x = x` refers to an outer `x`
+        for exp in stmt.expression_list.iter_mut() {
+            self.visit_expression_mut(exp);
+        }
+
+        for (identifier, attribute) in stmt
+            .identifier_list
+            .iter_mut()
+            .zip(stmt.attribute_list.iter())
+        {
+            self.declare(identifier, BindingKind::Local, *attribute);
+        }
+    }
+
+    fn visit_assignment_mut(&mut self, stmt: &mut AssignmentStatement) {
+        for var in stmt.variable_list.iter_mut() {
+            self.target(var);
+        }
+
+        for exp in stmt.expression_list.iter_mut() {
+            self.visit_expression_mut(exp);
+        }
+    }
+
+    fn visit_compound_assignment_mut(&mut self, stmt: &mut CompoundAssignmentStatement) {
+        match &mut stmt.variable {
+            Variable::Identifier(identifier) => {
+                let mut name = identifier.clone();
+                self.reference(&mut name, Access::Read);
+                self.reference(identifier, Access::Write);
+            }
+            var => self.visit_variable_mut(var),
+        }
+
+        self.visit_expression_mut(&mut stmt.expression);
+    }
+
+    fn visit_repeat_mut(&mut self, stmt: &mut RepeatStatement) {
+        // the condition still sees the locals of the block
+        self.scopes.push(vec![]);
+        visitor::walk_block_mut(self, &mut stmt.block);
+        self.visit_expression_mut(&mut stmt.condition);
+        self.scopes.pop();
+    }
+
+    fn visit_numeric_for_mut(&mut self, stmt: &mut NumericForStatement) {
+        self.visit_expression_mut(&mut stmt.start);
+        self.visit_expression_mut(&mut stmt.end);
+
+        if let Some(step) = &mut stmt.step {
+            self.visit_expression_mut(step);
+        }
+
+        self.scopes.push(vec![]);
+        self.declare(&mut stmt.identifier, BindingKind::ForVariable, None);
+        self.visit_block_mut(&mut stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_generic_for_mut(&mut self, stmt: &mut GenericForStatement) {
+        for exp in stmt.expression_list.iter_mut() {
+            self.visit_expression_mut(exp);
+        }
+
+        self.scopes.push(vec![]);
+
+        for identifier in stmt.identifier_list.iter_mut() {
+            self.declare(identifier, BindingKind::ForVariable, None);
+        }
+
+        self.visit_block_mut(&mut stmt.block);
+        self.scopes.pop();
+    }
+
+    fn visit_function_definition_mut(&mut self, stmt: &mut FunctionDefinitionStatement) {
+        self.target(&mut stmt.identifier);
+
+        let method = matches!(stmt.identifier, Variable::TableMethod(_));
+        self.function(&mut stmt.parameter_list, &mut stmt.block, method);
+    }
+
+    fn visit_local_function_definition_mut(&mut self, stmt: &mut LocalFunctionDefinitionStatement) {
+        // the local is in scope in its own body, so it may recurse
+        if let Variable::Identifier(identifier) = &mut stmt.identifier {
+            self.declare(identifier, BindingKind::LocalFunction, None);
+        }
+
+        self.function(&mut stmt.parameter_list, &mut stmt.block, false);
+    }
+
+    fn visit_anon_function_mut(&mut self, func: &mut AnonFunctionExpression) {
+        self.function(&mut func.parameter_list, &mut func.block, false);
+    }
+
+    fn visit_identifier_mut(&mut self, identifier: &mut Identifier) {
+        self.reference(identifier, Access::Read);
+    }
+}
+
+pub(super) fn resolve(parameters: &mut [Parameter], block: &mut Block, rename: bool) -> Scopes {
+    let mut resolver = Resolver {
+        rename,
+        scopes: vec![vec![]],
+        spans: vec![],
+        depth: 0,
+        counts: HashMap::new(),
+        bindings: vec![],
+        references: vec![],
+    };
+
+    resolver.declare_parameters(parameters);
+    resolver.visit_block_mut(block);
+
+    let unique_names = resolver
+        .bindings
+        .iter()
+        .enumerate()
+        .filter(|(_, binding)| binding.kind != BindingKind::SelfParameter)
+        .map(|(i, binding)| (binding.unique_name.clone(), BindingId(i)))
+        .collect();
+
+    Scopes {
+        bindings: resolver.bindings,
+        references: resolver.references,
+        unique_names,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{emit::lua::print, parser::parse, scope};
+
+    use super::Resolution;
+
+    fn renamed(source: &str) -> String {
+        let mut chunk = parse(source).unwrap();
+        scope::rename(&mut [], &mut chunk.block);
+
+        print(&chunk)
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            renamed(
+                "local x = 1
+                local x = x + 1
+                do local x = x * 2 print(x) end
+                print(x)"
+            ),
+            "local x@1 = 1
+local x@2 = x@1 + 1
+do
+    local x@3 = x@2 * 2
+    print(x@3)
+end
+print(x@2)"
+        );
+    }
+
+    #[test]
+    fn local_function_sees_itself() {
+        // `local function f` is in scope in its own body, while the `g` of
+        // `local g = function` is not and refers to a global
+        assert_eq!(
+            renamed(
+                "local function f(n) return f(n - 1) end
+                local g = function(n) return g(n - 1) end"
+            ),
+            "local function f@1(n@1)
+    return f@1(n@1 - 1)
+end
+local g@1 = function(n@2)
+    return g(n@2 - 1)
+end"
+        );
+    }
+
+    #[test]
+    fn repeat_condition_sees_body() {
+        assert_eq!(
+            renamed(
+                "local x = 0
+                repeat local x = x + 1 until x > 10
+                print(x)"
+            ),
+            "local x@1 = 0
+repeat
+    local x@2 = x@1 + 1
+until x@2 > 10
+print(x@1)"
+        );
+    }
+
+    #[test]
+    fn for_variables() {
+        // the start and iterator expressions are evaluated outside the loop,
+        // and the variables go out of scope after it
+        assert_eq!(
+            renamed(
+                "for i = i, 10 do local i = i end
+                for k, v in next, k do print(k, v) end
+                print(i, k)"
+            ),
+            "for i@1 = i, 10 do
+    local i@2 = i@1
+end
+for k@1, v@1 in next, k do
+    print(k@1, v@1)
+end
+print(i, k)"
+        );
+    }
+
+    #[test]
+    fn upvalues_captured() {
+        let chunk = parse("local a, b = 1, 2 local function f() return a end").unwrap();
+        let scopes = scope::resolve(&[], &chunk.block);
+
+        let a = scopes.lookup("a@1").unwrap();
+        let b = scopes.lookup("b@1").unwrap();
+
+        assert!(scopes.binding(a).captured);
+        assert!(!scopes.binding(b).captured);
+        assert_eq!(
+            scopes
+                .references_to(a)
+                .map(|reference| reference.resolution)
+                .collect::<Vec<_>>(),
+            [Resolution::Upvalue(a)]
+        );
+    }
+}
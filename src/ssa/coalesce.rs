@@ -0,0 +1,994 @@
+use std::collections::{HashMap, HashSet};
+
+use petgraph::{stable_graph::NodeIndex, visit::EdgeRef, Direction};
+
+use crate::{
+    cfg::{analysis, BasicBlock, CFGEdge, CFGNode, Cfg},
+    parser::ast::{
+        definition::{
+            AssignmentStatement, Expression, ExpressionKind, Identifier, LocalDeclarationStatement,
+            Parameter, Span, Statement, StatementKind, Variable,
+        },
+        visitor::{self, VisitorMut},
+    },
+};
+
+use super::{
+    operands::{self, Operands, Reads},
+    Ssa, Value,
+};
+
+/// Holds a value while a cycle of copies, such as a swap, is broken up.
+const SWAP: &str = "(swap)";
+
+/// Records the values a statement reads and writes.
+struct Access<'a> {
+    values: &'a HashMap<Identifier, Value>,
+    reads: Vec<Identifier>,
+    writes: Vec<Identifier>,
+}
+
+impl Operands for Access<'_> {
+    fn read(&mut self, identifier: &mut Identifier) {
+        if self.values.contains_key(identifier) {
+            self.reads.push(identifier.clone());
+        }
+    }
+
+    fn write(&mut self, identifier: &mut Identifier) {
+        if self.values.contains_key(identifier) {
+            self.writes.push(identifier.clone());
+        }
+    }
+}
+
+/// The reads and writes of everything a block evaluates, one entry per step.
+fn accesses(ssa: &mut Ssa, node: NodeIndex) -> Vec<(Vec<Identifier>, Vec<Identifier>)> {
+    let Some(block) = ssa.cfg.graph[node].block_mut() else {
+        return vec![];
+    };
+
+    let mut result = vec![];
+    let mut access = Access {
+        values: &ssa.values,
+        reads: vec![],
+        writes: vec![],
+    };
+
+    for stmt in block.statements.iter_mut() {
+        operands::visit_operands(&mut access, stmt);
+        result.push((
+            std::mem::take(&mut access.reads),
+            std::mem::take(&mut access.writes),
+        ));
+    }
+
+    if let Some(stmt) = &mut block.last_statement {
+        visitor::walk_last_statement_mut(&mut Reads(&mut access), stmt);
+    }
+
+    if let Some(exp) = &mut block.condition {
+        Reads(&mut access).visit_expression_mut(exp);
+    }
+
+    result.push((access.reads, vec![]));
+
+    result
+}
+
+/// Drops the phis whose value is never read, which the construction places
+/// for locals that are already out of scope.
+fn remove_dead_phis(ssa: &mut Ssa) {
+    let mut live = HashSet::new();
+
+    for node in ssa.cfg.graph.node_indices() {
+        for (reads, _) in accesses(ssa, node) {
+            live.extend(reads);
+        }
+    }
+
+    let mut worklist = live.iter().cloned().collect::<Vec<_>>();
+    let phis = ssa
+        .phis
+        .values()
+        .flatten()
+        .map(|phi| (phi.target.clone(), phi))
+        .collect::<HashMap<_, _>>();
+
+    while let Some(name) = worklist.pop() {
+        if let Some(phi) = phis.get(&name) {
+            for (_, argument) in phi.arguments.iter() {
+                if live.insert(argument.clone()) {
+                    worklist.push(argument.clone());
+                }
+            }
+        }
+    }
+
+    for phis in ssa.phis.values_mut() {
+        phis.retain(|phi| live.contains(&phi.target));
+    }
+
+    ssa.phis.retain(|_, phis| !phis.is_empty());
+}
+
+/// Gives every edge into a phi a block of its own to hold the copies, unless
+/// the source is a block that leads nowhere else.
+fn split_edges(ssa: &mut Ssa) {
+    let graph = &mut ssa.cfg.graph;
+
+    let mut edges = graph
+        .edge_indices()
+        .filter(|&edge| {
+            let (from, to) = graph.edge_endpoints(edge).unwrap();
+
+            ssa.phis.contains_key(&to)
+                && (graph[from].block().is_none()
+                    || graph.edges_directed(from, Direction::Outgoing).count() > 1)
+        })
+        .collect::<Vec<_>>();
+
+    // removing an edge moves the last one into its place
+    edges.sort_by(|a, b| b.cmp(a));
+
+    for edge in edges {
+        let (from, to) = graph.edge_endpoints(edge).unwrap();
+        let weight = graph.remove_edge(edge).unwrap();
+
+        let node = graph.add_node(CFGNode::Block(Box::default()));
+        graph.add_edge(from, node, weight);
+        graph.add_edge(node, to, CFGEdge::Fallthrough);
+
+        for phi in ssa.phis.get_mut(&to).unwrap() {
+            let argument = phi
+                .arguments
+                .iter()
+                .find(|(pred, _)| *pred == from)
+                .map(|(_, argument)| argument.clone());
+
+            if let Some(argument) = argument {
+                phi.arguments.push((node, argument));
+            }
+        }
+    }
+
+    for (&node, phis) in ssa.phis.iter_mut() {
+        for phi in phis.iter_mut() {
+            phi.arguments
+                .retain(|&(pred, _)| graph.find_edge(pred, node).is_some());
+        }
+    }
+}
+
+/// Which values are alive at the same time, and so cannot share a name.
+struct Interference {
+    pairs: HashSet<(Identifier, Identifier)>,
+}
+
+impl Interference {
+    fn add(&mut self, a: &Identifier, b: &Identifier) {
+        if a != b {
+            self.pairs.insert((a.clone(), b.clone()));
+            self.pairs.insert((b.clone(), a.clone()));
+        }
+    }
+
+    fn contains(&self, a: &Identifier, b: &Identifier) -> bool {
+        self.pairs.contains(&(a.clone(), b.clone()))
+    }
+
+    /// Every value written together interferes with the others and with
+    /// everything that is still alive afterwards.
+    fn define(&mut self, live: &HashSet<Identifier>, writes: &[Identifier]) {
+        for write in writes.iter() {
+            for other in live.iter().chain(writes) {
+                self.add(write, other);
+            }
+        }
+    }
+}
+
+fn interference(ssa: &mut Ssa) -> Interference {
+    let nodes = ssa.cfg.graph.node_indices().collect::<Vec<_>>();
+    let steps = nodes
+        .iter()
+        .map(|&node| (node, accesses(ssa, node)))
+        .collect::<HashMap<_, _>>();
+
+    let mut upward: HashMap<NodeIndex, HashSet<Identifier>> = HashMap::new();
+    let mut defined: HashMap<NodeIndex, HashSet<Identifier>> = HashMap::new();
+
+    for &node in nodes.iter() {
+        let mut written = ssa
+            .phis
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .map(|phi| phi.target.clone())
+            .collect::<HashSet<_>>();
+        let mut reads = HashSet::new();
+
+        for (step_reads, step_writes) in steps[&node].iter() {
+            reads.extend(
+                step_reads
+                    .iter()
+                    .filter(|read| !written.contains(*read))
+                    .cloned(),
+            );
+            written.extend(step_writes.iter().cloned());
+        }
+
+        upward.insert(node, reads);
+        defined.insert(node, written);
+    }
+
+    // the arguments of a phi are read at the end of the predecessor
+    let mut phi_reads: HashMap<NodeIndex, HashSet<Identifier>> = HashMap::new();
+
+    for phi in ssa.phis.values().flatten() {
+        for (pred, argument) in phi.arguments.iter() {
+            phi_reads.entry(*pred).or_default().insert(argument.clone());
+        }
+    }
+
+    let mut order = analysis::reverse_postorder(&ssa.cfg);
+    order.reverse();
+
+    let mut live_in: HashMap<NodeIndex, HashSet<Identifier>> = HashMap::new();
+    let mut live_out: HashMap<NodeIndex, HashSet<Identifier>> = HashMap::new();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for &node in order.iter() {
+            let mut out = phi_reads.get(&node).cloned().unwrap_or_default();
+
+            for succ in ssa.cfg.graph.neighbors_directed(node, Direction::Outgoing) {
+                out.extend(live_in.get(&succ).into_iter().flatten().cloned());
+            }
+
+            let mut live = upward[&node].clone();
+            live.extend(out.difference(&defined[&node]).cloned());
+
+            live_out.insert(node, out);
+
+            if live_in.get(&node) != Some(&live) {
+                live_in.insert(node, live);
+                changed = true;
+            }
+        }
+    }
+
+    let mut interference = Interference {
+        pairs: HashSet::new(),
+    };
+
+    for &node in order.iter() {
+        let mut live = live_out[&node].clone();
+
+        for (reads, writes) in steps[&node].iter().rev() {
+            interference.define(&live, writes);
+
+            for write in writes.iter() {
+                live.remove(write);
+            }
+
+            live.extend(reads.iter().cloned());
+        }
+
+        let targets = ssa
+            .phis
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .map(|phi| phi.target.clone())
+            .collect::<Vec<_>>();
+
+        interference.define(&live, &targets);
+    }
+
+    // parameters and the initial values of locals all exist from the start
+    let initial = live_out[&ssa.cfg.entry].iter().cloned().collect::<Vec<_>>();
+    interference.define(&HashSet::new(), &initial);
+
+    interference
+}
+
+/// Sets of values that end up sharing a name, as a union-find forest.
+struct Classes {
+    parents: HashMap<Identifier, Identifier>,
+    members: HashMap<Identifier, Vec<Identifier>>,
+}
+
+impl Classes {
+    fn find(&self, value: &Identifier) -> Identifier {
+        let mut value = value;
+
+        while let Some(parent) = self.parents.get(value) {
+            value = parent;
+        }
+
+        value.clone()
+    }
+
+    fn members(&self, root: &Identifier) -> Vec<Identifier> {
+        self.members
+            .get(root)
+            .cloned()
+            .unwrap_or_else(|| vec![root.clone()])
+    }
+
+    /// Joins the classes of two values unless any of their members interfere.
+    fn coalesce(&mut self, interference: &Interference, a: &Identifier, b: &Identifier) {
+        let (a, b) = (self.find(a), self.find(b));
+
+        if a == b {
+            return;
+        }
+
+        let (a_members, b_members) = (self.members(&a), self.members(&b));
+
+        if a_members
+            .iter()
+            .any(|x| b_members.iter().any(|y| interference.contains(x, y)))
+        {
+            return;
+        }
+
+        self.parents.insert(b.clone(), a.clone());
+        self.members.remove(&b);
+        self.members
+            .insert(a, a_members.into_iter().chain(b_members).collect());
+    }
+}
+
+/// The value a statement copies, if it only copies one value into another.
+fn copy(stmt: &Statement) -> Option<(&Identifier, &Identifier)> {
+    let (target, exp) = match &stmt.kind {
+        StatementKind::LocalDeclaration(LocalDeclarationStatement {
+            identifier_list,
+            expression_list,
+            ..
+        }) if identifier_list.len() == 1 && expression_list.len() == 1 => {
+            (&identifier_list[0], &expression_list[0])
+        }
+        StatementKind::Assignment(AssignmentStatement {
+            variable_list,
+            expression_list,
+        }) if variable_list.len() == 1 && expression_list.len() == 1 => match &variable_list[0] {
+            Variable::Identifier(target) => (target, &expression_list[0]),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    match &exp.kind {
+        ExpressionKind::Variable(Variable::Identifier(source)) => Some((target, source)),
+        _ => None,
+    }
+}
+
+struct Rename<'a>(&'a HashMap<Identifier, Identifier>);
+
+impl Operands for Rename<'_> {
+    fn read(&mut self, identifier: &mut Identifier) {
+        self.write(identifier);
+    }
+
+    fn write(&mut self, identifier: &mut Identifier) {
+        if let Some(name) = self.0.get(identifier) {
+            *identifier = name.clone();
+        }
+    }
+}
+
+fn assign(target: &str, source: &str) -> Statement {
+    let source = Expression::new(
+        ExpressionKind::Variable(Variable::Identifier(source.to_string())),
+        Span::default(),
+    );
+
+    Statement::new(
+        StatementKind::Assignment(AssignmentStatement {
+            variable_list: vec![Variable::Identifier(target.to_string())],
+            expression_list: vec![source],
+        }),
+        Span::default(),
+    )
+}
+
+/// Orders copies that happen all at once so that no value is overwritten
+/// before it is copied, going through a temporary to break up cycles.
+fn sequentialize(mut copies: Vec<(Identifier, Identifier)>) -> Vec<Statement> {
+    let mut statements = vec![];
+
+    while !copies.is_empty() {
+        let ready = copies
+            .iter()
+            .position(|(target, _)| copies.iter().all(|(_, source)| source != target));
+
+        match ready {
+            Some(i) => {
+                let (target, source) = copies.remove(i);
+                statements.push(assign(&target, &source));
+            }
+            None => {
+                // every target is still needed, so save one of them first
+                let target = copies[0].0.clone();
+
+                statements.push(Statement::new(
+                    StatementKind::LocalDeclaration(LocalDeclarationStatement {
+                        identifier_list: vec![SWAP.to_string()],
+                        attribute_list: vec![None],
+                        type_list: vec![None],
+                        expression_list: vec![Expression::new(
+                            ExpressionKind::Variable(Variable::Identifier(target.clone())),
+                            Span::default(),
+                        )],
+                    }),
+                    Span::default(),
+                ));
+
+                for (_, source) in copies.iter_mut() {
+                    if *source == target {
+                        *source = SWAP.to_string();
+                    }
+                }
+            }
+        }
+    }
+
+    statements
+}
+
+/// Records which of the given names a block reads or writes.
+struct Used<'a> {
+    names: &'a HashSet<Identifier>,
+    used: HashSet<Identifier>,
+}
+
+impl Operands for Used<'_> {
+    fn read(&mut self, identifier: &mut Identifier) {
+        self.write(identifier);
+    }
+
+    fn write(&mut self, identifier: &mut Identifier) {
+        if self.names.contains(identifier) {
+            self.used.insert(identifier.clone());
+        }
+    }
+}
+
+/// Declares the names that no local declaration introduces, such as a class
+/// whose values only come from copies, in a block of their own before the
+/// rest of the function.
+fn declare_undeclared(cfg: &mut Cfg, parameter_list: &[Parameter], names: &HashSet<Identifier>) {
+    let mut used = Used {
+        names,
+        used: HashSet::new(),
+    };
+
+    for node in cfg.graph.node_indices().collect::<Vec<_>>() {
+        operands::visit_block(&mut used, cfg, node);
+    }
+
+    let mut undeclared = used.used;
+
+    for param in parameter_list.iter() {
+        if let Parameter::Identifier(identifier) = param {
+            undeclared.remove(identifier);
+        }
+    }
+
+    for block in cfg.graph.node_weights().filter_map(CFGNode::block) {
+        for stmt in block.statements.iter() {
+            if let StatementKind::LocalDeclaration(decl) = &stmt.kind {
+                for identifier in decl.identifier_list.iter() {
+                    undeclared.remove(identifier);
+                }
+            }
+        }
+    }
+
+    if undeclared.is_empty() {
+        return;
+    }
+
+    let mut undeclared = undeclared.into_iter().collect::<Vec<_>>();
+    undeclared.sort();
+
+    let declaration = Statement::new(
+        StatementKind::LocalDeclaration(LocalDeclarationStatement {
+            attribute_list: vec![None; undeclared.len()],
+            type_list: vec![None; undeclared.len()],
+            identifier_list: undeclared,
+            expression_list: vec![],
+        }),
+        Span::default(),
+    );
+
+    // the first block may be a loop header, which must not declare them again
+    let start = cfg.graph.add_node(CFGNode::Block(Box::new(BasicBlock {
+        statements: vec![declaration],
+        ..Default::default()
+    })));
+
+    let edges = cfg
+        .graph
+        .edges_directed(cfg.entry, Direction::Outgoing)
+        .map(|edge| (edge.id(), edge.target()))
+        .collect::<Vec<_>>();
+
+    for (edge, target) in edges.into_iter().rev() {
+        cfg.graph.remove_edge(edge);
+        cfg.graph.add_edge(start, target, CFGEdge::Fallthrough);
+    }
+
+    cfg.graph.add_edge(cfg.entry, start, CFGEdge::Fallthrough);
+}
+
+pub(super) fn destruct(mut ssa: Ssa) -> (Vec<Parameter>, Cfg) {
+    remove_dead_phis(&mut ssa);
+    split_edges(&mut ssa);
+
+    let interference = interference(&mut ssa);
+    let mut classes = Classes {
+        parents: HashMap::new(),
+        members: HashMap::new(),
+    };
+
+    let mut phis = ssa.phis.iter().collect::<Vec<_>>();
+    phis.sort_by_key(|(node, _)| **node);
+
+    // a phi whose arguments share its name needs no copies at all
+    for (_, phis) in phis.iter() {
+        for phi in phis.iter() {
+            for (_, argument) in phi.arguments.iter() {
+                classes.coalesce(&interference, &phi.target, argument);
+            }
+        }
+    }
+
+    for node in ssa.cfg.graph.node_indices() {
+        for stmt in ssa.cfg.graph[node]
+            .block()
+            .into_iter()
+            .flat_map(|b| &b.statements)
+        {
+            if let Some((target, source)) = copy(stmt) {
+                if ssa.values.contains_key(target) && ssa.values.contains_key(source) {
+                    classes.coalesce(&interference, target, source);
+                }
+            }
+        }
+    }
+
+    // every class is named after its variable, or after its first value when
+    // another class of the same variable already took that name
+    let mut roots = ssa
+        .values
+        .keys()
+        .map(|value| classes.find(value))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    roots.sort_by_key(|root| (ssa.values[root].variable.clone(), ssa.values[root].version));
+
+    let mut taken = HashSet::new();
+    let mut class_names = HashMap::new();
+
+    for root in roots {
+        let variable = &ssa.values[&root].variable;
+        let name = if taken.insert(variable.clone()) {
+            variable.clone()
+        } else {
+            root.clone()
+        };
+
+        class_names.insert(root, name);
+    }
+
+    let names = ssa
+        .values
+        .keys()
+        .map(|value| (value.clone(), class_names[&classes.find(value)].clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut parameter_list = ssa.parameter_list;
+
+    for param in parameter_list.iter_mut() {
+        if let Parameter::Identifier(identifier) = param {
+            Rename(&names).write(identifier);
+        }
+    }
+
+    let mut cfg = ssa.cfg;
+    let class_names = class_names.into_values().collect::<HashSet<_>>();
+
+    for node in cfg.graph.node_indices().collect::<Vec<_>>() {
+        operands::visit_block(&mut Rename(&names), &mut cfg, node);
+
+        if let Some(block) = cfg.graph[node].block_mut() {
+            block.statements.retain(|stmt| {
+                !copy(stmt).is_some_and(|(target, source)| {
+                    target == source && class_names.contains(target)
+                })
+            });
+        }
+    }
+
+    for (node, phis) in phis {
+        let preds = cfg
+            .graph
+            .neighbors_directed(*node, Direction::Incoming)
+            .collect::<HashSet<_>>();
+
+        for pred in preds {
+            let copies = phis
+                .iter()
+                .filter_map(|phi| {
+                    let (_, argument) = phi.arguments.iter().find(|(p, _)| *p == pred)?;
+                    let (target, source) = (&names[&phi.target], &names[argument]);
+
+                    (target != source).then(|| (target.clone(), source.clone()))
+                })
+                .collect::<Vec<_>>();
+
+            let block = cfg.graph[pred]
+                .block_mut()
+                .expect("edges into phis start in blocks");
+            block.statements.extend(sequentialize(copies));
+        }
+    }
+
+    declare_undeclared(&mut cfg, &parameter_list, &class_names);
+
+    (parameter_list, cfg.cleanup())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use petgraph::{stable_graph::NodeIndex, Direction};
+
+    use crate::{
+        cfg::{CFGNode, Cfg},
+        parser::{
+            ast::definition::{
+                AssignmentStatement, ExpressionKind, LastStatementKind, LocalDeclarationStatement,
+                Parameter, Statement, StatementKind, Variable,
+            },
+            parse,
+        },
+        ssa::{construct, Ssa},
+    };
+
+    use super::{destruct, interference, sequentialize, split_edges, Classes, SWAP};
+
+    fn ssa(source: &str) -> Ssa {
+        let chunk = parse(source).unwrap();
+        construct(&[], &chunk.block, Default::default()).unwrap()
+    }
+
+    /// The block whose printed statements contain `text`.
+    fn block(cfg: &Cfg, text: &str) -> NodeIndex {
+        cfg.graph
+            .node_indices()
+            .find(|&node| format!("{:?}", cfg.graph[node]).contains(text))
+            .unwrap()
+    }
+
+    /// Every edge as `source -kind-> target`, with statements separated by
+    /// `;`.
+    fn edges(cfg: &Cfg) -> Vec<String> {
+        let name = |node| match format!("{:?}", cfg.graph[node]).replace('\n', "; ") {
+            name if name.is_empty() => "(empty)".to_string(),
+            name => name,
+        };
+
+        let mut edges = cfg
+            .graph
+            .edge_indices()
+            .map(|edge| {
+                let (source, target) = cfg.graph.edge_endpoints(edge).unwrap();
+                format!("{} -{:?}-> {}", name(source), cfg.graph[edge], name(target))
+            })
+            .collect::<Vec<_>>();
+        edges.sort();
+
+        edges
+    }
+
+    /// Forwards the copy `target = source` into the reads of `target`, which
+    /// is what leaves phis reading each other's values behind.
+    fn propagate(ssa: &mut Ssa, target: &str, source: &str) {
+        for phi in ssa.phis.values_mut().flatten() {
+            for (_, argument) in phi.arguments.iter_mut() {
+                if argument == target {
+                    *argument = source.to_string();
+                }
+            }
+        }
+
+        for node in ssa.cfg.graph.node_indices() {
+            let Some(block) = ssa.cfg.graph[node].block_mut() else {
+                continue;
+            };
+
+            block
+                .statements
+                .retain(|stmt| !matches!(super::copy(stmt), Some((t, _)) if t == target));
+
+            if let Some(LastStatementKind::Return(stmt)) =
+                block.last_statement.as_mut().map(|stmt| &mut stmt.kind)
+            {
+                for exp in stmt.expression_list.iter_mut() {
+                    if let ExpressionKind::Variable(Variable::Identifier(name)) = &mut exp.kind {
+                        if name == target {
+                            *name = source.to_string();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The target of the phi for `variable` at the block whose statements
+    /// contain `text`.
+    fn phi_target(ssa: &Ssa, text: &str, variable: &str) -> String {
+        ssa.phis[&block(&ssa.cfg, text)]
+            .iter()
+            .find(|phi| phi.variable == variable)
+            .map(|phi| phi.target.clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn swapped_phis() {
+        let mut ssa = ssa("local a, b = 1, 2
+            while p do local t = a a = b b = t end
+            return a, b");
+        let (a, b) = (
+            phi_target(&ssa, "test", "a@1"),
+            phi_target(&ssa, "test", "b@1"),
+        );
+
+        // the loop only swaps, so each phi reads the other one on the back edge
+        propagate(&mut ssa, "t@1#1", &a);
+        propagate(&mut ssa, "a@1#3", &b);
+        propagate(&mut ssa, "b@1#3", &a);
+
+        let (_, cfg) = destruct(ssa);
+
+        assert_eq!(
+            edges(&cfg),
+            vec![
+                "entry --> local a@1, b@1 = 1, 2",
+                "local (swap) = a@1; a@1 = b@1; b@1 = (swap) --> test p",
+                "local a@1, b@1 = 1, 2 --> test p",
+                "return a@1, b@1 --> exit",
+                "test p -false-> return a@1, b@1",
+                "test p -true-> local (swap) = a@1; a@1 = b@1; b@1 = (swap)",
+            ]
+        );
+    }
+
+    #[test]
+    fn lost_copy() {
+        let mut ssa = ssa("local x, y = 0
+            repeat y = x x = x + 1 until p(x)
+            return y");
+        let x = phi_target(&ssa, "test", "x@1");
+
+        // the loop returns the value from before the last increment
+        propagate(&mut ssa, "y@1#3", &x);
+
+        let (_, cfg) = destruct(ssa);
+
+        // the copy back into the phi only happens when the loop repeats, so
+        // the exit still reads the old value
+        assert_eq!(
+            edges(&cfg),
+            vec![
+                "entry --> local x@1#3; local x@1, y@1 = 0",
+                "local x@1#3; local x@1, y@1 = 0 --> x@1#3 = x@1 + 1; test p(x@1#3)",
+                "return x@1 --> exit",
+                "x@1 = x@1#3 --> x@1#3 = x@1 + 1; test p(x@1#3)",
+                "x@1#3 = x@1 + 1; test p(x@1#3) -false-> x@1 = x@1#3",
+                "x@1#3 = x@1 + 1; test p(x@1#3) -true-> return x@1",
+            ]
+        );
+    }
+
+    #[test]
+    fn critical_edges_split() {
+        let mut ssa = ssa("local x = 1 if p then x = 2 end return x");
+        let test = block(&ssa.cfg, "test");
+        let then = block(&ssa.cfg, "= 2");
+        let join = block(&ssa.cfg, "return");
+
+        split_edges(&mut ssa);
+
+        // the test leads elsewhere too, so its edge to the join gets a block,
+        // while the then branch can hold its copies itself
+        let preds = ssa
+            .cfg
+            .graph
+            .neighbors_directed(join, Direction::Incoming)
+            .collect::<HashSet<_>>();
+        let split = *preds.iter().find(|&&pred| pred != then).unwrap();
+
+        assert_eq!(preds.len(), 2);
+        assert!(ssa.cfg.graph[split].block().unwrap().is_empty());
+        assert_eq!(
+            ssa.cfg
+                .graph
+                .neighbors_directed(split, Direction::Incoming)
+                .collect::<Vec<_>>(),
+            vec![test]
+        );
+
+        let [phi] = ssa.phis[&join].as_slice() else {
+            panic!("one phi expected at the join");
+        };
+        let arguments = phi.arguments.iter().cloned().collect::<HashSet<_>>();
+        assert_eq!(
+            arguments,
+            HashSet::from([(split, "x@1#1".to_string()), (then, "x@1#3".to_string())])
+        );
+    }
+
+    #[test]
+    fn interference_blocks_coalescing() {
+        let mut ssa = ssa("local x = 0 local y = x x = 1 return x + y");
+        let interference = interference(&mut ssa);
+        let [x1, x2, y] = ["x@1#1", "x@1#2", "y@1#1"].map(String::from);
+
+        // `x` is overwritten while its copy in `y` is still alive
+        assert!(interference.contains(&y, &x2));
+        assert!(!interference.contains(&y, &x1));
+        assert!(!interference.contains(&x1, &x2));
+
+        let mut classes = Classes {
+            parents: HashMap::new(),
+            members: HashMap::new(),
+        };
+
+        classes.coalesce(&interference, &y, &x1);
+        assert_eq!(classes.find(&y), classes.find(&x1));
+
+        // `x@1#2` does not interfere with `x@1#1`, but with `y` in its class
+        classes.coalesce(&interference, &x1, &x2);
+        assert_ne!(classes.find(&x1), classes.find(&x2));
+    }
+
+    #[test]
+    fn copy_targets_declared() {
+        let (_, cfg) = destruct(ssa("local x = 0 local y = x x = 1 return x + y"));
+
+        // `x = 1` keeps the name of the local, as `y` took over its first value
+        assert_eq!(
+            format!("{:?}", cfg.graph[block(&cfg, "return")]),
+            "local x@1\nlocal y@1 = 0\nx@1 = 1\nreturn x@1 + y@1"
+        );
+    }
+
+    #[test]
+    fn copy_targets_declared_before_loop() {
+        let (_, cfg) = destruct(ssa("local i, j = 0
+            repeat j = i i = i + 1 until i > 10
+            return j"));
+
+        let start = cfg
+            .graph
+            .neighbors_directed(cfg.entry, Direction::Outgoing)
+            .next()
+            .unwrap();
+        let header = block(&cfg, "test");
+
+        assert_eq!(
+            format!("{:?}", cfg.graph[start]),
+            "local j@1#3\nlocal i@1, j@1 = 0"
+        );
+        assert!(matches!(&cfg.graph[header], CFGNode::Block(block)
+            if block.statements.iter().all(|stmt| !matches!(stmt.kind, StatementKind::LocalDeclaration(_)))));
+    }
+
+    #[test]
+    fn parameters_renamed() {
+        let chunk = parse("a = 1 return a").unwrap();
+        let ssa = construct(
+            &[Parameter::Identifier("a".to_string())],
+            &chunk.block,
+            Default::default(),
+        )
+        .unwrap();
+
+        let (parameters, _) = destruct(ssa);
+
+        assert_eq!(parameters, vec![Parameter::Identifier("a@1".to_string())]);
+    }
+
+    /// Runs copies one after the other, starting with every name holding
+    /// itself, and returns what each name holds in the end.
+    fn run(statements: &[Statement]) -> HashMap<String, String> {
+        let mut values = HashMap::<String, String>::new();
+
+        for stmt in statements {
+            let (target, source) = match &stmt.kind {
+                StatementKind::Assignment(AssignmentStatement {
+                    variable_list,
+                    expression_list,
+                }) => match (variable_list.as_slice(), expression_list.as_slice()) {
+                    ([Variable::Identifier(target)], [source]) => (target, source),
+                    _ => panic!("not a single copy: {stmt}"),
+                },
+                StatementKind::LocalDeclaration(LocalDeclarationStatement {
+                    identifier_list,
+                    expression_list,
+                    ..
+                }) => (&identifier_list[0], &expression_list[0]),
+                _ => panic!("not a copy: {stmt}"),
+            };
+
+            let ExpressionKind::Variable(Variable::Identifier(source)) = &source.kind else {
+                panic!("not a copy: {stmt}");
+            };
+
+            let value = values.get(source).cloned().unwrap_or(source.clone());
+            values.insert(target.clone(), value);
+        }
+
+        values.remove(SWAP);
+        values
+    }
+
+    fn copies(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(target, source)| (target.to_string(), source.to_string()))
+            .collect()
+    }
+
+    fn expected(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        copies(pairs).into_iter().collect()
+    }
+
+    #[test]
+    fn swap() {
+        let statements = sequentialize(copies(&[("a", "b"), ("b", "a")]));
+
+        // one copy goes through the temporary
+        assert_eq!(statements.len(), 3);
+        assert_eq!(run(&statements), expected(&[("a", "b"), ("b", "a")]));
+    }
+
+    #[test]
+    fn rotation() {
+        let pairs = [("a", "b"), ("b", "c"), ("c", "a")];
+        let statements = sequentialize(copies(&pairs));
+
+        assert_eq!(statements.len(), 4);
+        assert_eq!(run(&statements), expected(&pairs));
+    }
+
+    #[test]
+    fn chain_needs_no_temporary() {
+        let pairs = [("a", "b"), ("b", "c"), ("c", "d")];
+        let statements = sequentialize(copies(&pairs));
+
+        assert_eq!(statements.len(), 3);
+        assert_eq!(run(&statements), expected(&pairs));
+    }
+
+    #[test]
+    fn swap_with_a_value_read_elsewhere() {
+        // `c` reads the old `a` that the swap overwrites
+        let pairs = [("a", "b"), ("b", "a"), ("c", "a"), ("d", "d")];
+        let statements = sequentialize(copies(&pairs));
+
+        assert_eq!(run(&statements), expected(&pairs));
+    }
+}
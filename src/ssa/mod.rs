@@ -0,0 +1,285 @@
+//! Static single assignment form of the locals of a function.
+//!
+//! Every local gets a unique name first, e.g. `x@1` for the first local called
+//! `x`, and every assignment to it then defines a new version, e.g. `x@1#2`.
+//! Version 0 is the value the local holds when the function starts, which is
+//! the argument for a parameter.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use petgraph::{graph::EdgeIndex, stable_graph::NodeIndex};
+
+use crate::{
+    cfg::{
+        translator::{self, Options},
+        Cfg, Error,
+    },
+    parser::ast::definition::{Attribute, Block, Identifier, Parameter},
+    scope,
+};
+
+mod coalesce;
+mod operands;
+mod rename;
+
+/// Merges the versions of a variable that reach the start of a block.
+#[derive(Clone)]
+pub struct Phi {
+    pub variable: Identifier,
+    pub target: Identifier,
+    /// The version that flows in from every predecessor.
+    pub arguments: Vec<(NodeIndex, Identifier)>,
+}
+
+impl fmt::Debug for Phi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = phi(", self.target)?;
+
+        for (i, (_, argument)) in self.arguments.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{argument}")?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Value {
+    pub variable: Identifier,
+    pub version: usize,
+}
+
+pub struct Ssa {
+    pub cfg: Cfg,
+    /// The parameters, named after their initial versions.
+    pub parameter_list: Vec<Parameter>,
+    /// The phis at the start of every block that merges several versions.
+    pub phis: HashMap<NodeIndex, Vec<Phi>>,
+    /// The variable and version behind every versioned name.
+    pub values: HashMap<Identifier, Value>,
+    /// Locals that keep a single unversioned name, because a closure
+    /// captures them or they are closed at the end of their scope.
+    pub pinned: HashSet<Identifier>,
+}
+
+impl Ssa {
+    /// Whether `name` is a versioned local rather than a pinned local or a
+    /// global.
+    pub fn is_value(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    /// Removes blocks, along with the phi arguments flowing out of them.
+    pub fn remove_blocks(&mut self, mut nodes: Vec<NodeIndex>) {
+        // removing a block moves the last one into its place, so the highest
+        // indices go first
+        nodes.sort();
+
+        for node in nodes.into_iter().rev() {
+            self.remove_block(node);
+        }
+    }
+
+    /// Removes edges, along with the phi arguments of the ones that were the
+    /// last edge between their blocks.
+    pub fn remove_edges(&mut self, mut edges: Vec<EdgeIndex>) {
+        // removing an edge moves the last one into its place, so the highest
+        // indices go first
+        edges.sort();
+
+        for edge in edges.into_iter().rev() {
+            let (pred, succ) = self.cfg.graph.edge_endpoints(edge).unwrap();
+            self.cfg.graph.remove_edge(edge);
+
+            if self.cfg.graph.find_edge(pred, succ).is_none() {
+                for phi in self.phis.get_mut(&succ).into_iter().flatten() {
+                    phi.arguments.retain(|(node, _)| *node != pred);
+                }
+            }
+        }
+    }
+
+    /// Removes a block, along with the phi arguments flowing out of it. As in
+    /// the graph, the last block takes over the index of the removed one.
+    fn remove_block(&mut self, node: NodeIndex) {
+        let last = NodeIndex::new(self.cfg.graph.node_count() - 1);
+
+        self.cfg.graph.remove_node(node);
+        self.phis.remove(&node);
+
+        for phi in self.phis.values_mut().flatten() {
+            phi.arguments.retain(|(pred, _)| *pred != node);
+        }
+
+        if last == node {
+            return;
+        }
+
+        if let Some(phis) = self.phis.remove(&last) {
+            self.phis.insert(node, phis);
+        }
+
+        for phi in self.phis.values_mut().flatten() {
+            for (pred, _) in phi.arguments.iter_mut() {
+                if *pred == last {
+                    *pred = node;
+                }
+            }
+        }
+
+        for index in [&mut self.cfg.entry, &mut self.cfg.exit] {
+            if *index == last {
+                *index = node;
+            }
+        }
+    }
+}
+
+/// Translates a function body and brings its locals into SSA form.
+pub fn construct(
+    parameter_list: &[Parameter],
+    block: &Block,
+    options: Options,
+) -> Result<Ssa, Vec<Error>> {
+    let mut parameter_list = parameter_list.to_vec();
+    let mut block = block.clone();
+    let scopes = scope::rename(&mut parameter_list, &mut block);
+
+    // a closure may read or write a captured local at any call, and a
+    // to-be-closed value has to stay the same object until the end of its
+    // scope, so either lives in a single place
+    let pinned = scopes
+        .bindings
+        .into_iter()
+        .filter(|binding| {
+            binding.depth == 0 && (binding.captured || binding.attribute == Some(Attribute::Close))
+        })
+        .map(|binding| binding.unique_name)
+        .collect();
+
+    let cfg = translator::translate(&block, options)?;
+
+    Ok(rename::rename(cfg, parameter_list, pinned))
+}
+
+/// Leaves SSA form again. Phis become copies at the end of their
+/// predecessors, on new blocks where an edge would otherwise run from a
+/// branch into a join, and values that are never alive at the same time
+/// share a name so that most copies disappear. A name that only copies and
+/// assignments write is declared when the function starts, so none of them
+/// turns into a global. Returns the renamed parameters along with the graph.
+pub fn destruct(ssa: Ssa) -> (Vec<Parameter>, Cfg) {
+    coalesce::destruct(ssa)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use petgraph::{stable_graph::NodeIndex, Direction};
+
+    use crate::parser::{ast::definition::Parameter, parse};
+
+    use super::{construct, Ssa};
+
+    fn ssa(source: &str) -> Ssa {
+        let chunk = parse(source).unwrap();
+        construct(&[Parameter::VariableArg], &chunk.block, Default::default()).unwrap()
+    }
+
+    /// The block whose printed statements contain `text`.
+    fn block(ssa: &Ssa, text: &str) -> NodeIndex {
+        ssa.cfg
+            .graph
+            .node_indices()
+            .find(|&node| format!("{:?}", ssa.cfg.graph[node]).contains(text))
+            .unwrap()
+    }
+
+    fn predecessors(ssa: &Ssa, node: NodeIndex) -> HashSet<NodeIndex> {
+        ssa.cfg
+            .graph
+            .neighbors_directed(node, Direction::Incoming)
+            .collect()
+    }
+
+    #[test]
+    fn phi_at_if_else_join() {
+        let ssa = ssa("local x, y = 1, 0
+            if p then x = 2 else x = 3 end
+            return x, y");
+
+        // `y` is the same on both paths, so only `x` is merged
+        assert_eq!(ssa.phis.len(), 1);
+
+        let join = block(&ssa, "return");
+        let [phi] = ssa.phis[&join].as_slice() else {
+            panic!("one phi expected at the join");
+        };
+
+        assert_eq!(phi.variable, "x@1");
+        assert_eq!(
+            format!("{:?}", ssa.cfg.graph[join]),
+            format!("return {}, y@1#1", phi.target)
+        );
+
+        let then = block(&ssa, "= 2");
+        let otherwise = block(&ssa, "= 3");
+
+        assert_eq!(predecessors(&ssa, join), HashSet::from([then, otherwise]));
+        assert_eq!(phi.arguments.len(), 2);
+
+        for (pred, argument) in phi.arguments.iter() {
+            let defined = format!("{:?}", ssa.cfg.graph[*pred]);
+            assert!(defined.starts_with(&format!("{argument} = ")), "{defined}");
+        }
+    }
+
+    #[test]
+    fn phi_at_loop_header() {
+        let ssa = ssa("local i, n = 0, 10
+            while i < n do i = i + 1 end
+            return i");
+
+        assert_eq!(ssa.phis.len(), 1);
+
+        let header = block(&ssa, "test");
+        let [phi] = ssa.phis[&header].as_slice() else {
+            panic!("one phi expected at the header");
+        };
+
+        assert_eq!(phi.variable, "i@1");
+        assert_eq!(
+            format!("{:?}", ssa.cfg.graph[header]),
+            format!("test {} < n@1#1", phi.target)
+        );
+
+        let preheader = block(&ssa, "local");
+        let latch = block(&ssa, "+ 1");
+
+        assert_eq!(
+            predecessors(&ssa, header),
+            HashSet::from([preheader, latch])
+        );
+
+        let arguments = phi.arguments.iter().cloned().collect::<HashSet<_>>();
+        assert_eq!(
+            arguments,
+            HashSet::from([
+                (preheader, "i@1#1".to_string()),
+                (latch, "i@1#3".to_string()),
+            ])
+        );
+        assert_eq!(
+            format!("{:?}", ssa.cfg.graph[latch]),
+            format!("i@1#3 = {} + 1", phi.target)
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use petgraph::stable_graph::NodeIndex;
+
+use crate::{
+    cfg::Cfg,
+    parser::ast::{
+        definition::{
+            AnonFunctionExpression, AssignmentStatement, CompoundOperator, Expression,
+            ExpressionKind, Identifier, Statement, StatementKind, Variable,
+        },
+        visitor::{self, VisitorMut},
+    },
+};
+
+/// Receives the locals a statement reads and writes.
+pub(super) trait Operands {
+    fn read(&mut self, identifier: &mut Identifier);
+    fn write(&mut self, identifier: &mut Identifier);
+}
+
+/// Passes the locals an expression reads on to the operands. Nested functions
+/// only refer to pinned locals, so they are skipped.
+pub(super) struct Reads<'a, O>(pub(super) &'a mut O);
+
+impl<O: Operands> VisitorMut for Reads<'_, O> {
+    fn visit_identifier_mut(&mut self, identifier: &mut Identifier) {
+        self.0.read(identifier);
+    }
+
+    fn visit_anon_function_mut(&mut self, _func: &mut AnonFunctionExpression) {}
+}
+
+/// Reports every read of a statement before its writes, which is the order
+/// they happen in. A compound assignment to a local both reads and writes it,
+/// so it is split into a plain assignment first.
+pub(super) fn visit_operands<O: Operands>(operands: &mut O, stmt: &mut Statement) {
+    match &mut stmt.kind {
+        StatementKind::LocalDeclaration(decl) => {
+            for exp in decl.expression_list.iter_mut() {
+                Reads(operands).visit_expression_mut(exp);
+            }
+
+            for identifier in decl.identifier_list.iter_mut() {
+                operands.write(identifier);
+            }
+        }
+        StatementKind::Assignment(assignment) => {
+            for var in assignment.variable_list.iter_mut() {
+                if !matches!(var, Variable::Identifier(_)) {
+                    Reads(operands).visit_variable_mut(var);
+                }
+            }
+
+            for exp in assignment.expression_list.iter_mut() {
+                Reads(operands).visit_expression_mut(exp);
+            }
+
+            for var in assignment.variable_list.iter_mut() {
+                if let Variable::Identifier(identifier) = var {
+                    operands.write(identifier);
+                }
+            }
+        }
+        StatementKind::CompoundAssignment(compound) => {
+            if let Variable::Identifier(identifier) = &compound.variable {
+                let value = Expression::new(
+                    ExpressionKind::Variable(compound.variable.clone()),
+                    stmt.span,
+                );
+                let expression = binary(&compound.operator, value, compound.expression.clone());
+
+                stmt.kind = StatementKind::Assignment(AssignmentStatement {
+                    variable_list: vec![Variable::Identifier(identifier.clone())],
+                    expression_list: vec![expression],
+                });
+
+                return visit_operands(operands, stmt);
+            }
+
+            Reads(operands).visit_variable_mut(&mut compound.variable);
+            Reads(operands).visit_expression_mut(&mut compound.expression);
+        }
+        StatementKind::FunctionDefinition(def) => match &mut def.identifier {
+            Variable::Identifier(identifier) => operands.write(identifier),
+            var => Reads(operands).visit_variable_mut(var),
+        },
+        StatementKind::LocalFunctionDefinition(def) => {
+            if let Variable::Identifier(identifier) = &mut def.identifier {
+                operands.write(identifier);
+            }
+        }
+        _ => visitor::walk_statement_mut(&mut Reads(operands), stmt),
+    }
+}
+
+fn binary(operator: &CompoundOperator, a: Expression, b: Expression) -> Expression {
+    let kind = match operator {
+        CompoundOperator::Addition => ExpressionKind::Addition,
+        CompoundOperator::Subtraction => ExpressionKind::Subtraction,
+        CompoundOperator::Multiplication => ExpressionKind::Multiplication,
+        CompoundOperator::Division => ExpressionKind::Division,
+        CompoundOperator::Modulo => ExpressionKind::Modulo,
+        CompoundOperator::Exponentiation => ExpressionKind::Exponentiation,
+        CompoundOperator::Concatenation => ExpressionKind::Concatenation,
+    };
+
+    let span = a.span.to(b.span);
+    Expression::new(kind(Box::new(a), Box::new(b)), span)
+}
+
+/// Visits the operands of everything a block evaluates, in order.
+pub(super) fn visit_block<O: Operands>(operands: &mut O, cfg: &mut Cfg, node: NodeIndex) {
+    let Some(block) = cfg.graph[node].block_mut() else {
+        return;
+    };
+
+    for stmt in block.statements.iter_mut() {
+        visit_operands(operands, stmt);
+    }
+
+    if let Some(stmt) = &mut block.last_statement {
+        visitor::walk_last_statement_mut(&mut Reads(operands), stmt);
+    }
+
+    if let Some(exp) = &mut block.condition {
+        Reads(operands).visit_expression_mut(exp);
+    }
+}
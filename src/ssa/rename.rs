@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+
+use petgraph::{stable_graph::NodeIndex, Direction};
+
+use crate::{
+    cfg::{
+        analysis::{self, DominatorTree},
+        Cfg,
+    },
+    parser::ast::definition::{Identifier, Parameter, StatementKind, Variable},
+};
+
+use super::{
+    operands::{visit_block, Operands},
+    Phi, Ssa, Value,
+};
+
+/// Finds the blocks that write each variable, and the variables that are read
+/// in a different block than the one writing them. Only those can need a phi.
+struct Liveness<'a> {
+    variables: &'a HashSet<Identifier>,
+    node: NodeIndex,
+    written: HashSet<Identifier>,
+    live_in: HashSet<Identifier>,
+    definitions: HashMap<Identifier, HashSet<NodeIndex>>,
+}
+
+impl Operands for Liveness<'_> {
+    fn read(&mut self, identifier: &mut Identifier) {
+        if self.variables.contains(identifier) && !self.written.contains(identifier) {
+            self.live_in.insert(identifier.clone());
+        }
+    }
+
+    fn write(&mut self, identifier: &mut Identifier) {
+        if self.variables.contains(identifier) {
+            self.written.insert(identifier.clone());
+            self.definitions
+                .entry(identifier.clone())
+                .or_default()
+                .insert(self.node);
+        }
+    }
+}
+
+struct Renamer<'a> {
+    variables: &'a HashSet<Identifier>,
+    /// The current version of every variable, innermost definition last.
+    stacks: HashMap<Identifier, Vec<Identifier>>,
+    counters: HashMap<Identifier, usize>,
+    values: HashMap<Identifier, Value>,
+    /// The variables written so far, to restore the stacks after a subtree.
+    written: Vec<Identifier>,
+}
+
+impl Renamer<'_> {
+    fn version(&mut self, variable: &Identifier, version: usize) -> Identifier {
+        let name = format!("{variable}#{version}");
+
+        self.values.insert(
+            name.clone(),
+            Value {
+                variable: variable.clone(),
+                version,
+            },
+        );
+
+        name
+    }
+
+    fn current(&mut self, variable: &Identifier) -> Identifier {
+        match self.stacks.get(variable).and_then(|stack| stack.last()) {
+            Some(name) => name.clone(),
+            None => self.version(variable, 0),
+        }
+    }
+
+    fn define(&mut self, variable: &Identifier) -> Identifier {
+        let counter = self.counters.entry(variable.clone()).or_default();
+        *counter += 1;
+
+        let version = *counter;
+        let name = self.version(variable, version);
+
+        self.stacks
+            .entry(variable.clone())
+            .or_default()
+            .push(name.clone());
+        self.written.push(variable.clone());
+
+        name
+    }
+}
+
+impl Operands for Renamer<'_> {
+    fn read(&mut self, identifier: &mut Identifier) {
+        if self.variables.contains(identifier) {
+            *identifier = self.current(identifier);
+        }
+    }
+
+    fn write(&mut self, identifier: &mut Identifier) {
+        if self.variables.contains(identifier) {
+            *identifier = self.define(identifier);
+        }
+    }
+}
+
+/// Renames every block below `node` in the dominator tree, so the versions
+/// defined in a block are visible in exactly the blocks it dominates.
+fn rename_block(
+    renamer: &mut Renamer,
+    cfg: &mut Cfg,
+    phis: &mut HashMap<NodeIndex, Vec<Phi>>,
+    dominators: &DominatorTree,
+    node: NodeIndex,
+) {
+    let written = renamer.written.len();
+
+    for phi in phis.get_mut(&node).into_iter().flatten() {
+        phi.target = renamer.define(&phi.variable);
+    }
+
+    visit_block(renamer, cfg, node);
+
+    let mut successors = cfg
+        .graph
+        .neighbors_directed(node, Direction::Outgoing)
+        .collect::<Vec<_>>();
+    successors.sort();
+    successors.dedup();
+
+    for succ in successors {
+        for phi in phis.get_mut(&succ).into_iter().flatten() {
+            let argument = renamer.current(&phi.variable);
+            phi.arguments.push((node, argument));
+        }
+    }
+
+    for &child in dominators.children(node) {
+        rename_block(renamer, cfg, phis, dominators, child);
+    }
+
+    for variable in renamer.written.drain(written..) {
+        renamer.stacks.get_mut(&variable).unwrap().pop();
+    }
+}
+
+/// Places phis at the iterated dominance frontiers of the blocks writing each
+/// variable, then gives every write a new version.
+pub(super) fn rename(
+    mut cfg: Cfg,
+    mut parameter_list: Vec<Parameter>,
+    pinned: HashSet<Identifier>,
+) -> Ssa {
+    let mut variables = HashSet::new();
+
+    for param in parameter_list.iter() {
+        if let Parameter::Identifier(identifier) = param {
+            variables.insert(identifier.clone());
+        }
+    }
+
+    for node in cfg.graph.node_indices() {
+        for stmt in cfg.graph[node]
+            .block()
+            .into_iter()
+            .flat_map(|b| &b.statements)
+        {
+            match &stmt.kind {
+                StatementKind::LocalDeclaration(decl) => {
+                    variables.extend(decl.identifier_list.iter().cloned())
+                }
+                StatementKind::LocalFunctionDefinition(def) => {
+                    if let Variable::Identifier(identifier) = &def.identifier {
+                        variables.insert(identifier.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    variables.retain(|variable| !pinned.contains(variable));
+
+    let mut liveness = Liveness {
+        variables: &variables,
+        node: cfg.entry,
+        written: HashSet::new(),
+        live_in: HashSet::new(),
+        definitions: HashMap::new(),
+    };
+
+    for node in cfg.graph.node_indices().collect::<Vec<_>>() {
+        liveness.node = node;
+        liveness.written.clear();
+        visit_block(&mut liveness, &mut cfg, node);
+    }
+
+    let dominators = analysis::dominators(&cfg);
+    let frontiers = analysis::dominance_frontiers(&cfg, &dominators);
+    let mut phis: HashMap<NodeIndex, Vec<Phi>> = HashMap::new();
+
+    let mut live_in = liveness.live_in.into_iter().collect::<Vec<_>>();
+    live_in.sort();
+
+    for variable in live_in {
+        let mut placed = HashSet::new();
+        let mut worklist = liveness
+            .definitions
+            .get(&variable)
+            .map(|nodes| nodes.iter().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        while let Some(node) = worklist.pop() {
+            for &frontier in frontiers.get(&node).into_iter().flatten() {
+                if !placed.insert(frontier) {
+                    continue;
+                }
+
+                phis.entry(frontier).or_default().push(Phi {
+                    variable: variable.clone(),
+                    target: variable.clone(),
+                    arguments: vec![],
+                });
+
+                // the phi is another write to the variable
+                worklist.push(frontier);
+            }
+        }
+    }
+
+    let mut renamer = Renamer {
+        variables: &variables,
+        stacks: HashMap::new(),
+        counters: HashMap::new(),
+        values: HashMap::new(),
+        written: vec![],
+    };
+
+    for param in parameter_list.iter_mut() {
+        if let Parameter::Identifier(identifier) = param {
+            if variables.contains(identifier) {
+                *identifier = renamer.version(identifier, 0);
+            }
+        }
+    }
+
+    let entry = cfg.entry;
+    rename_block(&mut renamer, &mut cfg, &mut phis, &dominators, entry);
+
+    Ssa {
+        cfg,
+        parameter_list,
+        phis,
+        values: renamer.values,
+        pinned,
+    }
+}
@@ -17,7 +17,7 @@ pub enum Register {
     R16,
 }
 
-enum Value {
+pub enum Value {
     Immediate(f32),
     Register(Register),
 }
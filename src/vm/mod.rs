@@ -1,4 +1,5 @@
 pub mod assembler;
 pub mod disassembler;
 pub mod intrinsics;
+#[allow(clippy::module_inception)]
 pub mod vm;